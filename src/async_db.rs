@@ -0,0 +1,185 @@
+//! An async handle over a DB, for the collectors running on a tokio runtime.
+//!
+//! Like `tokio::fs`, which offers no positioned writes nor file locks, every operation runs the blocking
+//! operation of [`PhysicalDB`] on the blocking thread pool of tokio and awaits it, so the runtime threads are
+//! never blocked on the disk. The operations on a handle run one at a time, in the order they are awaited.
+
+use crate::{
+    DbHeader, DbIssue, PhysicalDB, RecordInfo, RecordValue, SharedDB, TSLiteError, TimeSeries,
+    Timestamp,
+};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A DB whose operations are `async`: each of them runs on the blocking thread pool of tokio.
+/// The operations run one at a time, in the order they are awaited. Cloning it gives another handle on the same DB.
+#[derive(Debug)]
+pub struct AsyncPhysicalDB<V: RecordValue = u8> {
+    db: SharedDB<V>,
+}
+
+impl<V: RecordValue> Clone for AsyncPhysicalDB<V> {
+    fn clone(&self) -> AsyncPhysicalDB<V> {
+        AsyncPhysicalDB {
+            db: self.db.clone(),
+        }
+    }
+}
+
+impl<V: RecordValue + Send + 'static> AsyncPhysicalDB<V> {
+    /// Create a DB at `path`, or open the one already there, see [`PhysicalDB::create_or_open`].
+    pub async fn create_or_open(
+        path: impl AsRef<Path>,
+        origin_date: Option<Timestamp>,
+    ) -> Result<AsyncPhysicalDB<V>, TSLiteError> {
+        let path = PathBuf::from(path.as_ref());
+        let db = blocking(move || PhysicalDB::create_or_open(&path, origin_date)).await?;
+        Ok(AsyncPhysicalDB::from(db))
+    }
+
+    /// Open the existing DB at `path`, see [`PhysicalDB::open_path`].
+    pub async fn open_path(path: impl AsRef<Path>) -> Result<AsyncPhysicalDB<V>, TSLiteError> {
+        let path = PathBuf::from(path.as_ref());
+        let db = blocking(move || PhysicalDB::open_path(&path)).await?;
+        Ok(AsyncPhysicalDB::from(db))
+    }
+
+    /// Use a DB already shared with other threads, such as the one returned by
+    /// [`PhysicalDB::register_for_shutdown`].
+    pub fn from_shared(db: SharedDB<V>) -> AsyncPhysicalDB<V> {
+        AsyncPhysicalDB { db }
+    }
+
+    /// The header of the DB as known in memory.
+    pub fn header(&self) -> DbHeader {
+        *self.db.lock().unwrap_or_else(|e| e.into_inner()).header()
+    }
+
+    /// Add a record, see [`PhysicalDB::append_record`].
+    pub async fn append_record(&self, record: RecordInfo<V>) -> Result<(), TSLiteError> {
+        self.run(move |db| db.append_record(record)).await
+    }
+
+    /// Add several records at once, see [`PhysicalDB::append_records`].
+    pub async fn append_records(&self, records: Vec<RecordInfo<V>>) -> Result<(), TSLiteError> {
+        self.run(move |db| db.append_records(&records)).await
+    }
+
+    /// Add a value dated `time`.
+    pub async fn append(&self, time: Timestamp, value: V) -> Result<(), TSLiteError> {
+        self.run(move |db| db.append(time, value)).await
+    }
+
+    /// Every record whose date is within `[start, end[`, see [`PhysicalDB::read_range`].
+    pub async fn read_range(
+        &self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+        let (start, end) = (start.into(), end.into());
+        self.run(move |db| db.read_range(start, end)).await
+    }
+
+    /// Look for the first issue of the DB file, see [`PhysicalDB::check_db_file`].
+    pub async fn check_db_file(&self) -> Result<DbIssue, TSLiteError> {
+        self.run(|db| db.check_db_file()).await
+    }
+
+    /// Write the pending records and sync the file, see [`PhysicalDB::sync`].
+    pub async fn sync(&self) -> Result<(), TSLiteError> {
+        self.run(|db| db.sync()).await
+    }
+
+    /// Close the DB, see [`PhysicalDB::close`]. Other handles can still use it, it is re-opened transparently.
+    pub async fn close(&self) -> Result<(), TSLiteError> {
+        self.run(|db| db.close()).await
+    }
+
+    /// Run `operation` on the DB on the blocking thread pool.
+    async fn run<T, F>(&self, operation: F) -> Result<T, TSLiteError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut PhysicalDB<V>) -> Result<T, TSLiteError> + Send + 'static,
+    {
+        let db = self.db.clone();
+        blocking(move || operation(&mut db.lock().unwrap_or_else(|e| e.into_inner()))).await
+    }
+}
+
+impl<V: RecordValue> From<PhysicalDB<V>> for AsyncPhysicalDB<V> {
+    fn from(db: PhysicalDB<V>) -> AsyncPhysicalDB<V> {
+        AsyncPhysicalDB {
+            db: Arc::new(Mutex::new(db)),
+        }
+    }
+}
+
+/// Run `f` on the blocking thread pool and await its result.
+async fn blocking<T, F>(f: F) -> Result<T, TSLiteError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, TSLiteError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| TSLiteError::Backend(format!("The blocking operation failed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LATEST;
+    use std::fs;
+
+    #[test]
+    fn async_appends_and_reads() {
+        let path = "async_db.db";
+        let _ = fs::remove_file(path);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+            let db: AsyncPhysicalDB = AsyncPhysicalDB::create_or_open(path, Some(origin))
+                .await
+                .expect("could not create db.");
+            let other = db.clone();
+            for i in 0..5 {
+                db.append(origin.add_seconds(i), i as u8)
+                    .await
+                    .expect("could not append record.");
+            }
+            other
+                .append_record(RecordInfo {
+                    time_offset: 10,
+                    value: 10,
+                })
+                .await
+                .expect("could not append record.");
+            assert_eq!(db.header().records_number, 6);
+
+            let values: Vec<u8> = db
+                .read_range(origin.add_seconds(3), LATEST)
+                .await
+                .unwrap()
+                .iter()
+                .map(|r| r.value)
+                .collect();
+            assert_eq!(values, vec![3, 4, 10]);
+            assert_eq!(db.check_db_file().await.unwrap(), DbIssue::None);
+            db.close().await.expect("could not close db.");
+
+            let db: AsyncPhysicalDB<u8> = AsyncPhysicalDB::open_path(path)
+                .await
+                .expect("could not open db.");
+            assert_eq!(db.header().records_number, 6);
+            assert_eq!(
+                AsyncPhysicalDB::<u16>::open_path(path).await.err(),
+                Some(TSLiteError::ValueWidthMismatch(1))
+            );
+        });
+
+        let _ = fs::remove_file(path);
+    }
+}
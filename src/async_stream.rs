@@ -0,0 +1,196 @@
+//! Records as a `futures` [`Stream`], for async consumers.
+//!
+//! The records are read from the file as the stream is polled, one at a time, so a stream over a large range
+//! doesn't hold it in memory. The reads themselves are blocking, like every read of the crate, and short.
+//! A following stream waits for new records by sleeping in a separate thread, so it doesn't depend on
+//! any async runtime.
+
+use crate::{PhysicalDB, Record, RecordValue, TSLiteError, Timestamp, LATEST};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+
+/// A stream over the records of a DB, returned by [`PhysicalDB::range_stream`] and [`PhysicalDB::follow_stream`].
+pub struct RecordStream<'a, V: RecordValue = u8> {
+    db: &'a mut PhysicalDB<V>,
+    /// The time offsets of the records to return, `None` once the stream is over.
+    offsets: Option<(u32, u32)>,
+    /// Index of the next record to read.
+    next: u64,
+    /// The quantized value of the record before the next one, to invert the delta transform.
+    previous: Option<V>,
+    /// How long to wait before looking for new records, when following the DB.
+    poll_interval: Option<Duration>,
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Stream every record whose date is within `[start, end[`.
+    /// Records are assumed to be chronologically ordered, so the stream ends at the first record past `end`.
+    pub fn range_stream(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> RecordStream<'_, V> {
+        let offsets = self.offset_range(&start.into(), &end.into());
+        RecordStream {
+            db: self,
+            offsets,
+            next: 0,
+            previous: None,
+            poll_interval: None,
+        }
+    }
+
+    /// Stream every record dated `start` or later, then the records appended afterwards, like `tail -f`.
+    /// The stream looks for new records every `poll_interval`, and never ends unless reading the DB fails.
+    pub fn follow_stream(
+        &mut self,
+        start: impl Into<Timestamp>,
+        poll_interval: Duration,
+    ) -> RecordStream<'_, V> {
+        let mut stream = self.range_stream(start, LATEST);
+        stream.poll_interval = Some(poll_interval);
+        stream
+    }
+}
+
+impl<V: RecordValue> RecordStream<'_, V> {
+    /// Read the next record within the offsets, `None` if there is none for now.
+    fn read_next(&mut self, start: u32, end: u32) -> Result<Option<Record<V>>, TSLiteError> {
+        if self.next == 0 {
+            self.db.refresh_if_changed()?;
+        }
+        loop {
+            if self.next >= self.db.header.records_number {
+                // Only a following stream looks for records appended since the last read.
+                let refreshed = self.poll_interval.is_some() && self.db.refresh_if_changed()?;
+                if !refreshed || self.next >= self.db.header.records_number {
+                    return Ok(None);
+                }
+            }
+            let index = self.next;
+            let mut record = self.db.read_raw_record(index)?;
+            record.value =
+                self.db
+                    .header
+                    .transforms
+                    .decode(index, record.value, &mut self.previous);
+            self.next += 1;
+            if record.time_offset >= end {
+                self.offsets = None;
+                return Ok(None);
+            }
+            if record.time_offset >= start {
+                return Ok(Some(record.resolve(&self.db.header)));
+            }
+        }
+    }
+}
+
+// The stream only holds a reference to the DB and plain values, nothing that pinning would protect.
+impl<V: RecordValue> Unpin for RecordStream<'_, V> {}
+
+impl<V: RecordValue> Stream for RecordStream<'_, V> {
+    type Item = Result<Record<V>, TSLiteError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let stream = self.get_mut();
+        let (start, end) = match stream.offsets {
+            Some(offsets) => offsets,
+            None => return Poll::Ready(None),
+        };
+        match stream.read_next(start, end) {
+            Ok(Some(record)) => Poll::Ready(Some(Ok(record))),
+            Ok(None) => match (stream.offsets, stream.poll_interval) {
+                (Some(_), Some(interval)) => {
+                    let waker = cx.waker().clone();
+                    thread::spawn(move || {
+                        thread::sleep(interval);
+                        waker.wake();
+                    });
+                    Poll::Pending
+                }
+                _ => {
+                    stream.offsets = None;
+                    Poll::Ready(None)
+                }
+            },
+            Err(e) => {
+                stream.offsets = None;
+                Poll::Ready(Some(Err(e)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, RecordInfo, Transforms};
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn stream_records() {
+        let path = "async_stream.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            transforms: Transforms {
+                delta: true,
+                ..Transforms::default()
+            },
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        for i in 0..100u32 {
+            db.append_record(RecordInfo {
+                time_offset: i * 10,
+                value: (i * 3) as u8,
+            })
+            .expect("could not append record.");
+        }
+
+        let values: Vec<u8> = block_on(
+            db.range_stream(origin.add_seconds(95), origin.add_seconds(135))
+                .map(|r| r.unwrap().value)
+                .collect(),
+        );
+        assert_eq!(values, vec![30, 33, 36, 39]);
+        let empty = block_on(db.range_stream(origin, origin).count());
+        assert_eq!(empty, 0);
+
+        // Another handle appends while the stream follows the DB.
+        let writer = thread::spawn(move || {
+            let mut other: PhysicalDB =
+                PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+            for i in 100..103u32 {
+                thread::sleep(Duration::from_millis(20));
+                other
+                    .append_record(RecordInfo {
+                        time_offset: i * 10,
+                        value: i as u8,
+                    })
+                    .expect("could not append record.");
+            }
+        });
+        let followed: Vec<u8> = block_on(
+            db.follow_stream(origin.add_seconds(980), Duration::from_millis(5))
+                .take(5)
+                .map(|r| r.unwrap().value)
+                .collect(),
+        );
+        writer.join().unwrap();
+        // The values wrapped around when they were appended.
+        assert_eq!(followed, vec![38, 41, 100, 101, 102]);
+
+        let _ = fs::remove_file(path);
+    }
+}
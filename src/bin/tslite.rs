@@ -0,0 +1,259 @@
+//! `tslite`, a command-line tool to inspect and fix DB files, built with the `cli` feature.
+//!
+//! ```text
+//! tslite info <file>
+//! tslite check <file>
+//! tslite repair <file>
+//! tslite dump <file> [--format csv|ndjson]
+//! tslite append <file> <value> [--at <unix seconds>]
+//! tslite tail <file> [-f] [--lines <n>] [--interval <milliseconds>]
+//! ```
+//!
+//! The values are read as the unsigned integers of the width recorded in the file, `--type` reads them as
+//! another type: `u8`, `u16`, `u32`, `u64`, `i8`, `i16`, `i32`, `i64`, `f32` or `f64`.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+use tslite::format::{self, RECORDS_START};
+use tslite::{DbHeader, ExportFormat, PhysicalDB, RecordInfo, RecordValue, TSLiteError, Timestamp};
+
+const USAGE: &str = "Usage: tslite [--type <type>] <command> <file> [arguments]
+
+Commands:
+  info <file>                             Print the header of the DB.
+  check <file>                            Check the DB, exit with 1 if there is an issue.
+  repair <file>                           Fix the number of records of the DB.
+  dump <file> [--format csv|ndjson]       Print every record, as CSV by default.
+  append <file> <value> [--at <seconds>]  Append a record, at the current date by default.
+  tail <file> [-f] [--lines <n>]          Print the last records, 10 by default, then with -f the records
+       [--interval <milliseconds>]        appended by other processes, checking every 500 ms by default.";
+
+/// The arguments of the command line, once the type is taken out.
+struct Arguments {
+    command: String,
+    path: String,
+    /// The positional arguments after the file.
+    rest: Vec<String>,
+    /// The options after the file, with their values.
+    options: Vec<(String, String)>,
+}
+
+impl Arguments {
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn usage_error(message: &str) -> TSLiteError {
+    TSLiteError::InvalidParameter(format!("{}\n\n{}", message, USAGE))
+}
+
+fn parse(mut args: Vec<String>) -> Result<(Option<String>, Arguments), TSLiteError> {
+    let mut value_type = None;
+    if args.first().map(String::as_str) == Some("--type") {
+        if args.len() < 2 {
+            return Err(usage_error("--type needs a value."));
+        }
+        value_type = Some(args.remove(1));
+        args.remove(0);
+    }
+    let mut args = args.into_iter();
+    let command = args.next().ok_or_else(|| usage_error("No command."))?;
+    let path = args.next().ok_or_else(|| usage_error("No file."))?;
+    let mut arguments = Arguments {
+        command,
+        path,
+        rest: Vec::new(),
+        options: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| usage_error(&format!("--{} needs a value.", name)))?;
+                arguments.options.push((name.to_string(), value));
+            }
+            None => arguments.rest.push(arg),
+        }
+    }
+    Ok((value_type, arguments))
+}
+
+/// Read the header of the DB at `path` without knowing the type of its values.
+fn read_header(path: &Path) -> Result<DbHeader, TSLiteError> {
+    let mut bytes = vec![0; RECORDS_START as usize];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .map_err(TSLiteError::Io)?;
+    format::decode_header(&bytes)
+}
+
+fn date(t: &Timestamp) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        t.year, t.month, t.day, t.hour, t.minute, t.second
+    )
+}
+
+fn info<V: RecordValue>(db: &mut PhysicalDB<V>) -> Result<(), TSLiteError> {
+    let header = *db.header();
+    println!("path: {}", db.path.display());
+    println!("origin: {}", date(&header.origin_date));
+    println!("records: {}", header.records_number);
+    println!("offset unit: {:?}", header.offset_unit);
+    println!("value width: {}", header.value_width());
+    if !header.transforms.is_identity() {
+        println!("transforms: {:?}", header.transforms);
+    }
+    if let Some(capacity) = header.ring_capacity() {
+        println!("ring capacity: {}", capacity);
+    }
+    if let Some(last) = db.last_n(1)?.pop() {
+        println!(
+            "last record: {}",
+            date(&header.offset_to_date(last.time_offset))
+        );
+    }
+    for (key, value) in header.labels() {
+        println!("label: {}={}", key, value);
+    }
+    Ok(())
+}
+
+fn numeric_option(args: &Arguments, name: &str, default: u64) -> Result<u64, TSLiteError> {
+    match args.option(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| usage_error(&format!("--{} needs a number.", name))),
+        None => Ok(default),
+    }
+}
+
+fn print_records<V: RecordValue>(header: &DbHeader, records: &[RecordInfo<V>]) {
+    for r in records {
+        println!(
+            "{},{}",
+            date(&header.offset_to_date(r.time_offset)),
+            r.value.as_f64()
+        );
+    }
+}
+
+/// Print the last records, then with `-f` the records appended by other processes, watched with
+/// `PhysicalDB::watch` until the tool is interrupted.
+fn tail<V: RecordValue>(db: &mut PhysicalDB<V>, args: &Arguments) -> Result<(), TSLiteError> {
+    let lines = numeric_option(args, "lines", 10)?;
+    let interval = Duration::from_millis(numeric_option(args, "interval", 500)?);
+    let records = db.last_n(lines as usize)?;
+    print_records(db.header(), &records);
+    if !args.rest.iter().any(|a| a == "-f") {
+        return Ok(());
+    }
+    let mut watch = db.watch(interval);
+    while let Some(record) = watch.next() {
+        print_records(watch.header(), &[record?]);
+    }
+    Ok(())
+}
+
+fn run<V: RecordValue>(args: &Arguments) -> Result<i32, TSLiteError> {
+    let mut db: PhysicalDB<V> = PhysicalDB::open_path(Path::new(&args.path))?;
+    match args.command.as_str() {
+        "info" => info(&mut db)?,
+        "check" => {
+            let issues = db.check_all()?;
+            for issue in &issues {
+                println!("{:?}", issue);
+            }
+            if !issues.is_empty() {
+                return Ok(1);
+            }
+            println!("no issue");
+        }
+        "repair" => {
+            let report = db.repair()?;
+            println!(
+                "records: {} -> {}, truncated octets: {}",
+                report.records_before, report.records_after, report.truncated_octets
+            );
+        }
+        "dump" => {
+            let format = match args.option("format").unwrap_or("csv") {
+                "csv" => ExportFormat::Csv,
+                "ndjson" => ExportFormat::Ndjson,
+                other => return Err(usage_error(&format!("Unknown format '{}'.", other))),
+            };
+            let header = *db.header();
+            let end = match db.last_n(1)?.pop() {
+                Some(last) => header.offset_to_date(last.time_offset).add_seconds(1),
+                None => header.origin_date,
+            };
+            db.export_to(io::stdout(), header.origin_date, end, format)?;
+        }
+        "append" => {
+            let value: f64 = match args.rest.first().map(|v| v.parse()) {
+                Some(Ok(value)) => value,
+                _ => return Err(usage_error("append needs a numeric value.")),
+            };
+            let value = V::from_f64(value);
+            match args.option("at") {
+                Some(at) => {
+                    let seconds = at
+                        .parse()
+                        .map_err(|_| usage_error("--at needs Unix seconds."))?;
+                    let time_offset = db.header().checked_offset(&Timestamp::from_unix(seconds))?;
+                    db.append_record(RecordInfo { time_offset, value })?;
+                }
+                None => db.append_record_now(value)?,
+            }
+        }
+        "tail" => tail(&mut db, args)?,
+        other => return Err(usage_error(&format!("Unknown command '{}'.", other))),
+    }
+    db.close()?;
+    Ok(0)
+}
+
+fn main() {
+    let (value_type, args) = match parse(env::args().skip(1).collect()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    let value_type = match value_type {
+        Some(value_type) => Ok(value_type),
+        None => {
+            read_header(Path::new(&args.path)).map(|h| format!("u{}", h.value_width() as u32 * 8))
+        }
+    };
+    let result = value_type.and_then(|value_type| match value_type.as_str() {
+        "u8" => run::<u8>(&args),
+        "u16" => run::<u16>(&args),
+        "u32" => run::<u32>(&args),
+        "u64" => run::<u64>(&args),
+        "i8" => run::<i8>(&args),
+        "i16" => run::<i16>(&args),
+        "i32" => run::<i32>(&args),
+        "i64" => run::<i64>(&args),
+        "f32" => run::<f32>(&args),
+        "f64" => run::<f64>(&args),
+        other => Err(usage_error(&format!("Unknown type '{}'.", other))),
+    });
+    match result {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("tslite: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Records holding variable-size payloads, such as short status strings.
+//!
+//! A blob DB is made of two files: an index, which is a DB of `u64` values, and the payloads, next to it with
+//! the `payloads` extension. Each payload is stored as its length on one octet followed by its octets, and the
+//! value of its record in the index is its position in the payload file. The records keep the fixed size
+//! the rest of the crate relies on, while the payloads can have any size up to [`MAX_BLOB_SIZE`].
+//!
+//! A payload is written and synced before its record is appended to the index, so a crash between the two
+//! only leaves an unreferenced payload at the end of the payload file.
+
+use crate::{telemetry, DbHeader, PhysicalDB, RecordInfo, TSLiteError, Timestamp};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// The largest payload a record can hold, its length being stored on one octet.
+pub const MAX_BLOB_SIZE: usize = u8::MAX as usize;
+
+/// A record of a [`BlobDB`] resolved against its origin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlobRecord {
+    pub time: Timestamp,
+    pub data: Vec<u8>,
+}
+
+/// A DB whose records hold payloads of up to [`MAX_BLOB_SIZE`] octets.
+#[derive(Debug)]
+pub struct BlobDB {
+    index: PhysicalDB<u64>,
+    payloads: File,
+}
+
+/// The path of the payload file of the blob DB whose index is at `path`.
+fn payloads_path(path: &Path) -> PathBuf {
+    path.with_extension("payloads")
+}
+
+impl BlobDB {
+    /// Create a new blob DB whose index is at `path`, with the same meaning for `origin_date` as
+    /// [`PhysicalDB::create`]. Fail with `AlreadyExists` if there is already an index at `path`.
+    pub fn create(path: &Path, origin_date: Option<Timestamp>) -> Result<BlobDB, TSLiteError> {
+        let index = PhysicalDB::create(path, origin_date)?;
+        let payloads = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(payloads_path(path))
+            .map_err(TSLiteError::Io)?;
+        Ok(BlobDB { index, payloads })
+    }
+
+    /// Open the blob DB whose index is at `path`, or create it if it doesn't exist.
+    pub fn new(path: &Path, origin_date: Option<Timestamp>) -> Result<BlobDB, TSLiteError> {
+        if !path.exists() {
+            return BlobDB::create(path, origin_date);
+        }
+        let index = PhysicalDB::new(path, None)?;
+        let payloads = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(payloads_path(path))
+            .map_err(TSLiteError::Io)?;
+        Ok(BlobDB { index, payloads })
+    }
+
+    /// The header of the index.
+    pub fn header(&self) -> &DbHeader {
+        self.index.header()
+    }
+
+    /// The number of records.
+    pub fn len(&self) -> u64 {
+        self.header().records_number
+    }
+
+    /// Whether there is no record.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append a record holding `data` at `time`.
+    /// Fail with `InvalidParameter` if `data` is longer than [`MAX_BLOB_SIZE`].
+    pub fn append_record(&mut self, time: Timestamp, data: &[u8]) -> Result<(), TSLiteError> {
+        if data.len() > MAX_BLOB_SIZE {
+            return Err(TSLiteError::InvalidParameter(format!(
+                "A payload holds at most {} octets, got {}.",
+                MAX_BLOB_SIZE,
+                data.len()
+            )));
+        }
+        self.index.refresh_if_changed()?;
+        let time_offset = self.index.header().checked_offset(&time)?;
+
+        let mut payload = Vec::with_capacity(1 + data.len());
+        payload.push(data.len() as u8);
+        payload.extend_from_slice(data);
+        let position = self
+            .payloads
+            .seek(SeekFrom::End(0))
+            .and_then(|position| {
+                self.payloads.write_all(&payload)?;
+                telemetry::sync_data(&self.payloads)?;
+                Ok(position)
+            })
+            .map_err(TSLiteError::Io)?;
+
+        self.index.append_record(RecordInfo {
+            time_offset,
+            value: position,
+        })
+    }
+
+    /// Read the record at index `rec_id`.
+    pub fn read_record(&mut self, rec_id: u64) -> Result<BlobRecord, TSLiteError> {
+        let record = self.index.read_record(rec_id)?;
+        Ok(BlobRecord {
+            time: self.index.header().offset_to_date(record.time_offset),
+            data: self.read_payload(record.value)?,
+        })
+    }
+
+    /// Every record whose date is within `[start, end[`.
+    pub fn records(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<Vec<BlobRecord>, TSLiteError> {
+        let index = self.index.records(start, end)?;
+        index
+            .into_iter()
+            .map(|r| {
+                Ok(BlobRecord {
+                    time: r.time,
+                    data: self.read_payload(r.value)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Read the payload stored at `position` in the payload file.
+    fn read_payload(&mut self, position: u64) -> Result<Vec<u8>, TSLiteError> {
+        let mut length = [0; 1];
+        self.payloads
+            .seek(SeekFrom::Start(position))
+            .and_then(|_| self.payloads.read_exact(&mut length))
+            .map_err(|_| TSLiteError::ShortRead("payload".to_string()))?;
+        let mut data = vec![0; length[0] as usize];
+        self.payloads
+            .read_exact(&mut data)
+            .map_err(|_| TSLiteError::ShortRead("payload".to_string()))?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LATEST;
+    use std::fs;
+
+    #[test]
+    fn blob_records() {
+        let path = Path::new("blob_records.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(payloads_path(path));
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db = BlobDB::create(path, Some(origin)).expect("could not create db.");
+        assert!(db.is_empty());
+        db.append_record(origin.add_seconds(10), b"booting")
+            .unwrap();
+        db.append_record(origin.add_seconds(20), b"").unwrap();
+        db.append_record(origin.add_seconds(30), &[7; MAX_BLOB_SIZE])
+            .unwrap();
+        assert!(matches!(
+            db.append_record(origin.add_seconds(40), &[0; MAX_BLOB_SIZE + 1]),
+            Err(TSLiteError::InvalidParameter(_))
+        ));
+        assert_eq!(
+            db.append_record(origin.add_seconds(-1), b"early"),
+            Err(TSLiteError::BeforeOrigin)
+        );
+        drop(db);
+
+        let mut db = BlobDB::new(path, None).expect("could not open db.");
+        assert_eq!(db.len(), 3);
+        assert_eq!(
+            db.read_record(0).unwrap(),
+            BlobRecord {
+                time: origin.add_seconds(10),
+                data: b"booting".to_vec(),
+            }
+        );
+        let records = db.records(origin.add_seconds(15), LATEST).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].data.is_empty());
+        assert_eq!(records[1].data, vec![7; MAX_BLOB_SIZE]);
+        assert_eq!(
+            fs::metadata(payloads_path(path)).unwrap().len(),
+            (1 + 7) + 1 + (1 + MAX_BLOB_SIZE as u64)
+        );
+        assert!(db.read_record(3).is_err());
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(payloads_path(path));
+    }
+}
@@ -0,0 +1,122 @@
+//! Checksums of the records, enabled with the `record_checksums` option of [`DbOptions`](crate::DbOptions).
+//!
+//! Each record is followed by the CRC32 of its octets, so a record damaged on the disk or only partly written
+//! is reported with `TSLiteError::RecordCorrupted` when it is read, instead of being returned with garbage in it.
+//! The option is recorded in the header by an empty extension entry. The files using it can't be read by the
+//! versions before it, which don't know that the records are longer.
+
+use crate::{DbHeader, RecordInfo, RecordValue, TSLiteError};
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+
+/// The extension tag recording that the records are followed by a checksum.
+pub(crate) const TAG: u8 = 0xF2;
+/// Size of the checksum following a record.
+pub const CHECKSUM_SIZE: u64 = 4;
+
+impl DbHeader {
+    /// Whether every record is followed by its checksum.
+    pub fn record_checksums(&self) -> bool {
+        self.extensions.get(TAG).is_some()
+    }
+
+    /// Size of a record as it is stored in the file, its checksum included.
+    pub fn record_size<V: RecordValue>(&self) -> u64 {
+        if self.record_checksums() {
+            RecordInfo::<V>::SIZE + CHECKSUM_SIZE
+        } else {
+            RecordInfo::<V>::SIZE
+        }
+    }
+
+    /// Serialize a record as it is stored in the file, followed by its checksum if the DB has them.
+    pub fn encode_record<V: RecordValue>(&self, record: &RecordInfo<V>) -> Vec<u8> {
+        let mut bytes = record.as_bytes();
+        if self.record_checksums() {
+            let mut crc = [0; CHECKSUM_SIZE as usize];
+            LittleEndian::write_u32(&mut crc, crc32fast::hash(&bytes));
+            bytes.extend_from_slice(&crc);
+        }
+        bytes
+    }
+
+    /// Deserialize the record at index `index` from the [`DbHeader::record_size`] octets it is stored on.
+    /// Fail with `RecordCorrupted` if it doesn't match its checksum.
+    /// The value is in its stored form: if the DB has transforms, they are not inverted.
+    pub fn decode_record<V: RecordValue>(
+        &self,
+        index: u64,
+        bytes: &[u8],
+    ) -> Result<RecordInfo<V>, TSLiteError> {
+        let size = RecordInfo::<V>::SIZE as usize;
+        if (bytes.len() as u64) < self.record_size::<V>() {
+            return Err(TSLiteError::ShortRead("record".to_string()));
+        }
+        if self.record_checksums() {
+            let crc = LittleEndian::read_u32(&bytes[size..size + CHECKSUM_SIZE as usize]);
+            if crc32fast::hash(&bytes[..size]) != crc {
+                return Err(TSLiteError::RecordCorrupted(index));
+            }
+        }
+        RecordInfo::try_from(&bytes[..size])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::RECORDS_START;
+    use crate::{DbIssue, DbOptions, PhysicalDB, Timestamp};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn detect_corrupted_record() {
+        let path = "checksum_records.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            record_checksums: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        for i in 0..4u16 {
+            db.append_record(RecordInfo {
+                time_offset: i as u32,
+                value: i * 10,
+            })
+            .expect("could not append record.");
+        }
+        db.update_record(3, 300).expect("could not update record.");
+        db.close().expect("could not close db.");
+        let size = RecordInfo::<u16>::SIZE + CHECKSUM_SIZE;
+        assert_eq!(fs::metadata(path).unwrap().len(), RECORDS_START + 4 * size);
+
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert!(db.header().record_checksums());
+        assert_eq!(db.read_record(3).unwrap().value, 300);
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+        db.close().expect("could not close db.");
+
+        // Flip a bit of the value of the third record.
+        let mut bytes = fs::read(path).unwrap();
+        bytes[(RECORDS_START + 2 * size + 4) as usize] ^= 1;
+        fs::write(path, bytes).unwrap();
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.read_record(1).unwrap().value, 10);
+        assert_eq!(db.read_record(2), Err(TSLiteError::RecordCorrupted(2)));
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::RecordCorrupted(2));
+        assert_eq!(
+            db.iter()
+                .collect::<Result<Vec<RecordInfo<u16>>, TSLiteError>>(),
+            Err(TSLiteError::RecordCorrupted(2))
+        );
+
+        let _ = fs::remove_file(path);
+    }
+}
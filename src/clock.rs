@@ -0,0 +1,100 @@
+//! The source of the current time, so that time-dependent behavior can be tested, and devices without a
+//! system clock can read their real-time clock instead.
+
+use crate::Timestamp;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Tell the current time, see [`DbOptions::clock`](crate::DbOptions::clock).
+pub trait Clock: Debug + Send + Sync {
+    /// The current date and time, in UTC.
+    fn now(&self) -> Timestamp;
+}
+
+/// The clock of the system.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::now()
+    }
+}
+
+/// A clock moving only when told to, to the nanosecond. Its clones share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Timestamp>>,
+}
+
+impl MockClock {
+    pub fn new(now: Timestamp) -> MockClock {
+        MockClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Move the clock `duration` forward.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = Timestamp::from_unix_nanos(now.unix_nanos() + duration.as_nanos() as i128);
+    }
+
+    /// Move the clock to `now`, which can be in the past.
+    pub fn set(&self, now: Timestamp) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Timestamp {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, PhysicalDB, TSLiteError};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn db_with_mock_clock() {
+        let path = "clock_mock.db";
+        let _ = fs::remove_file(path);
+
+        let start = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let options = DbOptions {
+            clock: Some(Arc::new(clock.clone())),
+            max_future_skew: Some(Duration::from_secs(60)),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB = PhysicalDB::create_with_options(Path::new(path), None, &options)
+            .expect("could not create db.");
+        assert_eq!(db.header().origin_date, start);
+        db.append_record_now(1).unwrap();
+        clock.advance(Duration::from_secs(90));
+        db.append_record_now(2).unwrap();
+        assert_eq!(db.read_record(1).unwrap().time_offset, 90);
+        assert_eq!(
+            crate::TimeSeries::append(&mut db, start.add_seconds(200), 3),
+            Err(TSLiteError::TooFarInFuture)
+        );
+        clock.set(start.add_seconds(180));
+        crate::TimeSeries::append(&mut db, start.add_seconds(200), 3).unwrap();
+        clock.advance(Duration::from_millis(1_500));
+        assert_eq!(
+            db.metrics().last_append_age,
+            Some(Duration::from_millis(1_500))
+        );
+        let last = crate::TimeSeries::last(&mut db, Duration::from_secs(100)).unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].time, start.add_seconds(90));
+        assert!(SystemClock.now() > start);
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,597 @@
+//! Compact encodings of blocks of records, for archives and transfers.
+//!
+//! A block starts with the codec used (1 octet) and the number of records (u32), followed by the records
+//! encoded with that codec. [`encode_block`] picks the codec by encoding a sample of the block with each
+//! of them, so the best one is used without any tuning.
+//!
+//! Time offset deltas are zigzag encoded, so blocks of unordered records are supported.
+//!
+//! [`block_records`] decodes a block one record at a time, so a large block can be iterated without
+//! holding all of its records in memory.
+//!
+//! The DB files keep their fixed-size records, which the reads by index rely on: the codecs apply to the
+//! blocks of records that are archived or transferred, such as the segments of a
+//! [`TieredSeries`](crate::TieredSeries).
+
+use crate::{RecordInfo, RecordValue, TSLiteError};
+use std::convert::TryFrom;
+
+/// Number of records of a block encoded with every codec to pick the best one.
+const SAMPLE_SIZE: usize = 64;
+/// Size of the block header: the codec and the number of records.
+const BLOCK_HEADER_SIZE: usize = 1 + 4;
+
+/// The encodings available for a block of records.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Codec {
+    /// The records as they are stored in a DB file.
+    Raw,
+    /// The time offsets as variable-length deltas and the values as they are stored.
+    /// Suits regularly sampled series.
+    Delta,
+    /// Runs of records with the same time offset delta and value, stored once with the length of the run.
+    /// Suits regularly sampled series whose value rarely changes.
+    Rle,
+    /// The time offsets as variable-length differences between consecutive deltas, which are 0 for a fixed
+    /// interval, and the values as they are stored. Suits regularly sampled series with long intervals,
+    /// whose deltas don't fit in one octet.
+    DeltaOfDelta,
+    /// A bit stream inspired by the Gorilla paper: the differences between consecutive deltas on a few bits,
+    /// and the values XORed with the previous one, keeping only the bits that changed.
+    /// Suits series whose value changes slowly, a repeated value and delta taking two bits.
+    Xor,
+}
+
+impl Codec {
+    /// The codecs from the simplest to the most complex.
+    const ALL: [Codec; 5] = [
+        Codec::Raw,
+        Codec::Delta,
+        Codec::DeltaOfDelta,
+        Codec::Rle,
+        Codec::Xor,
+    ];
+
+    fn id(self) -> u8 {
+        match self {
+            Codec::Raw => 0,
+            Codec::Delta => 1,
+            Codec::Rle => 2,
+            Codec::DeltaOfDelta => 3,
+            Codec::Xor => 4,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Codec> {
+        Codec::ALL.iter().copied().find(|c| c.id() == id)
+    }
+}
+
+/// Encode `records` with the codec that gives the smallest output on a sample of them.
+/// On a tie, the simplest codec is picked.
+pub fn encode_block<V: RecordValue>(records: &[RecordInfo<V>]) -> Vec<u8> {
+    let sample = &records[..records.len().min(SAMPLE_SIZE)];
+    let mut best = Codec::Raw;
+    let mut best_size = usize::MAX;
+    for codec in Codec::ALL.iter() {
+        let size = encode_block_with(*codec, sample).len();
+        if size < best_size {
+            best = *codec;
+            best_size = size;
+        }
+    }
+
+    encode_block_with(best, records)
+}
+
+/// Encode `records` with `codec`.
+pub fn encode_block_with<V: RecordValue>(codec: Codec, records: &[RecordInfo<V>]) -> Vec<u8> {
+    let mut out =
+        Vec::with_capacity(BLOCK_HEADER_SIZE + records.len() * RecordInfo::<V>::SIZE as usize);
+    out.push(codec.id());
+    out.extend(&(records.len() as u32).to_le_bytes());
+
+    let mut value = vec![0; V::WIDTH];
+    match codec {
+        Codec::Raw => {
+            for r in records {
+                out.extend(r.as_bytes());
+            }
+        }
+        Codec::Delta => {
+            let mut previous = 0;
+            for r in records {
+                write_varint(&mut out, zigzag(r.time_offset as i64 - previous));
+                r.value.encode(&mut value);
+                out.extend(&value);
+                previous = r.time_offset as i64;
+            }
+        }
+        Codec::DeltaOfDelta => {
+            let (mut previous, mut previous_delta) = (0, 0);
+            for r in records {
+                let delta = r.time_offset as i64 - previous;
+                write_varint(&mut out, zigzag(delta - previous_delta));
+                r.value.encode(&mut value);
+                out.extend(&value);
+                previous = r.time_offset as i64;
+                previous_delta = delta;
+            }
+        }
+        Codec::Rle => {
+            let mut previous = 0;
+            let mut run: Option<(i64, Vec<u8>, u64)> = None;
+            for r in records {
+                let delta = r.time_offset as i64 - previous;
+                previous = r.time_offset as i64;
+                r.value.encode(&mut value);
+                match run.as_mut() {
+                    Some((d, v, length)) if *d == delta && *v == value => *length += 1,
+                    _ => {
+                        if let Some(run) = run.take() {
+                            write_run(&mut out, run);
+                        }
+                        run = Some((delta, value.clone(), 1));
+                    }
+                }
+            }
+            if let Some(run) = run {
+                write_run(&mut out, run);
+            }
+        }
+        Codec::Xor => {
+            let mut bits = BitWriter::default();
+            let (mut previous, mut previous_delta) = (0, 0);
+            let mut previous_value = vec![0; V::WIDTH];
+            let mut window = None;
+            for r in records {
+                let delta = r.time_offset as i64 - previous;
+                write_delta_of_delta(&mut bits, delta - previous_delta);
+                previous = r.time_offset as i64;
+                previous_delta = delta;
+
+                r.value.encode(&mut value);
+                let xor: Vec<u8> = value
+                    .iter()
+                    .zip(&previous_value)
+                    .map(|(a, b)| a ^ b)
+                    .collect();
+                write_xor(&mut bits, &xor, &mut window);
+                previous_value.copy_from_slice(&value);
+            }
+            out.extend(bits.bytes);
+        }
+    }
+
+    out
+}
+
+/// Decode a block produced by [`encode_block`] or [`encode_block_with`].
+pub fn decode_block<V: RecordValue>(block: &[u8]) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+    block_records(block)?.collect()
+}
+
+/// Iterate over the records of a block produced by [`encode_block`] or [`encode_block_with`], decoding
+/// them as they are read. Only the header of the block is checked here, the iterator stops after the first
+/// error in the records.
+pub fn block_records<V: RecordValue>(block: &[u8]) -> Result<BlockRecords<'_, V>, TSLiteError> {
+    if block.len() < BLOCK_HEADER_SIZE {
+        return Err(TSLiteError::ShortRead("block".to_string()));
+    }
+    let codec = Codec::from_id(block[0])
+        .ok_or_else(|| TSLiteError::InvalidData(format!("unknown block codec {}", block[0])))?;
+    let count = u32::from_le_bytes([block[1], block[2], block[3], block[4]]) as usize;
+
+    let data = &block[BLOCK_HEADER_SIZE..];
+    Ok(BlockRecords {
+        codec,
+        remaining: count,
+        data,
+        bits: BitReader { data, position: 0 },
+        previous: 0,
+        previous_delta: 0,
+        previous_value: vec![0; V::WIDTH],
+        window: None,
+        run: None,
+        failed: false,
+    })
+}
+
+/// An iterator over the records of a block, see [`block_records`].
+#[derive(Debug)]
+pub struct BlockRecords<'a, V: RecordValue> {
+    codec: Codec,
+    remaining: usize,
+    /// The records not decoded yet, for the codecs working on octets.
+    data: &'a [u8],
+    /// The records not decoded yet, for [`Codec::Xor`].
+    bits: BitReader<'a>,
+    previous: i64,
+    previous_delta: i64,
+    /// The encoded value and meaningful bits of the last record, for [`Codec::Xor`].
+    previous_value: Vec<u8>,
+    window: Option<(usize, usize)>,
+    /// The delta, value and remaining length of the current run, for [`Codec::Rle`].
+    run: Option<(i64, V, u64)>,
+    failed: bool,
+}
+
+impl<V: RecordValue> BlockRecords<'_, V> {
+    fn decode_next(&mut self) -> Result<RecordInfo<V>, TSLiteError> {
+        let truncated = || TSLiteError::ShortRead("block".to_string());
+        let (delta, value) = match self.codec {
+            Codec::Raw => {
+                let size = RecordInfo::<V>::SIZE as usize;
+                if self.data.len() < size {
+                    return Err(truncated());
+                }
+                let record = RecordInfo::try_from(&self.data[..size])?;
+                self.data = &self.data[size..];
+                return Ok(record);
+            }
+            Codec::Delta => {
+                let delta = unzigzag(read_varint(&mut self.data).ok_or_else(truncated)?);
+                (delta, self.read_value()?)
+            }
+            Codec::DeltaOfDelta => {
+                self.previous_delta += unzigzag(read_varint(&mut self.data).ok_or_else(truncated)?);
+                (self.previous_delta, self.read_value()?)
+            }
+            Codec::Rle => {
+                if self.run.is_none() {
+                    let delta = unzigzag(read_varint(&mut self.data).ok_or_else(truncated)?);
+                    let length = read_varint(&mut self.data).ok_or_else(truncated)?;
+                    if length == 0 {
+                        return Err(truncated());
+                    }
+                    self.run = Some((delta, self.read_value()?, length));
+                }
+                let (delta, value, length) = self.run.as_mut().unwrap();
+                let (delta, value) = (*delta, *value);
+                *length -= 1;
+                if *length == 0 {
+                    self.run = None;
+                }
+                (delta, value)
+            }
+            Codec::Xor => {
+                self.previous_delta += read_delta_of_delta(&mut self.bits).ok_or_else(truncated)?;
+                read_xor(&mut self.bits, &mut self.previous_value, &mut self.window)
+                    .ok_or_else(truncated)?;
+                (self.previous_delta, V::decode(&self.previous_value))
+            }
+        };
+        self.previous += delta;
+        Ok(RecordInfo {
+            time_offset: self.previous as u32,
+            value,
+        })
+    }
+
+    fn read_value(&mut self) -> Result<V, TSLiteError> {
+        if self.data.len() < V::WIDTH {
+            return Err(TSLiteError::ShortRead("block".to_string()));
+        }
+        let value = V::decode(&self.data[..V::WIDTH]);
+        self.data = &self.data[V::WIDTH..];
+        Ok(value)
+    }
+}
+
+impl<V: RecordValue> Iterator for BlockRecords<'_, V> {
+    type Item = Result<RecordInfo<V>, TSLiteError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let record = self.decode_next();
+        self.failed = record.is_err();
+        Some(record)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            (0, Some(self.remaining))
+        }
+    }
+}
+
+fn write_run(out: &mut Vec<u8>, (delta, value, length): (i64, Vec<u8>, u64)) {
+    write_varint(out, zigzag(delta));
+    write_varint(out, length);
+    out.extend(value);
+}
+
+/// The prefixes and sizes of the zigzagged differences between deltas in [`Codec::Xor`], after a single
+/// `0` bit for a difference of 0.
+const DELTA_OF_DELTA_BUCKETS: [(u64, u32, u32); 4] = [
+    (0b10, 2, 7),
+    (0b110, 3, 9),
+    (0b1110, 4, 12),
+    (0b1111, 4, 64),
+];
+
+fn write_delta_of_delta(bits: &mut BitWriter, delta_of_delta: i64) {
+    let n = zigzag(delta_of_delta);
+    if n == 0 {
+        bits.push(false);
+        return;
+    }
+    for (prefix, prefix_size, size) in DELTA_OF_DELTA_BUCKETS.iter() {
+        if *size == 64 || n < 1 << size {
+            bits.push_bits(*prefix, *prefix_size);
+            bits.push_bits(n, *size);
+            return;
+        }
+    }
+}
+
+fn read_delta_of_delta(bits: &mut BitReader) -> Option<i64> {
+    let mut ones = 0;
+    while ones < 4 && bits.read()? {
+        ones += 1;
+    }
+    if ones == 0 {
+        return Some(0);
+    }
+    let (_, _, size) = DELTA_OF_DELTA_BUCKETS[ones - 1];
+    Some(unzigzag(bits.read_bits(size)?))
+}
+
+/// Write a value XORed with the previous one: `0` if they are equal, `10` and the bits of the previous window
+/// if the bits that changed fit in it, `11`, the number of leading zeros, the number of bits and the bits
+/// otherwise. The window is the number of leading and trailing zero bits of the last value written with `11`.
+fn write_xor(bits: &mut BitWriter, xor: &[u8], window: &mut Option<(usize, usize)>) {
+    let (leading, trailing) = (leading_zeros(xor), trailing_zeros(xor));
+    if leading == xor.len() * 8 {
+        bits.push(false);
+        return;
+    }
+    bits.push(true);
+    match *window {
+        Some((l, t)) if leading >= l && trailing >= t => {
+            bits.push(false);
+            write_value_bits(bits, xor, l, t);
+        }
+        _ => {
+            let size = window_field_size(xor.len());
+            bits.push(true);
+            bits.push_bits(leading as u64, size);
+            bits.push_bits((xor.len() * 8 - leading - trailing - 1) as u64, size);
+            write_value_bits(bits, xor, leading, trailing);
+            *window = Some((leading, trailing));
+        }
+    }
+}
+
+/// Read a value written by [`write_xor`] and XOR it into `value`, the previous value.
+fn read_xor(
+    bits: &mut BitReader,
+    value: &mut [u8],
+    window: &mut Option<(usize, usize)>,
+) -> Option<()> {
+    if !bits.read()? {
+        return Some(());
+    }
+    let (leading, trailing) = if bits.read()? {
+        let size = window_field_size(value.len());
+        let leading = bits.read_bits(size)? as usize;
+        let length = bits.read_bits(size)? as usize + 1;
+        let trailing = (value.len() * 8).checked_sub(leading + length)?;
+        *window = Some((leading, trailing));
+        (leading, trailing)
+    } else {
+        (*window)?
+    };
+    for i in (trailing..value.len() * 8 - leading).rev() {
+        if bits.read()? {
+            value[i / 8] ^= 1 << (i % 8);
+        }
+    }
+    Some(())
+}
+
+/// Write the bits of `xor`, a little-endian number, from the most significant one and without its `leading`
+/// and `trailing` bits.
+fn write_value_bits(bits: &mut BitWriter, xor: &[u8], leading: usize, trailing: usize) {
+    for i in (trailing..xor.len() * 8 - leading).rev() {
+        bits.push(xor[i / 8] >> (i % 8) & 1 == 1);
+    }
+}
+
+/// The number of bits holding a number of leading zeros or of meaningful bits of a value of `width` octets.
+fn window_field_size(width: usize) -> u32 {
+    usize::BITS - (width * 8).saturating_sub(1).leading_zeros()
+}
+
+fn leading_zeros(n: &[u8]) -> usize {
+    match n.iter().rposition(|b| *b != 0) {
+        Some(i) => (n.len() - 1 - i) * 8 + n[i].leading_zeros() as usize,
+        None => n.len() * 8,
+    }
+}
+
+fn trailing_zeros(n: &[u8]) -> usize {
+    match n.iter().position(|b| *b != 0) {
+        Some(i) => i * 8 + n[i].trailing_zeros() as usize,
+        None => n.len() * 8,
+    }
+}
+
+/// Bits written from the most significant bit of each octet, the last octet being padded with zeros.
+#[derive(Debug, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// The number of bits written in the last octet, 0 if it is full.
+    used: u32,
+}
+
+impl BitWriter {
+    fn push(&mut self, bit: bool) {
+        if self.used == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> self.used;
+        }
+        self.used = (self.used + 1) % 8;
+    }
+
+    /// Push the `count` lowest bits of `n`, the most significant one first.
+    fn push_bits(&mut self, n: u64, count: u32) {
+        for i in (0..count).rev() {
+            self.push(n >> i & 1 == 1);
+        }
+    }
+}
+
+/// Bits read in the order [`BitWriter`] writes them.
+#[derive(Debug)]
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self) -> Option<bool> {
+        let byte = self.data.get(self.position / 8)?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, count: u32) -> Option<u64> {
+        let mut n = 0;
+        for _ in 0..count {
+            n = n << 1 | self.read()? as u64;
+        }
+        Some(n)
+    }
+}
+
+/// Map signed integers to unsigned ones so that small magnitudes get small varints.
+pub(crate) fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+pub(crate) fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+/// LEB128: 7 bits per octet, the high bit is set on every octet but the last.
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Read a varint from the start of `data` and advance it, `None` if it is truncated or too long.
+pub(crate) fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut n = 0u64;
+    for (i, b) in data.iter().enumerate().take(10) {
+        n |= ((b & 0x7F) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            *data = &data[i + 1..];
+            return Some(n);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(offsets: &[u32], values: &[u8]) -> Vec<RecordInfo> {
+        offsets
+            .iter()
+            .zip(values)
+            .map(|(o, v)| RecordInfo {
+                time_offset: *o,
+                value: *v,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pick_codec_per_block() {
+        let constant = records(&(0..100).map(|i| i * 10).collect::<Vec<_>>(), &[7; 100]);
+        // The offsets are jittered and the values change a lot, otherwise the Xor codec is the smallest.
+        let regular = records(
+            &(0..100).map(|i| i * 10 + i % 3).collect::<Vec<_>>(),
+            &(0..100).map(|i| (i * 37 % 251) as u8).collect::<Vec<_>>(),
+        );
+        let minutes = records(
+            &(0..100).map(|i| i * 60_000 + i % 3).collect::<Vec<_>>(),
+            &(0..100).map(|i| (i * 37 % 251) as u8).collect::<Vec<_>>(),
+        );
+        let scattered = records(&[0, u32::MAX, 0, u32::MAX], &[1, 2, 3, 4]);
+        let unordered = records(&[50, 10, 10, 20, 20, 5], &[1, 1, 1, 1, 1, 2]);
+
+        for (block, codec) in [
+            (&constant, Codec::Rle),
+            (&regular, Codec::Delta),
+            (&minutes, Codec::DeltaOfDelta),
+            (&scattered, Codec::Raw),
+        ]
+        .iter()
+        {
+            let encoded = encode_block(block);
+            assert_eq!(encoded[0], codec.id());
+            assert_eq!(decode_block::<u8>(&encoded).unwrap(), **block);
+        }
+        assert!(encode_block(&constant).len() < 16);
+        // Three octets for the first delta, then one for each offset and one for each value.
+        assert_eq!(
+            encode_block(&minutes).len(),
+            BLOCK_HEADER_SIZE + 3 + 99 + 100
+        );
+
+        for codec in Codec::ALL.iter() {
+            let encoded = encode_block_with(*codec, &unordered);
+            assert_eq!(decode_block::<u8>(&encoded).unwrap(), unordered);
+            assert!(decode_block::<u8>(&encoded[..encoded.len() - 1]).is_err());
+        }
+        assert_eq!(
+            decode_block::<u8>(&encode_block::<u8>(&[])).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn xor_slowly_changing_values() {
+        // A temperature sampled every second, changing every minute.
+        let block: Vec<RecordInfo<f64>> = (0..3600)
+            .map(|i| RecordInfo {
+                time_offset: i,
+                value: 20.0 + (i / 60) as f64 * 0.25,
+            })
+            .collect();
+        let encoded = encode_block(&block);
+        assert_eq!(encoded[0], Codec::Xor.id());
+        assert!(encoded.len() < block.len() * RecordInfo::<f64>::SIZE as usize / 40);
+
+        let mut records = block_records::<f64>(&encoded).unwrap();
+        assert_eq!(records.next().unwrap().unwrap(), block[0]);
+        assert_eq!(records.size_hint(), (0, Some(3599)));
+        assert_eq!(records.collect::<Result<Vec<_>, _>>().unwrap(), block[1..]);
+        let mut records = block_records::<f64>(&encoded[..encoded.len() / 2]).unwrap();
+        assert!(records.by_ref().any(|r| r.is_err()));
+        assert!(records.next().is_none());
+
+        let narrow: Vec<RecordInfo<i16>> = (0..50)
+            .map(|i| RecordInfo {
+                time_offset: i * 5 + i % 2,
+                value: (i as i16 - 25) * 331,
+            })
+            .collect();
+        let encoded = encode_block_with(Codec::Xor, &narrow);
+        assert_eq!(decode_block::<i16>(&encoded).unwrap(), narrow);
+    }
+}
@@ -0,0 +1,195 @@
+//! Rewriting a DB file with only its header and its records.
+
+use crate::{digest, iter, telemetry, PhysicalDB, RecordValue, TSLiteError};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Rewrite the file with only its header and its records, and return the number of octets reclaimed.
+    ///
+    /// The octets left past the records, such as a partly written record or records a crash left uncounted,
+    /// are dropped, and the records of a ring are stored from the first slot. The new file is written next to
+    /// the DB and renamed over it once synced, so a complete DB is at the path whenever the process stops.
+    /// The other handles on the DB get a `StaleHandle` error until they are reopened, and the lock held on the
+    /// file, if any, is released.
+    /// Pending records of a buffered DB are not affected, they are written after the compacted ones.
+    pub fn compact(&mut self) -> Result<u64, TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        self.refresh_if_changed()?;
+        self.check_stale()?;
+        let before = self
+            .file
+            .as_ref()
+            .unwrap()
+            .metadata()
+            .map_err(TSLiteError::Io)?
+            .len();
+
+        let after = self.rewrite_from(0)?;
+
+        Ok(before.saturating_sub(after))
+    }
+
+    /// Replace the file with one holding the header and the records from index `first`, and return its length.
+    /// The new file is written next to the DB and renamed over it once synced, so the DB at the path is either
+    /// the previous one or the new one whenever the process stops, and the directory is synced after the rename.
+    /// The handle is reopened on the new file.
+    pub(crate) fn rewrite_from(&mut self, first: u64) -> Result<u64, TSLiteError> {
+        let path = compaction_path(&self.path);
+        let result = self.write_compacted_from(&path, first);
+        if result.is_err() {
+            let _ = fs::remove_file(&path);
+        }
+        let after = result?;
+        fs::rename(&path, &self.path)
+            .and_then(|_| telemetry::sync_parent(&self.path))
+            .map_err(TSLiteError::Io)?;
+        self.reopen()?;
+
+        Ok(after)
+    }
+
+    /// Write the header and the records in their order to a new file at `path`, followed by the digest footer
+    /// if the DB has the `file_digest` option, and return its length.
+    pub(crate) fn write_compacted(&mut self, path: &Path) -> Result<u64, TSLiteError> {
+        self.write_compacted_from(path, 0)
+    }
+
+    /// Same as [`PhysicalDB::write_compacted`] with only the records from index `first`, which are re-encoded
+    /// for their new index if there are records before them.
+    fn write_compacted_from(&mut self, path: &Path, first: u64) -> Result<u64, TSLiteError> {
+        let source = self.header;
+        let mut header = source;
+        header.records_number -= first;
+        if let Some(capacity) = header.ring_capacity() {
+            header.set_ring(capacity, 0)?;
+        }
+        let mut file = File::create(path).map_err(TSLiteError::Io)?;
+        let mut bytes = header.as_checked_bytes();
+        bytes.extend(header.as_checked_bytes());
+        file.write_all(&bytes).map_err(TSLiteError::Io)?;
+        let mut hasher = header
+            .file_digest()
+            .then(|| Sha256::new_with_prefix(&bytes));
+
+        // Values are re-encoded in their quantized form, so that the delta transform is re-applied to the
+        // records at their new index.
+        let transforms = source.transforms;
+        let mut source_chain = self.chain_before(first)?;
+        let mut destination_chain = None;
+        let size = header.record_size::<V>() as usize;
+        let mut buffer = vec![0; iter::CHUNK_RECORDS * size];
+        let mut copied = first;
+        while copied < source.records_number {
+            let n = self.read_records_into(copied, &mut buffer)?;
+            if first > 0 {
+                for (i, bytes) in buffer[..n * size].chunks_mut(size).enumerate() {
+                    let index = copied + i as u64;
+                    let mut record = source.decode_record::<V>(index, bytes)?;
+                    let quantized = transforms.undelta(index, record.value, &mut source_chain);
+                    record.value =
+                        transforms.delta(index - first, quantized, &mut destination_chain);
+                    bytes.copy_from_slice(&header.encode_record(&record));
+                }
+            }
+            file.write_all(&buffer[..n * size])
+                .map_err(TSLiteError::Io)?;
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&buffer[..n * size]);
+            }
+            copied += n as u64;
+        }
+        if let Some(hasher) = hasher {
+            file.write_all(&digest::footer(hasher))
+                .map_err(TSLiteError::Io)?;
+        }
+        telemetry::sync_all(&file).map_err(TSLiteError::Io)?;
+
+        file.metadata().map(|m| m.len()).map_err(TSLiteError::Io)
+    }
+}
+
+/// The path the file is rewritten to before being renamed over the DB at `path`. The suffix is appended to the
+/// whole file name, so it is never the path of the DB itself, whatever its extension.
+fn compaction_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".compact.tmp");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DbIssue, DbOptions, PhysicalDB, RecordInfo, TSLiteError, RECORDS_START};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
+
+    #[test]
+    fn compact_drops_dead_octets() {
+        let path = "compact.db";
+        let _ = fs::remove_file(path);
+
+        let options = DbOptions {
+            ring_capacity: Some(4),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB = PhysicalDB::create_with_options(Path::new(path), None, &options)
+            .expect("could not create db.");
+        for i in 0..6 {
+            db.append_record(RecordInfo {
+                time_offset: i,
+                value: i as u8,
+            })
+            .expect("could not append record.");
+        }
+        let mut other: PhysicalDB = PhysicalDB::open_path(Path::new(path)).unwrap();
+        assert_eq!(other.read_record(0).unwrap().value, 2);
+
+        // A partly written record at the end of the file.
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(&[0xAB; 3])
+            .unwrap();
+        assert_eq!(db.compact().unwrap(), 3);
+        assert_eq!(
+            fs::metadata(path).unwrap().len(),
+            RECORDS_START + 4 * RecordInfo::<u8>::SIZE
+        );
+        assert!(!Path::new("compact.db.compact.tmp").exists());
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+        let values: Vec<u8> = db.iter().map(|r| r.unwrap().value).collect();
+        assert_eq!(values, vec![2, 3, 4, 5]);
+        assert_eq!(db.compact().unwrap(), 0);
+
+        assert_eq!(other.check_stale(), Err(TSLiteError::StaleHandle));
+        other.reopen().unwrap();
+        assert_eq!(other.read_record(3).unwrap().value, 5);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn compact_db_named_like_its_temporary_file() {
+        let path = "named.compact";
+        let _ = fs::remove_file(path);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        db.append_record(RecordInfo {
+            time_offset: 0,
+            value: 7,
+        })
+        .expect("could not append record.");
+        assert_eq!(db.compact().unwrap(), 0);
+        assert_eq!(db.read_record(0).unwrap().value, 7);
+        assert!(!Path::new("named.compact.compact.tmp").exists());
+
+        let _ = fs::remove_file(path);
+    }
+}
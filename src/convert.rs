@@ -0,0 +1,263 @@
+//! Copies of a DB of one-octet values into a DB of wider values, and of the files of the first releases into
+//! the current format.
+
+use crate::format::{self, LEGACY_HEADER_SIZE, LEGACY_RECORD_SIZE};
+use crate::{DbOptions, PhysicalDB, RecordInfo, RecordValue, TSLiteError, LATEST};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// Number of records converted at once.
+const CONVERT_BATCH: usize = 4096;
+
+/// The value types a DB can be converted to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ValueEncoding {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+/// Copy the DB of one-octet values at `src` into a new DB at `dst` storing its values with `encoding`,
+/// and return the number of records copied.
+///
+/// The values are read with the transforms of the source applied, multiplied by `scale` if given,
+/// then rounded to the nearest integer and saturated for integer encodings.
+/// The new DB has the origin and offset unit of the source, without transforms.
+/// Fail with `AlreadyExists` if there is already a file at `dst`.
+pub fn convert(
+    src: &Path,
+    dst: &Path,
+    encoding: ValueEncoding,
+    scale: Option<f64>,
+) -> Result<u64, TSLiteError> {
+    if let Some(scale) = scale {
+        if !scale.is_finite() {
+            return Err(TSLiteError::InvalidParameter(
+                "The conversion scale must be finite.".to_string(),
+            ));
+        }
+    }
+    if !src.exists() {
+        return Err(TSLiteError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Could not convert {}: no such file", src.display()),
+        )));
+    }
+    let mut source: PhysicalDB<u8> = PhysicalDB::new(src, None)?;
+    let scale = scale.unwrap_or(1.0);
+    match encoding {
+        ValueEncoding::U8 => copy_as::<u8>(&mut source, dst, scale),
+        ValueEncoding::U16 => copy_as::<u16>(&mut source, dst, scale),
+        ValueEncoding::U32 => copy_as::<u32>(&mut source, dst, scale),
+        ValueEncoding::U64 => copy_as::<u64>(&mut source, dst, scale),
+        ValueEncoding::I8 => copy_as::<i8>(&mut source, dst, scale),
+        ValueEncoding::I16 => copy_as::<i16>(&mut source, dst, scale),
+        ValueEncoding::I32 => copy_as::<i32>(&mut source, dst, scale),
+        ValueEncoding::I64 => copy_as::<i64>(&mut source, dst, scale),
+        ValueEncoding::F32 => copy_as::<f32>(&mut source, dst, scale),
+        ValueEncoding::F64 => copy_as::<f64>(&mut source, dst, scale),
+    }
+}
+
+/// Copy the DB at `src`, written in the format of the first releases of the crate, into a new DB at `dst` in the
+/// current format, and return the number of records copied.
+///
+/// Those files have no magic, and opening them fails with `UnsupportedVersion(LEGACY_VERSION)`, see
+/// [`format::LEGACY_VERSION`]. The new DB has the origin of the source, offsets in seconds and one-octet values,
+/// like it. The octets past the records counted in the header, left by an interrupted append, are dropped.
+/// Fail with `InvalidData` if `src` isn't a legacy file, and with `AlreadyExists` if there is already a file at `dst`.
+pub fn upgrade_legacy(src: &Path, dst: &Path) -> Result<u64, TSLiteError> {
+    let file = File::open(src).map_err(TSLiteError::Io)?;
+    let len = file.metadata().map_err(TSLiteError::Io)?.len();
+    let mut reader = BufReader::new(file);
+    let mut header = [0; LEGACY_HEADER_SIZE as usize];
+    let read = reader.read_exact(&mut header);
+    let (origin, records) = read
+        .ok()
+        .and_then(|_| format::decode_legacy_header(&header, len))
+        .ok_or_else(|| {
+            TSLiteError::InvalidData(format!("{} is not a legacy DB file", src.display()))
+        })?;
+
+    let mut target: PhysicalDB<u8> = PhysicalDB::create(dst, Some(origin))?;
+    let mut batch = Vec::with_capacity(CONVERT_BATCH);
+    let mut bytes = [0; LEGACY_RECORD_SIZE as usize];
+    for _ in 0..records {
+        reader.read_exact(&mut bytes).map_err(TSLiteError::Io)?;
+        batch.push(RecordInfo {
+            time_offset: u32::from_le_bytes(<[u8; 4]>::try_from(&bytes[..4]).unwrap()),
+            value: bytes[4],
+        });
+        if batch.len() == CONVERT_BATCH {
+            target.append_records(&batch)?;
+            batch.clear();
+        }
+    }
+    target.append_records(&batch)?;
+    target.close()?;
+
+    Ok(target.header().records_number)
+}
+
+fn copy_as<T: RecordValue>(
+    source: &mut PhysicalDB<u8>,
+    dst: &Path,
+    scale: f64,
+) -> Result<u64, TSLiteError> {
+    let header = *source.header();
+    let options = DbOptions {
+        offset_unit: header.offset_unit,
+        ..DbOptions::default()
+    };
+    let mut target: PhysicalDB<T> =
+        PhysicalDB::create_with_options(dst, Some(header.origin_date), &options)?;
+
+    let mut batch = Vec::with_capacity(CONVERT_BATCH);
+    let mut failure = None;
+    source.scan_range(&header.origin_date, &LATEST, |r| {
+        if failure.is_some() {
+            return;
+        }
+        batch.push(RecordInfo {
+            time_offset: r.time_offset,
+            value: T::from_f64(r.value.as_f64() * scale),
+        });
+        if batch.len() == CONVERT_BATCH {
+            failure = target.append_records(&batch).err();
+            batch.clear();
+        }
+    })?;
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    target.append_records(&batch)?;
+    target.close()?;
+
+    Ok(target.header().records_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbIssue, Timestamp, Transforms};
+    use std::fs;
+
+    #[test]
+    fn upgrade_legacy_file() {
+        let src = "convert_legacy.db";
+        let dst = "convert_upgraded.db";
+        let _ = fs::remove_file(src);
+        let _ = fs::remove_file(dst);
+
+        // A file written by the first release: 2020-01-02 03:04:05, 3 records, then half of a fourth one.
+        fs::copy("tests/fixtures/legacy.db", src).unwrap();
+        assert_eq!(
+            PhysicalDB::<u8>::open_path(Path::new(src)).err(),
+            Some(TSLiteError::UnsupportedVersion(format::LEGACY_VERSION))
+        );
+
+        assert_eq!(upgrade_legacy(Path::new(src), Path::new(dst)), Ok(3));
+        let mut db: PhysicalDB = PhysicalDB::open_path(Path::new(dst)).unwrap();
+        assert_eq!(
+            db.header().origin_date,
+            Timestamp::new(2020, 1, 2, 3, 4, 5).unwrap()
+        );
+        let records: Vec<(u32, u8)> = db
+            .iter()
+            .map(|r| r.map(|r| (r.time_offset, r.value)).unwrap())
+            .collect();
+        assert_eq!(records, vec![(0, 10), (60, 20), (120, 30)]);
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+
+        // A DB in the current format isn't a legacy file.
+        let _ = fs::remove_file(src);
+        assert!(matches!(
+            upgrade_legacy(Path::new(dst), Path::new(src)),
+            Err(TSLiteError::InvalidData(_))
+        ));
+        assert_eq!(
+            upgrade_legacy(Path::new("tests/fixtures/legacy.db"), Path::new(dst)),
+            Err(TSLiteError::AlreadyExists)
+        );
+
+        let _ = fs::remove_file(src);
+        let _ = fs::remove_file(dst);
+    }
+
+    #[test]
+    fn convert_to_wider_values() {
+        let src = "convert_source.db";
+        let dst = ["convert_f32.db", "convert_i8.db"];
+        let _ = fs::remove_file(src);
+        for p in &dst {
+            let _ = fs::remove_file(p);
+        }
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            transforms: Transforms {
+                scale: Some(2.0),
+                ..Transforms::default()
+            },
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(src), Some(origin), &options)
+                .expect("could not create db.");
+        for (i, v) in [0, 10, 200, 255].iter().enumerate() {
+            db.append_record(RecordInfo {
+                time_offset: i as u32 * 5,
+                value: *v,
+            })
+            .expect("could not append record.");
+        }
+        db.close().expect("could not close db.");
+
+        // The source stores halves of the values, reads them back doubled and saturates 256 to 255.
+        assert_eq!(
+            convert(
+                Path::new(src),
+                Path::new(dst[0]),
+                ValueEncoding::F32,
+                Some(0.25)
+            ),
+            Ok(4)
+        );
+        let mut floats: PhysicalDB<f32> =
+            PhysicalDB::new(Path::new(dst[0]), None).expect("could not open db.");
+        assert_eq!(floats.header().origin_date, origin);
+        assert!(floats.header().transforms.is_identity());
+        assert_eq!(floats.read_record(3).unwrap().time_offset, 15);
+        let values: Vec<f32> = (0..4)
+            .map(|i| floats.read_record(i).unwrap().value)
+            .collect();
+        assert_eq!(values, vec![0.0, 2.5, 50.0, 63.75]);
+        assert_eq!(floats.check_db_file().unwrap(), DbIssue::None);
+
+        assert_eq!(
+            convert(Path::new(src), Path::new(dst[1]), ValueEncoding::I8, None),
+            Ok(4)
+        );
+        let mut bytes: PhysicalDB<i8> =
+            PhysicalDB::new(Path::new(dst[1]), None).expect("could not open db.");
+        assert_eq!(bytes.read_record(2).unwrap().value, i8::MAX);
+        assert_eq!(
+            convert(Path::new(src), Path::new(dst[1]), ValueEncoding::I8, None),
+            Err(TSLiteError::AlreadyExists)
+        );
+
+        let _ = fs::remove_file(src);
+        for p in &dst {
+            let _ = fs::remove_file(p);
+        }
+    }
+}
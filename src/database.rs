@@ -0,0 +1,201 @@
+//! A directory of DBs, one per series, looked up by the name of their series.
+//!
+//! The DB of the series `name` is the file `name.db` of the directory. The DBs are opened the first time
+//! their series is used and stay open until the [`Database`] is closed or dropped.
+
+use crate::{DbOptions, PhysicalDB, Record, RecordInfo, RecordValue, TSLiteError, Timestamp};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const EXTENSION: &str = "db";
+
+/// A directory holding a DB per series.
+#[derive(Debug)]
+pub struct Database<V: RecordValue = u8> {
+    root: PathBuf,
+    origin_date: Option<Timestamp>,
+    options: DbOptions,
+    /// The DBs opened so far, by series name.
+    opened: BTreeMap<String, PhysicalDB<V>>,
+}
+
+impl<V: RecordValue> Database<V> {
+    /// Open the database in the directory `root`, which is created if it doesn't exist.
+    /// The DBs of the new series are created with `origin_date`, with the same meaning as for
+    /// [`PhysicalDB::create`].
+    pub fn open(root: &Path, origin_date: Option<Timestamp>) -> Result<Database<V>, TSLiteError> {
+        Database::open_with_options(root, origin_date, &DbOptions::default())
+    }
+
+    /// Same as [`Database::open`], the DBs being created and opened with `options`.
+    pub fn open_with_options(
+        root: &Path,
+        origin_date: Option<Timestamp>,
+        options: &DbOptions,
+    ) -> Result<Database<V>, TSLiteError> {
+        fs::create_dir_all(root).map_err(TSLiteError::Io)?;
+        Ok(Database {
+            root: root.to_path_buf(),
+            origin_date,
+            options: options.clone(),
+            opened: BTreeMap::new(),
+        })
+    }
+
+    /// The directory of the database.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The path of the DB of the series `name`.
+    /// Fail with `InvalidParameter` if the name is empty, starts with a dot or holds a path separator.
+    pub fn series_path(&self, name: &str) -> Result<PathBuf, TSLiteError> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(TSLiteError::InvalidParameter(format!(
+                "Invalid series name {:?}.",
+                name
+            )));
+        }
+        Ok(self.root.join(format!("{}.{}", name, EXTENSION)))
+    }
+
+    /// The name of every series of the database, sorted.
+    pub fn series(&self) -> Result<Vec<String>, TSLiteError> {
+        let mut names: Vec<String> = fs::read_dir(&self.root)
+            .map_err(TSLiteError::Io)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && path.extension() == Some(EXTENSION.as_ref()))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// The DB of the series `name`, opened or created if it isn't yet.
+    pub fn series_db(&mut self, name: &str) -> Result<&mut PhysicalDB<V>, TSLiteError> {
+        if !self.opened.contains_key(name) {
+            let path = self.series_path(name)?;
+            let db = PhysicalDB::new_with_options(&path, self.origin_date, &self.options)?;
+            self.opened.insert(name.to_string(), db);
+        }
+        Ok(self.opened.get_mut(name).unwrap())
+    }
+
+    /// Append a record to the series `name`, which is created if it doesn't exist.
+    pub fn append(&mut self, name: &str, record: RecordInfo<V>) -> Result<(), TSLiteError> {
+        self.series_db(name)?.append_record(record)
+    }
+
+    /// Every record of the series `name` whose date is within `[start, end[`, none if there is no such series.
+    pub fn records(
+        &mut self,
+        name: &str,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<Vec<Record<V>>, TSLiteError> {
+        if !self.opened.contains_key(name) && !self.series_path(name)?.exists() {
+            return Ok(Vec::new());
+        }
+        self.series_db(name)?.records(start, end)
+    }
+
+    /// The records of every series whose date is within `[start, end[`, by series name.
+    pub fn records_all(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<BTreeMap<String, Vec<Record<V>>>, TSLiteError> {
+        let (start, end) = (start.into(), end.into());
+        let mut all = BTreeMap::new();
+        for name in self.series()? {
+            let records = self.series_db(&name)?.records(start, end)?;
+            all.insert(name, records);
+        }
+        Ok(all)
+    }
+
+    /// Close the DB of the series `name` and delete its file. Return whether there was one.
+    pub fn remove_series(&mut self, name: &str) -> Result<bool, TSLiteError> {
+        let path = self.series_path(name)?;
+        if let Some(mut db) = self.opened.remove(name) {
+            db.close()?;
+        }
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path).map_err(TSLiteError::Io)?;
+        Ok(true)
+    }
+
+    /// Close the DB of every series opened so far, see [`PhysicalDB::close`].
+    pub fn close(mut self) -> Result<(), TSLiteError> {
+        for db in self.opened.values_mut() {
+            db.close()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LATEST;
+
+    #[test]
+    fn directory_of_series() {
+        let root = Path::new("database_dir");
+        let _ = fs::remove_dir_all(root);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: Database<u16> =
+            Database::open(root, Some(origin)).expect("could not open database.");
+        for i in 0..5 {
+            let record = RecordInfo {
+                time_offset: i * 10,
+                value: i as u16,
+            };
+            db.append("cpu", record).unwrap();
+            db.append(
+                "memory",
+                RecordInfo {
+                    value: 100 + i as u16,
+                    ..record
+                },
+            )
+            .unwrap();
+        }
+        for name in ["", ".hidden", "a/b"].iter() {
+            assert!(matches!(
+                db.append(
+                    name,
+                    RecordInfo::<u16> {
+                        time_offset: 0,
+                        value: 0
+                    }
+                ),
+                Err(TSLiteError::InvalidParameter(_))
+            ));
+        }
+        db.close().unwrap();
+
+        let mut db: Database<u16> = Database::open(root, None).expect("could not open database.");
+        assert_eq!(db.series().unwrap(), vec!["cpu", "memory"]);
+        let cpu = db.records("cpu", origin.add_seconds(20), LATEST).unwrap();
+        assert_eq!(cpu.len(), 3);
+        assert_eq!(cpu[0].time, origin.add_seconds(20));
+        assert!(db.records("disk", origin, LATEST).unwrap().is_empty());
+        assert!(!db.series_path("disk").unwrap().exists());
+
+        let all = db.records_all(origin, origin.add_seconds(20)).unwrap();
+        assert_eq!(all.len(), 2);
+        let memory: Vec<u16> = all["memory"].iter().map(|r| r.value).collect();
+        assert_eq!(memory, vec![100, 101]);
+
+        assert!(db.remove_series("cpu").unwrap());
+        assert!(!db.remove_series("cpu").unwrap());
+        assert_eq!(db.series().unwrap(), vec!["memory"]);
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,85 @@
+//! Polars DataFrames of the records, for exploration outside of the crate.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError, Timestamp};
+use polars::prelude::{Column, DataFrame, Float64Chunked, Int64Chunked, IntoSeries, TimeUnit};
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// A DataFrame of every record whose date is within `[start, end[`, with a `time` column of UTC datetimes
+    /// in milliseconds and a `value` column of the values as `f64`.
+    pub fn to_polars(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<DataFrame, TSLiteError> {
+        self.refresh_if_changed()?;
+        let header = self.header;
+        let mut times = Vec::new();
+        let mut values = Vec::new();
+        self.scan_range(&start.into(), &end.into(), |r| {
+            times.push(header.offset_to_date(r.time_offset).unix_millis());
+            values.push(r.value.as_f64());
+        })?;
+
+        let times = Int64Chunked::from_vec("time".into(), times)
+            .into_datetime(TimeUnit::Milliseconds, None)
+            .into_series();
+        let values = Float64Chunked::from_vec("value".into(), values).into_series();
+        DataFrame::new(vec![Column::from(times), Column::from(values)])
+            .map_err(|e| TSLiteError::InvalidParameter(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordInfo;
+    use polars::prelude::{AnyValue, DataType};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn records_to_polars() {
+        let path = "dataframe_polars.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for i in 0..10u16 {
+            db.append_record(RecordInfo {
+                time_offset: i as u32 * 60,
+                value: i * 100,
+            })
+            .expect("could not append record.");
+        }
+
+        let df = db
+            .to_polars(origin.add_seconds(120), origin.add_seconds(300))
+            .unwrap();
+        assert_eq!(df.shape(), (3, 2));
+        assert_eq!(
+            df.column("time").unwrap().dtype(),
+            &DataType::Datetime(TimeUnit::Milliseconds, None)
+        );
+        let first = df.column("time").unwrap().get(0).unwrap();
+        assert_eq!(
+            first,
+            AnyValue::Datetime(
+                origin.add_seconds(120).unix_seconds() * 1000,
+                TimeUnit::Milliseconds,
+                None
+            )
+        );
+        let values: Vec<Option<f64>> = df
+            .column("value")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(values, vec![Some(200.0), Some(300.0), Some(400.0)]);
+        assert_eq!(db.to_polars(origin, origin).unwrap().height(), 0);
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,161 @@
+//! A digest of the whole file, enabled with the `file_digest` option of [`DbOptions`](crate::DbOptions), so a
+//! file archived or sent to another machine can be checked with [`PhysicalDB::verify_digest`].
+//!
+//! When the DB is closed, the SHA-256 of its header and records is written right after the records, in a
+//! footer starting with the magic `TSLD`. The next append overwrites the footer, so only the files of closed
+//! DBs have one. The option is recorded in the header by an empty extension entry.
+
+use crate::{DbHeader, PhysicalDB, RecordValue, TSLiteError, RECORDS_START};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// The extension tag recording that a digest footer is written when the DB is closed.
+pub(crate) const TAG: u8 = 0xF5;
+const MAGIC: &[u8; 4] = b"TSLD";
+/// Size of the footer following the records: the magic and the SHA-256.
+pub const DIGEST_FOOTER_SIZE: u64 = 4 + 32;
+
+impl DbHeader {
+    /// Whether a digest of the file is written after the records when the DB is closed.
+    pub fn file_digest(&self) -> bool {
+        self.extensions.get(TAG).is_some()
+    }
+}
+
+/// The footer holding `hasher`, the digest of the octets before it.
+pub(crate) fn footer(hasher: Sha256) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&hasher.finalize());
+    bytes
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Check the file against the digest in its footer: return `false` if the header or the records changed
+    /// since it was written. Fail with `InvalidData` if the file has no footer, because the DB doesn't have
+    /// the `file_digest` option or records were appended since it was closed.
+    /// Pending records of a buffered DB are not written: the file is checked as it is.
+    pub fn verify_digest(&mut self) -> Result<bool, TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        self.refresh_if_changed()?;
+        let expected = self.digest_footer()?.ok_or_else(|| {
+            TSLiteError::InvalidData(format!("no digest footer in {}", self.path.display()))
+        })?;
+
+        Ok(self.digest()?[MAGIC.len()..] == expected[MAGIC.len()..])
+    }
+
+    /// Write the footer after the records, once the header is re-read so the records appended by other
+    /// handles are not overwritten. The file is not synced.
+    pub(crate) fn write_digest(&mut self) -> Result<(), TSLiteError> {
+        self.refresh_if_changed()?;
+        self.check_stale()?;
+        let footer = self.digest()?;
+        let mut file = self.file.as_ref().unwrap();
+        file.seek(SeekFrom::Start(self.records_end()))
+            .and_then(|_| io::Write::write_all(&mut file, &footer))
+            .map_err(TSLiteError::Io)
+    }
+
+    /// The footer of the header and records currently in the file.
+    fn digest(&self) -> Result<Vec<u8>, TSLiteError> {
+        let end = self.records_end();
+        let mut file = self.file.as_ref().unwrap();
+        file.seek(SeekFrom::Start(0)).map_err(TSLiteError::Io)?;
+        let mut hasher = Sha256::new();
+        let read = io::copy(&mut file.take(end), &mut hasher).map_err(TSLiteError::Io)?;
+        if read < end {
+            return Err(TSLiteError::ShortRead("records".to_string()));
+        }
+        Ok(footer(hasher))
+    }
+
+    /// The footer after the records, `None` if the DB doesn't have the option or there is none.
+    pub(crate) fn digest_footer(&mut self) -> Result<Option<Vec<u8>>, TSLiteError> {
+        if !self.header.file_digest() {
+            return Ok(None);
+        }
+        let mut bytes = vec![0; DIGEST_FOOTER_SIZE as usize];
+        let mut file = self.file.as_ref().unwrap();
+        let read = file
+            .seek(SeekFrom::Start(self.records_end()))
+            .and_then(|_| file.read_exact(&mut bytes));
+        match read {
+            Ok(()) if bytes.starts_with(MAGIC) => Ok(Some(bytes)),
+            Ok(()) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(TSLiteError::Io(e)),
+        }
+    }
+
+    /// The position right after the records in the file.
+    pub(crate) fn records_end(&self) -> u64 {
+        RECORDS_START + self.header.records_number * self.header.record_size::<V>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, RecordInfo};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
+
+    #[test]
+    fn digest_footer_on_close() {
+        let (path, copy) = ("digest.db", "digest_copy.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(copy);
+
+        let options = DbOptions {
+            file_digest: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), None, &options).unwrap();
+        assert!(db.header().file_digest());
+        let records: Vec<RecordInfo<u16>> = (0..100)
+            .map(|i| RecordInfo {
+                time_offset: i,
+                value: i as u16,
+            })
+            .collect();
+        db.append_records(&records[..50]).unwrap();
+        db.close().unwrap();
+        assert_eq!(db.verify_digest(), Ok(true));
+        let end = db.records_end();
+        assert_eq!(fs::metadata(path).unwrap().len(), end + DIGEST_FOOTER_SIZE);
+
+        // The footer is not taken for records appended before a crash.
+        assert!(db.repair().unwrap().is_clean());
+        // Appending overwrites the footer, closing writes it again.
+        db.append_records(&records[50..]).unwrap();
+        assert!(matches!(
+            db.verify_digest(),
+            Err(TSLiteError::InvalidData(_))
+        ));
+        db.close().unwrap();
+        assert_eq!(db.verify_digest(), Ok(true));
+
+        // A copy damaged in transfer.
+        fs::copy(path, copy).unwrap();
+        let mut file = OpenOptions::new().write(true).open(copy).unwrap();
+        file.seek(SeekFrom::Start(RECORDS_START + 10)).unwrap();
+        file.write_all(&[0xFF]).unwrap();
+        drop(file);
+        let mut damaged: PhysicalDB<u16> = PhysicalDB::open_path(Path::new(copy)).unwrap();
+        assert_eq!(damaged.verify_digest(), Ok(false));
+        drop(damaged);
+
+        // Snapshots have a footer too.
+        let _ = fs::remove_file(copy);
+        db.snapshot(Path::new(copy)).unwrap();
+        let mut snapshot: PhysicalDB<u16> = PhysicalDB::open_path(Path::new(copy)).unwrap();
+        assert_eq!(snapshot.verify_digest(), Ok(true));
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(copy);
+    }
+}
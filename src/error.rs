@@ -0,0 +1,146 @@
+//! The errors of the crate.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// A wrapper for various type of error that can occur within TSLite.
+#[derive(Debug)]
+pub enum TSLiteError {
+    /// An I/O operation on a file or a stream failed, the `io::Error` is the source of this one.
+    Io(io::Error),
+    /// A file, a stream or a buffer ended before what was being read, which is held.
+    ShortRead(String),
+    /// Data read from a stream or a file other than a DB file doesn't have the expected format, with a description.
+    InvalidData(String),
+    /// A storage or a worker other than the DB file failed: SQLite, the ingestion writer…, with its message.
+    Backend(String),
+    /// A record index past the last record of the DB.
+    IndexOutOfBound,
+    /// A parameter given to a query is outside of its allowed domain.
+    InvalidParameter(String),
+    /// The file behind the handle was replaced or removed (by a compaction or a logrotate for example).
+    /// Use [`PhysicalDB::reopen`](crate::PhysicalDB::reopen) to open the new file.
+    StaleHandle,
+    /// A DB file already exists at the given path and overwriting it was not allowed.
+    AlreadyExists,
+    /// Neither the header nor its shadow copy match their checksum.
+    HeaderCorrupted,
+    /// The file doesn't start with the magic of a DB file, it wasn't written by tslite.
+    NotADatabase,
+    /// The file was written in a format version this version of the crate can't read, which is held.
+    UnsupportedVersion(u8),
+    /// A date does not exist, like the 31st of April.
+    InvalidTimestamp,
+    /// A date is too far from the origin of the DB for its offset to fit in 32 bits.
+    /// Roll over to a new DB with a later origin, or use a coarser [`OffsetUnit`](crate::OffsetUnit) for
+    /// long-lived series.
+    OffsetOverflow,
+    /// A record cannot be appended before the origin of the DB.
+    BeforeOrigin,
+    /// The values of the DB don't have the width of the type it is opened with, which is held.
+    ValueWidthMismatch(u8),
+    /// A record is dated further in the future than the `max_future_skew` option allows,
+    /// which usually means the clock of the device that produced it is wrong.
+    TooFarInFuture,
+    /// A record doesn't match its checksum, with its index.
+    /// See the `record_checksums` option of [`DbOptions`](crate::DbOptions).
+    RecordCorrupted(u64),
+}
+
+impl fmt::Display for TSLiteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TSLiteError::Io(e) => write!(f, "I/O error: {}", e),
+            TSLiteError::ShortRead(what) => write!(f, "Could not read {}: not enough octets", what),
+            TSLiteError::InvalidData(description) => write!(f, "Invalid data: {}", description),
+            TSLiteError::Backend(message) => f.write_str(message),
+            TSLiteError::IndexOutOfBound => f.write_str("Record index out of bound"),
+            TSLiteError::InvalidParameter(message) => write!(f, "Invalid parameter: {}", message),
+            TSLiteError::StaleHandle => f.write_str("The DB file was replaced or removed"),
+            TSLiteError::AlreadyExists => f.write_str("A DB file already exists at this path"),
+            TSLiteError::HeaderCorrupted => f.write_str("Both copies of the header are corrupted"),
+            TSLiteError::NotADatabase => f.write_str("Not a DB file"),
+            TSLiteError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported format version {}", version)
+            }
+            TSLiteError::InvalidTimestamp => f.write_str("Invalid date"),
+            TSLiteError::OffsetOverflow => f.write_str("Date too far from the origin of the DB"),
+            TSLiteError::BeforeOrigin => f.write_str("Date anterior to the origin of the DB"),
+            TSLiteError::ValueWidthMismatch(width) => {
+                write!(f, "The values of the DB are {} octets wide", width)
+            }
+            TSLiteError::TooFarInFuture => f.write_str("Date too far in the future"),
+            TSLiteError::RecordCorrupted(index) => write!(f, "Record {} is corrupted", index),
+        }
+    }
+}
+
+impl Error for TSLiteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TSLiteError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TSLiteError {
+    fn from(e: io::Error) -> TSLiteError {
+        TSLiteError::Io(e)
+    }
+}
+
+/// I/O errors are equal if they are of the same kind, as `io::Error` can't be compared.
+impl PartialEq for TSLiteError {
+    fn eq(&self, other: &TSLiteError) -> bool {
+        use TSLiteError::*;
+        match (self, other) {
+            (Io(a), Io(b)) => a.kind() == b.kind(),
+            (ShortRead(a), ShortRead(b))
+            | (InvalidData(a), InvalidData(b))
+            | (Backend(a), Backend(b))
+            | (InvalidParameter(a), InvalidParameter(b)) => a == b,
+            (UnsupportedVersion(a), UnsupportedVersion(b))
+            | (ValueWidthMismatch(a), ValueWidthMismatch(b)) => a == b,
+            (RecordCorrupted(a), RecordCorrupted(b)) => a == b,
+            (IndexOutOfBound, IndexOutOfBound)
+            | (StaleHandle, StaleHandle)
+            | (AlreadyExists, AlreadyExists)
+            | (HeaderCorrupted, HeaderCorrupted)
+            | (NotADatabase, NotADatabase)
+            | (InvalidTimestamp, InvalidTimestamp)
+            | (OffsetOverflow, OffsetOverflow)
+            | (BeforeOrigin, BeforeOrigin)
+            | (TooFarInFuture, TooFarInFuture) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PhysicalDB;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn io_error_as_source() {
+        let path = "error_source.db";
+        let _ = fs::remove_file(path);
+
+        let e = PhysicalDB::<u8>::open_path(Path::new(path)).unwrap_err();
+        assert_eq!(e, TSLiteError::Io(io::ErrorKind::NotFound.into()));
+        let source = e.source().expect("no source.");
+        assert_eq!(
+            source.downcast_ref::<io::Error>().map(|e| e.kind()),
+            Some(io::ErrorKind::NotFound)
+        );
+        assert!(e.to_string().starts_with("I/O error: "));
+
+        let boxed: Box<dyn Error> = Box::new(TSLiteError::RecordCorrupted(3));
+        assert_eq!(boxed.to_string(), "Record 3 is corrupted");
+        assert!(boxed.source().is_none());
+    }
+}
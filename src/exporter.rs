@@ -0,0 +1,213 @@
+//! Operational metrics of the DBs themselves, in the Prometheus text exposition format.
+//!
+//! Unlike the `metrics` feature, which feeds an exporter installed by the application,
+//! this needs nothing else: serve the output of [`prometheus_text`] on a `/metrics` endpoint.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError, Timestamp};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Operation counters kept by each DB handle since it was opened.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct OpCounters {
+    /// The date of the last append, according to the clock of the DB.
+    last_append: Option<Timestamp>,
+    write_errors: u64,
+    read_errors: u64,
+}
+
+impl OpCounters {
+    pub(crate) fn appended(&mut self, now: Timestamp) {
+        self.last_append = Some(now);
+    }
+
+    /// Count a failed write, and pass the result through.
+    pub(crate) fn count_write<T>(
+        &mut self,
+        result: Result<T, TSLiteError>,
+    ) -> Result<T, TSLiteError> {
+        if result.is_err() {
+            self.write_errors += 1;
+        }
+        result
+    }
+
+    /// Count a failed read, and pass the result through. Reading past the last record is not a failure.
+    pub(crate) fn count_read<T>(
+        &mut self,
+        result: Result<T, TSLiteError>,
+    ) -> Result<T, TSLiteError> {
+        match result {
+            Err(TSLiteError::IndexOutOfBound) | Ok(_) => {}
+            Err(_) => self.read_errors += 1,
+        }
+        result
+    }
+}
+
+/// A snapshot of the operational state of a DB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbMetrics {
+    /// Path of the DB, used as the `db` label.
+    pub path: PathBuf,
+    /// Number of records in the DB.
+    pub records: u64,
+    /// Size of the DB file in octets, 0 if it can't be read.
+    pub file_size: u64,
+    /// Time elapsed since the last append through this handle, `None` if there was none.
+    pub last_append_age: Option<Duration>,
+    /// Number of failed appends since the DB was opened.
+    pub write_errors: u64,
+    /// Number of failed reads since the DB was opened.
+    pub read_errors: u64,
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// A snapshot of the operational metrics of the DB, to be exported with [`prometheus_text`].
+    pub fn metrics(&self) -> DbMetrics {
+        DbMetrics {
+            path: self.path.clone(),
+            records: self.header.records_number,
+            file_size: std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0),
+            last_append_age: self.counters.last_append.map(|t| {
+                let elapsed = self.options.now().unix_nanos() - t.unix_nanos();
+                Duration::from_nanos(elapsed.clamp(0, u64::MAX as i128) as u64)
+            }),
+            write_errors: self.counters.write_errors,
+            read_errors: self.counters.read_errors,
+        }
+    }
+}
+
+/// Format the metrics of several DBs in the Prometheus text format, each DB labelled by its path.
+pub fn prometheus_text(dbs: &[DbMetrics]) -> String {
+    let mut out = String::new();
+    let labels: Vec<String> = dbs
+        .iter()
+        .map(|db| format!("db=\"{}\"", escape(&db.path.to_string_lossy())))
+        .collect();
+
+    family(
+        &mut out,
+        "tslite_records",
+        "gauge",
+        "Number of records in the DB.",
+    );
+    for (db, labels) in dbs.iter().zip(&labels) {
+        let _ = writeln!(out, "tslite_records{{{}}} {}", labels, db.records);
+    }
+    family(
+        &mut out,
+        "tslite_file_size_bytes",
+        "gauge",
+        "Size of the DB file.",
+    );
+    for (db, labels) in dbs.iter().zip(&labels) {
+        let _ = writeln!(out, "tslite_file_size_bytes{{{}}} {}", labels, db.file_size);
+    }
+    family(
+        &mut out,
+        "tslite_last_append_age_seconds",
+        "gauge",
+        "Time since the last append, absent if there was none.",
+    );
+    for (db, labels) in dbs.iter().zip(&labels) {
+        if let Some(age) = db.last_append_age {
+            let _ = writeln!(
+                out,
+                "tslite_last_append_age_seconds{{{}}} {}",
+                labels,
+                age.as_secs_f64()
+            );
+        }
+    }
+    family(
+        &mut out,
+        "tslite_errors_total",
+        "counter",
+        "Failed operations since the DB was opened.",
+    );
+    for (db, labels) in dbs.iter().zip(&labels) {
+        let _ = writeln!(
+            out,
+            "tslite_errors_total{{{},op=\"write\"}} {}",
+            labels, db.write_errors
+        );
+        let _ = writeln!(
+            out,
+            "tslite_errors_total{{{},op=\"read\"}} {}",
+            labels, db.read_errors
+        );
+    }
+
+    out
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value: backslashes, double quotes and line feeds.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, RecordInfo, Timestamp};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn export_db_metrics() {
+        let path = "exporter.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::now().add_seconds(-60);
+        let options = DbOptions {
+            max_future_skew: Some(Duration::from_secs(0)),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        assert_eq!(db.metrics().last_append_age, None);
+        for i in 0..3 {
+            db.append_record(RecordInfo {
+                time_offset: i,
+                value: 1,
+            })
+            .expect("could not append record.");
+        }
+        assert!(db
+            .append_record(RecordInfo {
+                time_offset: 3600,
+                value: 1,
+            })
+            .is_err());
+        assert_eq!(db.read_record(10), Err(TSLiteError::IndexOutOfBound));
+
+        let metrics = db.metrics();
+        assert_eq!(metrics.records, 3);
+        assert_eq!(metrics.file_size, fs::metadata(path).unwrap().len());
+        assert!(metrics.last_append_age.is_some());
+        assert_eq!(metrics.write_errors, 1);
+        assert_eq!(metrics.read_errors, 0);
+
+        let text = prometheus_text(&[metrics]);
+        assert!(
+            text.contains("# TYPE tslite_records gauge\ntslite_records{db=\"exporter.db\"} 3\n")
+        );
+        assert!(text.contains("tslite_errors_total{db=\"exporter.db\",op=\"write\"} 1\n"));
+        assert!(text.contains("tslite_last_append_age_seconds{db=\"exporter.db\"} "));
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,211 @@
+//! The extension area at the end of the header, holding optional properties of a DB.
+//!
+//! The area has a fixed size: the length of the entries on 16 bits, the entries, then reserved octets
+//! set to zero. Each entry is a tag, the length of its data and its data, one octet each for the tag and
+//! the length. Properties added later get a new tag, so files written before them are still read, and
+//! entries whose tag is unknown are kept as they are.
+//!
+//! The tags from 0xF0 are reserved for the properties of the crate itself, such as the timescale.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Size of the serialized extension area in the header.
+pub(crate) const EXTENSIONS_SIZE: u64 = 64;
+/// Room for the entries, after their length.
+const CAPACITY: usize = EXTENSIONS_SIZE as usize - 2;
+
+/// The entries of the extension area of a header: tagged data, whose tags unknown to this version are kept as they are.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Extensions {
+    len: usize,
+    entries: [u8; CAPACITY],
+}
+
+impl Default for Extensions {
+    fn default() -> Extensions {
+        Extensions {
+            len: 0,
+            entries: [0; CAPACITY],
+        }
+    }
+}
+
+impl Extensions {
+    /// The data of the entry tagged `tag`.
+    pub fn get(&self, tag: u8) -> Option<&[u8]> {
+        self.iter().find(|(t, _)| *t == tag).map(|(_, data)| data)
+    }
+
+    /// Set the data of the entry tagged `tag`, replacing the previous one.
+    /// Fail with `InvalidParameter` if the area has no room left for it.
+    pub fn set(&mut self, tag: u8, data: &[u8]) -> Result<(), TSLiteError> {
+        let previous = self.get(tag).map_or(0, |d| 2 + d.len());
+        if data.len() > u8::MAX as usize || self.len - previous + 2 + data.len() > CAPACITY {
+            return Err(TSLiteError::InvalidParameter(format!(
+                "No room left in the header for {} octets.",
+                data.len()
+            )));
+        }
+        self.remove(tag);
+        self.entries[self.len] = tag;
+        self.entries[self.len + 1] = data.len() as u8;
+        self.entries[self.len + 2..self.len + 2 + data.len()].copy_from_slice(data);
+        self.len += 2 + data.len();
+        Ok(())
+    }
+
+    /// Remove the entry tagged `tag`, and return whether there was one.
+    pub fn remove(&mut self, tag: u8) -> bool {
+        let mut position = 0;
+        while position < self.len {
+            let size = 2 + self.entries[position + 1] as usize;
+            if self.entries[position] == tag {
+                self.entries
+                    .copy_within(position + size..self.len, position);
+                self.len -= size;
+                for octet in &mut self.entries[self.len..] {
+                    *octet = 0;
+                }
+                return true;
+            }
+            position += size;
+        }
+        false
+    }
+
+    /// The tag and data of every entry, in the order they are stored.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &[u8])> {
+        let mut rest = &self.entries[..self.len];
+        std::iter::from_fn(move || {
+            let (&tag, after) = rest.split_first()?;
+            let (&size, after) = after.split_first()?;
+            let (data, after) = after.split_at(size as usize);
+            rest = after;
+            Some((tag, data))
+        })
+    }
+
+    pub(crate) fn as_bytes(&self) -> Vec<u8> {
+        let mut store = vec![0; EXTENSIONS_SIZE as usize];
+        LittleEndian::write_u16(&mut store[..2], self.len as u16);
+        store[2..].copy_from_slice(&self.entries);
+        store
+    }
+
+    /// Deserialize the extension area, return `None` if its entries overflow it.
+    pub(crate) fn from_bytes(d: &[u8]) -> Option<Extensions> {
+        let len = LittleEndian::read_u16(&d[..2]) as usize;
+        if len > CAPACITY {
+            return None;
+        }
+        let mut extensions = Extensions::default();
+        extensions.entries[..len].copy_from_slice(&d[2..2 + len]);
+        extensions.len = len;
+        // Every entry must end within the entries.
+        let mut position = 0;
+        while position < len {
+            if position + 1 >= len {
+                return None;
+            }
+            position += 2 + extensions.entries[position + 1] as usize;
+        }
+        if position != len {
+            return None;
+        }
+        Some(extensions)
+    }
+}
+
+/// Serialized as the sequence of the tags and data of its entries.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Extensions {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Extensions {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Extensions, D::Error> {
+        let entries: Vec<(u8, Vec<u8>)> = serde::Deserialize::deserialize(deserializer)?;
+        let mut extensions = Extensions::default();
+        for (tag, data) in entries {
+            extensions
+                .set(tag, &data)
+                .map_err(serde::de::Error::custom)?;
+        }
+        Ok(extensions)
+    }
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Set the data of the extension entry tagged `tag` and write the header.
+    pub fn set_extension(&mut self, tag: u8, data: &[u8]) -> Result<(), TSLiteError> {
+        self.refresh_if_changed()?;
+        let mut extensions = self.header.extensions;
+        extensions.set(tag, data)?;
+        self.header.extensions = extensions;
+        self.write_header()
+    }
+
+    /// Remove the extension entry tagged `tag` and write the header, return whether there was one.
+    pub fn remove_extension(&mut self, tag: u8) -> Result<bool, TSLiteError> {
+        self.refresh_if_changed()?;
+        let mut extensions = self.header.extensions;
+        if !extensions.remove(tag) {
+            return Ok(false);
+        }
+        self.header.extensions = extensions;
+        self.write_header()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RecordInfo, Timestamp};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn header_extensions() {
+        let path = "extension_header.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        assert_eq!(db.header().extensions.iter().count(), 0);
+        db.set_extension(1, b"abc").unwrap();
+        db.set_extension(7, &[42]).unwrap();
+        db.set_extension(1, b"de").unwrap();
+        assert!(db.set_extension(9, &[0; 60]).is_err());
+        db.append_record(RecordInfo {
+            time_offset: 5,
+            value: 3,
+        })
+        .unwrap();
+        db.close().unwrap();
+
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        let entries: Vec<(u8, &[u8])> = db.header().extensions.iter().collect();
+        assert_eq!(entries, vec![(7, &[42][..]), (1, &b"de"[..])]);
+        assert_eq!(db.read_record(0).unwrap().value, 3);
+        assert_eq!(db.remove_extension(7), Ok(true));
+        assert_eq!(db.remove_extension(7), Ok(false));
+        assert_eq!(db.header().extensions.get(1), Some(&b"de"[..]));
+        assert_eq!(db.header().extensions.get(7), None);
+
+        let mut full = Extensions::default();
+        full.set(2, &[1; 60]).unwrap();
+        assert_eq!(Extensions::from_bytes(&full.as_bytes()), Some(full));
+        let mut overflowing = full.as_bytes();
+        overflowing[3] = 61;
+        assert_eq!(Extensions::from_bytes(&overflowing), None);
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,158 @@
+//! Copying the records of a time range into a new DB, such as a month to archive.
+
+use crate::{ring, DbOptions, PhysicalDB, RecordInfo, RecordValue, TSLiteError, Timestamp};
+use std::fs;
+use std::path::Path;
+
+/// Number of records appended to the new DB at once.
+const EXTRACT_BATCH: usize = 4096;
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Copy every record whose date is within `[from, to[` into a new DB at `dest`, and return the number of
+    /// records copied. Along with [`PhysicalDB::prune_before`], it moves the old records of a DB to an archive.
+    ///
+    /// The new DB starts at `from`, or at the origin of this DB if it is later, the offsets of the records
+    /// being rebased onto it. It has the offset unit, transforms and record checksums of this DB, and the
+    /// entries of its header extensions, such as its labels, but not its ring capacity.
+    /// Fail with `AlreadyExists` if there is already a file at `dest`.
+    pub fn extract_range(
+        &mut self,
+        from: impl Into<Timestamp>,
+        to: impl Into<Timestamp>,
+        dest: &Path,
+    ) -> Result<u64, TSLiteError> {
+        let (from, to) = (from.into(), to.into());
+        self.flush()?;
+        self.refresh_if_changed()?;
+        let header = self.header;
+        let options = DbOptions {
+            offset_unit: header.offset_unit,
+            transforms: header.transforms,
+            record_checksums: header.record_checksums(),
+            ..DbOptions::default()
+        };
+        let origin = Timestamp {
+            nanosecond: 0,
+            ..from.max(header.origin_date)
+        };
+        let mut extracted: PhysicalDB<V> =
+            PhysicalDB::create_with_options(dest, Some(origin), &options)?;
+        let result = self.extract_into(&mut extracted, from, to);
+        if result.is_err() {
+            drop(extracted);
+            let _ = fs::remove_file(dest);
+        }
+        result
+    }
+
+    /// Copy the header extensions of this DB and its records within `[from, to[` to the empty DB `extracted`.
+    fn extract_into(
+        &mut self,
+        extracted: &mut PhysicalDB<V>,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> Result<u64, TSLiteError> {
+        let header = self.header;
+        for (tag, data) in header.extensions.iter().filter(|(t, _)| *t != ring::TAG) {
+            extracted.header.extensions.set(tag, data)?;
+        }
+        extracted.write_header()?;
+        let target = extracted.header;
+
+        let mut batch = Vec::with_capacity(EXTRACT_BATCH);
+        let mut written = 0;
+        let mut failure = None;
+        self.scan_range(&from, &to, |r| {
+            if failure.is_some() {
+                return;
+            }
+            let time_offset = match target.checked_offset(&header.offset_to_date(r.time_offset)) {
+                Ok(time_offset) => time_offset,
+                Err(e) => {
+                    failure = Some(e);
+                    return;
+                }
+            };
+            batch.push(RecordInfo {
+                time_offset,
+                value: r.value,
+            });
+            if batch.len() == EXTRACT_BATCH {
+                failure = extracted.append_records(&batch).err();
+                written += batch.len() as u64;
+                batch.clear();
+            }
+        })?;
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        extracted.append_records(&batch)?;
+        written += batch.len() as u64;
+        extracted.close()?;
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DbIssue, DbOptions, PhysicalDB, RecordInfo, Timestamp, Transforms};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn extract_a_month() {
+        let (path, archive) = ("extract.db", "extract_archive.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(archive);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            transforms: Transforms {
+                delta: true,
+                ..Transforms::default()
+            },
+            record_checksums: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options).unwrap();
+        db.set_label("sensor", "kitchen").unwrap();
+        // One record a day, from January to March.
+        let records: Vec<RecordInfo<u16>> = (0..91)
+            .map(|day| RecordInfo {
+                time_offset: day * 86_400,
+                value: (day * 3) as u16,
+            })
+            .collect();
+        db.append_records(&records).unwrap();
+
+        let february = Timestamp::new(2020, 2, 1, 0, 0, 0).unwrap();
+        let march = Timestamp::new(2020, 3, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            db.extract_range(february, march, Path::new(archive))
+                .unwrap(),
+            29
+        );
+        let mut extracted: PhysicalDB<u16> = PhysicalDB::open_path(Path::new(archive)).unwrap();
+        let header = *extracted.header();
+        assert_eq!(header.origin_date, february);
+        assert!(header.transforms.delta);
+        assert!(header.record_checksums());
+        assert_eq!(header.label("sensor"), Some("kitchen".to_string()));
+        let copied: Vec<(u32, u16)> = extracted
+            .iter()
+            .map(|r| r.map(|r| (r.time_offset, r.value)).unwrap())
+            .collect();
+        assert_eq!(copied.len(), 29);
+        assert_eq!(copied[0], (0, 31 * 3));
+        assert_eq!(copied[28], (28 * 86_400, 59 * 3));
+        assert_eq!(extracted.check_db_file().unwrap(), DbIssue::None);
+        assert!(db
+            .extract_range(february, march, Path::new(archive))
+            .is_err());
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(archive);
+    }
+}
@@ -0,0 +1,34 @@
+//! Fail points at the boundaries of the writes, to check that an application recovers from a crash or an
+//! I/O error at any step of a write.
+//!
+//! They are only active with the `failpoints` feature, and are configured by name with the `fail` crate:
+//! `fail::cfg(fail_points::FSYNC, "return")` makes the syncs fail with an I/O error, and `"panic"` simulates
+//! a crash right before them. The message given to `return`, as in `"return(disk full)"`, becomes the message
+//! of the error.
+//!
+//! The configuration of the `fail` crate is global to the process, so tests using it are best kept in their
+//! own test binary.
+
+use std::io;
+
+/// Before the records are written, when appending or moving them.
+pub const WRITE_RECORD: &str = "tslite::write_record";
+/// Before a header is written.
+pub const WRITE_HEADER: &str = "tslite::write_header";
+/// Before the DB file is synced to the disk.
+pub const FSYNC: &str = "tslite::fsync";
+
+#[cfg(feature = "failpoints")]
+pub(crate) fn hit(name: &str) -> io::Result<()> {
+    fail::fail_point!(name, |message: Option<String>| {
+        Err(io::Error::other(message.unwrap_or_else(|| {
+            format!("Fail point {} triggered.", name)
+        })))
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "failpoints"))]
+pub(crate) fn hit(_name: &str) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,186 @@
+//! Forecasting helpers, enabled by the `analytics` feature.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError, Timestamp};
+
+/// Parameters of an additive Holt-Winters (triple exponential smoothing) model.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HoltWinters {
+    /// Smoothing factor of the level, within `[0, 1]`.
+    pub alpha: f64,
+    /// Smoothing factor of the trend, within `[0, 1]`.
+    pub beta: f64,
+    /// Smoothing factor of the seasonality, within `[0, 1]`.
+    pub gamma: f64,
+    /// Number of records in one season (e.g. 24 for hourly records with a daily cycle).
+    pub season_length: usize,
+    /// How many standard deviations of the one-step error the confidence band spans (1.96 for ~95%).
+    pub z_score: f64,
+}
+
+/// A predicted point with its confidence band.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ForecastPoint {
+    pub time: Timestamp,
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl HoltWinters {
+    fn validate(&self) -> Result<(), TSLiteError> {
+        let in_unit = |x: f64| (0.0..=1.0).contains(&x);
+        if !in_unit(self.alpha) || !in_unit(self.beta) || !in_unit(self.gamma) {
+            return Err(TSLiteError::InvalidParameter(
+                "Holt-Winters smoothing factors must be within [0, 1].".to_string(),
+            ));
+        }
+        if self.season_length == 0 {
+            return Err(TSLiteError::InvalidParameter(
+                "Holt-Winters season length must be at least 1.".to_string(),
+            ));
+        }
+        if self.z_score < 0.0 {
+            return Err(TSLiteError::InvalidParameter(
+                "Holt-Winters z-score cannot be negative.".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fit the model over `values` and predict the next `horizon` values.
+    /// Return the predictions alongside the standard deviation of the one-step errors.
+    fn fit_predict(&self, values: &[f64], horizon: usize) -> (Vec<f64>, f64) {
+        let m = self.season_length;
+
+        // Initialize the components from the first two seasons.
+        let mut level = values[..m].iter().sum::<f64>() / m as f64;
+        let mut trend = (0..m)
+            .map(|i| (values[m + i] - values[i]) / m as f64)
+            .sum::<f64>()
+            / m as f64;
+        let mut seasonal: Vec<f64> = values[..m].iter().map(|v| v - level).collect();
+
+        let mut sse = 0.0;
+        for (t, value) in values.iter().enumerate().skip(m) {
+            let s = seasonal[t % m];
+            let error = value - (level + trend + s);
+            sse += error * error;
+
+            let last_level = level;
+            level = self.alpha * (value - s) + (1.0 - self.alpha) * (level + trend);
+            trend = self.beta * (level - last_level) + (1.0 - self.beta) * trend;
+            seasonal[t % m] = self.gamma * (value - level) + (1.0 - self.gamma) * s;
+        }
+        let sigma = (sse / (values.len() - m) as f64).sqrt();
+
+        let n = values.len();
+        let predictions = (1..=horizon)
+            .map(|h| level + h as f64 * trend + seasonal[(n + h - 1) % m])
+            .collect();
+        (predictions, sigma)
+    }
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Fit an additive Holt-Winters model over the records within `[start, end[` and predict the `horizon` next points.
+    /// Records are treated as evenly spaced samples, the predicted points are spaced by the average interval between
+    /// the records of the range. The confidence band widens with the square root of the distance to the last record.
+    /// At least two full seasons of records are needed to initialize the model.
+    pub fn forecast(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        model: &HoltWinters,
+        horizon: usize,
+    ) -> Result<Vec<ForecastPoint>, TSLiteError> {
+        model.validate()?;
+
+        let mut offsets: Vec<u32> = Vec::new();
+        let mut values: Vec<f64> = Vec::new();
+        self.scan_range(&start.into(), &end.into(), |r| {
+            offsets.push(r.time_offset);
+            values.push(r.value.as_f64());
+        })?;
+        if values.len() < 2 * model.season_length {
+            return Err(TSLiteError::InvalidParameter(format!(
+                "Holt-Winters needs at least {} records, got {}.",
+                2 * model.season_length,
+                values.len()
+            )));
+        }
+
+        let first = offsets[0] as f64;
+        let last = offsets[offsets.len() - 1];
+        let step = (last as f64 - first) / (offsets.len() - 1) as f64;
+        let last_date = self.offset_to_date(last);
+        let unit = self.header().offset_unit.as_secs_f64();
+
+        let (predictions, sigma) = model.fit_predict(&values, horizon);
+        Ok(predictions
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let h = (i + 1) as f64;
+                let band = model.z_score * sigma * h.sqrt();
+                ForecastPoint {
+                    time: last_date.add_seconds((step * h * unit).round() as i64),
+                    value,
+                    lower: value - band,
+                    upper: value + band,
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordInfo;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn forecast_seasonal_series() {
+        let path = "forecast.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        // A perfectly periodic series: 10, 20, 30, 20, 10, 20, 30, 20, ...
+        let season = [10, 20, 30, 20];
+        for i in 0..16 {
+            db.append_record(RecordInfo {
+                time_offset: i * 60,
+                value: season[i as usize % 4],
+            })
+            .expect("could not append record.");
+        }
+
+        let model = HoltWinters {
+            alpha: 0.5,
+            beta: 0.1,
+            gamma: 0.1,
+            season_length: 4,
+            z_score: 1.96,
+        };
+        let end = origin.add_seconds(3600);
+        let points = db
+            .forecast(origin, end, &model, 4)
+            .expect("could not forecast.");
+        assert_eq!(points.len(), 4);
+        for (i, p) in points.iter().enumerate() {
+            assert!((p.value - season[i] as f64).abs() < 0.5);
+            assert!(p.lower <= p.value && p.value <= p.upper);
+            assert_eq!(p.time, origin.add_seconds(60 * (16 + i as i64)));
+        }
+
+        // Not enough records for two seasons.
+        let short = origin.add_seconds(60 * 7);
+        assert!(db.forecast(origin, short, &model, 4).is_err());
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,146 @@
+//! The layout of a DB file, for the tools reading or writing DB files without going through [`PhysicalDB`].
+//!
+//! A file is the header followed by its CRC32, a copy of both, then the records one after the other,
+//! see the [crate documentation](crate#file-orga) for the fields of the header and of the records.
+//! Every number is little-endian.
+//!
+//! [`PhysicalDB`]: crate::PhysicalDB
+
+use crate::{
+    checked_header, extension, transform, DbHeader, RecordInfo, RecordValue, TSLiteError, Timestamp,
+};
+use std::convert::TryFrom;
+
+/// The magic octets every copy of the header starts with.
+pub const MAGIC: &[u8; 4] = b"TSLT";
+/// The version of the file format, following the magic, bumped whenever the layout of the header changes.
+/// Files of another version are rejected with `UnsupportedVersion`.
+pub const FORMAT_VERSION: u8 = 2;
+/// The format version reported for the files written before the magic and the format version were added,
+/// whose header is only the origin and the record count. They are read with [`decode_legacy_header`]
+/// and upgraded to the current format with [`upgrade_legacy`](crate::upgrade_legacy).
+pub const LEGACY_VERSION: u8 = 0;
+/// Size of the header of a legacy file: 7 for the origin and 8 for the record count.
+pub const LEGACY_HEADER_SIZE: u64 = 7 + 8;
+/// Size of a record of a legacy file: 4 for the time offset in seconds and 1 for the value.
+pub const LEGACY_RECORD_SIZE: u64 = 4 + 1;
+/// Size of the magic and the format version, at the start of the serialized header.
+pub const PREAMBLE_SIZE: u64 = MAGIC.len() as u64 + 1;
+/// Size of a serialized header, without its checksum.
+/// The magic and the format version, 7 for the origin, 8 for the record count, 1 for the offset unit,
+/// then the transforms and the extensions.
+pub const HEADER_SIZE: u64 = PREAMBLE_SIZE + 7 + 8 + 1 + TRANSFORMS_SIZE + EXTENSIONS_SIZE;
+/// Size of the serialized transforms, within the header.
+pub const TRANSFORMS_SIZE: u64 = transform::TRANSFORMS_SIZE;
+/// Size of the extension area, at the end of the header.
+pub const EXTENSIONS_SIZE: u64 = extension::EXTENSIONS_SIZE;
+/// Position of the extension area within the serialized header.
+pub const EXTENSIONS_START: u64 = HEADER_SIZE - EXTENSIONS_SIZE;
+/// Size of one copy of the header followed by its checksum.
+pub const HEADER_COPY_SIZE: u64 = HEADER_SIZE + 4;
+/// Position of the first record, after the primary and shadow copies of the header.
+pub const RECORDS_START: u64 = 2 * HEADER_COPY_SIZE;
+/// Size of the time offset of a serialized record, the value follows it.
+pub const TIME_OFFSET_SIZE: u64 = 4;
+
+/// Position in the file of the record at index `id`, in a DB of `V` values without record checksums.
+/// [`DbHeader::record_size`] gives the size of the records of any DB.
+pub fn record_position<V: RecordValue>(id: u64) -> u64 {
+    RECORDS_START + id * RecordInfo::<V>::SIZE
+}
+
+/// Number of whole records in a file of `len` octets, in a DB of `V` values without record checksums.
+pub fn record_capacity<V: RecordValue>(len: u64) -> u64 {
+    len.saturating_sub(RECORDS_START) / RecordInfo::<V>::SIZE
+}
+
+/// Serialize `header` as it is at the start of a file: followed by its CRC32, then by a copy of both.
+pub fn encode_header(header: &DbHeader) -> Vec<u8> {
+    let mut bytes = header.as_checked_bytes();
+    bytes.extend(header.as_checked_bytes());
+    bytes
+}
+
+/// Deserialize the header at the start of a file, from the first of its two copies matching its checksum.
+/// `bytes` must hold at least [`RECORDS_START`] octets.
+pub fn decode_header(bytes: &[u8]) -> Result<DbHeader, TSLiteError> {
+    if (bytes.len() as u64) < RECORDS_START {
+        return Err(TSLiteError::ShortRead("header".to_string()));
+    }
+    checked_header(bytes).map(|(header, _)| header)
+}
+
+/// Deserialize the header of a legacy file of `len` octets starting with `bytes`: its origin and its record count.
+/// `None` if it isn't one: a legacy header holds a valid origin and counts no more records than the file holds.
+pub fn decode_legacy_header(bytes: &[u8], len: u64) -> Option<(Timestamp, u64)> {
+    if (bytes.len() as u64) < LEGACY_HEADER_SIZE || bytes.starts_with(MAGIC) {
+        return None;
+    }
+    let year = u16::from_le_bytes([bytes[0], bytes[1]]);
+    let origin = Timestamp::new(year, bytes[2], bytes[3], bytes[4], bytes[5], bytes[6]).ok()?;
+    let records = u64::from_le_bytes(<[u8; 8]>::try_from(&bytes[7..15]).unwrap());
+    let capacity = len.saturating_sub(LEGACY_HEADER_SIZE) / LEGACY_RECORD_SIZE;
+    if records > capacity {
+        return None;
+    }
+    Some((origin, records))
+}
+
+/// Serialize a record as it is stored in a file without record checksums, see [`DbHeader::encode_record`].
+pub fn encode_record<V: RecordValue>(record: &RecordInfo<V>) -> Vec<u8> {
+    record.as_bytes()
+}
+
+/// Deserialize a record stored in a file, `None` if `bytes` holds less than a record.
+/// Its checksum, if the DB has them, is not checked, see [`DbHeader::decode_record`].
+/// The value is in its stored form: if the DB has transforms, they are not inverted.
+pub fn decode_record<V: RecordValue>(bytes: &[u8]) -> Option<RecordInfo<V>> {
+    RecordInfo::try_from(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PhysicalDB, Timestamp};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn read_file_with_format() {
+        let path = "format_layout.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for i in 0..3u16 {
+            db.append_record(RecordInfo {
+                time_offset: i as u32 * 10,
+                value: i * 1000,
+            })
+            .expect("could not append record.");
+        }
+        db.close().expect("could not close db.");
+
+        let bytes = fs::read(path).unwrap();
+        assert_eq!(bytes.len() as u64, record_position::<u16>(3));
+        assert_eq!(record_capacity::<u16>(bytes.len() as u64 + 5), 3);
+        let header = decode_header(&bytes).unwrap();
+        assert_eq!(header.origin_date, origin);
+        assert_eq!(header.records_number, 3);
+        assert_eq!(
+            &bytes[..RECORDS_START as usize],
+            &encode_header(&header)[..]
+        );
+        assert!(decode_header(&bytes[..10]).is_err());
+
+        let position = record_position::<u16>(2) as usize;
+        let record: RecordInfo<u16> = decode_record(&bytes[position..]).unwrap();
+        assert_eq!(record.time_offset, 20);
+        assert_eq!(record.value, 2000);
+        assert_eq!(encode_record(&record), &bytes[position..]);
+        assert_eq!(decode_record::<u16>(&bytes[position + 1..]), None);
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,155 @@
+//! A queue letting many threads append to a DB through a single writer thread.
+
+use crate::{DbHeader, DbOptions, PhysicalDB, RecordInfo, RecordValue, TSLiteError, Timestamp};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// A cloneable handle to send records to an [`Ingestor`] from any thread.
+#[derive(Debug)]
+pub struct IngestSender<V: RecordValue> {
+    sender: SyncSender<RecordInfo<V>>,
+    header: DbHeader,
+    options: DbOptions,
+}
+
+impl<V: RecordValue> Clone for IngestSender<V> {
+    fn clone(&self) -> IngestSender<V> {
+        IngestSender {
+            sender: self.sender.clone(),
+            header: self.header,
+            options: self.options.clone(),
+        }
+    }
+}
+
+impl<V: RecordValue> IngestSender<V> {
+    /// Queue a record, blocking while the queue is full.
+    /// The record is validated right away, but written later: write errors are reported by [`Ingestor::finish`],
+    /// and once the writer stopped every call fails.
+    pub fn send(&self, time: Timestamp, value: V) -> Result<(), TSLiteError> {
+        let time_offset = self.header.checked_offset(&time)?;
+        if let Some(skew) = self.options.max_future_skew {
+            if time > self.options.now().add_seconds(skew.as_secs() as i64) {
+                return Err(TSLiteError::TooFarInFuture);
+            }
+        }
+        self.sender
+            .send(RecordInfo { time_offset, value })
+            .map_err(|_| TSLiteError::Backend("The ingestion writer stopped.".to_string()))
+    }
+}
+
+/// Owns a DB and appends the records sent to it from a dedicated thread.
+///
+/// Records are queued in a bounded channel, so producers are slowed down when the disk can't keep up.
+/// The writer commits every record waiting in the queue at once, with a single sync.
+#[derive(Debug)]
+pub struct Ingestor<V: RecordValue> {
+    sender: IngestSender<V>,
+    writer: JoinHandle<Result<PhysicalDB<V>, TSLiteError>>,
+}
+
+impl<V: RecordValue + Send + 'static> Ingestor<V> {
+    /// Move `db` to a writer thread, with a queue of at most `capacity` records (at least 1).
+    pub fn spawn(db: PhysicalDB<V>, capacity: usize) -> Ingestor<V> {
+        let capacity = capacity.max(1);
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let sender = IngestSender {
+            sender,
+            header: db.header,
+            options: db.options.clone(),
+        };
+        let writer = thread::spawn(move || write_loop(db, receiver, capacity));
+        Ingestor { sender, writer }
+    }
+
+    /// A handle for another producer.
+    pub fn sender(&self) -> IngestSender<V> {
+        self.sender.clone()
+    }
+
+    /// Queue a record, see [`IngestSender::send`].
+    pub fn send(&self, time: Timestamp, value: V) -> Result<(), TSLiteError> {
+        self.sender.send(time, value)
+    }
+
+    /// Wait for every queued record to be written and give the DB back.
+    /// Blocks until every [`IngestSender`] is dropped. Fail with the error that stopped the writer, if any.
+    pub fn finish(self) -> Result<PhysicalDB<V>, TSLiteError> {
+        drop(self.sender);
+        self.writer
+            .join()
+            .map_err(|_| TSLiteError::Backend("The ingestion writer panicked.".to_string()))?
+    }
+}
+
+fn write_loop<V: RecordValue>(
+    mut db: PhysicalDB<V>,
+    receiver: Receiver<RecordInfo<V>>,
+    capacity: usize,
+) -> Result<PhysicalDB<V>, TSLiteError> {
+    let mut batch = Vec::with_capacity(capacity);
+    while let Ok(record) = receiver.recv() {
+        batch.push(record);
+        while batch.len() < capacity {
+            match receiver.try_recv() {
+                Ok(record) => batch.push(record),
+                Err(_) => break,
+            }
+        }
+        // Producers race each other, keep the batch in chronological order.
+        batch.sort_by_key(|r| r.time_offset);
+        db.append_records(&batch)?;
+        batch.clear();
+    }
+
+    Ok(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn concurrent_producers() {
+        let path = "ingestor.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        let ingestor = Ingestor::spawn(db, 16);
+        assert_eq!(
+            ingestor.send(origin.add_seconds(-1), 0),
+            Err(TSLiteError::BeforeOrigin)
+        );
+
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let sender = ingestor.sender();
+                thread::spawn(move || {
+                    for i in 0..250 {
+                        sender
+                            .send(origin.add_seconds(i * 4 + p), p as u8)
+                            .expect("could not send record.");
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let mut db = ingestor.finish().expect("could not write records.");
+        assert_eq!(db.read_header().unwrap().records_number, 1000);
+        let records = db
+            .records(origin, origin.add_seconds(1000))
+            .expect("could not read range.");
+        assert_eq!(records.len(), 1000);
+        assert_eq!(records.iter().filter(|r| r.value == 3).count(), 250);
+
+        let _ = fs::remove_file(path);
+    }
+}
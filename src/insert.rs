@@ -0,0 +1,303 @@
+//! Inserting records at their place in time rather than at the end of the file, and upserting them.
+
+use crate::{
+    iter, telemetry, write_at, PhysicalDB, RecordInfo, RecordValue, TSLiteError, RECORDS_START,
+};
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Insert a record after the last record not posterior to it, so the records stay chronologically ordered
+    /// without [`PhysicalDB::reorder_record`]. Return the index it was inserted at.
+    ///
+    /// The position is found with a binary search, and only the records after it are moved, by chunks.
+    /// A record posterior to every other one is appended like with [`PhysicalDB::append_record`].
+    /// Once a ring DB is full, its oldest record is dropped to make room and handed to the eviction callback,
+    /// see [`PhysicalDB::on_evict`]: the records before the position are moved instead. Inserting a record
+    /// anterior to every record of a full ring fails with `InvalidParameter`, as it would be dropped right away.
+    /// The insertion is not journaled: if the process stops in the middle, a record can be left twice in the
+    /// file, which [`PhysicalDB::check_db_file`] reports if it breaks the order.
+    pub fn insert_record(&mut self, record: RecordInfo<V>) -> Result<u64, TSLiteError> {
+        self.flush()?;
+        if self.file.is_none() {
+            self.open()?;
+        }
+        self.refresh_if_changed()?;
+        self.check_stale()?;
+        self.check_future_skew(record.time_offset)?;
+
+        let total = self.header.records_number;
+        let position = match record.time_offset.checked_add(1) {
+            Some(after) => self.first_not_before(after)?,
+            None => total,
+        };
+        if position == total {
+            self.append_record(record)?;
+            return Ok(self.header.records_number - 1);
+        }
+        if self.header.ring_capacity() == Some(total) {
+            return self.insert_in_full_ring(record, position);
+        }
+
+        // Records are moved with their quantized values, so the delta transform can be re-applied at their
+        // new index. Each chunk is written where it was read, shifted by the record carried from the chunk
+        // before, so no record is overwritten before it is read.
+        self.unroll_ring()?;
+        let header = self.header;
+        let transforms = header.transforms;
+        let size = header.record_size::<V>() as usize;
+        let mut source_chain = self.chain_before(position)?;
+        let mut destination_chain = source_chain;
+        let mut carried = RecordInfo {
+            time_offset: record.time_offset,
+            value: transforms.quantize(record.value),
+        };
+        let mut buffer = vec![0; iter::CHUNK_RECORDS * size];
+        let mut index = position;
+        while index < total {
+            let n = self.read_records_into(index, &mut buffer)?;
+            for (i, bytes) in buffer[..n * size].chunks_mut(size).enumerate() {
+                let current = index + i as u64;
+                let stored = header.decode_record::<V>(current, bytes)?;
+                let quantized = transforms.undelta(current, stored.value, &mut source_chain);
+                let moved = RecordInfo {
+                    time_offset: carried.time_offset,
+                    value: transforms.delta(current, carried.value, &mut destination_chain),
+                };
+                bytes.copy_from_slice(&header.encode_record(&moved));
+                carried = RecordInfo {
+                    time_offset: stored.time_offset,
+                    value: quantized,
+                };
+            }
+            let file = self.file.as_ref().unwrap();
+            write_at(
+                file,
+                &buffer[..n * size],
+                RECORDS_START + index * size as u64,
+            )
+            .map_err(TSLiteError::Io)?;
+            index += n as u64;
+        }
+        let last = RecordInfo {
+            time_offset: carried.time_offset,
+            value: transforms.delta(total, carried.value, &mut destination_chain),
+        };
+        let file = self.file.as_ref().unwrap();
+        write_at(
+            file,
+            &header.encode_record(&last),
+            RECORDS_START + total * size as u64,
+        )
+        .and_then(|_| telemetry::sync_data(file))
+        .map_err(TSLiteError::Io)?;
+        self.header.records_number += 1;
+        self.write_header()?;
+        self.unordered_from = self
+            .unordered_from
+            .map(|i| if i >= position { i + 1 } else { i });
+
+        Ok(position)
+    }
+
+    /// Replace the value of the record at `time_offset`, or insert a record there with
+    /// [`PhysicalDB::insert_record`] if there is none, and return its index. Backfilling corrections this way
+    /// doesn't leave two records at the same date. If several records are at `time_offset`, the first one is
+    /// replaced.
+    pub fn upsert(&mut self, time_offset: u32, value: V) -> Result<u64, TSLiteError> {
+        self.flush()?;
+        self.refresh_if_changed()?;
+        let index = self.first_not_before(time_offset)?;
+        if index < self.header.records_number
+            && self.read_raw_record(index)?.time_offset == time_offset
+        {
+            self.update_record(index, value)?;
+            return Ok(index);
+        }
+        self.insert_record(RecordInfo { time_offset, value })
+    }
+
+    /// Insert a record before the record at `position` of a full ring, dropping its oldest record, and return
+    /// the index it was inserted at. Rings don't support the delta transform, so the records are moved as they
+    /// are stored.
+    fn insert_in_full_ring(
+        &mut self,
+        record: RecordInfo<V>,
+        position: u64,
+    ) -> Result<u64, TSLiteError> {
+        if position == 0 {
+            return Err(TSLiteError::InvalidParameter(
+                "The record is older than every record of the full ring.".to_string(),
+            ));
+        }
+        self.evict_overwritten(1)?;
+        self.unroll_ring()?;
+        let size = self.header.record_size::<V>() as usize;
+        let mut buffer = vec![0; iter::CHUNK_RECORDS * size];
+        let mut index = 1;
+        while index < position {
+            let n = self.read_records_into(index, &mut buffer)?;
+            let n = n.min((position - index) as usize);
+            let file = self.file.as_ref().unwrap();
+            write_at(
+                file,
+                &buffer[..n * size],
+                RECORDS_START + (index - 1) * size as u64,
+            )
+            .map_err(TSLiteError::Io)?;
+            index += n as u64;
+        }
+        self.write_record(position - 1, &record)?;
+        telemetry::sync_data(self.file.as_ref().unwrap()).map_err(TSLiteError::Io)?;
+
+        Ok(position - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DbIssue, DbOptions, PhysicalDB, RecordInfo, TSLiteError, Transforms};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    fn records(db: &mut PhysicalDB<u16>) -> Vec<(u32, u16)> {
+        db.iter()
+            .map(|r| r.map(|r| (r.time_offset, r.value)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn insert_in_order() {
+        let path = "insert.db";
+        let _ = fs::remove_file(path);
+
+        // The delta transform makes every moved record re-encoded, across a keyframe.
+        let options = DbOptions {
+            transforms: Transforms {
+                delta: true,
+                ..Transforms::default()
+            },
+            record_checksums: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), None, &options)
+                .expect("could not create db.");
+        let appended: Vec<RecordInfo<u16>> = (0..300)
+            .map(|i| RecordInfo {
+                time_offset: i * 10,
+                value: (i * 7 % 1000) as u16,
+            })
+            .collect();
+        db.append_records(&appended).unwrap();
+
+        let insert = |db: &mut PhysicalDB<u16>, time_offset: u32, value: u16| {
+            db.insert_record(RecordInfo { time_offset, value })
+                .expect("could not insert record.")
+        };
+        assert_eq!(insert(&mut db, 15, 1), 2);
+        assert_eq!(insert(&mut db, 0, 2), 1);
+        assert_eq!(insert(&mut db, 5000, 3), 302);
+        assert_eq!(insert(&mut db, 2995, 4), 302);
+
+        let mut expected: Vec<(u32, u16)> =
+            appended.iter().map(|r| (r.time_offset, r.value)).collect();
+        expected.insert(2, (15, 1));
+        expected.insert(1, (0, 2));
+        expected.push((5000, 3));
+        expected.insert(302, (2995, 4));
+        assert_eq!(records(&mut db), expected);
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn insert_in_full_ring() {
+        let path = "insert_ring.db";
+        let _ = fs::remove_file(path);
+
+        let options = DbOptions {
+            ring_capacity: Some(4),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), None, &options)
+                .expect("could not create db.");
+        for i in 0..6 {
+            db.append_record(RecordInfo {
+                time_offset: i * 10,
+                value: i as u16,
+            })
+            .unwrap();
+        }
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        db.on_evict(move |records| {
+            let mut sink = sink.lock().unwrap();
+            sink.extend(records.iter().map(|r| r.value));
+        });
+
+        let insert = |db: &mut PhysicalDB<u16>, time_offset: u32| {
+            db.insert_record(RecordInfo {
+                time_offset,
+                value: 99,
+            })
+        };
+        assert_eq!(insert(&mut db, 35), Ok(1));
+        assert_eq!(records(&mut db), vec![(30, 3), (35, 99), (40, 4), (50, 5)]);
+        assert_eq!(*evicted.lock().unwrap(), vec![2]);
+        // Anterior to every record kept by the ring.
+        assert!(matches!(
+            insert(&mut db, 5),
+            Err(TSLiteError::InvalidParameter(_))
+        ));
+        assert!(db.upsert(5, 1).is_err());
+        assert_eq!(records(&mut db), vec![(30, 3), (35, 99), (40, 4), (50, 5)]);
+        assert_eq!(*evicted.lock().unwrap(), vec![2]);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn upsert_by_offset() {
+        let path = "upsert.db";
+        let _ = fs::remove_file(path);
+
+        let options = DbOptions {
+            transforms: Transforms {
+                delta: true,
+                ..Transforms::default()
+            },
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), None, &options)
+                .expect("could not create db.");
+        for i in 0..5 {
+            db.append_record(RecordInfo {
+                time_offset: i * 10,
+                value: i as u16,
+            })
+            .unwrap();
+        }
+
+        assert_eq!(db.upsert(20, 200).unwrap(), 2);
+        assert_eq!(db.upsert(25, 250).unwrap(), 3);
+        assert_eq!(db.upsert(25, 251).unwrap(), 3);
+        assert_eq!(db.upsert(60, 600).unwrap(), 6);
+        assert_eq!(
+            records(&mut db),
+            vec![
+                (0, 0),
+                (10, 1),
+                (20, 200),
+                (25, 251),
+                (30, 3),
+                (40, 4),
+                (60, 600)
+            ]
+        );
+
+        let _ = fs::remove_file(path);
+    }
+}
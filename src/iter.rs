@@ -0,0 +1,248 @@
+//! Lazy iteration over the records of a DB, from either end, read from the file by chunks.
+
+use crate::{PhysicalDB, RecordInfo, RecordValue, TSLiteError};
+use std::collections::VecDeque;
+use std::iter::Rev;
+
+/// The number of records read from the file at once.
+pub(crate) const CHUNK_RECORDS: usize = 256;
+
+/// An iterator over the records of a DB in the order they are stored, see [`PhysicalDB::iter`].
+/// It can also be iterated from the end, see [`PhysicalDB::iter_rev`]. It stops after the first error.
+#[derive(Debug)]
+pub struct Records<'a, V: RecordValue = u8> {
+    db: &'a mut PhysicalDB<V>,
+    /// The range of the records not read yet, known once the iteration started.
+    next: u64,
+    back: Option<u64>,
+    /// The records read ahead from the front and from the back.
+    front_chunk: VecDeque<RecordInfo<V>>,
+    back_chunk: VecDeque<RecordInfo<V>>,
+    failed: bool,
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Iterate over every record, reading them from the file by chunks rather than one at a time.
+    /// The records are the ones of the DB when the iteration starts, with their values decoded as
+    /// [`PhysicalDB::read_record`] does.
+    pub fn iter(&mut self) -> Records<'_, V> {
+        Records {
+            db: self,
+            next: 0,
+            back: None,
+            front_chunk: VecDeque::new(),
+            back_chunk: VecDeque::new(),
+            failed: false,
+        }
+    }
+
+    /// Iterate over every record from the last one, reading them from the end of the file by chunks.
+    pub fn iter_rev(&mut self) -> Rev<Records<'_, V>> {
+        self.iter().rev()
+    }
+
+    /// The last `n` records, or every record if there are fewer, in the order they are stored.
+    /// Only the end of the file is read.
+    pub fn last_n(&mut self, n: usize) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+        let mut records = self
+            .iter_rev()
+            .take(n)
+            .collect::<Result<Vec<RecordInfo<V>>, TSLiteError>>()?;
+        records.reverse();
+        Ok(records)
+    }
+}
+
+impl<V: RecordValue> Records<'_, V> {
+    /// The end of the range of the records not read yet, reading the number of records on the first call.
+    fn back(&mut self) -> Result<u64, TSLiteError> {
+        match self.back {
+            Some(back) => Ok(back),
+            None => {
+                self.db.refresh_if_changed()?;
+                let back = self.db.header().records_number;
+                self.back = Some(back);
+                Ok(back)
+            }
+        }
+    }
+
+    /// Read and decode the `count` records from index `first`.
+    fn read_chunk(
+        &mut self,
+        first: u64,
+        count: usize,
+    ) -> Result<VecDeque<RecordInfo<V>>, TSLiteError> {
+        let header = *self.db.header();
+        let size = header.record_size::<V>() as usize;
+        let mut buffer = vec![0; count * size];
+        let n = self.db.read_records_into(first, &mut buffer)?;
+        if n != count {
+            return Err(TSLiteError::IndexOutOfBound);
+        }
+
+        let mut previous = self.db.chain_before(first)?;
+        buffer
+            .chunks(size)
+            .enumerate()
+            .map(|(i, bytes)| {
+                let index = first + i as u64;
+                let mut record = header.decode_record::<V>(index, bytes)?;
+                record.value = header.transforms.decode(index, record.value, &mut previous);
+                Ok(record)
+            })
+            .collect()
+    }
+
+    fn advance(&mut self) -> Result<Option<RecordInfo<V>>, TSLiteError> {
+        let back = self.back()?;
+        if self.front_chunk.is_empty() && self.next < back {
+            let count = CHUNK_RECORDS.min((back - self.next) as usize);
+            self.front_chunk = self.read_chunk(self.next, count)?;
+            self.next += count as u64;
+        }
+        Ok(self
+            .front_chunk
+            .pop_front()
+            .or_else(|| self.back_chunk.pop_front()))
+    }
+
+    fn advance_back(&mut self) -> Result<Option<RecordInfo<V>>, TSLiteError> {
+        let back = self.back()?;
+        if self.back_chunk.is_empty() && self.next < back {
+            let count = CHUNK_RECORDS.min((back - self.next) as usize);
+            let first = back - count as u64;
+            self.back_chunk = self.read_chunk(first, count)?;
+            self.back = Some(first);
+        }
+        Ok(self
+            .back_chunk
+            .pop_back()
+            .or_else(|| self.front_chunk.pop_back()))
+    }
+
+    /// Fuse the iteration after the first error.
+    fn fuse(
+        &mut self,
+        result: Result<Option<RecordInfo<V>>, TSLiteError>,
+    ) -> Option<Result<RecordInfo<V>, TSLiteError>> {
+        match result {
+            Ok(record) => record.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<V: RecordValue> Iterator for Records<'_, V> {
+    type Item = Result<RecordInfo<V>, TSLiteError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.advance();
+        self.fuse(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.back {
+            Some(back) if !self.failed => {
+                let left =
+                    (back - self.next) as usize + self.front_chunk.len() + self.back_chunk.len();
+                (left, Some(left))
+            }
+            _ => (0, None),
+        }
+    }
+}
+
+impl<V: RecordValue> DoubleEndedIterator for Records<'_, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.advance_back();
+        self.fuse(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, Timestamp, Transforms};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn iterate_by_chunks() {
+        let path = "iter_records.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            transforms: Transforms {
+                delta: true,
+                ..Transforms::default()
+            },
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        assert!(db.iter().next().is_none());
+
+        let count = CHUNK_RECORDS as u32 * 2 + 10;
+        for i in 0..count {
+            db.append_record(RecordInfo {
+                time_offset: i,
+                value: (i * 3 % 1000) as u16,
+            })
+            .expect("could not append record.");
+        }
+
+        let records: Vec<RecordInfo<u16>> = db.iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records.len(), count as usize);
+        for (i, r) in records.iter().enumerate() {
+            assert_eq!(r.time_offset, i as u32);
+            assert_eq!(r.value, (i * 3 % 1000) as u16);
+        }
+
+        let mut iter = db.iter();
+        iter.next();
+        assert_eq!(
+            iter.size_hint(),
+            (count as usize - 1, Some(count as usize - 1))
+        );
+        assert_eq!(db.iter().nth(300).unwrap().unwrap().value, 900);
+
+        let last: Vec<u32> = db
+            .iter_rev()
+            .take(3)
+            .map(|r| r.unwrap().time_offset)
+            .collect();
+        assert_eq!(last, vec![count - 1, count - 2, count - 3]);
+        let last = db.last_n(300).unwrap();
+        assert_eq!(last.len(), 300);
+        assert_eq!(last[0].time_offset, count - 300);
+        assert_eq!(last[0].value, ((count - 300) * 3 % 1000) as u16);
+        assert_eq!(last[299].time_offset, count - 1);
+        assert_eq!(db.last_n(count as usize + 1).unwrap().len(), count as usize);
+
+        // Both ends meet without skipping or repeating a record.
+        let mut iter = db.iter();
+        let mut seen = Vec::new();
+        while let Some(front) = iter.next() {
+            seen.push(front.unwrap().time_offset);
+            if let Some(back) = iter.next_back() {
+                seen.push(back.unwrap().time_offset);
+            }
+        }
+        seen.sort_unstable();
+        assert_eq!(seen, (0..count).collect::<Vec<u32>>());
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,120 @@
+//! Key/value labels describing a DB, such as `sensor=kitchen` or `unit=celsius`, so a file copied around
+//! between machines still tells what it holds.
+//!
+//! The labels are recorded in the header by an extension entry holding `key=value` lines, so they share the
+//! extension area with the other optional properties of the DB: a few dozen octets in total.
+
+use crate::{DbHeader, PhysicalDB, RecordValue, TSLiteError};
+
+/// The extension tag recording the labels.
+pub(crate) const TAG: u8 = 0xF4;
+
+impl DbHeader {
+    /// The labels of the DB, in the order they were first set.
+    pub fn labels(&self) -> Vec<(String, String)> {
+        let data = self.extensions.get(TAG).unwrap_or(&[]);
+        String::from_utf8_lossy(data)
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// The value of the label `key`.
+    pub fn label(&self, key: &str) -> Option<String> {
+        self.labels()
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Set the label `key` to `value`, replacing its previous value, and write the header.
+    /// Fail with `InvalidParameter` if the key is empty or holds a `=`, if the key or the value holds a line
+    /// break, or if the header has no room left for the label.
+    pub fn set_label(&mut self, key: &str, value: &str) -> Result<(), TSLiteError> {
+        if key.is_empty() || key.contains(['=', '\n']) || value.contains('\n') {
+            return Err(TSLiteError::InvalidParameter(format!(
+                "Invalid label {}={}.",
+                key, value
+            )));
+        }
+        self.refresh_if_changed()?;
+        let mut labels = self.header.labels();
+        match labels.iter_mut().find(|(k, _)| k == key) {
+            Some(label) => label.1 = value.to_string(),
+            None => labels.push((key.to_string(), value.to_string())),
+        }
+        self.write_labels(&labels)
+    }
+
+    /// Remove the label `key` and write the header, return whether there was one.
+    pub fn remove_label(&mut self, key: &str) -> Result<bool, TSLiteError> {
+        self.refresh_if_changed()?;
+        let mut labels = self.header.labels();
+        let count = labels.len();
+        labels.retain(|(k, _)| k != key);
+        if labels.len() == count {
+            return Ok(false);
+        }
+        self.write_labels(&labels)?;
+        Ok(true)
+    }
+
+    fn write_labels(&mut self, labels: &[(String, String)]) -> Result<(), TSLiteError> {
+        if labels.is_empty() {
+            return self.remove_extension(TAG).map(|_| ());
+        }
+        let lines: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        self.set_extension(TAG, lines.join("\n").as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PhysicalDB, TSLiteError};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn labels_in_header() {
+        let path = "labels.db";
+        let _ = fs::remove_file(path);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        db.set_label("sensor", "kitchen").unwrap();
+        db.set_label("unit", "celsius").unwrap();
+        db.set_label("sensor", "garage").unwrap();
+        for (key, value) in [("", "x"), ("a=b", "x"), ("a", "x\ny")].iter() {
+            assert!(matches!(
+                db.set_label(key, value),
+                Err(TSLiteError::InvalidParameter(_))
+            ));
+        }
+        assert!(db.set_label("notes", &"x".repeat(64)).is_err());
+        db.close().unwrap();
+
+        let mut db: PhysicalDB =
+            PhysicalDB::open_path(Path::new(path)).expect("could not open db.");
+        assert_eq!(
+            db.header().labels(),
+            vec![
+                ("sensor".to_string(), "garage".to_string()),
+                ("unit".to_string(), "celsius".to_string())
+            ]
+        );
+        assert_eq!(db.header().label("unit"), Some("celsius".to_string()));
+        assert_eq!(db.remove_label("sensor"), Ok(true));
+        assert_eq!(db.remove_label("sensor"), Ok(false));
+        assert_eq!(db.header().label("sensor"), None);
+        assert_eq!(db.remove_label("unit"), Ok(true));
+        assert_eq!(db.header().extensions.iter().count(), 0);
+
+        let _ = fs::remove_file(path);
+    }
+}
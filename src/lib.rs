@@ -1,12 +1,38 @@
 //! A very simple embedded time-serie database.
 //!
-//! Right now you can only store data that fit in one octet.
+//! Values are one octet by default, any fixed-size type implementing [`RecordValue`] can be stored instead,
+//! such as the wider integers and the `f32` and `f64` floats.
 //!
 //! All the operation are made directly on the DB file, so this can get very I/O intensive if you do a lot of operation.
 //! If you are going to push data and read data a lot, you really shouldn't use it directly.
 //!
 //! If you intend to do a lot of operation you should have an layer that will operate in-memory and periodically
-//! dump them to the filesystem.
+//! dump them to the filesystem. The `buffered` option of [`DbOptions`] does this for the appends: they are kept
+//! in memory until [`PhysicalDB::flush`]. [`MemDB`] also serves the reads from memory.
+//!
+//! # Features
+//!
+//! - `chrono` (default): conversions between [`Timestamp`] and `chrono::DateTime<Utc>`.
+//!   Without it, dates are handled with the crate's own Unix-seconds based arithmetic.
+//! - `analytics`: forecasting helpers such as `PhysicalDB::forecast`.
+//! - `signal`: `install_signal_handler`, which persists the DBs registered for shutdown on SIGTERM and SIGINT.
+//! - `metrics`: counters and histograms emitted through the `metrics` facade: `tslite_records_appended`,
+//!   `tslite_fsync_duration_seconds` and `tslite_corruptions_detected`.
+//! - `sqlite`: `SqliteSeries`, a [`TimeSeries`] stored in a blob of a SQLite database.
+//! - `stream`: `PhysicalDB::range_stream` and `PhysicalDB::follow_stream`, reading records as a `futures` `Stream`.
+//! - `failpoints`: activates the [`fail_points`] at the boundaries of the writes, configured with the `fail` crate.
+//! - `object-store`: `TieredSeries`, a [`TimeSeries`] keeping its older records as segments in an object storage.
+//! - `zstd`: `TieredSeries::set_compression`, compressing the segments with zstd. Enables `object-store`.
+//! - `chrono-tz`: DBs recording the timezone of their deployment, with `PhysicalDB::create_local`, calendar days
+//!   in local time with `PhysicalDB::local_days`, and exports dated in local time.
+//! - `datafusion`: `TsliteTable`, a DataFusion table over DB files, to query them in SQL.
+//! - `polars`: `PhysicalDB::to_polars`, the records of a range as a Polars `DataFrame`.
+//! - `arrow`: `PhysicalDB::to_arrow`, the records of a range as an Arrow `RecordBatch`.
+//! - `cli`: the `tslite` command-line tool, with `info`, `check`, `repair`, `dump` and `append` commands.
+//! - `serde`: `Serialize` and `Deserialize` for [`Timestamp`], [`RecordInfo`], [`DbHeader`] and [`DbIssue`].
+//! - `prometheus`: `latest_exposition` and `remote_read`, DB files backing a Prometheus scrape target or
+//!   remote-read endpoint.
+//! - `tokio`: `AsyncPhysicalDB`, a handle whose operations are `async`, running on the blocking thread pool of tokio.
 //!
 //! # DB encoding
 //!
@@ -19,48 +45,232 @@
 //! # File orga
 //!
 //! ```text
-//! +--------------------------------------------+
-//! | HEADER | RECORD1 | RECORD2 | RECORD3 | ... |
-//! +--------------------------------------------+
+//! +------------------------------------------------------------------------------+
+//! | HEADER | HEADER CRC | SHADOW HEADER | SHADOW CRC | RECORD1 | RECORD2 | ... |
+//! +------------------------------------------------------------------------------+
 //! ```
 //!
+//! The header is followed by a CRC32 of its bytes, then by a copy of itself and its CRC32.
+//! The primary copy is always written and synced first, so if a write is torn one of the two copies
+//! is still valid and the header can be recovered from it. Appends only update the primary copy, the
+//! shadow copy catches up when the DB is synced or closed.
+//!
 //! ```text
 //! +-------------------------------------------[HEADER]---------------------------------------------+
+//! |--------------------------[MAGIC]---------------------------|------------[VERSION]--------------|
+//! |                           "TSLT"                           |                                   |
+//! |                           32bit                            |               8bit                |
 //! |--------------------------[TIMESTAMP]------------------------|---------[RECORD COUNT]-----------|
 //! |      year      |  month |  day   |  hour  | minute | second |              64bit               |
 //! |     16bit      |  8bit  |  8bit  |  8bit  |  8bit  |  8bit  |                                  |
+//! |----[OFFSET UNIT]----|-------------------------------[TRANSFORMS]-------------------------------|
+//! |                     |    flags    |     clamp min     |     clamp max     |       scale       |
+//! |        8bit         |    8bit     |        f64        |        f64        |        f64        |
+//! |-----------------------------------------[EXTENSIONS]-----------------------------------------|
+//! | entries length |                  entries, then reserved octets set to zero                  |
+//! |     16bit      |                                  62 octets                                  |
 //! +------------------------------------------------------------------------------------------------+
 //! ```
 //!
+//! The magic tells a DB file from any other file, and the version is the one of the format the file is
+//! written in, [`format::FORMAT_VERSION`] for the files written by this version of the crate.
+//! The offset unit tells whether the time offsets of the records are microseconds, milliseconds, seconds, minutes
+//! or hours, see [`OffsetUnit`].
+//! The transforms flags tell which of delta, clamp and scale are enabled, see [`Transforms`].
+//! The extensions are tagged entries holding optional properties of the DB, see [`Extensions`].
+//! The tags from 0xF0 are reserved for the crate, the timescale of the offsets is one of them, see [`Timescale`].
+//! So is the number of octets of the values, [`RecordValue::WIDTH`] of the type they are stored as, recorded
+//! unless it is one, see [`DbHeader::value_width`].
+//!
 //! ```text
-//! +-------------------[RECORD]------------+
-//! |--------[TIME OFFSET]--------|-[VALUE]-|
-//! |            32bit            |   8bit  |
-//! +---------------------------------------+
+//! +---------------------[RECORD]---------------------+
+//! |--------[TIME OFFSET]--------|-------[VALUE]------|
+//! |            32bit            | RecordValue::WIDTH |
+//! +--------------------------------------------------+
 //! ```
+//!
+//! The records of a ring DB, see [`DbOptions::ring_capacity`], wrap around the end of the file: the slot of the
+//! oldest one is recorded in the extensions.
+//! So are the key/value labels describing a DB, see [`PhysicalDB::set_label`].
+//! With [`DbOptions::file_digest`], closing the DB writes a footer after the records: the magic `TSLD` and the
+//! SHA-256 of the octets before it, see [`PhysicalDB::verify_digest`].
+//!
+//! The [`format`](mod@format) module exposes the sizes and positions above, and the functions encoding and decoding them.
 
-extern crate chrono;
-
+#[cfg(feature = "chrono")]
 use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
-use std::string::String;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use std::cmp::{Ord, Ordering};
-
-/// A wrapper for various type of error that can occur within TSLite.
-#[derive(Debug, PartialEq)]
-pub enum TSLiteError {
-    IOError(String),
-    IndexOutOfBound,
-}
+use std::convert::TryFrom;
+
+#[cfg(feature = "tokio")]
+mod async_db;
+#[cfg(feature = "stream")]
+mod async_stream;
+mod blob;
+mod calendar;
+mod checksum;
+mod clock;
+mod codec;
+mod compact;
+mod convert;
+mod database;
+#[cfg(feature = "polars")]
+mod dataframe;
+mod digest;
+mod error;
+mod exporter;
+mod extension;
+mod extract;
+pub mod fail_points;
+#[cfg(feature = "analytics")]
+mod forecast;
+pub mod format;
+mod ingest;
+mod insert;
+mod iter;
+mod labels;
+mod lock;
+mod maintenance;
+mod memdb;
+mod merge;
+mod multiseries;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod promql;
+mod query;
+#[cfg(feature = "arrow")]
+mod record_batch;
+mod repair;
+mod retention;
+mod ring;
+mod rollup;
+mod series;
+mod shutdown;
+mod simulation;
+mod snapshot;
+mod sql;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+mod stream;
+#[cfg(feature = "datafusion")]
+mod table_provider;
+mod telemetry;
+#[cfg(feature = "object-store")]
+mod tiered;
+mod timescale;
+#[cfg(feature = "chrono-tz")]
+mod timezone;
+mod transform;
+mod tsdb;
+mod value;
+mod wal;
+mod watch;
+
+#[cfg(feature = "tokio")]
+pub use async_db::AsyncPhysicalDB;
+#[cfg(feature = "stream")]
+pub use async_stream::RecordStream;
+pub use blob::{BlobDB, BlobRecord, MAX_BLOB_SIZE};
+pub use checksum::CHECKSUM_SIZE;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use codec::{
+    block_records, decode_block, encode_block, encode_block_with, BlockRecords, Codec,
+};
+pub use convert::{convert, upgrade_legacy, ValueEncoding};
+pub use database::Database;
+pub use digest::DIGEST_FOOTER_SIZE;
+pub use error::TSLiteError;
+pub use exporter::{prometheus_text, DbMetrics};
+pub use extension::Extensions;
+#[cfg(feature = "analytics")]
+pub use forecast::{ForecastPoint, HoltWinters};
+pub use ingest::{IngestSender, Ingestor};
+pub use iter::Records;
+pub use maintenance::{MaintenanceEvent, Scheduler, SchedulerHandle, Task};
+pub use memdb::{FlushPolicy, MemDB};
+pub use multiseries::{MultiSeriesDB, SeriesValue};
+#[cfg(feature = "prometheus")]
+pub use prometheus::{
+    decode_read_request, latest_exposition, remote_read, LabelMatcher, MatchKind, RemoteReadQuery,
+};
+pub use promql::{Comparison, PromQuery, PromSample, RangeFunction};
+
+pub use query::{
+    cross_correlate, Crossing, CrossingDirection, Interval, LagCorrelation, Point, ThresholdReport,
+};
+pub use repair::RepairReport;
+pub use series::{Aggregation, Stats, TimeSeries};
+#[cfg(all(feature = "signal", unix))]
+pub use shutdown::install_signal_handler;
+pub use shutdown::{flush_and_close_all, SharedDB};
+pub use simulation::{Fault, SimDisk, SimSeries};
+pub use sql::SqlQuery;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteSeries;
+pub use stats::DbStats;
+pub use stream::ExportFormat;
+#[cfg(feature = "datafusion")]
+pub use table_provider::TsliteTable;
+#[cfg(feature = "object-store")]
+pub use tiered::{DirectoryStore, ObjectStore, TieredSeries};
+pub use timescale::{tai_utc_offset, Timescale};
+pub use transform::Transforms;
+pub use tsdb::{import_tsdb_block, ImportedSeries};
+pub use value::RecordValue;
+pub use watch::Watch;
+
+use format::{
+    EXTENSIONS_START, FORMAT_VERSION, HEADER_COPY_SIZE, HEADER_SIZE, MAGIC, PREAMBLE_SIZE,
+    RECORDS_START, TIME_OFFSET_SIZE,
+};
+
+/// A date before any record, to bound a range on its start.
+pub(crate) const EARLIEST: Timestamp = Timestamp {
+    year: 0,
+    month: 1,
+    day: 1,
+    hour: 0,
+    minute: 0,
+    second: 0,
+    nanosecond: 0,
+};
+
+/// A date past any record, to bound a range on its end.
+pub(crate) const LATEST: Timestamp = Timestamp {
+    year: u16::MAX,
+    month: 12,
+    day: 31,
+    hour: 23,
+    minute: 59,
+    second: 59,
+    nanosecond: 0,
+};
+
+/// How long to wait for another process to write the header of the DB file it just created, and how many times.
+const CREATION_WAIT: std::time::Duration = std::time::Duration::from_millis(10);
+const CREATION_WAIT_ATTEMPTS: u32 = 100;
+
+/// Size of a serialized timestamp.
+const TIMESTAMP_SIZE: usize = 7;
+
+/// Number of nanoseconds in a second.
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
 
 /// A way to store date and time in 56bits / 7 octets.
 /// There is no awareness of timezone, everything is assumed to be Utc+0.
+/// `nanosecond` is the fraction of the second, which the 7 octets don't hold: it is only kept by the records
+/// of a DB whose offset unit is shorter than a second, such as [`OffsetUnit::Milliseconds`].
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Timestamp {
     pub year: u16,
     pub month: u8,
@@ -68,8 +278,10 @@ pub struct Timestamp {
     pub hour: u8,
     pub minute: u8,
     pub second: u8,
+    pub nanosecond: u32,
 }
 
+#[cfg(feature = "chrono")]
 impl From<chrono::DateTime<Utc>> for Timestamp {
     fn from(d: chrono::DateTime<Utc>) -> Timestamp {
         Timestamp {
@@ -79,35 +291,37 @@ impl From<chrono::DateTime<Utc>> for Timestamp {
             hour: d.hour() as u8,
             minute: d.minute() as u8,
             second: d.second() as u8,
+            // chrono represents a leap second with more than a second of nanoseconds.
+            nanosecond: d.nanosecond().min(NANOS_PER_SECOND as u32 - 1),
         }
     }
 }
 
-impl From<&[u8]> for Timestamp {
-    fn from(d: &[u8]) -> Timestamp {
-        let mut reader = Cursor::new(d);
-        Timestamp {
-            year: reader.read_u16::<LittleEndian>().unwrap(),
-            month: reader.read_u8().unwrap(),
-            day: reader.read_u8().unwrap(),
-            hour: reader.read_u8().unwrap(),
-            minute: reader.read_u8().unwrap(),
-            second: reader.read_u8().unwrap(),
+/// Deserialize a date, without checking that it exists.
+/// Fail with `ShortRead` if `d` holds less than a serialized date.
+impl TryFrom<&[u8]> for Timestamp {
+    type Error = TSLiteError;
+
+    fn try_from(d: &[u8]) -> Result<Timestamp, TSLiteError> {
+        if d.len() < TIMESTAMP_SIZE {
+            return Err(TSLiteError::ShortRead("date".to_string()));
         }
+        let mut reader = Cursor::new(d);
+        Ok(Timestamp {
+            year: reader.read_u16::<LittleEndian>()?,
+            month: reader.read_u8()?,
+            day: reader.read_u8()?,
+            hour: reader.read_u8()?,
+            minute: reader.read_u8()?,
+            second: reader.read_u8()?,
+            nanosecond: 0,
+        })
     }
 }
 
 impl PartialOrd for Timestamp {
     fn partial_cmp(&self, other: &Timestamp) -> Option<Ordering> {
-        Some(
-            self.year
-                .cmp(&other.year)
-                .then(self.month.cmp(&other.month))
-                .then(self.day.cmp(&other.day))
-                .then(self.hour.cmp(&other.hour))
-                .then(self.minute.cmp(&other.minute))
-                .then(self.second.cmp(&other.second)),
-        )
+        Some(self.cmp(other))
     }
 }
 
@@ -120,19 +334,47 @@ impl Ord for Timestamp {
             .then(self.hour.cmp(&other.hour))
             .then(self.minute.cmp(&other.minute))
             .then(self.second.cmp(&other.second))
+            .then(self.nanosecond.cmp(&other.nanosecond))
+    }
+}
+
+/// Fail if the fields of the timestamp don't designate an existing date and time, see [`Timestamp::is_valid`].
+#[cfg(feature = "chrono")]
+impl TryFrom<&Timestamp> for DateTime<Utc> {
+    type Error = InvalidTimestamp;
+
+    fn try_from(t: &Timestamp) -> Result<DateTime<Utc>, InvalidTimestamp> {
+        if !t.is_valid() {
+            return Err(InvalidTimestamp);
+        }
+        let date = Utc
+            .with_ymd_and_hms(
+                t.year as i32,
+                t.month as u32,
+                t.day as u32,
+                t.hour as u32,
+                t.minute as u32,
+                t.second as u32,
+            )
+            .single()
+            .ok_or(InvalidTimestamp)?;
+        Ok(date + chrono::Duration::nanoseconds(t.nanosecond as i64))
     }
 }
 
-impl Into<DateTime<Utc>> for &Timestamp {
-    fn into(self) -> DateTime<Utc> {
-        Utc.ymd(self.year as i32, self.month as u32, self.day as u32)
-            .and_hms(self.hour as u32, self.minute as u32, self.second as u32)
+#[cfg(feature = "chrono")]
+impl TryFrom<Timestamp> for DateTime<Utc> {
+    type Error = InvalidTimestamp;
+
+    fn try_from(t: Timestamp) -> Result<DateTime<Utc>, InvalidTimestamp> {
+        DateTime::try_from(&t)
     }
 }
 
 impl Timestamp {
+    /// Serialize the timestamp on 7 octets, without its fraction of second.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut store: Vec<u8> = Vec::with_capacity(7);
+        let mut store: Vec<u8> = Vec::with_capacity(TIMESTAMP_SIZE);
         store.write_u16::<LittleEndian>(self.year).unwrap();
         store.push(self.month);
         store.push(self.day);
@@ -143,203 +385,870 @@ impl Timestamp {
     }
 
     /// Compute the number of second between two date.
+    /// The result wraps around if `date` is anterior or more than 136 years after `self`,
+    /// use [`DbHeader::checked_offset`] to compute the offset of a record.
     pub fn offset(&self, date: &Timestamp) -> u32 {
-        let me: DateTime<Utc> = self.into();
-        let other: DateTime<Utc> = date.into();
-        (other - me).num_seconds() as u32
+        self.signed_offset(date) as u32
+    }
+
+    /// Compute the number of second between two date, negative if `date` is anterior to `self`.
+    pub(crate) fn signed_offset(&self, date: &Timestamp) -> i64 {
+        date.unix_seconds() - self.unix_seconds()
+    }
+
+    /// The current date and time, with its fraction of second.
+    pub fn now() -> Timestamp {
+        let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_nanos() as i128,
+            Err(e) => -(e.duration().as_nanos() as i128),
+        };
+        Timestamp::from_unix_nanos(nanos)
+    }
+
+    /// Build a timestamp from a number of seconds since 1970-01-01 00:00:00 UTC.
+    pub fn from_unix(seconds: i64) -> Timestamp {
+        let (year, month, day) = calendar::civil_from_days(seconds.div_euclid(86_400));
+        let time = seconds.rem_euclid(86_400);
+        Timestamp {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time % 3600 / 60) as u8,
+            second: (time % 60) as u8,
+            nanosecond: 0,
+        }
+    }
+
+    /// Build a timestamp from a number of milliseconds since 1970-01-01 00:00:00 UTC.
+    pub fn from_unix_millis(millis: i64) -> Timestamp {
+        Timestamp::from_unix_nanos(millis as i128 * 1_000_000)
+    }
+
+    /// Build a timestamp from a number of microseconds since 1970-01-01 00:00:00 UTC.
+    pub fn from_unix_micros(micros: i64) -> Timestamp {
+        Timestamp::from_unix_nanos(micros as i128 * 1_000)
+    }
+
+    /// Build a timestamp from a number of nanoseconds since 1970-01-01 00:00:00 UTC.
+    pub(crate) fn from_unix_nanos(nanos: i128) -> Timestamp {
+        let seconds = nanos.div_euclid(NANOS_PER_SECOND as i128) as i64;
+        Timestamp {
+            nanosecond: nanos.rem_euclid(NANOS_PER_SECOND as i128) as u32,
+            ..Timestamp::from_unix(seconds)
+        }
+    }
+
+    /// The number of seconds since 1970-01-01 00:00:00 UTC, the fraction of second being dropped.
+    pub fn unix_seconds(&self) -> i64 {
+        let days = calendar::days_from_civil(self.year as i64, self.month as u32, self.day as u32);
+        days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    /// The number of milliseconds since 1970-01-01 00:00:00 UTC, rounded down.
+    pub fn unix_millis(&self) -> i64 {
+        self.unix_seconds() * 1000 + (self.nanosecond / 1_000_000) as i64
+    }
+
+    /// The number of microseconds since 1970-01-01 00:00:00 UTC, rounded down.
+    pub fn unix_micros(&self) -> i64 {
+        self.unix_seconds() * 1_000_000 + (self.nanosecond / 1_000) as i64
+    }
+
+    /// The number of nanoseconds since 1970-01-01 00:00:00 UTC.
+    pub(crate) fn unix_nanos(&self) -> i128 {
+        self.unix_seconds() as i128 * NANOS_PER_SECOND as i128 + self.nanosecond as i128
+    }
+
+    /// The timestamp `seconds` seconds after this one (or before if negative).
+    pub fn add_seconds(&self, seconds: i64) -> Timestamp {
+        Timestamp {
+            nanosecond: self.nanosecond,
+            ..Timestamp::from_unix(self.unix_seconds() + seconds)
+        }
+    }
+
+    /// The timestamp `millis` milliseconds after this one (or before if negative).
+    pub fn add_millis(&self, millis: i64) -> Timestamp {
+        Timestamp::from_unix_nanos(self.unix_nanos() + millis as i128 * 1_000_000)
+    }
+
+    /// The same date and time, on a whole second.
+    pub fn truncate_to_second(&self) -> Timestamp {
+        Timestamp {
+            nanosecond: 0,
+            ..*self
+        }
+    }
+
+    /// Build a timestamp, checking that it designates an existing date and time.
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Timestamp, InvalidTimestamp> {
+        let timestamp = Timestamp {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond: 0,
+        };
+        if !timestamp.is_valid() {
+            return Err(InvalidTimestamp);
+        }
+        Ok(timestamp)
+    }
+
+    /// Decode a serialized timestamp, checking that it is valid.
+    fn decode(d: &[u8]) -> Result<Timestamp, TSLiteError> {
+        let t = Timestamp::try_from(d)?;
+        Ok(Timestamp::new(
+            t.year, t.month, t.day, t.hour, t.minute, t.second,
+        )?)
     }
 
     /// Check if a date is valid.
+    #[cfg(feature = "chrono")]
     pub fn is_valid(&self) -> bool {
-        let mut valid = true;
-        valid &= 1 <= self.month && self.month <= 12;
-        valid &= 1 <= self.day;
-        valid &= self.hour < 24;
-        valid &= self.minute < 60;
-        valid &= self.second < 60;
-
-        // As usual, we have to handle febuary as an edge-case.
-        if self.month == 2 {
-            // We check if this year is a leap year
-            let factor = |x| self.year % x == 0;
-            let leap = factor(4) && (!factor(100) || factor(400));
-            if leap {
-                valid &= self.day <= 29;
-            } else {
-                valid &= self.day <= 28;
-            }
-        } else {
-            valid &=
-                (self.month % 2 == 0 && self.day <= 30) || (self.month % 2 == 1 && self.day <= 31);
-        }
+        (self.nanosecond as i64) < NANOS_PER_SECOND
+            && chrono::NaiveDate::from_ymd_opt(self.year as i32, self.month as u32, self.day as u32)
+                .and_then(|d| {
+                    d.and_hms_opt(self.hour as u32, self.minute as u32, self.second as u32)
+                })
+                .is_some()
+    }
+
+    /// Check if a date is valid.
+    /// Any out of range field is normalized away by the Unix-seconds round trip, so it won't compare equal.
+    #[cfg(not(feature = "chrono"))]
+    pub fn is_valid(&self) -> bool {
+        self.month <= 12 && Timestamp::from_unix_nanos(self.unix_nanos()) == *self
+    }
+}
 
-        valid
+/// The fields of a [`Timestamp`] do not designate an existing date and time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InvalidTimestamp;
+
+impl From<InvalidTimestamp> for TSLiteError {
+    fn from(_: InvalidTimestamp) -> TSLiteError {
+        TSLiteError::InvalidTimestamp
     }
 }
 
 /// Represent an entry in the database.
 /// `time_offset` represent the number of seconds passed since the origin date of the DB.
 /// It's a u32, which means you should be able to store record up to 136 years after the origin date of the DB.
+/// `value` can be of any type implementing [`RecordValue`], one octet by default.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub struct RecordInfo {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordInfo<V: RecordValue = u8> {
     pub time_offset: u32,
-    pub value: u8,
+    pub value: V,
 }
 
-impl From<&[u8]> for RecordInfo {
-    fn from(d: &[u8]) -> RecordInfo {
-        let mut reader = Cursor::new(d);
-        RecordInfo {
-            time_offset: reader.read_u32::<LittleEndian>().unwrap(),
-            value: reader.read_u8().unwrap(),
+/// Deserialize a record, fail with `ShortRead` if `d` holds less than [`RecordInfo::SIZE`] octets.
+impl<V: RecordValue> TryFrom<&[u8]> for RecordInfo<V> {
+    type Error = TSLiteError;
+
+    fn try_from(d: &[u8]) -> Result<RecordInfo<V>, TSLiteError> {
+        if (d.len() as u64) < RecordInfo::<V>::SIZE {
+            return Err(TSLiteError::ShortRead("record".to_string()));
         }
+        let mut reader = Cursor::new(d);
+        Ok(RecordInfo {
+            time_offset: reader.read_u32::<LittleEndian>()?,
+            value: V::decode(&d[TIME_OFFSET_SIZE as usize..RecordInfo::<V>::SIZE as usize]),
+        })
     }
 }
 
-impl PartialOrd for RecordInfo {
-    fn partial_cmp(&self, other: &RecordInfo) -> Option<Ordering> {
-        Some(self.time_offset.cmp(&other.time_offset))
+impl<V: RecordValue + Eq> PartialOrd for RecordInfo<V> {
+    fn partial_cmp(&self, other: &RecordInfo<V>) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl Ord for RecordInfo {
+impl<V: RecordValue + Eq> Ord for RecordInfo<V> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.time_offset.cmp(&other.time_offset)
     }
 }
 
-impl RecordInfo {
+impl<V: RecordValue> RecordInfo<V> {
+    /// Size of a serialized record.
+    pub const SIZE: u64 = TIME_OFFSET_SIZE + V::WIDTH as u64;
+
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut store: Vec<u8> = Vec::with_capacity(4 + 1); // 4 time_offset, 1 value
-        store.write_u32::<LittleEndian>(self.time_offset).unwrap();
-        store.write_u8(self.value).unwrap();
+        let mut store: Vec<u8> = vec![0; RecordInfo::<V>::SIZE as usize];
+        (&mut store[..TIME_OFFSET_SIZE as usize])
+            .write_u32::<LittleEndian>(self.time_offset)
+            .unwrap();
+        self.value.encode(&mut store[TIME_OFFSET_SIZE as usize..]);
         store
     }
 }
 
+/// The unit of the time offsets of the records of a DB.
+/// With a coarser unit, the 32bit offsets cover a longer time: about 136 years with seconds, 8000 years with
+/// minutes and 490000 years with hours, but only 49 days with milliseconds and 71 minutes with microseconds.
+/// Dates are rounded down to the unit when records are appended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OffsetUnit {
+    #[default]
+    Seconds,
+    Minutes,
+    Hours,
+    /// Records keep the milliseconds of their dates. Files using it can't be read by the versions before it.
+    Milliseconds,
+    /// Records keep the microseconds of their dates. Files using it can't be read by the versions before it.
+    Microseconds,
+}
+
+impl OffsetUnit {
+    /// The number of whole seconds in one unit, 0 for the units shorter than a second.
+    pub fn seconds(self) -> i64 {
+        self.nanoseconds() / NANOS_PER_SECOND
+    }
+
+    /// The number of nanoseconds in one unit.
+    pub fn nanoseconds(self) -> i64 {
+        match self {
+            OffsetUnit::Seconds => NANOS_PER_SECOND,
+            OffsetUnit::Minutes => 60 * NANOS_PER_SECOND,
+            OffsetUnit::Hours => 3600 * NANOS_PER_SECOND,
+            OffsetUnit::Milliseconds => 1_000_000,
+            OffsetUnit::Microseconds => 1_000,
+        }
+    }
+
+    /// The length of one unit, in seconds.
+    pub fn as_secs_f64(self) -> f64 {
+        self.nanoseconds() as f64 / NANOS_PER_SECOND as f64
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            OffsetUnit::Seconds => 0,
+            OffsetUnit::Minutes => 1,
+            OffsetUnit::Hours => 2,
+            OffsetUnit::Milliseconds => 3,
+            OffsetUnit::Microseconds => 4,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<OffsetUnit> {
+        match id {
+            0 => Some(OffsetUnit::Seconds),
+            1 => Some(OffsetUnit::Minutes),
+            2 => Some(OffsetUnit::Hours),
+            3 => Some(OffsetUnit::Milliseconds),
+            4 => Some(OffsetUnit::Microseconds),
+            _ => None,
+        }
+    }
+}
+
+/// A record resolved against the origin of its DB, as returned by the range APIs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Record<V: RecordValue = u8> {
+    pub time: Timestamp,
+    pub value: V,
+}
+
+#[cfg(feature = "chrono")]
+impl<V: RecordValue> Record<V> {
+    /// The date of the record as a chrono `DateTime`, fail if it was not resolved from a DB and is invalid.
+    pub fn datetime(&self) -> Result<DateTime<Utc>, InvalidTimestamp> {
+        DateTime::try_from(&self.time)
+    }
+}
+
+impl<V: RecordValue> RecordInfo<V> {
+    /// Resolve the time offset of the record against the origin and offset unit of its DB.
+    pub fn resolve(&self, header: &DbHeader) -> Record<V> {
+        Record {
+            time: header.offset_to_date(self.time_offset),
+            value: self.value,
+        }
+    }
+
+    /// The date of the record as a chrono `DateTime`, resolved against the origin and offset unit of its DB.
+    #[cfg(feature = "chrono")]
+    pub fn absolute_time(&self, header: &DbHeader) -> DateTime<Utc> {
+        header.offset_to_datetime(self.time_offset)
+    }
+}
+
 /// The header of a DB file.
 /// `origin_date` is the date that will be use has the origin. The DB *cannot* contain any record anterior to this date.
+/// `offset_unit` is the unit of the time offsets of the records.
+/// `transforms` are applied to the values of the records, see [`Transforms`].
+/// `extensions` hold optional properties of the DB, see [`Extensions`].
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DbHeader {
     pub origin_date: Timestamp,
     pub records_number: u64,
+    pub offset_unit: OffsetUnit,
+    pub transforms: Transforms,
+    pub extensions: Extensions,
 }
 
-impl From<&[u8]> for DbHeader {
-    fn from(d: &[u8]) -> DbHeader {
-        let timestamp = Timestamp::from(d);
-        let mut reader = Cursor::new(d);
-        reader.set_position(7);
-        DbHeader {
-            origin_date: timestamp,
-            records_number: reader.read_u64::<LittleEndian>().unwrap(),
+/// Deserialize a header, without its checksum. Its magic, format version and origin date are not checked.
+/// Fail with `ShortRead` if `d` holds less than [`HEADER_SIZE`] octets.
+impl TryFrom<&[u8]> for DbHeader {
+    type Error = TSLiteError;
+
+    fn try_from(d: &[u8]) -> Result<DbHeader, TSLiteError> {
+        if (d.len() as u64) < HEADER_SIZE {
+            return Err(TSLiteError::ShortRead("header".to_string()));
         }
+        // The fields following the magic and the format version.
+        let fields = &d[PREAMBLE_SIZE as usize..];
+        let timestamp = Timestamp::try_from(fields)?;
+        let mut reader = Cursor::new(fields);
+        reader.set_position(TIMESTAMP_SIZE as u64);
+        Ok(DbHeader {
+            origin_date: timestamp,
+            records_number: reader.read_u64::<LittleEndian>()?,
+            offset_unit: OffsetUnit::from_id(fields[15]).unwrap_or_default(),
+            transforms: Transforms::from_bytes(&fields[16..]),
+            extensions: Extensions::from_bytes(&d[EXTENSIONS_START as usize..]).unwrap_or_default(),
+        })
     }
 }
 
 impl DbHeader {
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut store: Vec<u8> = Vec::with_capacity(7 + 8); // 7 for timestamp, 8 for record number.
+        let mut store: Vec<u8> = Vec::with_capacity(HEADER_SIZE as usize);
+        store.extend_from_slice(MAGIC);
+        store.push(FORMAT_VERSION);
         store.extend(self.origin_date.as_bytes());
         store
             .write_u64::<LittleEndian>(self.records_number)
             .unwrap();
+        store.push(self.offset_unit.id());
+        store.extend(self.transforms.as_bytes());
+        store.extend(self.extensions.as_bytes());
+        store
+    }
+
+    /// Check that the values of the DB are `V` values, by their width.
+    /// Fail with `ValueWidthMismatch` otherwise.
+    pub(crate) fn check_width<V: RecordValue>(&self) -> Result<(), TSLiteError> {
+        if self.value_width() as usize != V::WIDTH {
+            return Err(TSLiteError::ValueWidthMismatch(self.value_width()));
+        }
+        Ok(())
+    }
+
+    /// The timescale of the time offsets, recorded in the extensions. UTC unless the DB was created with another one.
+    pub fn timescale(&self) -> Timescale {
+        Timescale::from_extensions(&self.extensions).unwrap_or_default()
+    }
+
+    /// The number of nanoseconds between the origin and `date` on the timescale of the DB, negative if `date`
+    /// is anterior.
+    pub(crate) fn elapsed(&self, date: &Timestamp) -> i128 {
+        let timescale = self.timescale();
+        let seconds = timescale.seconds(date) - timescale.seconds(&self.origin_date);
+        seconds as i128 * NANOS_PER_SECOND as i128 + date.nanosecond as i128
+            - self.origin_date.nanosecond as i128
+    }
+
+    /// The time offset of `date` in the unit of the DB, rounded down, negative if `date` is anterior to the origin.
+    pub fn date_to_offset(&self, date: &Timestamp) -> i64 {
+        self.elapsed(date)
+            .div_euclid(self.offset_unit.nanoseconds() as i128) as i64
+    }
+
+    /// The time offset of a record appended at `date`.
+    /// Fail with `BeforeOrigin` if `date` is anterior to the origin, and with `OffsetOverflow` if it is too far from it.
+    pub fn checked_offset(&self, date: &Timestamp) -> Result<u32, TSLiteError> {
+        let offset = self.date_to_offset(date);
+        if offset < 0 {
+            return Err(TSLiteError::BeforeOrigin);
+        }
+        if offset > u32::MAX as i64 {
+            return Err(TSLiteError::OffsetOverflow);
+        }
+        Ok(offset as u32)
+    }
+
+    /// The smallest time offset whose date is not anterior to `date`.
+    pub(crate) fn offset_not_before(&self, date: &Timestamp) -> i64 {
+        -(-self.elapsed(date)).div_euclid(self.offset_unit.nanoseconds() as i128) as i64
+    }
+
+    /// Resolve a time offset into an absolute date.
+    /// On the TAI timescale, an offset within a leap second resolves to the second before it, see [`DbHeader::is_leap_second`].
+    pub fn offset_to_date(&self, time_offset: u32) -> Timestamp {
+        self.resolve_offset(time_offset).0
+    }
+
+    /// Resolve a time offset into a chrono `DateTime`, see [`DbHeader::offset_to_date`].
+    #[cfg(feature = "chrono")]
+    pub fn offset_to_datetime(&self, time_offset: u32) -> DateTime<Utc> {
+        // A resolved date is normalized from a number of seconds, so its fields are always in range.
+        DateTime::try_from(&self.offset_to_date(time_offset)).expect("resolved dates are valid")
+    }
+
+    /// The time offset of a record appended at the chrono `DateTime` `date`, see [`DbHeader::checked_offset`].
+    #[cfg(feature = "chrono")]
+    pub fn datetime_to_offset(&self, date: &DateTime<Utc>) -> Result<u32, TSLiteError> {
+        self.checked_offset(&Timestamp::from(*date))
+    }
+
+    /// Whether a time offset falls within a leap second, which can only happen on the TAI timescale.
+    pub fn is_leap_second(&self, time_offset: u32) -> bool {
+        self.resolve_offset(time_offset).1
+    }
+
+    fn resolve_offset(&self, time_offset: u32) -> (Timestamp, bool) {
+        let timescale = self.timescale();
+        let nanos = time_offset as i128 * self.offset_unit.nanoseconds() as i128
+            + self.origin_date.nanosecond as i128;
+        let seconds = nanos.div_euclid(NANOS_PER_SECOND as i128) as i64;
+        let (date, leap) = timescale.date(timescale.seconds(&self.origin_date) + seconds);
+        let date = Timestamp {
+            nanosecond: nanos.rem_euclid(NANOS_PER_SECOND as i128) as u32,
+            ..date
+        };
+        (date, leap)
+    }
+
+    /// Serialize the header followed by its CRC32.
+    fn as_checked_bytes(&self) -> Vec<u8> {
+        let mut store = self.as_bytes();
+        let crc = crc32fast::hash(&store);
+        store.write_u32::<LittleEndian>(crc).unwrap();
         store
     }
+
+    /// Deserialize a header followed by its CRC32, return `None` if the checksum doesn't match, if it lacks the magic,
+    /// or if its format version or offset unit is unknown.
+    /// Fail if the checksum matches but the origin date is invalid.
+    fn from_checked_bytes(d: &[u8]) -> Result<Option<DbHeader>, TSLiteError> {
+        let (data, crc) = d.split_at(HEADER_SIZE as usize);
+        let crc = Cursor::new(crc).read_u32::<LittleEndian>().unwrap();
+        if crc32fast::hash(data) != crc
+            || !data.starts_with(MAGIC)
+            || data[MAGIC.len()] != FORMAT_VERSION
+        {
+            return Ok(None);
+        }
+        let fields = &data[PREAMBLE_SIZE as usize..];
+        let extensions = Extensions::from_bytes(&data[EXTENSIONS_START as usize..]);
+        if OffsetUnit::from_id(fields[15]).is_none()
+            || extensions
+                .filter(|e| value::width_from_extensions(e).is_some())
+                .and_then(|e| Timescale::from_extensions(&e))
+                .is_none()
+        {
+            // Written by a newer version, or damaged in a way the checksum missed.
+            return Ok(None);
+        }
+        Timestamp::decode(fields)?;
+        DbHeader::try_from(data).map(Some)
+    }
+}
+
+/// Which copy of the header could be read from the file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum HeaderCopy {
+    Primary,
+    Shadow,
+}
+
+/// Read both copies of the header from `file` and return the first one that matches its checksum.
+fn read_checked_header(mut file: &File) -> Result<(DbHeader, HeaderCopy), TSLiteError> {
+    file.seek(SeekFrom::Start(0)).map_err(TSLiteError::Io)?;
+    let mut buffer = Vec::with_capacity(RECORDS_START as usize);
+    file.take(RECORDS_START)
+        .read_to_end(&mut buffer)
+        .map_err(TSLiteError::Io)?;
+    if !buffer.starts_with(MAGIC) {
+        let len = file.metadata().map_err(TSLiteError::Io)?.len();
+        if format::decode_legacy_header(&buffer, len).is_some() {
+            return Err(TSLiteError::UnsupportedVersion(format::LEGACY_VERSION));
+        }
+    }
+    if (buffer.len() as u64) < RECORDS_START {
+        if !buffer.starts_with(MAGIC) {
+            return Err(TSLiteError::NotADatabase);
+        }
+        return Err(TSLiteError::ShortRead("header".to_string()));
+    }
+    checked_header(&buffer)
+}
+
+/// Return the first copy of the header in `buffer` that matches its checksum.
+/// `buffer` holds the primary and shadow copies, as they are at the start of a DB file.
+pub(crate) fn checked_header(buffer: &[u8]) -> Result<(DbHeader, HeaderCopy), TSLiteError> {
+    let (primary, shadow) = buffer[..RECORDS_START as usize].split_at(HEADER_COPY_SIZE as usize);
+    if let Some(header) = DbHeader::from_checked_bytes(primary)? {
+        return Ok((header, HeaderCopy::Primary));
+    }
+    telemetry::corruption_detected();
+    if let Some(header) = DbHeader::from_checked_bytes(shadow)? {
+        return Ok((header, HeaderCopy::Shadow));
+    }
+
+    // Tell a file written by another version, or not by tslite at all, from a damaged one.
+    match [primary, shadow]
+        .iter()
+        .find(|copy| copy.starts_with(MAGIC))
+    {
+        Some(copy) if copy[MAGIC.len()] != FORMAT_VERSION => {
+            Err(TSLiteError::UnsupportedVersion(copy[MAGIC.len()]))
+        }
+        Some(_) => Err(TSLiteError::HeaderCorrupted),
+        None => Err(TSLiteError::NotADatabase),
+    }
 }
 
 /// Potential Issue in the DB file
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DbIssue {
-    /// If a record is not properly chonologicaly ordered.
-    UnorderedRecord,
-    /// If the header is corrupted (cannot be fully read or data are wrong).
+    /// If a record is not properly chonologicaly ordered, with the index of the record anterior to the one before it.
+    UnorderedRecord(u64),
+    /// If the header is corrupted (cannot be fully read or does not match its checksum).
+    /// If only the primary copy is damaged, [`PhysicalDB::refresh`] restores it from the shadow copy.
     HeaderCorrupted,
     /// If the date of the DB is invalid.
     OriginDateInvalid,
     /// If a record is corrupted (cannot be fully read or data are wrong) with its index.
     RecordCorrupted(u64),
     /// If the number of record in the header doesn't match the amount that can be read from the physical file.
+    /// [`PhysicalDB::repair`] fixes it.
     MismatchRecordAmount,
     /// Indicate that there is no known issue
     None,
 }
 
-/// a DB in file
+/// The length and modification date of the DB file, used to notice when another process changed it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Options used when creating a DB.
+#[derive(Debug, Clone, Default)]
+pub struct DbOptions {
+    /// Overwrite the file if one already exists at the DB path, `false` by default.
+    pub overwrite: bool,
+    /// Reorder the records in [`PhysicalDB::close`] if some were appended out of order since the DB was opened,
+    /// `false` by default.
+    pub sort_on_close: bool,
+    /// The unit of the time offsets, recorded in the header. Seconds by default.
+    pub offset_unit: OffsetUnit,
+    /// The transforms applied to the values, recorded in the header. None by default.
+    pub transforms: Transforms,
+    /// The timescale of the time offsets, recorded in the header. UTC by default.
+    pub timescale: Timescale,
+    /// The timezone of the deployment, recorded in the header. The origin and the offsets are still in UTC,
+    /// the timezone is used for the calendar days and the exports. `None` by default.
+    #[cfg(feature = "chrono-tz")]
+    pub timezone: Option<chrono_tz::Tz>,
+    /// Reject the records dated more than this after the current time with `TSLiteError::TooFarInFuture`.
+    /// Not recorded in the file, `None` (no limit) by default.
+    pub max_future_skew: Option<std::time::Duration>,
+    /// The source of the current time, used for the default origin, [`PhysicalDB::append_record_now`]
+    /// and `max_future_skew`. Not recorded in the file, `None` (the system clock) by default.
+    pub clock: Option<std::sync::Arc<dyn Clock>>,
+    /// Keep the appended records in memory until [`PhysicalDB::flush`] or [`PhysicalDB::close`] writes them
+    /// at once. They are not seen by the reads until then. Dropping the DB writes them too, but ignores any error:
+    /// close it to know they were written.
+    /// Not recorded in the file, `false` by default.
+    pub buffered: bool,
+    /// Journal every append before writing it, so one interrupted by a crash is replayed when the DB is opened,
+    /// at the cost of a second sync per append. See [`PhysicalDB::new`]. Not recorded in the file, `false` by default.
+    pub wal: bool,
+    /// Follow every record with its CRC32, so damaged records are detected when they are read.
+    /// Recorded in the header, `false` by default.
+    pub record_checksums: bool,
+    /// Keep at most this many records, each append overwriting the oldest record once the DB is full.
+    /// Not supported with the delta transform and the journal. Recorded in the header, `None` by default.
+    pub ring_capacity: Option<u64>,
+    /// Write a SHA-256 of the header and the records after them when the DB is closed, see
+    /// [`PhysicalDB::verify_digest`]. Recorded in the header, `false` by default.
+    pub file_digest: bool,
+}
+
+impl DbOptions {
+    /// The current time according to the clock of the options.
+    pub(crate) fn now(&self) -> Timestamp {
+        self.clock.as_ref().map_or_else(Timestamp::now, |c| c.now())
+    }
+}
+
+/// Identify a file on the filesystem independently of its path.
+/// Only available on Unix, where it is the device and inode numbers.
+#[cfg(unix)]
+fn file_identity(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Write all of `bytes` at `pos` in `file`, without moving its cursor on Unix.
+#[cfg(unix)]
+fn write_at(file: &File, bytes: &[u8], pos: u64) -> std::io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(bytes, pos)
+}
+
+#[cfg(not(unix))]
+fn write_at(mut file: &File, bytes: &[u8], pos: u64) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(pos))?;
+    file.write_all(bytes)
+}
+
+/// a DB in file, storing values of type `V`.
 #[derive(Debug)]
-pub struct PhysicalDB {
+pub struct PhysicalDB<V: RecordValue = u8> {
     pub path: PathBuf,
     pub file: Option<File>,
-    pub header: DbHeader,
+    /// The in-memory header is the source of truth, every mutation updates it before writing it to the file.
+    header: DbHeader,
+    last_seen: Option<FileState>,
+    options: DbOptions,
+    /// The latest time offset known to be in the file, only tracked with `sort_on_close`.
+    last_offset: Option<u32>,
+    /// Index of the first record appended out of order since the DB was opened.
+    unordered_from: Option<u64>,
+    /// The records appended with the `buffered` option and not written yet.
+    pending: Vec<RecordInfo<V>>,
+    evictor: Option<retention::Evictor<V>>,
+    counters: exporter::OpCounters,
+    /// Whether this handle wrote to the file since it was opened, so closing it writes the digest footer.
+    written: bool,
+    /// Whether appends updated the primary copy of the header since the shadow copy was last written.
+    shadow_behind: bool,
+    value: PhantomData<V>,
 }
 
-impl PhysicalDB {
+impl<V: RecordValue> PhysicalDB<V> {
     /// This function will create a new database file or open it if it already exists.
     /// The second argument the date with which to initialize the database. It is optional, if you give `None`
     /// it will use the current date and time. If the file exists, the date is ignored complitely.
-    pub fn new(
+    /// If an append journaled with the `wal` option was interrupted, it is replayed first.
+    /// Same as [`PhysicalDB::create_or_open`].
+    pub fn new(path: &Path, origin_date: Option<Timestamp>) -> Result<PhysicalDB<V>, TSLiteError> {
+        PhysicalDB::new_with_options(path, origin_date, &DbOptions::default())
+    }
+
+    /// Same as [`PhysicalDB::new`] but with explicit options.
+    /// With `overwrite` set, an existing file is replaced by an empty DB instead of being opened.
+    pub fn new_with_options(
         path: &Path,
-        origin_date: Option<chrono::DateTime<Utc>>,
-    ) -> Result<PhysicalDB, TSLiteError> {
-        // We need to first check if file exist because we are going to need to write
-        // or read the header depending on it.
-        if path.exists() {
-            let mut file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-
-            file.seek(SeekFrom::Start(0))
-                .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-            let mut buffer = [0; 15]; // Header takes 15 bytes.
-            let n = file
-                .read(&mut buffer[..])
-                .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-            if n == 15 {
-                let header: DbHeader = DbHeader::from(&buffer[..]);
-                return Ok(PhysicalDB {
-                    path: PathBuf::from(path),
-                    file: Some(file), // don't want to open the file right away.
-                    header,
-                });
-            } else {
-                return Err(TSLiteError::IOError(
-                    "DB File header is corrupted.".to_string(),
-                ));
+        origin_date: Option<Timestamp>,
+        options: &DbOptions,
+    ) -> Result<PhysicalDB<V>, TSLiteError> {
+        PhysicalDB::create_or_open_with_options(path, origin_date, options)
+    }
+
+    /// Create a new database file at `path`, or open it if there is already one, like a daemon does when it
+    /// restarts. `origin_date` is only used to create the file, `None` meaning the current date and time.
+    /// The file is created only if it doesn't exist, in a single step, so processes racing to create it end up
+    /// opening the same DB. One that finds the file before its header is fully written waits for it.
+    pub fn create_or_open(
+        path: &Path,
+        origin_date: Option<Timestamp>,
+    ) -> Result<PhysicalDB<V>, TSLiteError> {
+        PhysicalDB::create_or_open_with_options(path, origin_date, &DbOptions::default())
+    }
+
+    /// Same as [`PhysicalDB::create_or_open`] but with explicit options, which only apply to a created file.
+    /// With `overwrite` set, an existing file is replaced by an empty DB instead of being opened.
+    pub fn create_or_open_with_options(
+        path: &Path,
+        origin_date: Option<Timestamp>,
+        options: &DbOptions,
+    ) -> Result<PhysicalDB<V>, TSLiteError> {
+        // The date and the options aren't validated when the file is opened, so it is opened right away if it exists.
+        if options.overwrite || !path.exists() {
+            match PhysicalDB::create_with_options(path, origin_date, options) {
+                Err(TSLiteError::AlreadyExists) => {}
+                created => return created,
+            }
+        }
+        let mut attempts = 0;
+        loop {
+            match PhysicalDB::open_path_with_options(path, options) {
+                Err(e @ TSLiteError::NotADatabase) | Err(e @ TSLiteError::ShortRead(_))
+                    if attempts < CREATION_WAIT_ATTEMPTS =>
+                {
+                    // Another process may have just created the file and not written its header yet.
+                    let head = fs::read(path).unwrap_or_default();
+                    let partial = (head.len() as u64) < RECORDS_START
+                        && (head.starts_with(MAGIC) || MAGIC.starts_with(&head));
+                    if !partial {
+                        return Err(e);
+                    }
+                    attempts += 1;
+                    std::thread::sleep(CREATION_WAIT);
+                }
+                opened => return opened,
             }
         }
+    }
+
+    /// Open an existing database file, reading and validating its header.
+    /// Fail with an `Io` error if there is no file at `path`, with `NotADatabase` or `UnsupportedVersion` if it isn't
+    /// a DB file this version can read, and with `ValueWidthMismatch` if its values aren't `V` values.
+    /// The files of the first releases, without a magic, are converted with [`upgrade_legacy`].
+    /// If an append journaled with the `wal` option was interrupted, it is replayed first.
+    pub fn open_path(path: &Path) -> Result<PhysicalDB<V>, TSLiteError> {
+        PhysicalDB::open_path_with_options(path, &DbOptions::default())
+    }
 
-        // If it doesn't exist we just create a DB the usual way.
-        PhysicalDB::create(path, origin_date)
+    /// Same as [`PhysicalDB::open_path`] but with explicit options. The options recorded in the header, such as the
+    /// offset unit or the transforms, are the ones of the file, whatever `options` says.
+    pub fn open_path_with_options(
+        path: &Path,
+        options: &DbOptions,
+    ) -> Result<PhysicalDB<V>, TSLiteError> {
+        let file = lock::share(OpenOptions::new().read(true).write(true))
+            .open(path)
+            .map_err(TSLiteError::Io)?;
+
+        let (header, copy) = read_checked_header(&file)?;
+        header.check_width::<V>()?;
+        let mut db = PhysicalDB {
+            path: PathBuf::from(path),
+            file: Some(file),
+            header,
+            last_seen: None,
+            options: options.clone(),
+            last_offset: None,
+            unordered_from: None,
+            pending: Vec::new(),
+            evictor: None,
+            counters: exporter::OpCounters::default(),
+            written: false,
+            shadow_behind: false,
+            value: PhantomData,
+        };
+        if copy == HeaderCopy::Shadow {
+            // The primary copy is damaged, restore it from the shadow one.
+            db.write_header()?;
+        }
+        db.replay_journal()?;
+        Ok(db)
     }
 
     /// This function will create a new database file.
-    /// Warning: It will *not* check if there is already a file at `path`, if there is one, it will be overwritten.
+    /// It will fail with `TSLiteError::AlreadyExists` if there is already a file at `path`,
+    /// use [`PhysicalDB::create_with_options`] to overwrite it instead.
     /// The second argument the date with which to initialize the database. It is optional, if you give `None`
     /// it will use the current date and time.
     pub fn create(
         path: &Path,
-        origin_date: Option<chrono::DateTime<Utc>>,
-    ) -> Result<PhysicalDB, TSLiteError> {
-        let mut file = File::create(path).map_err(|e| TSLiteError::IOError(e.to_string()))?;
+        origin_date: Option<Timestamp>,
+    ) -> Result<PhysicalDB<V>, TSLiteError> {
+        PhysicalDB::create_with_options(path, origin_date, &DbOptions::default())
+    }
+
+    /// Same as [`PhysicalDB::create`] but with explicit options.
+    /// Warning: with `overwrite` set, an existing file at `path` is truncated and its records are lost.
+    pub fn create_with_options(
+        path: &Path,
+        origin_date: Option<Timestamp>,
+        options: &DbOptions,
+    ) -> Result<PhysicalDB<V>, TSLiteError> {
+        if let Some(date) = origin_date {
+            if !date.is_valid() {
+                return Err(TSLiteError::InvalidTimestamp);
+            }
+        }
+        options.transforms.validate()?;
+        if let Some(capacity) = options.ring_capacity {
+            if capacity == 0 || options.transforms.delta || options.wal {
+                return Err(TSLiteError::InvalidParameter(
+                    "A ring must hold at least one record, without the delta transform and the journal."
+                        .to_string(),
+                ));
+            }
+        }
+
+        let mut open_options = OpenOptions::new();
+        lock::share(open_options.write(true));
+        if options.overwrite {
+            open_options.create(true).truncate(true);
+        } else {
+            open_options.create_new(true);
+        }
+        let mut file = open_options.open(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => TSLiteError::AlreadyExists,
+            _ => TSLiteError::Io(e),
+        })?;
+        // A journal left by an overwritten DB doesn't apply to the new one.
+        wal::commit(path).map_err(TSLiteError::Io)?;
 
         // Store the origin date using or own time stamp format. See the Timestamp struct for more info.
         // It lose every timezone info, so everything is normalized as utc+0 before being written.
-        let date = Timestamp::from(origin_date.unwrap_or_else(Utc::now));
+        // The fraction of second isn't stored, so it is dropped right away to keep the header as it is in the file.
+        let date = origin_date
+            .unwrap_or_else(|| options.now())
+            .truncate_to_second();
+        let mut extensions = Extensions::default();
+        options.timescale.record(&mut extensions);
+        if options.record_checksums {
+            extensions.set(checksum::TAG, &[])?;
+        }
+        if options.file_digest {
+            extensions.set(digest::TAG, &[])?;
+        }
+        #[cfg(feature = "chrono-tz")]
+        if let Some(tz) = options.timezone {
+            extensions.set(timezone::TAG, tz.name().as_bytes())?;
+        }
         // We always start with an empty DB, so we store 0 for the number of records.
-        let header = DbHeader {
+        let mut header = DbHeader {
             origin_date: date,
             records_number: 0,
+            offset_unit: options.offset_unit,
+            transforms: options.transforms,
+            extensions,
         };
+        header.set_value_type::<V>()?;
+        if let Some(capacity) = options.ring_capacity {
+            header.set_ring(capacity, 0)?;
+        }
 
-        file.write(&header.as_bytes())
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
+        let mut bytes = header.as_checked_bytes();
+        bytes.extend(header.as_checked_bytes());
+        file.write_all(&bytes).map_err(TSLiteError::Io)?;
 
         Ok(PhysicalDB {
             path: PathBuf::from(path),
             file: None, // don't want to open the file right away.
             header,
+            last_seen: None,
+            options: options.clone(),
+            last_offset: None,
+            unordered_from: None,
+            pending: Vec::new(),
+            evictor: None,
+            counters: exporter::OpCounters::default(),
+            written: false,
+            shadow_behind: false,
+            value: PhantomData,
         })
     }
 
@@ -350,145 +1259,501 @@ impl PhysicalDB {
         }
 
         self.file = Some(
-            OpenOptions::new()
-                .read(true)
-                .write(true)
+            lock::share(OpenOptions::new().read(true).write(true))
                 .open(&self.path)
-                .map_err(|e| TSLiteError::IOError(e.to_string()))?,
+                .map_err(TSLiteError::Io)?,
         );
         Ok(())
     }
 
+    /// Take an exclusive advisory lock on the DB file, waiting until it is available.
+    /// Other processes using the lock functions will wait until [`PhysicalDB::unlock`] is called
+    /// or the file is closed. The lock is advisory: processes that do not take it are not blocked.
+    /// Fail with an `Io` error of kind `Unsupported` on platforms other than Unix and Windows.
+    pub fn lock_exclusive(&mut self) -> Result<(), TSLiteError> {
+        self.lock(true, true).map(|_| ())
+    }
+
+    /// Take a shared advisory lock on the DB file, waiting until no one holds an exclusive lock.
+    pub fn lock_shared(&mut self) -> Result<(), TSLiteError> {
+        self.lock(false, true).map(|_| ())
+    }
+
+    /// Try to take an exclusive advisory lock on the DB file without waiting.
+    /// Return `false` if another handle already holds a lock.
+    pub fn try_lock_exclusive(&mut self) -> Result<bool, TSLiteError> {
+        self.lock(true, false)
+    }
+
+    /// Release the advisory lock held on the DB file.
+    pub fn unlock(&mut self) -> Result<(), TSLiteError> {
+        match self.file.as_ref() {
+            Some(file) => lock::unlock(file).map_err(TSLiteError::Io),
+            None => Ok(()),
+        }
+    }
+
+    fn lock(&mut self, exclusive: bool, blocking: bool) -> Result<bool, TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        lock::lock(self.file.as_ref().unwrap(), exclusive, blocking).map_err(TSLiteError::Io)
+    }
+
     /// Drop the database file to close it.
     /// Make sure to sync all IO operation before closing it.
+    /// With the `sort_on_close` option, records appended out of order are put back in place first.
     pub fn close(&mut self) -> Result<(), TSLiteError> {
+        self.flush()?;
+        if let Some(first) = self.unordered_from {
+            self.sort_from(first)?;
+            self.unordered_from = None;
+        }
         if self.file.is_some() {
-            self.file
-                .as_ref()
-                .unwrap()
-                .sync_all()
-                .map_err(|e| TSLiteError::IOError(e.to_string()))?;
+            self.write_shadow_header()?;
+            if self.written && self.header.file_digest() {
+                self.write_digest()?;
+                self.written = false;
+            }
+            telemetry::sync_all(self.file.as_ref().unwrap()).map_err(TSLiteError::Io)?;
             self.file = None; // Files are close when dropped/out of scope.
         }
 
         Ok(())
     }
 
-    /// Read the header from the file.
-    /// Does not update the header in memory.
-    pub fn read_header(&mut self) -> Result<DbHeader, TSLiteError> {
-        if self.file.is_none() {
-            self.open()?;
+    /// Write the pending records and sync the file, without closing it, to make everything appended so far durable.
+    /// The shadow copy of the header is brought up to date with the primary one.
+    pub fn sync(&mut self) -> Result<(), TSLiteError> {
+        self.flush()?;
+        if self.file.is_some() {
+            self.write_shadow_header()?;
+            telemetry::sync_all(self.file.as_ref().unwrap()).map_err(TSLiteError::Io)?;
         }
 
-        let mut fref = self.file.as_ref().unwrap();
-        fref.seek(SeekFrom::Start(0))
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        let mut buffer = [0; 15]; // Header takes 15 bytes.
-        let n = fref
-            .read(&mut buffer[..])
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        if n == 15 {
-            let header: DbHeader = DbHeader::from(&buffer[..]);
-            return Ok(header);
-        }
-
-        Err(TSLiteError::IOError(
-            "Could not read header: not enough octets.".to_string(),
-        ))
+        Ok(())
     }
 
-    /// Check if a given record index exist within the database.
-    fn check_record_index(&self, rec_id: u64) -> Result<bool, TSLiteError> {
-        let metadata = self
-            .file
-            .as_ref()
-            .unwrap()
-            .metadata()
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        if metadata.len() >= (/* header size */(7+8) + /* records size */(4+1) * rec_id) {
-            return Ok(true);
+    /// Copy the header to the shadow copy if appends left it behind the primary one. The file is not synced.
+    fn write_shadow_header(&mut self) -> Result<(), TSLiteError> {
+        if !self.shadow_behind {
+            return Ok(());
         }
+        let bytes = self.header.as_checked_bytes();
+        fail_points::hit(fail_points::WRITE_HEADER)
+            .and_then(|_| write_at(self.file.as_ref().unwrap(), &bytes, HEADER_COPY_SIZE))
+            .map_err(TSLiteError::Io)?;
+        self.shadow_behind = false;
+        Ok(())
+    }
 
-        Ok(false)
+    /// The header of the DB as known in memory.
+    /// Use [`PhysicalDB::refresh`] to re-sync it if another process may have written to the file.
+    pub fn header(&self) -> &DbHeader {
+        &self.header
     }
 
-    /// The size of the header and record are static.
-    /// So the position of each record is deterministic.
-    /// If `n` is the record id, then its position within the file can be computed with :
-    /// pos(n) = (7 + 8) + (5*n)
-    pub fn read_record(&mut self, rec_id: u64) -> Result<RecordInfo, TSLiteError> {
+    /// Re-read the header from the file and replace the one in memory.
+    /// If the primary copy of the header is damaged, it is restored from the shadow copy.
+    pub fn refresh(&mut self) -> Result<(), TSLiteError> {
         if self.file.is_none() {
             self.open()?;
         }
 
-        let id_exist = self.check_record_index(rec_id)?;
+        let (header, copy) = read_checked_header(self.file.as_ref().unwrap())?;
+        header.check_width::<V>()?;
+        self.header = header;
+        self.last_offset = None;
+        if copy == HeaderCopy::Shadow {
+            self.write_header()?;
+        }
+        Ok(())
+    }
+
+    /// Write the in-memory header to the file.
+    /// The primary copy is written, synced and verified before the shadow copy is updated,
+    /// so that at any time at least one of them is valid.
+    pub(crate) fn write_header(&mut self) -> Result<(), TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        self.written = true;
+        let bytes = self.header.as_checked_bytes();
+        let mut fref = self.file.as_ref().unwrap();
+        for position in [0, HEADER_COPY_SIZE].iter() {
+            fail_points::hit(fail_points::WRITE_HEADER).map_err(TSLiteError::Io)?;
+            fref.seek(SeekFrom::Start(*position))
+                .map_err(TSLiteError::Io)?;
+            fref.write_all(&bytes).map_err(TSLiteError::Io)?;
+            telemetry::sync_data(fref).map_err(TSLiteError::Io)?;
+
+            if *position == 0 {
+                let (_, copy) = read_checked_header(fref)?;
+                if copy != HeaderCopy::Primary {
+                    // The header read back doesn't match its checksum.
+                    return Err(TSLiteError::HeaderCorrupted);
+                }
+            }
+        }
+        self.shadow_behind = false;
+
+        Ok(())
+    }
+
+    /// Read the header from the file, verifying its magic, its format version and its checksum.
+    /// If the primary copy is damaged, the shadow copy is returned instead.
+    /// Fail with `NotADatabase` if the file wasn't written by tslite, and with `UnsupportedVersion` if it was
+    /// written in another format version, [`LEGACY_VERSION`](format::LEGACY_VERSION) for the files of the first
+    /// releases, which [`upgrade_legacy`] converts.
+    /// Does not update the header in memory, use [`PhysicalDB::refresh`] for that.
+    pub fn read_header(&mut self) -> Result<DbHeader, TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        read_checked_header(self.file.as_ref().unwrap()).map(|(header, _)| header)
+    }
+
+    /// Check that the opened file is still the one at `path`.
+    /// Return `TSLiteError::StaleHandle` if the file was removed or replaced by another one.
+    /// The detection relies on inode numbers, so it never reports a stale handle on non-Unix platforms.
+    pub fn check_stale(&self) -> Result<(), TSLiteError> {
+        let file = match self.file.as_ref() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let opened = file.metadata().map_err(TSLiteError::Io)?;
+        let on_disk = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Err(TSLiteError::StaleHandle),
+        };
+        if file_identity(&opened) != file_identity(&on_disk) {
+            return Err(TSLiteError::StaleHandle);
+        }
+
+        Ok(())
+    }
+
+    /// Drop the current handle and open the file currently at `path`, re-reading its header.
+    /// Use it to recover from a `TSLiteError::StaleHandle`.
+    pub fn reopen(&mut self) -> Result<(), TSLiteError> {
+        self.file = None;
+        self.last_seen = None;
+        self.shadow_behind = false;
+        self.open()?;
+        self.refresh()
+    }
+
+    /// Check if the file was modified since the last call, by another process for example,
+    /// and if so re-read the header to update the number of records.
+    /// The change is detected by polling the length and modification date of the file.
+    /// Return `true` if the header was refreshed.
+    pub fn refresh_if_changed(&mut self) -> Result<bool, TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        self.check_stale()?;
+        let metadata = self
+            .file
+            .as_ref()
+            .unwrap()
+            .metadata()
+            .map_err(TSLiteError::Io)?;
+        let state = FileState {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        };
+        if self.last_seen == Some(state) {
+            return Ok(false);
+        }
+
+        self.refresh()?;
+        self.last_seen = Some(state);
+        Ok(true)
+    }
+
+    /// Check if a given record index exist within the database.
+    fn check_record_index(&self, rec_id: u64) -> Result<bool, TSLiteError> {
+        let metadata = self
+            .file
+            .as_ref()
+            .unwrap()
+            .metadata()
+            .map_err(TSLiteError::Io)?;
+        if metadata.len() >= RECORDS_START + self.header.record_size::<V>() * rec_id {
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// The size of the header and record are static.
+    /// So the position of each record is deterministic.
+    /// If `n` is the record id, then its position within the file can be computed with :
+    /// pos(n) = RECORDS_START + (record_size * n), see [`DbHeader::record_size`].
+    pub fn read_record(&mut self, rec_id: u64) -> Result<RecordInfo<V>, TSLiteError> {
+        let mut record = self.read_raw_record(rec_id)?;
+        let transforms = self.header.transforms;
+        if !transforms.is_identity() {
+            let mut previous = self.chain_before(rec_id)?;
+            record.value = transforms.decode(rec_id, record.value, &mut previous);
+        }
+        Ok(record)
+    }
+
+    /// Read a record as it is stored, without inverting the transforms of the DB.
+    fn read_raw_record(&mut self, rec_id: u64) -> Result<RecordInfo<V>, TSLiteError> {
+        let result = self.read_stored_record(rec_id);
+        self.counters.count_read(result)
+    }
+
+    fn read_stored_record(&mut self, rec_id: u64) -> Result<RecordInfo<V>, TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        let slot = self.header.record_slot(rec_id);
+        let id_exist = self.check_record_index(slot)?;
         if !id_exist {
             return Err(TSLiteError::IndexOutOfBound);
         }
 
-        let pos = (7 + 8) + (rec_id * 5);
+        let size = self.header.record_size::<V>();
+        let pos = RECORDS_START + (slot * size);
         let mut fref = self.file.as_ref().unwrap();
-        fref.seek(SeekFrom::Start(pos))
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        let mut buffer = [0; 5]; // Header takes 15 bytes.
-        let n = fref
-            .read(&mut buffer[..])
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        if n == 5 {
-            let record: RecordInfo = RecordInfo::from(&buffer[..]);
-            return Ok(record);
-        }
-
-        Err(TSLiteError::IOError(
-            "Could not read record: not enough octets.".to_string(),
-        ))
+        fref.seek(SeekFrom::Start(pos)).map_err(TSLiteError::Io)?;
+        let mut buffer = vec![0; size as usize];
+        let n = fref.read(&mut buffer[..]).map_err(TSLiteError::Io)?;
+        if n == size as usize {
+            return self.header.decode_record(rec_id, &buffer);
+        }
+
+        Err(TSLiteError::ShortRead("record".to_string()))
     }
 
-    /// This utility function will update the number of record in the database.
-    pub fn update_record_number(&mut self, drn: u64) -> Result<(), TSLiteError> {
+    /// Read the serialized records starting at index `first` into `buf`, as many whole records as
+    /// fit in it and exist in the DB, and return how many were read.
+    /// Decode them with [`DbHeader::decode_record`] from `&buf[i * size..(i + 1) * size]`, `size` being
+    /// [`DbHeader::record_size`], which is `RecordInfo::SIZE` unless the DB has record checksums.
+    /// The values are in their stored form: if the DB has [`Transforms`], they are not inverted.
+    pub fn read_records_into(&mut self, first: u64, buf: &mut [u8]) -> Result<usize, TSLiteError> {
         if self.file.is_none() {
             self.open()?;
         }
+        if first > self.header.records_number {
+            return Err(TSLiteError::IndexOutOfBound);
+        }
 
+        let size = self.header.record_size::<V>();
+        let count = (buf.len() as u64 / size).min(self.header.records_number - first);
         let mut fref = self.file.as_ref().unwrap();
-        fref.seek(SeekFrom::Start(7)) // The record number is always at position 7
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        fref.write_u64::<LittleEndian>(self.header.records_number + drn)
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        fref.sync_data()
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
+        // The records of a ring can wrap around the end of the file, they are then read in two parts.
+        let mut read = 0;
+        while read < count {
+            let n = (count - read).min(self.header.contiguous_slots(first + read));
+            let slot = self.header.record_slot(first + read);
+            fref.seek(SeekFrom::Start(RECORDS_START + slot * size))
+                .map_err(TSLiteError::Io)?;
+            fref.read_exact(&mut buf[(read * size) as usize..((read + n) * size) as usize])
+                .map_err(|_| TSLiteError::ShortRead("records".to_string()))?;
+            read += n;
+        }
+
+        Ok(count as usize)
+    }
+
+    /// This utility function will update the number of record in the database.
+    pub fn update_record_number(&mut self, drn: u64) -> Result<(), TSLiteError> {
         self.header.records_number += drn;
+        self.write_header()
+    }
 
-        Ok(())
+    /// Move every record by `seconds` (backward if negative), to correct the clock of the device that produced them.
+    /// Only the origin of the DB is rewritten, with the same crash safety as any header update.
+    /// Fail with `InvalidTimestamp` if the origin or the date of the last record would go out of the range of
+    /// [`Timestamp`], in which case nothing is changed.
+    pub fn shift(&mut self, seconds: i64) -> Result<(), TSLiteError> {
+        self.refresh_if_changed()?;
+        self.check_stale()?;
+        let origin = self.header.origin_date.unix_seconds() + seconds;
+        let mut last = origin;
+        if self.header.records_number > 0 {
+            let offset = self
+                .read_raw_record(self.header.records_number - 1)?
+                .time_offset;
+            last += (offset as i64 * self.header.offset_unit.nanoseconds())
+                .div_euclid(NANOS_PER_SECOND);
+        }
+        if [origin, last]
+            .iter()
+            .any(|s| Timestamp::from_unix(*s).unix_seconds() != *s)
+        {
+            return Err(TSLiteError::InvalidTimestamp);
+        }
+
+        self.header.origin_date = Timestamp::from_unix(origin);
+        self.write_header()
     }
 
     /// Add a record in the database.
-    pub fn append_record(&mut self, rec_nfo: RecordInfo) -> Result<(), TSLiteError> {
+    /// The record and the header are written with a single sync: if the process crashes in between,
+    /// the header can lag behind the record, which is then overwritten by the next append, or the
+    /// record can be missing while the header counts it, which `check_db_file` reports.
+    /// Only the primary copy of the header is updated, the shadow copy is written by [`PhysicalDB::sync`]
+    /// and [`PhysicalDB::close`]. If the primary copy gets damaged before, the header is recovered from
+    /// the shadow copy without the records appended since, and [`PhysicalDB::repair`] counts them back.
+    pub fn append_record(&mut self, rec_nfo: RecordInfo<V>) -> Result<(), TSLiteError> {
+        self.append_records(&[rec_nfo])
+    }
+
+    /// Add several records with a single write of the records, a single write of the header and a single sync,
+    /// which is much faster than appending them one by one.
+    /// Nothing is written if any of the records is rejected.
+    /// With the `buffered` option, the records are only kept in memory, see [`PhysicalDB::flush`].
+    pub fn append_records(&mut self, records: &[RecordInfo<V>]) -> Result<(), TSLiteError> {
+        if self.options.buffered {
+            for r in records {
+                self.check_future_skew(r.time_offset)?;
+            }
+            self.pending.extend_from_slice(records);
+            return Ok(());
+        }
+        self.write_records(records)
+    }
+
+    /// Write the records appended with the `buffered` option, with a single write and a single sync.
+    /// They are kept in memory if the write fails, so it can be retried.
+    pub fn flush(&mut self) -> Result<(), TSLiteError> {
+        let pending = std::mem::take(&mut self.pending);
+        let result = self.write_records(&pending);
+        if result.is_err() {
+            self.pending = pending;
+        }
+        result
+    }
+
+    /// The number of records appended with the `buffered` option and not written yet.
+    pub fn pending_records(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn write_records(&mut self, records: &[RecordInfo<V>]) -> Result<(), TSLiteError> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let result = self.write_batch(records);
+        self.counters.count_write(result)?;
+        self.counters.appended(self.options.now());
+        telemetry::records_appended(records.len() as u64);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, records: &[RecordInfo<V>]) -> Result<(), TSLiteError> {
         if self.file.is_none() {
             self.open()?;
         }
+        self.check_stale()?;
+        for r in records {
+            self.check_future_skew(r.time_offset)?;
+        }
+        let first = self.header.records_number;
+        if self.header.ring_capacity().is_some() {
+            if self.options.wal {
+                return Err(TSLiteError::InvalidParameter(
+                    "The journal is not supported by ring DBs.".to_string(),
+                ));
+            }
+            // A batch wrapping around the end of the ring is written in two parts.
+            let contiguous = self
+                .header
+                .contiguous_slots(first)
+                .min(records.len() as u64);
+            if contiguous < records.len() as u64 {
+                let (head, tail) = records.split_at(contiguous as usize);
+                self.write_batch(head)?;
+                return self.write_batch(tail);
+            }
+            self.evict_overwritten(records.len() as u64)?;
+        }
+        if self.options.sort_on_close {
+            for (i, r) in records.iter().enumerate() {
+                self.track_order(first + i as u64, r.time_offset)?;
+            }
+        }
 
-        // write record
-        let mut fref = self.file.as_ref().unwrap();
-        fref.seek(SeekFrom::End(0))
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        fref.write(&rec_nfo.as_bytes())
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        fref.sync_all()
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
+        // The records and the primary copy of the updated header are staged, then written in this order and
+        // synced once. The shadow copy is left as it was until the DB is synced or closed, so a torn write of
+        // the primary copy can't damage both: the shadow copy then holds the header of an earlier append.
+        // The records go at the position given by the header rather than at the end of the file,
+        // so the remains of an append interrupted before its header update get overwritten.
+        let transforms = self.header.transforms;
+        let mut previous = self.chain_before(first)?;
+        let size = self.header.record_size::<V>();
+        let mut bytes = Vec::with_capacity(records.len() * size as usize);
+        for (i, r) in records.iter().enumerate() {
+            let mut record = *r;
+            record.value = transforms.encode(first + i as u64, record.value, &mut previous);
+            bytes.extend(self.header.encode_record(&record));
+        }
+        let mut header = self.header;
+        header.ring_append(records.len() as u64)?;
+        let header_bytes = header.as_checked_bytes();
+        let pos = RECORDS_START + self.header.record_slot(first) * size;
 
-        // Update DbHeader
-        self.update_record_number(1)?;
+        if self.options.wal {
+            wal::begin(&self.path, first, &bytes)?;
+        }
+        self.written = true;
+        let file = self.file.as_ref().unwrap();
+        fail_points::hit(fail_points::WRITE_RECORD)
+            .and_then(|_| write_at(file, &bytes, pos))
+            .map_err(TSLiteError::Io)?;
+        fail_points::hit(fail_points::WRITE_HEADER)
+            .and_then(|_| write_at(file, &header_bytes, 0))
+            .map_err(TSLiteError::Io)?;
+        telemetry::sync_data(file).map_err(TSLiteError::Io)?;
+        self.header = header;
+        self.shadow_behind = true;
+        if self.options.wal {
+            wal::commit(&self.path).map_err(TSLiteError::Io)?;
+        }
 
         Ok(())
     }
 
+    /// Fail with `TooFarInFuture` if a record at `time_offset` is further in the future than the options allow.
+    pub(crate) fn check_future_skew(&self, time_offset: u32) -> Result<(), TSLiteError> {
+        if let Some(skew) = self.options.max_future_skew {
+            let limit = self.options.now().add_seconds(skew.as_secs() as i64);
+            if self.header.offset_to_date(time_offset) > limit {
+                return Err(TSLiteError::TooFarInFuture);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remember if the record appended at `index` with `time_offset` is out of order.
+    fn track_order(&mut self, index: u64, time_offset: u32) -> Result<(), TSLiteError> {
+        if self.last_offset.is_none() && index > 0 {
+            self.last_offset = Some(self.read_raw_record(index - 1)?.time_offset);
+        }
+        match self.last_offset {
+            Some(last) if time_offset < last => {
+                self.unordered_from.get_or_insert(index);
+            }
+            _ => self.last_offset = Some(time_offset),
+        }
+        Ok(())
+    }
+
     /// Append a record with the current time.
-    pub fn append_record_now(&mut self, value: u8) -> Result<(), TSLiteError> {
-        let origin = self.header.origin_date;
-        let now = Timestamp::from(Utc::now());
-        let off = origin.offset(&now);
+    pub fn append_record_now(&mut self, value: V) -> Result<(), TSLiteError> {
+        let now = self.options.now();
+        let off = self.header.checked_offset(&now)?;
         let nfo = RecordInfo {
             value,
             time_offset: off,
@@ -497,8 +1762,16 @@ impl PhysicalDB {
         self.append_record(nfo)
     }
 
+    /// Append a record at the chrono `DateTime` `date`, rounded down to the offset unit of the DB.
+    #[cfg(feature = "chrono")]
+    pub fn append_datetime(&mut self, date: DateTime<Utc>, value: V) -> Result<(), TSLiteError> {
+        self.refresh_if_changed()?;
+        let time_offset = self.header.datetime_to_offset(&date)?;
+        self.append_record(RecordInfo { time_offset, value })
+    }
+
     /// Change the value of a record within the database.
-    pub fn update_record(&mut self, rec_id: u64, value: u8) -> Result<(), TSLiteError> {
+    pub fn update_record(&mut self, rec_id: u64, value: V) -> Result<(), TSLiteError> {
         if self.file.is_none() {
             self.open()?;
         }
@@ -508,54 +1781,263 @@ impl PhysicalDB {
             return Err(TSLiteError::IndexOutOfBound);
         }
 
-        let pos = (7 + 8) + (rec_id * 5) + 4; // header + records + timestamp
-        let mut fref = self.file.as_ref().unwrap();
-        fref.seek(SeekFrom::Start(pos))
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        fref.write(&[value])
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
-        fref.sync_all()
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
+        let transforms = self.header.transforms;
+        let mut previous = self.chain_before(rec_id)?;
+        // With the delta transform, the next record is stored relatively to this one so it is re-encoded too.
+        let next = rec_id + 1;
+        let following = if transforms.delta && next < self.header.records_number {
+            let mut chain = previous;
+            transforms.undelta(rec_id, self.read_raw_record(rec_id)?.value, &mut chain);
+            Some(transforms.undelta(next, self.read_raw_record(next)?.value, &mut chain))
+        } else {
+            None
+        };
+
+        let stored = transforms.encode(rec_id, value, &mut previous);
+        self.write_value(rec_id, stored)?;
+        if let Some(quantized) = following {
+            let stored = transforms.delta(next, quantized, &mut previous);
+            self.write_value(next, stored)?;
+        }
+        telemetry::sync_all(self.file.as_ref().unwrap()).map_err(TSLiteError::Io)?;
 
         Ok(())
     }
 
+    /// Write the value of the record at index `rec_id` as it is given, without syncing the file.
+    /// With record checksums, the whole record is written again so its checksum matches the new value.
+    fn write_value(&mut self, rec_id: u64, value: V) -> Result<(), TSLiteError> {
+        if self.header.record_checksums() {
+            let time_offset = self.read_raw_record(rec_id)?.time_offset;
+            return self.write_record(rec_id, &RecordInfo { time_offset, value });
+        }
+        let slot = self.header.record_slot(rec_id);
+        let pos = RECORDS_START + (slot * RecordInfo::<V>::SIZE) + TIME_OFFSET_SIZE; // header + records + timestamp
+        let mut bytes = vec![0; V::WIDTH];
+        value.encode(&mut bytes);
+        fail_points::hit(fail_points::WRITE_RECORD).map_err(TSLiteError::Io)?;
+        self.written = true;
+        let mut fref = self.file.as_ref().unwrap();
+        fref.seek(SeekFrom::Start(pos)).map_err(TSLiteError::Io)?;
+        fref.write_all(&bytes).map_err(TSLiteError::Io)
+    }
+
     /// Perform check to find any issue in the database file.
-    /// It will return the first issue it find. You might need to run this function
-    /// until it return `DbIssue::None` to check for all possible issue.
+    /// It will return the first issue it find, see [`PhysicalDB::check_all`] to get all of them at once.
     pub fn check_db_file(&mut self) -> Result<DbIssue, TSLiteError> {
+        Ok(self.check_all()?.first().copied().unwrap_or(DbIssue::None))
+    }
+
+    /// Look for every issue of the database file in one pass, in the order they are found: the header first,
+    /// then the records, then the record amount. Return an empty list if there is none.
+    /// A damaged primary copy of the header is reported, and the records are then checked against the shadow copy.
+    /// Only the records the file holds are checked, the missing ones being reported as `MismatchRecordAmount`.
+    pub fn check_all(&mut self) -> Result<Vec<DbIssue>, TSLiteError> {
         if self.file.is_none() {
             self.open()?;
         }
 
-        // First try to read the header
-        let res_header = self.read_header();
-        if res_header.is_err() {
-            return Ok(DbIssue::HeaderCorrupted);
+        let mut issues = Vec::new();
+        let header = match read_checked_header(self.file.as_ref().unwrap()) {
+            Ok((header, HeaderCopy::Primary)) => header,
+            Ok((header, HeaderCopy::Shadow)) => {
+                issues.push(DbIssue::HeaderCorrupted);
+                header
+            }
+            Err(TSLiteError::InvalidTimestamp) => return Ok(vec![DbIssue::OriginDateInvalid]),
+            Err(_) => return Ok(vec![DbIssue::HeaderCorrupted]),
+        };
+
+        let len = self
+            .file
+            .as_ref()
+            .unwrap()
+            .metadata()
+            .map_err(TSLiteError::Io)?
+            .len();
+        let available = len.saturating_sub(RECORDS_START) / header.record_size::<V>();
+        let mut time_offset = 0;
+        for i in 0..header.records_number.min(available) {
+            match self.read_raw_record(i) {
+                Err(_) => {
+                    telemetry::corruption_detected();
+                    issues.push(DbIssue::RecordCorrupted(i));
+                }
+                Ok(record) => {
+                    if time_offset > record.time_offset {
+                        issues.push(DbIssue::UnorderedRecord(i));
+                    }
+                    time_offset = record.time_offset;
+                }
+            }
         }
-        let header = res_header.unwrap();
-        if !header.origin_date.is_valid() {
-            return Ok(DbIssue::OriginDateInvalid);
+
+        if available < header.records_number {
+            telemetry::corruption_detected();
+            issues.push(DbIssue::MismatchRecordAmount);
         }
 
-        let mut time_offset = 0;
-        for i in 0..header.records_number {
-            let res_record = self.read_record(i);
-            if res_record.is_err() {
-                return Ok(DbIssue::RecordCorrupted(i));
+        Ok(issues)
+    }
+
+    /// Convert a `[start, end[` time range into a range of time offsets relative to the origin of the DB.
+    /// Return `None` if no record can fall in the range (empty range or range anterior to the origin).
+    pub(crate) fn offset_range(&self, start: &Timestamp, end: &Timestamp) -> Option<(u32, u32)> {
+        let start = self.header.offset_not_before(start).max(0);
+        let end = self.header.offset_not_before(end);
+        if end <= start {
+            return None;
+        }
+
+        Some((
+            start.min(u32::MAX as i64) as u32,
+            end.min(u32::MAX as i64) as u32,
+        ))
+    }
+
+    /// Resolve a time offset into an absolute date using the origin of the DB.
+    pub(crate) fn offset_to_date(&self, time_offset: u32) -> Timestamp {
+        self.header.offset_to_date(time_offset)
+    }
+
+    /// Return every record whose date is within `[start, end[`, with its date resolved against the origin.
+    pub fn records(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<Vec<Record<V>>, TSLiteError> {
+        let header = self.header;
+        let mut records = Vec::new();
+        self.scan_range(&start.into(), &end.into(), |r| {
+            records.push(r.resolve(&header))
+        })?;
+        Ok(records)
+    }
+
+    /// Replace the content of `records` with every record whose date is within `[start, end[`.
+    /// Unlike [`PhysicalDB::records`], the allocation of `records` is reused across calls.
+    pub fn read_range_into(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        records: &mut Vec<RecordInfo<V>>,
+    ) -> Result<(), TSLiteError> {
+        records.clear();
+        self.scan_range(&start.into(), &end.into(), |r| records.push(r))
+    }
+
+    /// Return every record whose date is within `[start, end[`.
+    /// The first record of the range is found with a binary search rather than by reading the records before it.
+    pub fn read_range(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+        let mut records = Vec::new();
+        self.read_range_into(start, end, &mut records)?;
+        Ok(records)
+    }
+
+    /// The index of the first record whose time offset is not lower than `time_offset`, `records_number` if
+    /// there is none. Records are assumed to be chronologically ordered.
+    pub(crate) fn first_not_before(&mut self, time_offset: u32) -> Result<u64, TSLiteError> {
+        let (mut low, mut high) = (0, self.header.records_number);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.read_raw_record(middle)?.time_offset < time_offset {
+                low = middle + 1;
+            } else {
+                high = middle;
             }
-            if time_offset > res_record.as_ref().unwrap().time_offset {
-                return Ok(DbIssue::UnorderedRecord);
+        }
+        Ok(low)
+    }
+
+    /// Call `f` on every record whose date is within `[start, end[`, in the order they are stored.
+    /// Records are assumed to be chronologically ordered: the scan starts at the first record not before `start`,
+    /// found with a binary search, and stops at the first record past `end`.
+    /// The records are read from the file by chunks, so only one chunk is held in memory at a time.
+    pub(crate) fn scan_range<F>(
+        &mut self,
+        start: &Timestamp,
+        end: &Timestamp,
+        mut f: F,
+    ) -> Result<(), TSLiteError>
+    where
+        F: FnMut(RecordInfo<V>),
+    {
+        self.refresh_if_changed()?;
+        let (start, end) = match self.offset_range(start, end) {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
+        let header = self.header;
+        let first = self.first_not_before(start)?;
+        let mut previous = self.chain_before(first)?;
+        let size = header.record_size::<V>() as usize;
+        let mut buffer = vec![0; iter::CHUNK_RECORDS * size];
+        let mut index = first;
+        while index < header.records_number {
+            let result = self.read_records_into(index, &mut buffer);
+            let count = self.counters.count_read(result)?;
+            for bytes in buffer[..count * size].chunks(size) {
+                let result = header.decode_record::<V>(index, bytes);
+                let mut record = self.counters.count_read(result)?;
+                record.value = header.transforms.decode(index, record.value, &mut previous);
+                if record.time_offset >= end {
+                    return Ok(());
+                }
+                f(record);
+                index += 1;
             }
-            time_offset = res_record.as_ref().unwrap().time_offset;
         }
 
-        let id_exist = self.check_record_index(header.records_number)?;
-        if !id_exist {
-            return Ok(DbIssue::MismatchRecordAmount);
+        Ok(())
+    }
+
+    /// Write a whole record at index `rec_id`, without syncing the file.
+    fn write_record(&mut self, rec_id: u64, record: &RecordInfo<V>) -> Result<(), TSLiteError> {
+        let pos =
+            RECORDS_START + (self.header.record_slot(rec_id) * self.header.record_size::<V>());
+        fail_points::hit(fail_points::WRITE_RECORD).map_err(TSLiteError::Io)?;
+        self.written = true;
+        let mut fref = self.file.as_ref().unwrap();
+        fref.seek(SeekFrom::Start(pos)).map_err(TSLiteError::Io)?;
+        fref.write_all(&self.header.encode_record(record))
+            .map_err(TSLiteError::Io)
+    }
+
+    /// Insertion sort of the records from index `first`, the records before it being assumed to be ordered.
+    /// Only two records are held in memory at a time, and the cost depends on how far the records are
+    /// from their place rather than on the size of the DB, which suits a few late appends.
+    fn sort_from(&mut self, first: u64) -> Result<(), TSLiteError> {
+        if self.header.transforms.delta {
+            // Moving a record changes the values it is a delta of, so everything is re-encoded.
+            return self.reorder_record();
+        }
+        if self.file.is_none() {
+            self.open()?;
         }
 
-        Ok(DbIssue::None)
+        for i in first.max(1)..self.header.records_number {
+            let record = self.read_raw_record(i)?;
+            let mut j = i;
+            while j > 0 {
+                let previous = self.read_raw_record(j - 1)?;
+                if previous.time_offset <= record.time_offset {
+                    break;
+                }
+                self.write_record(j, &previous)?;
+                j -= 1;
+            }
+            if j != i {
+                self.write_record(j, &record)?;
+            }
+        }
+        telemetry::sync_all(self.file.as_ref().unwrap()).map_err(TSLiteError::Io)?;
+
+        Ok(())
     }
 
     /// Reorder the record in the DB.
@@ -564,85 +2046,168 @@ impl PhysicalDB {
     /// - Read all the record
     /// - reorder them in-memory
     /// - dump *all* the record in the DB
+    ///
     /// It means that if you have just one record wrong you end up re-writing the whole DB.
     pub fn reorder_record(&mut self) -> Result<(), TSLiteError> {
         if self.file.is_none() {
             self.open()?;
         }
 
-        let mut records: Vec<RecordInfo> = Vec::with_capacity(self.header.records_number as usize);
+        // The records are sorted with their quantized values, so the delta transform is re-applied
+        // in the new order while the other transforms are left untouched.
+        let transforms = self.header.transforms;
+        let mut previous = None;
+        let mut records: Vec<RecordInfo<V>> =
+            Vec::with_capacity(self.header.records_number as usize);
         for i in 0..(self.header.records_number) {
-            records.push(self.read_record(i)?);
+            let mut record = self.read_raw_record(i)?;
+            record.value = transforms.undelta(i, record.value, &mut previous);
+            records.push(record);
+        }
+        records.sort_unstable_by_key(|r| r.time_offset);
+        let mut previous = None;
+        for (i, r) in records.iter_mut().enumerate() {
+            r.value = transforms.delta(i as u64, r.value, &mut previous);
         }
-        records.sort_unstable();
+        self.written = true;
         let mut fref = self.file.as_ref().unwrap();
-        fref.seek(SeekFrom::Start(/* offset header */ 15))
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
+        fref.seek(SeekFrom::Start(RECORDS_START))
+            .map_err(TSLiteError::Io)?;
         for r in &records {
-            fref.write(&r.as_bytes())
-                .map_err(|e| TSLiteError::IOError(e.to_string()))?;
+            fref.write(&self.header.encode_record(r))
+                .map_err(TSLiteError::Io)?;
+        }
+        telemetry::sync_all(fref).map_err(TSLiteError::Io)?;
+        // The records of a ring are now stored from the first slot.
+        if let Some(capacity) = self.header.ring_capacity() {
+            self.header.set_ring(capacity, 0)?;
+            self.write_header()?;
         }
-        fref.sync_all()
-            .map_err(|e| TSLiteError::IOError(e.to_string()))?;
 
         Ok(())
     }
 }
 
+impl<V: RecordValue> Drop for PhysicalDB<V> {
+    /// Close the DB if it wasn't, see [`PhysicalDB::close`]. As errors can't be reported from there, they are
+    /// ignored: call `close` before dropping the DB to know the pending records were written and synced.
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
 /// Maybe I can use a in-memory FS for the test instead of dumping files
 /// on disk ?
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::prelude::*;
-    use std::error::Error;
     use std::fs;
-    use std::io::prelude::*;
     use std::path::Path;
 
     #[test]
     fn create_db_origin_now() {
-        fs::remove_file("create_db_origin_now.db");
-        let r = PhysicalDB::create(&Path::new("create_db_origin_now.db"), None);
+        let _ = fs::remove_file("create_db_origin_now.db");
+        let r = PhysicalDB::<u8>::create(Path::new("create_db_origin_now.db"), None);
         assert!(r.is_ok());
-        fs::remove_file("create_db_origin_now.db");
+        let _ = fs::remove_file("create_db_origin_now.db");
     }
 
+    #[cfg(feature = "chrono")]
     #[test]
     fn create_db_origin_specific() {
-        fs::remove_file("create_db_origin_specific.db");
+        let _ = fs::remove_file("create_db_origin_specific.db");
 
-        let origin_date = Utc.ymd(1994, 07, 08).and_hms(6, 55, 34);
-        let wr = PhysicalDB::create(
-            &Path::new("create_db_origin_specific.db"),
-            Some(origin_date),
+        let origin_date = Utc.with_ymd_and_hms(1994, 7, 8, 6, 55, 34).unwrap();
+        let wr = PhysicalDB::<u8>::create(
+            Path::new("create_db_origin_specific.db"),
+            Some(origin_date.into()),
         );
         assert!(wr.is_ok());
 
         let mut f = File::open("create_db_origin_specific.db").unwrap();
-        let mut buf: Vec<u8> = Vec::with_capacity(7 + 8);
+        let mut buf: Vec<u8> = Vec::with_capacity(RECORDS_START as usize);
         let rr = f.read_to_end(&mut buf).map_err(|e| e.to_string());
         assert!(rr.is_ok());
-        assert!(rr.map(|v| v == (7 + 8)).unwrap_or(false));
+        assert!(rr.map(|v| v == RECORDS_START as usize).unwrap_or(false));
+
+        let db_header = DbHeader::try_from(buf.as_slice()).unwrap();
+        assert_eq!(db_header.records_number, 0);
+        assert_eq!(db_header.origin_date.year, 1994);
+        assert_eq!(db_header.origin_date.month, 7);
+        assert_eq!(db_header.origin_date.day, 8);
+        assert_eq!(db_header.origin_date.hour, 6);
+        assert_eq!(db_header.origin_date.minute, 55);
+        assert_eq!(db_header.origin_date.second, 34);
+
+        let _ = fs::remove_file("create_db_origin_specific.db");
+    }
+
+    #[test]
+    fn open_existing_db() {
+        let path = "open_path.db";
+        let _ = fs::remove_file(path);
+
+        assert!(matches!(
+            PhysicalDB::<u8>::open_path(Path::new(path)),
+            Err(TSLiteError::Io(_))
+        ));
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        db.append(origin.add_seconds(3), 7)
+            .expect("could not append record.");
+        db.close().expect("could not close db.");
+
+        let mut db: PhysicalDB =
+            PhysicalDB::open_path(Path::new(path)).expect("could not open db.");
+        assert_eq!(db.header().origin_date, origin);
+        assert_eq!(db.header().records_number, 1);
+        assert_eq!(db.read_record(0).unwrap().value, 7);
+        assert_eq!(
+            PhysicalDB::<u16>::open_path(Path::new(path)).err(),
+            Some(TSLiteError::ValueWidthMismatch(1))
+        );
 
-        let dbHeader = DbHeader::from(buf.as_slice());
-        assert_eq!(dbHeader.records_number, 0);
-        assert_eq!(dbHeader.origin_date.year, 1994);
-        assert_eq!(dbHeader.origin_date.month, 07);
-        assert_eq!(dbHeader.origin_date.day, 08);
-        assert_eq!(dbHeader.origin_date.hour, 6);
-        assert_eq!(dbHeader.origin_date.minute, 55);
-        assert_eq!(dbHeader.origin_date.second, 34);
+        let _ = fs::remove_file(path);
+    }
 
-        fs::remove_file("create_db_origin_specific.db");
+    #[test]
+    fn create_or_open_race() {
+        let path = "create_or_open.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let db: PhysicalDB =
+                        PhysicalDB::create_or_open(Path::new(path), Some(origin.add_seconds(i)))
+                            .expect("could not create or open db.");
+                    db.header().origin_date
+                })
+            })
+            .collect();
+        let origins: Vec<Timestamp> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(origins.iter().all(|o| *o == origins[0]));
+
+        let mut db: PhysicalDB =
+            PhysicalDB::create_or_open(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().origin_date, origins[0]);
+        db.append(origins[0], 1).expect("could not append record.");
+        let db: PhysicalDB = PhysicalDB::create_or_open(Path::new(path), Some(Timestamp::now()))
+            .expect("could not open db.");
+        assert_eq!(db.header().records_number, 1);
+
+        let _ = fs::remove_file(path);
     }
 
     #[test]
     fn append_record() {
         let path = "append_record.db";
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
 
-        let mut db = PhysicalDB::create(&Path::new(path), None).expect("could not create db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
         let header = db.read_header().expect("could not read header.");
         assert_eq!(header.records_number, 0);
 
@@ -660,13 +2225,107 @@ mod tests {
         let header = db.read_header().expect("could not read header.");
         assert_eq!(header.records_number, 1);
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
     }
 
     #[test]
     fn today_is_valid() {
-        let today = Timestamp::from(Utc::now());
-        assert_eq!(today.is_valid(), true);
+        let today = Timestamp::now();
+        assert!(today.is_valid());
+    }
+
+    #[test]
+    fn validate_timestamp() {
+        assert!(Timestamp::new(2021, 7, 31, 0, 0, 0).is_ok());
+        assert!(Timestamp::new(2021, 8, 31, 0, 0, 0).is_ok());
+        assert!(Timestamp::new(2021, 12, 31, 23, 59, 59).is_ok());
+        assert_eq!(Timestamp::new(2021, 9, 31, 0, 0, 0), Err(InvalidTimestamp));
+        assert_eq!(Timestamp::new(2021, 4, 31, 0, 0, 0), Err(InvalidTimestamp));
+        assert!(Timestamp::new(2020, 2, 29, 0, 0, 0).is_ok());
+        assert!(Timestamp::new(2000, 2, 29, 0, 0, 0).is_ok());
+        assert!(Timestamp::new(1900, 2, 29, 0, 0, 0).is_err());
+        assert!(Timestamp::new(2021, 0, 1, 0, 0, 0).is_err());
+        assert!(Timestamp::new(2021, 13, 1, 0, 0, 0).is_err());
+        assert!(Timestamp::new(2021, 1, 0, 0, 0, 0).is_err());
+        assert!(Timestamp::new(2021, 1, 1, 24, 0, 0).is_err());
+        assert!(Timestamp::new(2021, 1, 1, 0, 60, 0).is_err());
+        assert!(Timestamp::new(2021, 1, 1, 0, 0, 60).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_origin() {
+        let path = "invalid_origin.db";
+        let _ = fs::remove_file(path);
+
+        let invalid = Timestamp {
+            year: 2021,
+            month: 6,
+            day: 31,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            nanosecond: 0,
+        };
+        let res = PhysicalDB::<u8>::create(Path::new(path), Some(invalid));
+        assert_eq!(res.err(), Some(TSLiteError::InvalidTimestamp));
+        assert!(!Path::new(path).exists());
+
+        // Write an invalid origin with a valid checksum, as a buggy writer would.
+        let header = DbHeader {
+            origin_date: invalid,
+            records_number: 0,
+            offset_unit: OffsetUnit::Seconds,
+            transforms: Transforms::default(),
+            extensions: Extensions::default(),
+        };
+        let mut bytes = header.as_checked_bytes();
+        bytes.extend(header.as_checked_bytes());
+        fs::write(path, bytes).unwrap();
+        let res = PhysicalDB::<u8>::new(Path::new(path), None);
+        assert_eq!(res.err(), Some(TSLiteError::InvalidTimestamp));
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn unix_conversion_matches_chrono() {
+        let date = Utc.with_ymd_and_hms(2024, 2, 29, 23, 59, 58).unwrap();
+        let ts = Timestamp::from(date);
+        assert_eq!(ts.unix_seconds(), date.timestamp());
+        assert_eq!(Timestamp::from_unix(date.timestamp()), ts);
+        assert_eq!(
+            ts.add_seconds(2),
+            Timestamp::from(date + chrono::Duration::seconds(2))
+        );
+        assert_eq!(DateTime::<Utc>::try_from(ts), Ok(date));
+        let invalid = Timestamp {
+            day: 30,
+            month: 2,
+            ..ts
+        };
+        assert_eq!(DateTime::<Utc>::try_from(invalid), Err(InvalidTimestamp));
+        let invalid = Timestamp {
+            nanosecond: 1_000_000_000,
+            ..ts
+        };
+        assert_eq!(DateTime::<Utc>::try_from(&invalid), Err(InvalidTimestamp));
+
+        let header = DbHeader {
+            origin_date: ts,
+            records_number: 0,
+            offset_unit: OffsetUnit::Minutes,
+            transforms: Transforms::default(),
+            extensions: Extensions::default(),
+        };
+        let record = RecordInfo {
+            time_offset: 3,
+            value: 0u8,
+        };
+        assert_eq!(
+            record.absolute_time(&header),
+            date + chrono::Duration::minutes(3)
+        );
     }
 
     #[test]
@@ -678,6 +2337,7 @@ mod tests {
             hour: 5,
             minute: 24,
             second: 23,
+            nanosecond: 0,
         };
         let d2 = Timestamp {
             year: 1993,
@@ -686,21 +2346,23 @@ mod tests {
             hour: 8,
             minute: 0,
             second: 1,
+            nanosecond: 0,
         };
 
-        assert_eq!(d1 > d2, true);
-        assert_eq!(d1 < d2, false);
-        assert_eq!(d1 == d2, false);
+        assert!(d1 > d2);
+        assert!(d1 >= d2);
+        assert!(d1 != d2);
     }
 
     #[test]
     fn check_healthy_db() {
         let path = "healthy.db";
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
 
-        let mut db = PhysicalDB::create(&Path::new(path), None).expect("could not create db.");
-        let header = db.read_header().expect("could not read header.");
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let _header = db.read_header().expect("could not read header.");
 
         // Add 10 record in the DB
         for i in 0..10 {
@@ -715,17 +2377,18 @@ mod tests {
         let err = db.check_db_file().expect("could not check db file.");
         assert_eq!(err, DbIssue::None);
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
     }
 
     #[test]
     fn check_unordered_db() {
         let path = "unordered.db";
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
 
-        let mut db = PhysicalDB::create(&Path::new(path), None).expect("could not create db.");
-        let header = db.read_header().expect("could not read header.");
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let _header = db.read_header().expect("could not read header.");
 
         // Add 10 record in the DB
         for i in 0..10 {
@@ -738,19 +2401,20 @@ mod tests {
         }
 
         let err = db.check_db_file().expect("could not check db file.");
-        assert_eq!(err, DbIssue::UnorderedRecord);
+        assert_eq!(err, DbIssue::UnorderedRecord(1));
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
     }
 
     #[test]
     fn reorder_db() {
         let path = "reordered.db";
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
 
-        let mut db = PhysicalDB::create(&Path::new(path), None).expect("could not create db.");
-        let header = db.read_header().expect("could not read header.");
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let _header = db.read_header().expect("could not read header.");
 
         // Add 10 record in the DB in reverse order
         for i in 0..10 {
@@ -763,24 +2427,67 @@ mod tests {
         }
 
         let err = db.check_db_file().expect("could not check db file.");
-        assert_eq!(err, DbIssue::UnorderedRecord);
+        assert_eq!(err, DbIssue::UnorderedRecord(1));
 
         let res = db.reorder_record();
-        assert_eq!(res.is_ok(), true);
+        assert!(res.is_ok());
 
         let err = db.check_db_file().expect("could not check db file.");
         assert_eq!(err, DbIssue::None);
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn check_all_issues() {
+        let path = "check_all.db";
+        let _ = fs::remove_file(path);
+
+        let options = DbOptions {
+            record_checksums: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB = PhysicalDB::create_with_options(Path::new(path), None, &options)
+            .expect("could not create db.");
+        for offset in &[10, 20, 5, 30, 40] {
+            db.append_record(RecordInfo {
+                time_offset: *offset,
+                value: 1,
+            })
+            .expect("could not append record.");
+        }
+        assert_eq!(db.check_all().unwrap(), vec![DbIssue::UnorderedRecord(2)]);
+        db.close().expect("could not close db.");
+
+        // Damage the primary header, the fourth record, and cut the last one.
+        let size = db.header().record_size::<u8>();
+        let mut bytes = fs::read(path).unwrap();
+        bytes[20] ^= 1;
+        bytes[(RECORDS_START + 3 * size) as usize] ^= 1;
+        bytes.truncate((RECORDS_START + 4 * size + 1) as usize);
+        fs::write(path, bytes).unwrap();
+        assert_eq!(
+            db.check_all().unwrap(),
+            vec![
+                DbIssue::HeaderCorrupted,
+                DbIssue::UnorderedRecord(2),
+                DbIssue::RecordCorrupted(3),
+                DbIssue::MismatchRecordAmount,
+            ]
+        );
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::HeaderCorrupted);
+
+        let _ = fs::remove_file(path);
     }
 
     #[test]
     fn update_record() {
         let path = "update_record.db";
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
 
-        let mut db = PhysicalDB::create(&Path::new(path), None).expect("could not create db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
         let header = db.read_header().expect("could not read header.");
         assert_eq!(header.records_number, 0);
         let origin_record = RecordInfo {
@@ -799,6 +2506,790 @@ mod tests {
         fs_record = db.read_record(0).expect("could not get record.");
         assert_eq!(updated_value, fs_record.value);
 
-        fs::remove_file(path);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn refresh_after_external_append() {
+        let path = "external_append.db";
+        let _ = fs::remove_file(path);
+
+        let mut writer: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let mut reader: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert!(reader.refresh_if_changed().expect("could not refresh."));
+        assert!(!reader.refresh_if_changed().expect("could not refresh."));
+        assert_eq!(reader.header().records_number, 0);
+
+        writer
+            .append_record(RecordInfo {
+                time_offset: 5,
+                value: 10,
+            })
+            .expect("could not append record.");
+
+        assert!(reader.refresh_if_changed().expect("could not refresh."));
+        assert_eq!(reader.header().records_number, 1);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_handle_after_replace() {
+        let path = "stale_handle.db";
+        let replacement = "stale_handle.db.new";
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(replacement);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        db.open().expect("could not open db.");
+        assert_eq!(db.check_stale(), Ok(()));
+
+        let mut other: PhysicalDB =
+            PhysicalDB::create(Path::new(replacement), None).expect("could not create db.");
+        other
+            .append_record(RecordInfo {
+                time_offset: 5,
+                value: 10,
+            })
+            .expect("could not append record.");
+        fs::rename(replacement, path).expect("could not replace db.");
+
+        let record = RecordInfo {
+            time_offset: 6,
+            value: 11,
+        };
+        assert_eq!(db.append_record(record), Err(TSLiteError::StaleHandle));
+        db.reopen().expect("could not reopen db.");
+        assert_eq!(db.header().records_number, 1);
+        db.append_record(record).expect("could not append record.");
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn create_does_not_overwrite() {
+        let path = "create_no_overwrite.db";
+        let _ = fs::remove_file(path);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        db.append_record(RecordInfo {
+            time_offset: 5,
+            value: 10,
+        })
+        .expect("could not append record.");
+
+        let again = PhysicalDB::<u8>::create(Path::new(path), None);
+        assert_eq!(again.err(), Some(TSLiteError::AlreadyExists));
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.read_header().unwrap().records_number, 1);
+
+        let options = DbOptions {
+            overwrite: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB = PhysicalDB::create_with_options(Path::new(path), None, &options)
+            .expect("could not overwrite db.");
+        assert_eq!(db.read_header().unwrap().records_number, 0);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn refresh_header() {
+        let path = "refresh_header.db";
+        let _ = fs::remove_file(path);
+
+        let mut writer: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let mut reader: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        writer
+            .append_record(RecordInfo {
+                time_offset: 5,
+                value: 10,
+            })
+            .expect("could not append record.");
+        assert_eq!(writer.header().records_number, 1);
+        assert_eq!(reader.header().records_number, 0);
+
+        reader.refresh().expect("could not refresh header.");
+        assert_eq!(reader.header().records_number, 1);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn recover_torn_header() {
+        let path = "torn_header.db";
+        let _ = fs::remove_file(path);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        db.append_record(RecordInfo {
+            time_offset: 5,
+            value: 10,
+        })
+        .expect("could not append record.");
+        db.close().expect("could not close db.");
+
+        // Scramble the record count of the primary copy.
+        let mut f = OpenOptions::new().write(true).open(path).unwrap();
+        f.seek(SeekFrom::Start(12)).unwrap();
+        f.write_all(&[0xFF, 0xFF]).unwrap();
+        drop(f);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().records_number, 1);
+        let mut buffer = [0; HEADER_COPY_SIZE as usize];
+        let mut f = File::open(path).unwrap();
+        f.read_exact(&mut buffer).unwrap();
+        assert!(DbHeader::from_checked_bytes(&buffer).unwrap().is_some());
+
+        // Scramble both copies.
+        let mut f = OpenOptions::new().write(true).open(path).unwrap();
+        f.seek(SeekFrom::Start(12)).unwrap();
+        f.write_all(&[0xFF, 0xFF]).unwrap();
+        f.seek(SeekFrom::Start(HEADER_COPY_SIZE + 12)).unwrap();
+        f.write_all(&[0xFF, 0xFF]).unwrap();
+        drop(f);
+        assert_eq!(db.read_header().err(), Some(TSLiteError::HeaderCorrupted));
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn shadow_header_written_on_sync() {
+        let path = "shadow_header.db";
+        let _ = fs::remove_file(path);
+
+        let copies = || {
+            let bytes = fs::read(path).unwrap();
+            let (primary, shadow) =
+                bytes[..RECORDS_START as usize].split_at(HEADER_COPY_SIZE as usize);
+            let count = |copy| {
+                DbHeader::from_checked_bytes(copy)
+                    .unwrap()
+                    .unwrap()
+                    .records_number
+            };
+            (count(primary), count(shadow))
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        for i in 0..2 {
+            db.append_record(RecordInfo {
+                time_offset: i,
+                value: 1,
+            })
+            .expect("could not append record.");
+        }
+        assert_eq!(copies(), (2, 0));
+        db.sync().expect("could not sync db.");
+        assert_eq!(copies(), (2, 2));
+
+        // The primary copy is torn after an append: the records appended since the sync are counted back.
+        db.append_record(RecordInfo {
+            time_offset: 2,
+            value: 1,
+        })
+        .expect("could not append record.");
+        let mut f = OpenOptions::new().write(true).open(path).unwrap();
+        f.seek(SeekFrom::Start(12)).unwrap();
+        f.write_all(&[0xFF, 0xFF]).unwrap();
+        drop(f);
+        db.reopen().expect("could not reopen db.");
+        assert_eq!(db.header().records_number, 2);
+        db.repair().expect("could not repair db.");
+        assert_eq!(db.header().records_number, 3);
+        db.close().expect("could not close db.");
+        assert_eq!(copies(), (3, 3));
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn reject_foreign_files() {
+        let path = "foreign_file.db";
+        let _ = fs::remove_file(path);
+
+        fs::write(path, b"time,value\n2020-01-01T00:00:00Z,1\n").unwrap();
+        assert_eq!(
+            PhysicalDB::<u8>::new(Path::new(path), None).err(),
+            Some(TSLiteError::NotADatabase)
+        );
+        fs::write(path, vec![0x42; RECORDS_START as usize]).unwrap();
+        assert_eq!(
+            PhysicalDB::<u8>::new(Path::new(path), None).err(),
+            Some(TSLiteError::NotADatabase)
+        );
+
+        // A file written in a later version of the format.
+        let _ = fs::remove_file(path);
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        db.close().expect("could not close db.");
+        let mut bytes = fs::read(path).unwrap();
+        assert!(bytes.starts_with(MAGIC));
+        bytes[MAGIC.len()] = FORMAT_VERSION + 1;
+        bytes[HEADER_COPY_SIZE as usize + MAGIC.len()] = FORMAT_VERSION + 1;
+        fs::write(path, bytes).unwrap();
+        assert_eq!(
+            db.read_header().err(),
+            Some(TSLiteError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn advisory_lock() {
+        let path = "advisory_lock.db";
+        let _ = fs::remove_file(path);
+
+        let mut first: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let mut second: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+
+        first.lock_exclusive().expect("could not lock db.");
+        assert_eq!(second.try_lock_exclusive(), Ok(false));
+        first.unlock().expect("could not unlock db.");
+        assert_eq!(second.try_lock_exclusive(), Ok(true));
+        second.unlock().expect("could not unlock db.");
+
+        first.lock_shared().expect("could not lock db.");
+        second.lock_shared().expect("could not lock db.");
+        first.close().expect("could not close db.");
+        second.close().expect("could not close db.");
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn read_into_buffers() {
+        let path = "read_into.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for i in 0..5 {
+            db.append_record(RecordInfo {
+                time_offset: i * 10,
+                value: i as u8,
+            })
+            .expect("could not append record.");
+        }
+
+        let mut records = vec![RecordInfo {
+            time_offset: 0,
+            value: 255,
+        }];
+        db.read_range_into(origin.add_seconds(10), origin.add_seconds(30), &mut records)
+            .expect("could not read range.");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].value, 1);
+        assert_eq!(records[1].value, 2);
+
+        let size = RecordInfo::<u8>::SIZE as usize;
+        let mut buf = vec![0; size * 3 + 1];
+        assert_eq!(db.read_records_into(3, &mut buf).unwrap(), 2);
+        assert_eq!(
+            RecordInfo::<u8>::try_from(&buf[size..2 * size])
+                .unwrap()
+                .value,
+            4
+        );
+        assert_eq!(db.read_records_into(0, &mut buf).unwrap(), 3);
+        assert_eq!(
+            RecordInfo::<u8>::try_from(&buf[..size])
+                .unwrap()
+                .time_offset,
+            0
+        );
+        assert_eq!(
+            RecordInfo::<u8>::try_from(&buf[..size - 1]),
+            Err(TSLiteError::ShortRead("record".to_string()))
+        );
+        assert_eq!(db.read_records_into(5, &mut buf).unwrap(), 0);
+        assert_eq!(
+            db.read_records_into(6, &mut buf),
+            Err(TSLiteError::IndexOutOfBound)
+        );
+
+        db.append_record(RecordInfo {
+            time_offset: 40,
+            value: 5,
+        })
+        .expect("could not append record.");
+        assert_eq!(db.first_not_before(0).unwrap(), 0);
+        assert_eq!(db.first_not_before(35).unwrap(), 4);
+        assert_eq!(db.first_not_before(41).unwrap(), 6);
+        let records = db
+            .read_range(origin.add_seconds(40), LATEST)
+            .expect("could not read range.");
+        assert_eq!(
+            records.iter().map(|r| r.value).collect::<Vec<u8>>(),
+            vec![4, 5]
+        );
+        assert!(db
+            .read_range(origin.add_seconds(41), LATEST)
+            .unwrap()
+            .is_empty());
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn sort_on_close() {
+        let path = "sort_on_close.db";
+        let _ = fs::remove_file(path);
+
+        let options = DbOptions {
+            sort_on_close: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB = PhysicalDB::create_with_options(Path::new(path), None, &options)
+            .expect("could not create db.");
+        for offset in &[10, 20, 30] {
+            db.append_record(RecordInfo {
+                time_offset: *offset,
+                value: 1,
+            })
+            .expect("could not append record.");
+        }
+        db.close().expect("could not close db.");
+
+        // The last offset is read back from the file by the new handle.
+        let mut db: PhysicalDB = PhysicalDB::new_with_options(Path::new(path), None, &options)
+            .expect("could not open db.");
+        for offset in &[40, 5, 25, 50] {
+            db.append_record(RecordInfo {
+                time_offset: *offset,
+                value: 2,
+            })
+            .expect("could not append record.");
+        }
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::UnorderedRecord(4));
+        db.close().expect("could not close db.");
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+        let offsets: Vec<u32> = (0..7)
+            .map(|i| db.read_record(i).unwrap().time_offset)
+            .collect();
+        assert_eq!(offsets, vec![5, 10, 20, 25, 30, 40, 50]);
+
+        // Without the option, the records are left as appended.
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        db.append_record(RecordInfo {
+            time_offset: 0,
+            value: 3,
+        })
+        .expect("could not append record.");
+        db.close().expect("could not close db.");
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::UnorderedRecord(7));
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn append_over_interrupted_record() {
+        let path = "interrupted_append.db";
+        let _ = fs::remove_file(path);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        db.append_record(RecordInfo {
+            time_offset: 5,
+            value: 10,
+        })
+        .expect("could not append record.");
+        db.close().expect("could not close db.");
+
+        // Simulate a crash after part of a record was written but before the header update.
+        let mut f = OpenOptions::new().append(true).open(path).unwrap();
+        f.write_all(&[0xFF, 0xFF]).unwrap();
+        drop(f);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        db.append_record(RecordInfo {
+            time_offset: 6,
+            value: 11,
+        })
+        .expect("could not append record.");
+        assert_eq!(db.read_header().unwrap().records_number, 2);
+        assert_eq!(
+            db.read_record(1).unwrap(),
+            RecordInfo {
+                time_offset: 6,
+                value: 11
+            }
+        );
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn offset_unit() {
+        let path = "offset_unit.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp {
+            year: 2000,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            nanosecond: 0,
+        };
+        let options = DbOptions {
+            offset_unit: OffsetUnit::Hours,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        // Two centuries after the origin is out of reach of second offsets.
+        let late = Timestamp::new(2200, 6, 1, 12, 30, 0).unwrap();
+        db.append(origin.add_seconds(5400), 1)
+            .expect("could not append record.");
+        db.append(late, 2).expect("could not append record.");
+        assert_eq!(db.read_record(0).unwrap().time_offset, 1);
+
+        db.close().expect("could not close db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().offset_unit, OffsetUnit::Hours);
+        let records = db
+            .records(origin.add_seconds(1), late.add_seconds(1))
+            .expect("could not read range.");
+        assert_eq!(records.len(), 2);
+        // Dates are rounded down to the hour.
+        assert_eq!(records[0].time, origin.add_seconds(3600));
+        assert_eq!(
+            records[1].time,
+            Timestamp::new(2200, 6, 1, 12, 0, 0).unwrap()
+        );
+        assert_eq!(db.records(origin.add_seconds(3601), late).unwrap().len(), 1);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn millisecond_offsets() {
+        let path = "offset_unit_ms.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            offset_unit: OffsetUnit::Milliseconds,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        for i in 0..4 {
+            db.append(origin.add_millis(i * 250), i as u8)
+                .expect("could not append record.");
+        }
+        db.append(Timestamp::from_unix_millis(origin.unix_millis() + 1_500), 4)
+            .expect("could not append record.");
+        assert_eq!(db.read_record(4).unwrap().time_offset, 1_500);
+
+        db.close().expect("could not close db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().offset_unit, OffsetUnit::Milliseconds);
+        let records = db
+            .records(origin.add_millis(250), origin.add_seconds(1))
+            .expect("could not read range.");
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].time.nanosecond, 250_000_000);
+        assert_eq!(records[2].time.unix_millis(), origin.unix_millis() + 750);
+        assert!(records[0].time > origin.add_millis(249));
+        assert_eq!(records[0].time.truncate_to_second(), origin);
+
+        #[cfg(feature = "chrono")]
+        {
+            let date = records[1].datetime().unwrap();
+            assert_eq!(date.timestamp_subsec_millis(), 500);
+            assert_eq!(Timestamp::from(date), records[1].time);
+        }
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn microsecond_offsets() {
+        let path = "offset_unit_us.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            offset_unit: OffsetUnit::Microseconds,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        let date = Timestamp::from_unix_micros(origin.unix_micros() + 1_234_567);
+        db.append(date, 1).expect("could not append record.");
+        assert_eq!(db.read_record(0).unwrap().time_offset, 1_234_567);
+        // About 71 minutes fit in the offsets.
+        assert_eq!(
+            db.append(origin.add_seconds(4295), 2),
+            Err(TSLiteError::OffsetOverflow)
+        );
+
+        #[cfg(feature = "chrono")]
+        {
+            let late = DateTime::<Utc>::try_from(origin).unwrap()
+                + chrono::Duration::nanoseconds(2_000_000_999);
+            db.append_datetime(late, 3)
+                .expect("could not append record.");
+            assert_eq!(db.header().datetime_to_offset(&late), Ok(2_000_000));
+            assert_eq!(
+                db.header().offset_to_datetime(2_000_000),
+                late - chrono::Duration::nanoseconds(999)
+            );
+        }
+
+        db.close().expect("could not close db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().offset_unit, OffsetUnit::Microseconds);
+        let records = db
+            .records(origin, origin.add_seconds(3))
+            .expect("could not read range.");
+        assert_eq!(records[0].time, date);
+        assert_eq!(records[0].time.nanosecond, 234_567_000);
+
+        #[cfg(feature = "chrono")]
+        {
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[1].datetime().unwrap().timestamp_subsec_micros(), 0);
+            assert_eq!(
+                records[1].time.unix_micros(),
+                origin.unix_micros() + 2_000_000
+            );
+        }
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn append_records_at_once() {
+        let path = "append_records.db";
+        let _ = fs::remove_file(path);
+
+        let options = DbOptions {
+            max_future_skew: Some(std::time::Duration::from_secs(60)),
+            ..DbOptions::default()
+        };
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        let records: Vec<RecordInfo<u16>> = (0..100)
+            .map(|i| RecordInfo {
+                time_offset: i,
+                value: i as u16 * 2,
+            })
+            .collect();
+        db.append_records(&records)
+            .expect("could not append records.");
+        db.append_records(&[]).expect("could not append records.");
+        assert_eq!(db.header().records_number, 100);
+        assert_eq!(
+            fs::metadata(path).unwrap().len(),
+            RECORDS_START + 100 * RecordInfo::<u16>::SIZE
+        );
+
+        // A single rejected record rejects the whole batch.
+        let late = db
+            .header()
+            .checked_offset(&Timestamp::now().add_seconds(3600))
+            .unwrap();
+        let batch = [
+            RecordInfo {
+                time_offset: 100,
+                value: 1,
+            },
+            RecordInfo {
+                time_offset: late,
+                value: 2,
+            },
+        ];
+        assert_eq!(db.append_records(&batch), Err(TSLiteError::TooFarInFuture));
+        db.close().expect("could not close db.");
+
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().records_number, 100);
+        assert_eq!(db.read_record(99).unwrap().value, 198);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn buffered_appends() {
+        let path = "buffered_appends.db";
+        let _ = fs::remove_file(path);
+
+        let options = DbOptions {
+            buffered: true,
+            ..DbOptions::default()
+        };
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        for i in 0..3 {
+            db.append(origin.add_seconds(i), i as u8)
+                .expect("could not append record.");
+        }
+        assert_eq!(db.pending_records(), 3);
+        assert_eq!(db.header().records_number, 0);
+        assert_eq!(fs::metadata(path).unwrap().len(), RECORDS_START);
+
+        db.flush().expect("could not flush db.");
+        assert_eq!(db.pending_records(), 0);
+        assert_eq!(db.header().records_number, 3);
+        assert_eq!(db.read_record(2).unwrap().value, 2);
+
+        db.append(origin.add_seconds(3), 3)
+            .expect("could not append record.");
+        db.close().expect("could not close db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().records_number, 4);
+        assert_eq!(db.read_record(3).unwrap().value, 3);
+
+        // Pending records are written by sync, and by drop if the DB isn't closed.
+        let mut db: PhysicalDB = PhysicalDB::new_with_options(Path::new(path), None, &options)
+            .expect("could not open db.");
+        db.append(origin.add_seconds(4), 4)
+            .expect("could not append record.");
+        db.sync().expect("could not sync db.");
+        assert_eq!(db.pending_records(), 0);
+        db.append(origin.add_seconds(5), 5)
+            .expect("could not append record.");
+        drop(db);
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().records_number, 6);
+        assert_eq!(db.read_record(5).unwrap().value, 5);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn future_skew() {
+        let path = "future_skew.db";
+        let _ = fs::remove_file(path);
+
+        let options = DbOptions {
+            max_future_skew: Some(std::time::Duration::from_secs(60)),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB = PhysicalDB::create_with_options(Path::new(path), None, &options)
+            .expect("could not create db.");
+        let now = Timestamp::now();
+        db.append(now.add_seconds(30), 1)
+            .expect("could not append record.");
+        assert_eq!(
+            db.append(now.add_seconds(3600), 2),
+            Err(TSLiteError::TooFarInFuture)
+        );
+        assert_eq!(
+            db.append(now.add_seconds(-3600), 3),
+            Err(TSLiteError::BeforeOrigin)
+        );
+        assert_eq!(db.header().records_number, 1);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn shift_records() {
+        let path = "shift.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        db.append(origin.add_seconds(10), 1)
+            .expect("could not append record.");
+        db.append(origin.add_seconds(7200), 2)
+            .expect("could not append record.");
+
+        db.shift(-3600).expect("could not shift db.");
+        db.close().expect("could not close db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().origin_date, origin.add_seconds(-3600));
+        let records = db.records(origin, origin.add_seconds(7200)).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].time, origin.add_seconds(3600));
+        assert_eq!(records[0].value, 2);
+
+        let too_late = Timestamp::new(u16::MAX, 12, 31, 23, 0, 0).unwrap();
+        assert_eq!(
+            db.shift(too_late.unix_seconds() - origin.unix_seconds()),
+            Err(TSLiteError::InvalidTimestamp)
+        );
+        assert_eq!(
+            db.read_header().unwrap().origin_date,
+            origin.add_seconds(-3600)
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let path = "serde_round_trip.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 12, 30, 0).unwrap();
+        let mut db: PhysicalDB<u16> = PhysicalDB::create(Path::new(path), Some(origin)).unwrap();
+        db.set_label("unit", "celsius").unwrap();
+        let header = *db.header();
+        let json = serde_json::to_string(&header).unwrap();
+        let back: DbHeader = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.as_bytes(), header.as_bytes());
+        assert_eq!(back.label("unit"), Some("celsius".to_string()));
+
+        let record = RecordInfo {
+            time_offset: 42,
+            value: 7u16,
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            "{\"time_offset\":42,\"value\":7}"
+        );
+        let back: RecordInfo<u16> =
+            serde_json::from_str("{\"time_offset\":42,\"value\":7}").unwrap();
+        assert_eq!(back, record);
+        let issue = DbIssue::RecordCorrupted(3);
+        let back: DbIssue = serde_json::from_str(&serde_json::to_string(&issue).unwrap()).unwrap();
+        assert_eq!(back, issue);
+        // Entries overflowing the extension area are rejected.
+        let entry = format!("\"extensions\":[[1,{:?}],", vec![0u8; 60]);
+        let overflow = json.replacen("\"extensions\":[", &entry, 1);
+        assert!(serde_json::from_str::<DbHeader>(&overflow).is_err());
+
+        let _ = fs::remove_file(path);
     }
 }
@@ -0,0 +1,194 @@
+//! Platform specific file sharing and advisory locking.
+//!
+//! On Unix, locks are taken with `flock`. On Windows, files are opened with a share mode that lets other
+//! processes read, write and rename them, and locks are taken with `LockFileEx` on a single byte far past
+//! the end of the file. Locking a byte no one reads or writes keeps the locks advisory, as on Unix,
+//! instead of making other processes I/O fail. On the other platforms, taking a lock fails with an
+//! `Unsupported` I/O error.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+
+/// Let other processes open, write and rename the file while we hold it open.
+#[cfg(windows)]
+pub(crate) fn share(options: &mut OpenOptions) -> &mut OpenOptions {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+    };
+    options.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+}
+
+#[cfg(not(windows))]
+pub(crate) fn share(options: &mut OpenOptions) -> &mut OpenOptions {
+    options
+}
+
+/// Take a lock on `file`, waiting for it unless `blocking` is false.
+/// Return `false` if the lock is held by someone else and `blocking` is false.
+#[cfg(unix)]
+pub(crate) fn lock(file: &File, exclusive: bool, blocking: bool) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut operation = if exclusive {
+        libc::LOCK_EX
+    } else {
+        libc::LOCK_SH
+    };
+    if !blocking {
+        operation |= libc::LOCK_NB;
+    }
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        return Ok(true);
+    }
+
+    let error = io::Error::last_os_error();
+    if error.kind() == io::ErrorKind::WouldBlock {
+        return Ok(false);
+    }
+    Err(error)
+}
+
+/// Release the lock held on `file`.
+#[cfg(unix)]
+pub(crate) fn unlock(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) } == 0 {
+        return Ok(());
+    }
+    Err(io::Error::last_os_error())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn lock(_file: &File, _exclusive: bool, _blocking: bool) -> io::Result<bool> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file locks are not supported on this platform",
+    ))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn unlock(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// The overlapped structure pointing to the byte used as a lock.
+#[cfg(windows)]
+fn lock_region() -> windows_sys::Win32::System::IO::OVERLAPPED {
+    use windows_sys::Win32::System::IO::{OVERLAPPED, OVERLAPPED_0_0};
+
+    let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+    overlapped.Anonymous.Anonymous = OVERLAPPED_0_0 {
+        Offset: u32::MAX,
+        OffsetHigh: i32::MAX as u32,
+    };
+    overlapped
+}
+
+#[cfg(windows)]
+pub(crate) fn lock(file: &File, exclusive: bool, blocking: bool) -> io::Result<bool> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Foundation::ERROR_LOCK_VIOLATION;
+    use windows_sys::Win32::Storage::FileSystem::{
+        LockFileEx, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
+    };
+
+    let mut flags = 0;
+    if exclusive {
+        flags |= LOCKFILE_EXCLUSIVE_LOCK;
+    }
+    if !blocking {
+        flags |= LOCKFILE_FAIL_IMMEDIATELY;
+    }
+    let mut overlapped = lock_region();
+    let ret = unsafe { LockFileEx(file.as_raw_handle() as _, flags, 0, 1, 0, &mut overlapped) };
+    if ret != 0 {
+        return Ok(true);
+    }
+
+    let error = io::Error::last_os_error();
+    if error.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) {
+        return Ok(false);
+    }
+    Err(error)
+}
+
+#[cfg(windows)]
+pub(crate) fn unlock(file: &File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::UnlockFileEx;
+
+    let mut overlapped = lock_region();
+    let ret = unsafe { UnlockFileEx(file.as_raw_handle() as _, 0, 1, 0, &mut overlapped) };
+    if ret != 0 {
+        return Ok(());
+    }
+    Err(io::Error::last_os_error())
+}
+
+#[cfg(all(test, any(unix, windows)))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn open(path: &str) -> File {
+        share(OpenOptions::new().read(true).write(true).create(true))
+            .open(path)
+            .expect("could not open file.")
+    }
+
+    #[test]
+    fn shared_lock() {
+        let path = "lock_shared.db";
+        let _ = fs::remove_file(path);
+
+        let (first, second) = (open(path), open(path));
+        assert!(lock(&first, false, true).unwrap());
+        assert!(lock(&second, false, false).unwrap());
+        // A shared lock keeps exclusive locks out.
+        let third = open(path);
+        assert!(!lock(&third, true, false).unwrap());
+        unlock(&first).unwrap();
+        unlock(&second).unwrap();
+        assert!(lock(&third, true, false).unwrap());
+        unlock(&third).unwrap();
+
+        drop((first, second, third));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn exclusive_lock() {
+        let path = "lock_exclusive.db";
+        let _ = fs::remove_file(path);
+
+        let (first, second) = (open(path), open(path));
+        assert!(lock(&first, true, true).unwrap());
+        assert!(!lock(&second, false, false).unwrap());
+        unlock(&first).unwrap();
+        assert!(lock(&second, true, true).unwrap());
+        unlock(&second).unwrap();
+
+        drop((first, second));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn non_blocking_lock_held_elsewhere() {
+        let path = "lock_non_blocking.db";
+        let _ = fs::remove_file(path);
+
+        let (first, second) = (open(path), open(path));
+        assert!(lock(&first, true, false).unwrap());
+        assert!(!lock(&second, true, false).unwrap());
+        assert!(!lock(&second, false, false).unwrap());
+        // Dropping the handle releases its lock.
+        drop(first);
+        assert!(lock(&second, true, false).unwrap());
+        unlock(&second).unwrap();
+
+        drop(second);
+        let _ = fs::remove_file(path);
+    }
+}
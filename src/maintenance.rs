@@ -0,0 +1,246 @@
+//! Periodic maintenance of a DB from a background thread.
+
+use crate::{DbIssue, PhysicalDB, RecordValue, SharedDB, TSLiteError};
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+type TaskFn<V> = dyn FnMut(&mut PhysicalDB<V>) -> Result<(), TSLiteError> + Send;
+type EventFn = dyn FnMut(MaintenanceEvent) + Send;
+
+/// A maintenance operation run by a [`Scheduler`].
+pub enum Task<V: RecordValue> {
+    /// Check the integrity of the DB, and reorder its records if they are not in chronological order.
+    Check,
+    /// Drop the records older than the given age.
+    Prune { keep: Duration },
+    /// Any other operation, such as a compaction or a rollup into another DB.
+    Custom(Box<TaskFn<V>>),
+}
+
+impl<V: RecordValue> fmt::Debug for Task<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Task::Check => f.write_str("Check"),
+            Task::Prune { keep } => f.debug_struct("Prune").field("keep", keep).finish(),
+            Task::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// What happened during a maintenance run.
+#[derive(Debug, PartialEq)]
+pub enum MaintenanceEvent {
+    /// A check found an issue. `UnorderedRecord` is fixed right away, the other issues are only reported.
+    Issue(DbIssue),
+    /// Records were pruned.
+    Pruned(u64),
+    /// A task failed, it is run again at its next interval.
+    Failed(TSLiteError),
+}
+
+/// Runs maintenance tasks on a shared DB at regular intervals.
+pub struct Scheduler<V: RecordValue> {
+    db: SharedDB<V>,
+    jobs: Vec<(Duration, Task<V>)>,
+    on_event: Option<Box<EventFn>>,
+}
+
+impl<V: RecordValue> fmt::Debug for Scheduler<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("jobs", &self.jobs)
+            .finish()
+    }
+}
+
+impl<V: RecordValue + Send + 'static> Scheduler<V> {
+    pub fn new(db: SharedDB<V>) -> Scheduler<V> {
+        Scheduler {
+            db,
+            jobs: Vec::new(),
+            on_event: None,
+        }
+    }
+
+    /// Run `task` every `interval`, the first run happening one interval after the start.
+    pub fn every(&mut self, interval: Duration, task: Task<V>) -> &mut Scheduler<V> {
+        self.jobs.push((interval, task));
+        self
+    }
+
+    /// Set a callback receiving the issues found and the errors met by the tasks.
+    pub fn on_event<F>(&mut self, callback: F) -> &mut Scheduler<V>
+    where
+        F: FnMut(MaintenanceEvent) + Send + 'static,
+    {
+        self.on_event = Some(Box::new(callback));
+        self
+    }
+
+    /// Start running the tasks in a background thread, until the returned handle is stopped or dropped.
+    pub fn start(self) -> SchedulerHandle {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = stop.clone();
+        let thread = thread::spawn(move || self.run(&signal));
+        SchedulerHandle {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(mut self, stop: &(Mutex<bool>, Condvar)) {
+        let start = Instant::now();
+        let mut due: Vec<Instant> = self.jobs.iter().map(|(i, _)| start + *i).collect();
+        let (stopped, condvar) = stop;
+        loop {
+            let next = match due.iter().min() {
+                Some(next) => *next,
+                None => return,
+            };
+            let mut guard = stopped.lock().unwrap_or_else(|e| e.into_inner());
+            while !*guard && Instant::now() < next {
+                let timeout = next.saturating_duration_since(Instant::now());
+                guard = condvar
+                    .wait_timeout(guard, timeout)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+            if *guard {
+                return;
+            }
+            drop(guard);
+
+            let now = Instant::now();
+            for (i, (interval, task)) in self.jobs.iter_mut().enumerate() {
+                if due[i] > now {
+                    continue;
+                }
+                due[i] = now + *interval;
+                let mut db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+                let events = run_task(&mut db, task);
+                drop(db);
+                if let Some(on_event) = self.on_event.as_mut() {
+                    events.into_iter().for_each(on_event);
+                }
+            }
+        }
+    }
+}
+
+fn run_task<V: RecordValue>(db: &mut PhysicalDB<V>, task: &mut Task<V>) -> Vec<MaintenanceEvent> {
+    let result = match task {
+        Task::Check => db.check_db_file().and_then(|issue| match issue {
+            DbIssue::None => Ok(Vec::new()),
+            DbIssue::UnorderedRecord(_) => db
+                .reorder_record()
+                .map(|_| vec![MaintenanceEvent::Issue(issue)]),
+            _ => Ok(vec![MaintenanceEvent::Issue(issue)]),
+        }),
+        Task::Prune { keep } => db.prune_older_than(*keep).map(|n| match n {
+            0 => Vec::new(),
+            n => vec![MaintenanceEvent::Pruned(n)],
+        }),
+        Task::Custom(f) => f(db).map(|_| Vec::new()),
+    };
+    result.unwrap_or_else(|e| vec![MaintenanceEvent::Failed(e)])
+}
+
+/// Stops the scheduler it comes from when stopped or dropped.
+#[derive(Debug)]
+pub struct SchedulerHandle {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SchedulerHandle {
+    /// Stop the scheduler, waiting for the task being run, if any, to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let (stopped, condvar) = &*self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RecordInfo, Timestamp};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::mpsc;
+
+    #[test]
+    fn scheduled_maintenance() {
+        let path = "maintenance.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::now().add_seconds(-7200);
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for offset in &[10, 3700, 7100, 5] {
+            db.append_record(RecordInfo {
+                time_offset: *offset,
+                value: 1,
+            })
+            .expect("could not append record.");
+        }
+        let db = Arc::new(Mutex::new(db));
+
+        let (sender, receiver) = mpsc::channel();
+        let mut scheduler = Scheduler::new(db.clone());
+        let runs = Arc::new(Mutex::new(0));
+        let counter = runs.clone();
+        scheduler
+            .every(Duration::from_millis(10), Task::Check)
+            .every(
+                Duration::from_millis(30),
+                Task::Prune {
+                    keep: Duration::from_secs(3600),
+                },
+            )
+            .every(
+                Duration::from_millis(10),
+                Task::Custom(Box::new(move |_| {
+                    *counter.lock().unwrap() += 1;
+                    Ok(())
+                })),
+            )
+            .on_event(move |e| {
+                let _ = sender.send(e);
+            });
+        let handle = scheduler.start();
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            receiver.recv_timeout(timeout).unwrap(),
+            MaintenanceEvent::Issue(DbIssue::UnorderedRecord(3))
+        );
+        assert_eq!(
+            receiver.recv_timeout(timeout).unwrap(),
+            MaintenanceEvent::Pruned(2)
+        );
+        handle.stop();
+        assert!(*runs.lock().unwrap() > 0);
+
+        let mut db = db.lock().unwrap();
+        assert_eq!(db.read_header().unwrap().records_number, 2);
+        assert_eq!(db.read_record(0).unwrap().time_offset, 3700);
+
+        let _ = fs::remove_file(path);
+    }
+}
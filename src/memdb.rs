@@ -0,0 +1,268 @@
+//! An in-memory layer over a DB: the records are read from RAM and the appends are written to the file
+//! in batches, when enough of them are pending or the oldest of them waited long enough.
+
+use crate::{
+    DbHeader, PhysicalDB, Record, RecordInfo, RecordValue, TSLiteError, TimeSeries, Timestamp,
+};
+use std::time::Duration;
+
+/// When a [`MemDB`] writes its pending records to its file. With neither limit, only
+/// [`MemDB::flush`] and [`MemDB::close`] write them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FlushPolicy {
+    /// Write the pending records once there are this many of them.
+    pub max_pending: Option<usize>,
+    /// Write the pending records once the oldest of them has been waiting this long, according to the clock
+    /// of the DB. Checked on every append and by [`MemDB::flush_if_due`], which can be called from a timer.
+    pub max_age: Option<Duration>,
+}
+
+/// A DB whose records are all held in memory, the appends being written to its file in batches according to
+/// its [`FlushPolicy`].
+/// The records are assumed to be chronologically ordered, as in the file.
+/// The pending records are lost if the `MemDB` is dropped without being closed.
+#[derive(Debug)]
+pub struct MemDB<V: RecordValue = u8> {
+    db: PhysicalDB<V>,
+    /// Every record, with its value decoded.
+    records: Vec<RecordInfo<V>>,
+    /// The number of records already in the file, the others are pending.
+    written: usize,
+    policy: FlushPolicy,
+    /// When the oldest pending record was appended.
+    pending_since: Option<Timestamp>,
+}
+
+impl<V: RecordValue> MemDB<V> {
+    /// Load every record of `db` in memory.
+    pub fn new(mut db: PhysicalDB<V>, policy: FlushPolicy) -> Result<MemDB<V>, TSLiteError> {
+        let records = db
+            .iter()
+            .collect::<Result<Vec<RecordInfo<V>>, TSLiteError>>()?;
+        Ok(MemDB {
+            written: records.len(),
+            db,
+            records,
+            policy,
+            pending_since: None,
+        })
+    }
+
+    /// The header of the DB, whose number of records only counts the ones written to the file.
+    pub fn header(&self) -> &DbHeader {
+        self.db.header()
+    }
+
+    /// The number of records, pending ones included.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Whether there is no record.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// The number of records not written to the file yet.
+    pub fn pending_records(&self) -> usize {
+        self.records.len() - self.written
+    }
+
+    /// Add a record, written to the file later according to the [`FlushPolicy`].
+    pub fn append_record(&mut self, record: RecordInfo<V>) -> Result<(), TSLiteError> {
+        self.append_records(&[record])
+    }
+
+    /// Add several records, written to the file later according to the [`FlushPolicy`].
+    /// Nothing is added if any of the records is rejected. If the records are added but the flush they trigger
+    /// fails, the error is returned and they stay pending until the next flush: they must not be appended again.
+    pub fn append_records(&mut self, records: &[RecordInfo<V>]) -> Result<(), TSLiteError> {
+        for r in records {
+            self.db.check_future_skew(r.time_offset)?;
+        }
+        if records.is_empty() {
+            return Ok(());
+        }
+        self.records.extend_from_slice(records);
+        if self.pending_since.is_none() {
+            self.pending_since = Some(self.db.options.now());
+        }
+        self.flush_if_due().map(|_| ())
+    }
+
+    /// Read the record at index `rec_id`, pending ones included.
+    pub fn read_record(&self, rec_id: u64) -> Result<RecordInfo<V>, TSLiteError> {
+        self.records
+            .get(rec_id as usize)
+            .copied()
+            .ok_or(TSLiteError::IndexOutOfBound)
+    }
+
+    /// Every record whose date is within `[start, end[`.
+    pub fn read_range(
+        &self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Vec<RecordInfo<V>> {
+        self.slice(&start.into(), &end.into()).to_vec()
+    }
+
+    /// Every record whose date is within `[start, end[`, with its date resolved against the origin.
+    pub fn records(
+        &self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Vec<Record<V>> {
+        let header = self.db.header();
+        self.slice(&start.into(), &end.into())
+            .iter()
+            .map(|r| r.resolve(header))
+            .collect()
+    }
+
+    /// The records within `[start, end[`, found with binary searches.
+    fn slice(&self, start: &Timestamp, end: &Timestamp) -> &[RecordInfo<V>] {
+        let header = self.db.header();
+        let start = header.offset_not_before(start);
+        let end = header.offset_not_before(end);
+        let first = self
+            .records
+            .partition_point(|r| (r.time_offset as i64) < start);
+        let last = self
+            .records
+            .partition_point(|r| (r.time_offset as i64) < end);
+        &self.records[first..last.max(first)]
+    }
+
+    /// Write the pending records to the file at once.
+    pub fn flush(&mut self) -> Result<(), TSLiteError> {
+        self.db.append_records(&self.records[self.written..])?;
+        self.db.flush()?;
+        self.written = self.records.len();
+        self.pending_since = None;
+        Ok(())
+    }
+
+    /// Write the pending records if the [`FlushPolicy`] says so, and return whether they were written.
+    pub fn flush_if_due(&mut self) -> Result<bool, TSLiteError> {
+        let full = self
+            .policy
+            .max_pending
+            .is_some_and(|max| self.pending_records() >= max);
+        let old = match (self.policy.max_age, self.pending_since) {
+            (Some(age), Some(since)) => {
+                self.db.options.now().unix_nanos() - since.unix_nanos() >= age.as_nanos() as i128
+            }
+            _ => false,
+        };
+        if !full && !old {
+            return Ok(false);
+        }
+        self.flush()?;
+        Ok(true)
+    }
+
+    /// Write the pending records, close the file and give the DB back.
+    pub fn close(mut self) -> Result<PhysicalDB<V>, TSLiteError> {
+        self.flush()?;
+        self.db.close()?;
+        Ok(self.db)
+    }
+}
+
+impl<V: RecordValue> TimeSeries for MemDB<V> {
+    type Value = V;
+
+    fn append(&mut self, time: Timestamp, value: V) -> Result<(), TSLiteError> {
+        let time_offset = self.header().checked_offset(&time)?;
+        self.append_record(RecordInfo { time_offset, value })
+    }
+
+    fn range(&mut self, start: Timestamp, end: Timestamp) -> Result<Vec<Record<V>>, TSLiteError> {
+        Ok(self.records(start, end))
+    }
+
+    fn now(&self) -> Timestamp {
+        self.db.options.now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, MockClock, LATEST};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn write_behind() {
+        let path = "memdb.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(origin);
+        let options = DbOptions {
+            clock: Some(Arc::new(clock.clone())),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        db.append(origin, 1).expect("could not append record.");
+        let policy = FlushPolicy {
+            max_pending: Some(3),
+            max_age: Some(Duration::from_secs(60)),
+        };
+        let mut mem = MemDB::new(db, policy).expect("could not load db.");
+        assert_eq!(mem.len(), 1);
+
+        mem.append(origin.add_seconds(1), 2).unwrap();
+        mem.append(origin.add_seconds(2), 3).unwrap();
+        assert_eq!(mem.pending_records(), 2);
+        assert_eq!(mem.header().records_number, 1);
+        assert_eq!(mem.read_record(2).unwrap().value, 3);
+        let values: Vec<u8> = mem
+            .records(origin.add_seconds(1), LATEST)
+            .iter()
+            .map(|r| r.value)
+            .collect();
+        assert_eq!(values, vec![2, 3]);
+
+        // The third pending record reaches the threshold.
+        mem.append(origin.add_seconds(3), 4).unwrap();
+        assert_eq!(mem.pending_records(), 0);
+        assert_eq!(mem.header().records_number, 4);
+
+        // A pending record waiting long enough is written by the timer.
+        mem.append(origin.add_seconds(4), 5).unwrap();
+        assert!(!mem.flush_if_due().unwrap());
+        clock.advance(Duration::from_secs(60));
+        assert!(mem.flush_if_due().unwrap());
+        assert_eq!(mem.header().records_number, 5);
+
+        mem.append(origin.add_seconds(5), 6).unwrap();
+        let db = mem.close().expect("could not close db.");
+        assert_eq!(db.header().records_number, 6);
+
+        // Ages below a second are not rounded.
+        let policy = FlushPolicy {
+            max_pending: None,
+            max_age: Some(Duration::from_millis(500)),
+        };
+        let mut mem = MemDB::new(db, policy).unwrap();
+        mem.append(origin.add_seconds(60), 7).unwrap();
+        clock.advance(Duration::from_millis(499));
+        mem.append(origin.add_seconds(61), 8).unwrap();
+        assert_eq!(mem.pending_records(), 2);
+        clock.advance(Duration::from_millis(1));
+        assert!(mem.flush_if_due().unwrap());
+        let db = mem.close().expect("could not close db.");
+
+        let mem = MemDB::new(db, FlushPolicy::default()).unwrap();
+        assert_eq!(mem.read_range(origin.add_seconds(5), LATEST).len(), 3);
+        assert_eq!(mem.read_record(8), Err(TSLiteError::IndexOutOfBound));
+
+        let _ = fs::remove_file(path);
+    }
+}
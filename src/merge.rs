@@ -0,0 +1,157 @@
+//! Merging the records of two DBs, such as the ones recorded by two devices, into a new DB.
+
+use crate::{DbHeader, DbOptions, PhysicalDB, RecordInfo, RecordValue, TSLiteError, Timestamp};
+use std::fs;
+use std::path::Path;
+
+/// Number of records appended to the merged DB at once.
+const MERGE_BATCH: usize = 4096;
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Merge the records of this DB and of the DB at `other` into a new DB at `dest`, interleaved by date,
+    /// and return the number of records written.
+    ///
+    /// The new DB has the earlier of the two origins and the finer of the two offset units, the offsets of the
+    /// records being rebased onto them, and no transforms: the values are copied decoded. Records at the same
+    /// date are both kept, the ones of this DB first. Both DBs are read once, by chunks, and are expected to be
+    /// chronologically ordered.
+    /// Fail with `AlreadyExists` if there is already a file at `dest`, with `ValueWidthMismatch` if the DB at
+    /// `other` doesn't hold `V` values, and with `OffsetOverflow` if a record is too late for the new offsets,
+    /// in which case the file at `dest` is removed.
+    pub fn merge(&mut self, other: &Path, dest: &Path) -> Result<u64, TSLiteError> {
+        self.flush()?;
+        self.refresh_if_changed()?;
+        let mut other: PhysicalDB<V> = PhysicalDB::open_path(other)?;
+        let (first, second) = (self.header, other.header);
+        let options = DbOptions {
+            offset_unit: if first.offset_unit.nanoseconds() <= second.offset_unit.nanoseconds() {
+                first.offset_unit
+            } else {
+                second.offset_unit
+            },
+            ..DbOptions::default()
+        };
+        let origin = first.origin_date.min(second.origin_date);
+        let mut merged: PhysicalDB<V> =
+            PhysicalDB::create_with_options(dest, Some(origin), &options)?;
+        let result = self.merge_into(&mut other, &mut merged);
+        if result.is_err() {
+            drop(merged);
+            let _ = fs::remove_file(dest);
+        }
+        result
+    }
+
+    /// Append the records of this DB and of `other`, interleaved by date, to the empty DB `merged`.
+    fn merge_into(
+        &mut self,
+        other: &mut PhysicalDB<V>,
+        merged: &mut PhysicalDB<V>,
+    ) -> Result<u64, TSLiteError> {
+        let (first, second, target) = (self.header, other.header, merged.header);
+        let dated = |header: DbHeader| {
+            move |r: Result<RecordInfo<V>, TSLiteError>| {
+                r.map(|r| (header.offset_to_date(r.time_offset), r.value))
+            }
+        };
+        let mut left = self.iter().map(dated(first)).peekable();
+        let mut right = other.iter().map(dated(second)).peekable();
+        let mut batch = Vec::with_capacity(MERGE_BATCH);
+        let mut written = 0;
+        loop {
+            let take_left = match (left.peek(), right.peek()) {
+                (None, None) => break,
+                (Some(Err(_)), _) | (Some(_), None) => true,
+                (None, Some(_)) | (_, Some(Err(_))) => false,
+                (Some(Ok((l, _))), Some(Ok((r, _)))) => l <= r,
+            };
+            let (date, value): (Timestamp, V) = match take_left {
+                true => left.next().unwrap()?,
+                false => right.next().unwrap()?,
+            };
+            batch.push(RecordInfo {
+                time_offset: target.checked_offset(&date)?,
+                value,
+            });
+            if batch.len() == MERGE_BATCH {
+                merged.append_records(&batch)?;
+                written += batch.len() as u64;
+                batch.clear();
+            }
+        }
+        merged.append_records(&batch)?;
+        written += batch.len() as u64;
+        merged.close()?;
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DbIssue, DbOptions, OffsetUnit, PhysicalDB, RecordInfo, Timestamp};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn merge_two_devices() {
+        let (first, second, dest) = ("merge_a.db", "merge_b.db", "merge_dest.db");
+        for path in [first, second, dest].iter() {
+            let _ = fs::remove_file(path);
+        }
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut a: PhysicalDB<u16> =
+            PhysicalDB::create(Path::new(first), Some(origin.add_seconds(60))).unwrap();
+        for i in 0..5 {
+            a.append_record(RecordInfo {
+                time_offset: i * 10,
+                value: i as u16,
+            })
+            .unwrap();
+        }
+        let options = DbOptions {
+            offset_unit: OffsetUnit::Milliseconds,
+            ..DbOptions::default()
+        };
+        let mut b: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(second), Some(origin), &options).unwrap();
+        for i in 0..4 {
+            b.append_record(RecordInfo {
+                time_offset: 55_000 + i * 10_500,
+                value: 100 + i as u16,
+            })
+            .unwrap();
+        }
+        drop(b);
+
+        assert_eq!(a.merge(Path::new(second), Path::new(dest)).unwrap(), 9);
+        let mut merged: PhysicalDB<u16> = PhysicalDB::open_path(Path::new(dest)).unwrap();
+        assert_eq!(merged.header().origin_date, origin);
+        assert_eq!(merged.header().offset_unit, OffsetUnit::Milliseconds);
+        let records: Vec<(u32, u16)> = merged
+            .iter()
+            .map(|r| r.map(|r| (r.time_offset, r.value)).unwrap())
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                (55_000, 100),
+                (60_000, 0),
+                (65_500, 101),
+                (70_000, 1),
+                (76_000, 102),
+                (80_000, 2),
+                (86_500, 103),
+                (90_000, 3),
+                (100_000, 4),
+            ]
+        );
+        assert_eq!(merged.check_db_file().unwrap(), DbIssue::None);
+        assert!(a.merge(Path::new(second), Path::new(dest)).is_err());
+
+        for path in [first, second, dest].iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
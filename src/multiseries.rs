@@ -0,0 +1,264 @@
+//! Several series sharing one DB, such as the metrics of a device.
+//!
+//! A multi-series DB is made of two files: a DB whose values are [`SeriesValue`]s, which tag each record with
+//! the id of its series, and the names of the series, next to it with the `series` extension. Each name is
+//! stored on its own line, and the id of a series is the number of its line. The records of every series are
+//! appended to the same DB, so they are chronologically ordered together and a range query reads the records
+//! of the other series in the range as well.
+//!
+//! A name is written and synced before the first record of its series is appended, so a crash between the
+//! two only leaves a series without records.
+
+use crate::{
+    telemetry, DbHeader, PhysicalDB, Record, RecordInfo, RecordValue, TSLiteError, Timestamp,
+};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A value tagged with the id of its series, the record value of a [`MultiSeriesDB`].
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct SeriesValue<V: RecordValue> {
+    pub series: u16,
+    pub value: V,
+}
+
+impl<V: RecordValue> RecordValue for SeriesValue<V> {
+    const WIDTH: usize = 2 + V::WIDTH;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[..2].copy_from_slice(&self.series.to_le_bytes());
+        self.value.encode(&mut out[2..]);
+    }
+
+    fn decode(bytes: &[u8]) -> SeriesValue<V> {
+        SeriesValue {
+            series: u16::from_le_bytes([bytes[0], bytes[1]]),
+            value: V::decode(&bytes[2..]),
+        }
+    }
+
+    fn as_f64(&self) -> f64 {
+        self.value.as_f64()
+    }
+
+    /// The value of the series 0 closest to `value`: the DB of a [`MultiSeriesDB`] must not use transforms.
+    fn from_f64(value: f64) -> SeriesValue<V> {
+        SeriesValue {
+            series: 0,
+            value: V::from_f64(value),
+        }
+    }
+}
+
+/// A DB holding the records of several named series.
+#[derive(Debug)]
+pub struct MultiSeriesDB<V: RecordValue = u8> {
+    db: PhysicalDB<SeriesValue<V>>,
+    names: File,
+    /// The name of every series, by id.
+    series: Vec<String>,
+}
+
+/// The path of the file naming the series of the multi-series DB at `path`.
+fn names_path(path: &Path) -> PathBuf {
+    path.with_extension("series")
+}
+
+impl<V: RecordValue> MultiSeriesDB<V> {
+    /// Create a new multi-series DB at `path`, with the same meaning for `origin_date` as
+    /// [`PhysicalDB::create`]. Fail with `AlreadyExists` if there is already a DB at `path`.
+    pub fn create(
+        path: &Path,
+        origin_date: Option<Timestamp>,
+    ) -> Result<MultiSeriesDB<V>, TSLiteError> {
+        let db = PhysicalDB::create(path, origin_date)?;
+        let names = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(names_path(path))
+            .map_err(TSLiteError::Io)?;
+        Ok(MultiSeriesDB {
+            db,
+            names,
+            series: Vec::new(),
+        })
+    }
+
+    /// Open the multi-series DB at `path`, or create it if it doesn't exist.
+    pub fn new(
+        path: &Path,
+        origin_date: Option<Timestamp>,
+    ) -> Result<MultiSeriesDB<V>, TSLiteError> {
+        if !path.exists() {
+            return MultiSeriesDB::create(path, origin_date);
+        }
+        let db = PhysicalDB::new(path, None)?;
+        let content = fs::read(names_path(path)).map_err(TSLiteError::Io)?;
+        let series = String::from_utf8_lossy(&content)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let names = OpenOptions::new()
+            .append(true)
+            .open(names_path(path))
+            .map_err(TSLiteError::Io)?;
+        Ok(MultiSeriesDB { db, names, series })
+    }
+
+    /// The header of the DB, whose number of records counts the records of every series.
+    pub fn header(&self) -> &DbHeader {
+        self.db.header()
+    }
+
+    /// The name of every series, in the order of their ids.
+    pub fn series(&self) -> &[String] {
+        &self.series
+    }
+
+    /// The id of the series named `name`.
+    pub fn series_id(&self, name: &str) -> Option<u16> {
+        self.series
+            .iter()
+            .position(|s| s == name)
+            .map(|id| id as u16)
+    }
+
+    /// Append a record to the series named `series`, which is created if there is none.
+    /// Fail with `InvalidParameter` if the name is empty or holds a line break, or if there are already
+    /// `u16::MAX + 1` series.
+    pub fn append_to(&mut self, series: &str, record: RecordInfo<V>) -> Result<(), TSLiteError> {
+        let id = match self.series_id(series) {
+            Some(id) => id,
+            None => self.add_series(series)?,
+        };
+        self.db.append_record(RecordInfo {
+            time_offset: record.time_offset,
+            value: SeriesValue {
+                series: id,
+                value: record.value,
+            },
+        })
+    }
+
+    /// Every record of the series named `series` whose date is within `[start, end[`, none if there is no
+    /// such series.
+    pub fn records_of(
+        &mut self,
+        series: &str,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<Vec<Record<V>>, TSLiteError> {
+        let id = match self.series_id(series) {
+            Some(id) => id,
+            None => return Ok(Vec::new()),
+        };
+        let records = self.db.records(start, end)?;
+        Ok(records
+            .into_iter()
+            .filter(|r| r.value.series == id)
+            .map(|r| Record {
+                time: r.time,
+                value: r.value.value,
+            })
+            .collect())
+    }
+
+    /// Write the name of a new series and return its id.
+    fn add_series(&mut self, name: &str) -> Result<u16, TSLiteError> {
+        if name.is_empty() || name.contains('\n') {
+            return Err(TSLiteError::InvalidParameter(format!(
+                "Invalid series name {:?}.",
+                name
+            )));
+        }
+        if self.series.len() > u16::MAX as usize {
+            return Err(TSLiteError::InvalidParameter(format!(
+                "A DB holds at most {} series.",
+                u16::MAX as usize + 1
+            )));
+        }
+        let id = self.series.len() as u16;
+        self.names
+            .write_all(format!("{}\n", name).as_bytes())
+            .and_then(|_| telemetry::sync_data(&self.names))
+            .map_err(TSLiteError::Io)?;
+        self.series.push(name.to_string());
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LATEST;
+
+    #[test]
+    fn series_in_one_file() {
+        let path = Path::new("multiseries.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(names_path(path));
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: MultiSeriesDB<f32> =
+            MultiSeriesDB::create(path, Some(origin)).expect("could not create db.");
+        for i in 0..10 {
+            let temperature = RecordInfo {
+                time_offset: i * 10,
+                value: 20.0 + i as f32,
+            };
+            db.append_to("temperature", temperature).unwrap();
+            if i % 2 == 0 {
+                let humidity = RecordInfo {
+                    time_offset: i * 10 + 5,
+                    value: 50.0 - i as f32,
+                };
+                db.append_to("humidity", humidity).unwrap();
+            }
+        }
+        assert!(matches!(
+            db.append_to(
+                "bad\nname",
+                RecordInfo {
+                    time_offset: 100,
+                    value: 0.0,
+                }
+            ),
+            Err(TSLiteError::InvalidParameter(_))
+        ));
+        drop(db);
+
+        let mut db: MultiSeriesDB<f32> =
+            MultiSeriesDB::new(path, None).expect("could not open db.");
+        assert_eq!(db.series(), ["temperature", "humidity"]);
+        assert_eq!(db.header().records_number, 15);
+        let humidity = db
+            .records_of("humidity", origin.add_seconds(20), LATEST)
+            .unwrap();
+        let values: Vec<f32> = humidity.iter().map(|r| r.value).collect();
+        assert_eq!(values, vec![48.0, 46.0, 44.0, 42.0]);
+        assert_eq!(humidity[0].time, origin.add_seconds(25));
+        let temperature = db
+            .records_of("temperature", origin, origin.add_seconds(30))
+            .unwrap();
+        assert_eq!(temperature.len(), 3);
+        assert!(db
+            .records_of("pressure", origin, LATEST)
+            .unwrap()
+            .is_empty());
+
+        db.append_to(
+            "pressure",
+            RecordInfo {
+                time_offset: 100,
+                value: 1013.0,
+            },
+        )
+        .unwrap();
+        assert_eq!(db.series_id("pressure"), Some(2));
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(names_path(path));
+    }
+}
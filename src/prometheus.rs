@@ -0,0 +1,345 @@
+//! DB files backing a Prometheus scrape target or remote-read endpoint, with the `prometheus` feature.
+//!
+//! Each DB is a series whose metric name is the name of its file, `temperature.db` being `temperature`, and
+//! whose labels are the ones recorded in its header, see [`PhysicalDB::set_label`].
+//! [`latest_exposition`] formats the latest value of each DB in the text exposition format, to be served on a
+//! `/metrics` endpoint. [`decode_read_request`] and [`remote_read`] handle the protobuf messages of the
+//! remote-read protocol, only with sample responses: the HTTP bodies are compressed with the block format of
+//! Snappy, which is left to the server. Regular expression matchers are not supported.
+
+use crate::codec::{read_varint, write_varint};
+use crate::{PhysicalDB, RecordValue, TSLiteError, Timestamp};
+use std::fmt::Write;
+
+/// How a [`LabelMatcher`] compares the value of its label.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MatchKind {
+    Equal,
+    NotEqual,
+}
+
+/// A condition on the value of a label, a missing label having an empty value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelMatcher {
+    pub kind: MatchKind,
+    pub name: String,
+    pub value: String,
+}
+
+/// A query of a remote-read request: the samples within `[start_ms, end_ms]` of the series matching every matcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteReadQuery {
+    /// Start of the range, in milliseconds since the Unix epoch.
+    pub start_ms: i64,
+    /// End of the range, included, in milliseconds since the Unix epoch.
+    pub end_ms: i64,
+    pub matchers: Vec<LabelMatcher>,
+}
+
+impl RemoteReadQuery {
+    /// Whether the series with `labels` matches every matcher of the query.
+    pub fn matches(&self, labels: &[(String, String)]) -> bool {
+        self.matchers.iter().all(|m| {
+            let value = labels
+                .iter()
+                .find(|(name, _)| *name == m.name)
+                .map_or("", |(_, value)| value.as_str());
+            (value == m.value) == (m.kind == MatchKind::Equal)
+        })
+    }
+}
+
+/// The labels of the series of a DB, `__name__` first.
+fn series_labels<V: RecordValue>(db: &PhysicalDB<V>) -> Vec<(String, String)> {
+    let name = db
+        .path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut labels = vec![("__name__".to_string(), name)];
+    labels.extend(db.header().labels());
+    labels
+}
+
+/// Format the latest value of each DB in the Prometheus text format, with its date. The DBs without records
+/// are left out.
+pub fn latest_exposition<V: RecordValue>(
+    dbs: &mut [&mut PhysicalDB<V>],
+) -> Result<String, TSLiteError> {
+    let mut samples = Vec::new();
+    for db in dbs.iter_mut() {
+        if let Some(record) = db.last_n(1)?.pop() {
+            let record = record.resolve(db.header());
+            samples.push((series_labels(db), record));
+        }
+    }
+    samples.sort_by(|a, b| a.0[0].1.cmp(&b.0[0].1));
+
+    let mut out = String::new();
+    let mut family = None;
+    for (labels, record) in samples {
+        let name = &labels[0].1;
+        if family != Some(name.clone()) {
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            family = Some(name.clone());
+        }
+        let labels: Vec<String> = labels[1..]
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+            .collect();
+        let _ = writeln!(
+            out,
+            "{}{{{}}} {} {}",
+            name,
+            labels.join(","),
+            record.value.as_f64(),
+            record.time.unix_millis()
+        );
+    }
+    Ok(out)
+}
+
+/// Escape a label value: backslashes, double quotes and line feeds.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn malformed(what: &str) -> TSLiteError {
+    TSLiteError::InvalidData(format!("Remote-read request: {}", what))
+}
+
+/// A protobuf field: its number and its value, a varint or the content of a length-delimited field.
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Read the next field of a protobuf message and advance `data`.
+fn next_field<'a>(data: &mut &'a [u8]) -> Result<(u64, Field<'a>), TSLiteError> {
+    let key = read_varint(data).ok_or_else(|| malformed("truncated field key."))?;
+    let skip = |data: &mut &'a [u8], n: usize| -> Result<&'a [u8], TSLiteError> {
+        if data.len() < n {
+            return Err(malformed("truncated field."));
+        }
+        let (value, rest) = data.split_at(n);
+        *data = rest;
+        Ok(value)
+    };
+    let field = match key & 7 {
+        0 => Field::Varint(read_varint(data).ok_or_else(|| malformed("truncated varint."))?),
+        1 => skip(data, 8).map(|_| Field::Fixed)?,
+        2 => {
+            let n = read_varint(data).ok_or_else(|| malformed("truncated length."))?;
+            Field::Bytes(skip(data, n as usize)?)
+        }
+        5 => skip(data, 4).map(|_| Field::Fixed)?,
+        _ => return Err(malformed("unsupported wire type.")),
+    };
+    Ok((key >> 3, field))
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, TSLiteError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| malformed("invalid UTF-8 string."))
+}
+
+/// Decode the queries of a remote-read request, its body once decompressed.
+/// Fail with `InvalidData` if it is malformed and with `InvalidParameter` if a matcher uses a regular expression.
+pub fn decode_read_request(mut body: &[u8]) -> Result<Vec<RemoteReadQuery>, TSLiteError> {
+    let mut queries = Vec::new();
+    while !body.is_empty() {
+        if let (1, Field::Bytes(mut message)) = next_field(&mut body)? {
+            let mut query = RemoteReadQuery {
+                start_ms: 0,
+                end_ms: 0,
+                matchers: Vec::new(),
+            };
+            while !message.is_empty() {
+                match next_field(&mut message)? {
+                    (1, Field::Varint(n)) => query.start_ms = n as i64,
+                    (2, Field::Varint(n)) => query.end_ms = n as i64,
+                    (3, Field::Bytes(matcher)) => query.matchers.push(decode_matcher(matcher)?),
+                    _ => {}
+                }
+            }
+            queries.push(query);
+        }
+    }
+    Ok(queries)
+}
+
+fn decode_matcher(mut message: &[u8]) -> Result<LabelMatcher, TSLiteError> {
+    let mut matcher = LabelMatcher {
+        kind: MatchKind::Equal,
+        name: String::new(),
+        value: String::new(),
+    };
+    while !message.is_empty() {
+        match next_field(&mut message)? {
+            (1, Field::Varint(0)) => matcher.kind = MatchKind::Equal,
+            (1, Field::Varint(1)) => matcher.kind = MatchKind::NotEqual,
+            (1, Field::Varint(_)) => {
+                return Err(TSLiteError::InvalidParameter(
+                    "Regular expression matchers are not supported.".to_string(),
+                ))
+            }
+            (2, Field::Bytes(name)) => matcher.name = utf8(name)?,
+            (3, Field::Bytes(value)) => matcher.value = utf8(value)?,
+            _ => {}
+        }
+    }
+    Ok(matcher)
+}
+
+/// Append a length-delimited field to a protobuf message.
+fn write_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(out, field << 3 | 2);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Answer the queries of a remote-read request with the samples of the DBs matching them, and return the
+/// body of the response before its compression.
+pub fn remote_read<V: RecordValue>(
+    dbs: &mut [&mut PhysicalDB<V>],
+    queries: &[RemoteReadQuery],
+) -> Result<Vec<u8>, TSLiteError> {
+    let mut response = Vec::new();
+    for query in queries {
+        let start = Timestamp::from_unix_millis(query.start_ms);
+        let end = Timestamp::from_unix_millis(query.end_ms.saturating_add(1));
+        let mut result = Vec::new();
+        for db in dbs.iter_mut() {
+            let labels = series_labels(db);
+            if !query.matches(&labels) {
+                continue;
+            }
+            let mut series = Vec::new();
+            for (name, value) in &labels {
+                let mut label = Vec::new();
+                write_bytes(&mut label, 1, name.as_bytes());
+                write_bytes(&mut label, 2, value.as_bytes());
+                write_bytes(&mut series, 1, &label);
+            }
+            for record in db.records(start, end)? {
+                let mut sample = Vec::with_capacity(20);
+                write_varint(&mut sample, 1 << 3 | 1);
+                sample.extend_from_slice(&record.value.as_f64().to_le_bytes());
+                write_varint(&mut sample, 2 << 3);
+                write_varint(&mut sample, record.time.unix_millis() as u64);
+                write_bytes(&mut series, 2, &sample);
+            }
+            write_bytes(&mut result, 1, &series);
+        }
+        write_bytes(&mut response, 1, &result);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordInfo;
+    use std::fs;
+    use std::path::Path;
+
+    fn create(path: &str, room: &str, values: &[f32]) -> PhysicalDB<f32> {
+        let _ = fs::remove_file(path);
+        let origin = Timestamp::from_unix(1_600_000_000);
+        let mut db = PhysicalDB::create(Path::new(path), Some(origin)).unwrap();
+        db.set_label("room", room).unwrap();
+        for (i, value) in values.iter().enumerate() {
+            db.append_record(RecordInfo {
+                time_offset: i as u32 * 10,
+                value: *value,
+            })
+            .unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn exposition_and_remote_read() {
+        let mut kitchen = create("prom_temperature.db", "kitchen", &[20.5, 21.0, 21.5]);
+        let mut empty = create("prom_humidity.db", "kitchen", &[]);
+        assert_eq!(
+            latest_exposition(&mut [&mut kitchen, &mut empty]).unwrap(),
+            "# TYPE prom_temperature gauge\nprom_temperature{room=\"kitchen\"} 21.5 1600000020000\n"
+        );
+
+        // A query over [1600000010000, 1600000020000] for room="kitchen", as encoded by Prometheus.
+        let mut matcher = Vec::new();
+        write_bytes(&mut matcher, 2, b"room");
+        write_bytes(&mut matcher, 3, b"kitchen");
+        let mut query = vec![0x08];
+        write_varint(&mut query, 1_600_000_010_000);
+        query.push(0x10);
+        write_varint(&mut query, 1_600_000_020_000);
+        write_bytes(&mut query, 3, &matcher);
+        let mut request = Vec::new();
+        write_bytes(&mut request, 1, &query);
+        request.extend_from_slice(&[0x10, 0x00]);
+        let queries = decode_read_request(&request).unwrap();
+        assert_eq!(
+            queries,
+            vec![RemoteReadQuery {
+                start_ms: 1_600_000_010_000,
+                end_ms: 1_600_000_020_000,
+                matchers: vec![LabelMatcher {
+                    kind: MatchKind::Equal,
+                    name: "room".to_string(),
+                    value: "kitchen".to_string(),
+                }],
+            }]
+        );
+
+        let response = remote_read(&mut [&mut kitchen], &queries).unwrap();
+        let result = match next_field(&mut response.as_slice()).unwrap() {
+            (1, Field::Bytes(result)) => result,
+            _ => panic!("expected a query result."),
+        };
+        let mut series = match next_field(&mut &result[..]).unwrap() {
+            (1, Field::Bytes(series)) => series,
+            _ => panic!("expected a series."),
+        };
+        let mut labels = 0;
+        let mut samples = Vec::new();
+        while !series.is_empty() {
+            match next_field(&mut series).unwrap() {
+                (1, Field::Bytes(_)) => labels += 1,
+                (2, Field::Bytes(sample)) => {
+                    let mut value = [0; 8];
+                    value.copy_from_slice(&sample[1..9]);
+                    let value = f64::from_le_bytes(value);
+                    let mut time = &sample[10..];
+                    samples.push((read_varint(&mut time).unwrap(), value));
+                }
+                _ => panic!("unexpected field."),
+            }
+        }
+        assert_eq!(labels, 2);
+        assert_eq!(
+            samples,
+            vec![(1_600_000_010_000, 21.0), (1_600_000_020_000, 21.5)]
+        );
+
+        let mut regex = Vec::new();
+        write_varint(&mut regex, 1 << 3);
+        write_varint(&mut regex, 2);
+        let mut query = Vec::new();
+        write_bytes(&mut query, 3, &regex);
+        let mut request = Vec::new();
+        write_bytes(&mut request, 1, &query);
+        assert!(matches!(
+            decode_read_request(&request),
+            Err(TSLiteError::InvalidParameter(_))
+        ));
+        assert!(decode_read_request(&[0x0A, 0x05, 0x08]).is_err());
+
+        let _ = fs::remove_file("prom_temperature.db");
+        let _ = fs::remove_file("prom_humidity.db");
+    }
+}
@@ -0,0 +1,330 @@
+//! Evaluation of a small subset of PromQL over DB files, for users used to Prometheus queries.
+//!
+//! ```text
+//! temperature
+//! avg_over_time(temperature[10m])
+//! rate(requests[5m]) > 2
+//! ```
+//!
+//! A metric name selects every DB whose file is named after it (`temperature` selects `temperature.db`
+//! in any directory), so the same query runs over several files. Supported are bare selectors, the `rate`
+//! and `<aggregation>_over_time` functions applied to a range selector, and a trailing comparison with
+//! a number, which filters the results like in Prometheus. Label matchers are not supported.
+
+use crate::sql::parse_duration;
+use crate::{PhysicalDB, RecordValue, Stats, TSLiteError, Timestamp};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How far back a bare selector looks for the latest record, like the default of Prometheus.
+const LOOKBACK: i64 = 5 * 60;
+
+/// The functions applied to a range selector.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RangeFunction {
+    /// Per-second increase of a counter, accounting for resets.
+    Rate,
+    AvgOverTime,
+    MinOverTime,
+    MaxOverTime,
+    SumOverTime,
+    CountOverTime,
+}
+
+/// The comparisons filtering the results.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, a: f64, b: f64) -> bool {
+        match self {
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+            Comparison::Greater => a > b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+        }
+    }
+}
+
+/// A parsed PromQL expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromQuery {
+    /// The metric name, matched against the file names of the DBs.
+    pub metric: String,
+    /// The function and its range, `None` for a bare selector.
+    pub function: Option<(RangeFunction, Duration)>,
+    /// Only keep the results for which the comparison with the number holds.
+    pub filter: Option<(Comparison, f64)>,
+}
+
+/// One element of the result of a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromSample {
+    /// The path of the DB the value comes from.
+    pub path: PathBuf,
+    pub value: f64,
+}
+
+fn syntax_error(message: &str) -> TSLiteError {
+    TSLiteError::InvalidParameter(format!("Invalid PromQL expression: {}", message))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == ':'
+}
+
+/// Split `text` after its leading metric or function name.
+fn split_name(text: &str) -> Result<(&str, &str), TSLiteError> {
+    let end = text.find(|c| !is_name_char(c)).unwrap_or(text.len());
+    if end == 0 || text.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(syntax_error("expected a name."));
+    }
+    Ok((&text[..end], text[end..].trim_start()))
+}
+
+impl PromQuery {
+    /// Parse an expression, failing with `InvalidParameter` if it is not in the supported subset.
+    pub fn parse(expression: &str) -> Result<PromQuery, TSLiteError> {
+        let (name, mut rest) = split_name(expression.trim())?;
+        let mut query = PromQuery {
+            metric: name.to_string(),
+            function: None,
+            filter: None,
+        };
+
+        if let Some(arguments) = rest.strip_prefix('(') {
+            let function = match name {
+                "rate" => RangeFunction::Rate,
+                "avg_over_time" => RangeFunction::AvgOverTime,
+                "min_over_time" => RangeFunction::MinOverTime,
+                "max_over_time" => RangeFunction::MaxOverTime,
+                "sum_over_time" => RangeFunction::SumOverTime,
+                "count_over_time" => RangeFunction::CountOverTime,
+                _ => return Err(syntax_error(&format!("unknown function '{}'.", name))),
+            };
+            let (metric, arguments) = split_name(arguments.trim_start())?;
+            let arguments = arguments
+                .strip_prefix('[')
+                .ok_or_else(|| syntax_error("functions take a range selector."))?;
+            let close = arguments
+                .find(']')
+                .ok_or_else(|| syntax_error("expected ']'."))?;
+            let range = parse_duration(arguments[..close].trim())
+                .ok_or_else(|| syntax_error("invalid range."))?;
+            rest = arguments[close + 1..]
+                .trim_start()
+                .strip_prefix(')')
+                .ok_or_else(|| syntax_error("expected ')'."))?
+                .trim_start();
+            query.metric = metric.to_string();
+            query.function = Some((function, range));
+        }
+
+        if !rest.is_empty() {
+            let operators = [
+                ("==", Comparison::Equal),
+                ("!=", Comparison::NotEqual),
+                (">=", Comparison::GreaterOrEqual),
+                ("<=", Comparison::LessOrEqual),
+                (">", Comparison::Greater),
+                ("<", Comparison::Less),
+            ];
+            let (symbol, comparison) = operators
+                .iter()
+                .find(|(s, _)| rest.starts_with(s))
+                .ok_or_else(|| syntax_error("expected a comparison."))?;
+            let number = rest[symbol.len()..]
+                .trim()
+                .parse()
+                .map_err(|_| syntax_error("comparisons are only supported with a number."))?;
+            query.filter = Some((*comparison, number));
+        }
+
+        Ok(query)
+    }
+
+    /// Whether the DB is selected by the metric name of the query.
+    pub fn selects<V: RecordValue>(&self, db: &PhysicalDB<V>) -> bool {
+        db.path.file_stem().is_some_and(|s| *s == *self.metric)
+    }
+
+    /// Evaluate the query at `at` over the DBs it selects among `dbs`.
+    /// A DB without a value at that time, such as a range holding less than two records for `rate`, is left out.
+    pub fn evaluate<V: RecordValue>(
+        &self,
+        dbs: &mut [&mut PhysicalDB<V>],
+        at: Timestamp,
+    ) -> Result<Vec<PromSample>, TSLiteError> {
+        let mut samples = Vec::new();
+        for db in dbs.iter_mut().filter(|db| self.selects(db)) {
+            let value = match self.function {
+                None => latest(db, at)?,
+                Some((function, range)) => apply(db, function, range, at)?,
+            };
+            let value = match value {
+                Some(value) => value,
+                None => continue,
+            };
+            if let Some((comparison, threshold)) = self.filter {
+                if !comparison.holds(value, threshold) {
+                    continue;
+                }
+            }
+            samples.push(PromSample {
+                path: db.path.clone(),
+                value,
+            });
+        }
+        Ok(samples)
+    }
+}
+
+/// The value of the latest record within the lookback window ending at `at`, included.
+fn latest<V: RecordValue>(
+    db: &mut PhysicalDB<V>,
+    at: Timestamp,
+) -> Result<Option<f64>, TSLiteError> {
+    let mut value = None;
+    db.scan_range(&at.add_seconds(1 - LOOKBACK), &at.add_seconds(1), |r| {
+        value = Some(r.value.as_f64())
+    })?;
+    Ok(value)
+}
+
+/// Apply `function` over the records within `]at - range, at]`.
+fn apply<V: RecordValue>(
+    db: &mut PhysicalDB<V>,
+    function: RangeFunction,
+    range: Duration,
+    at: Timestamp,
+) -> Result<Option<f64>, TSLiteError> {
+    let start = at.add_seconds(1 - range.as_secs() as i64);
+    let end = at.add_seconds(1);
+    if function != RangeFunction::Rate {
+        let mut stats = Stats::default();
+        db.scan_range(&start, &end, |r| stats.push(r.value.as_f64()))?;
+        return Ok(match function {
+            RangeFunction::AvgOverTime => stats.mean(),
+            RangeFunction::MinOverTime => stats.min,
+            RangeFunction::MaxOverTime => stats.max,
+            RangeFunction::SumOverTime => Some(stats.sum).filter(|_| stats.count > 0),
+            _ => Some(stats.count as f64).filter(|_| stats.count > 0),
+        });
+    }
+
+    // A decrease is a counter reset: the counter restarted from zero.
+    let mut first = None;
+    let mut last: Option<(u32, f64)> = None;
+    let mut increase = 0.0;
+    db.scan_range(&start, &end, |r| {
+        let value = r.value.as_f64();
+        first.get_or_insert(r.time_offset);
+        if let Some((_, previous)) = last {
+            increase += if value < previous {
+                value
+            } else {
+                value - previous
+            };
+        }
+        last = Some((r.time_offset, value));
+    })?;
+    let seconds = db.header().offset_unit.as_secs_f64();
+    Ok(match (first, last) {
+        (Some(first), Some((last, _))) if last > first => {
+            Some(increase / ((last - first) as f64 * seconds))
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordInfo;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn parse_and_evaluate() {
+        assert_eq!(
+            PromQuery::parse("rate( requests[5m] ) > 2").unwrap(),
+            PromQuery {
+                metric: "requests".to_string(),
+                function: Some((RangeFunction::Rate, Duration::from_secs(300))),
+                filter: Some((Comparison::Greater, 2.0)),
+            }
+        );
+        for invalid in &[
+            "",
+            "increase(requests[5m])",
+            "rate(requests)",
+            "requests{job=\"api\"}",
+            "requests > other",
+        ] {
+            assert!(PromQuery::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let paths = ["requests.db", "promql/requests.db", "temperature.db"];
+        let _ = fs::create_dir("promql");
+        let mut dbs: Vec<PhysicalDB> = paths
+            .iter()
+            .map(|p| {
+                let _ = fs::remove_file(p);
+                PhysicalDB::create(Path::new(p), Some(origin)).expect("could not create db.")
+            })
+            .collect();
+        // A counter growing by 3 per minute with a reset, one growing by 1 per minute, and a gauge.
+        let series: [&[u8]; 3] = [&[0, 3, 6, 2, 5], &[0, 1, 2, 3, 4], &[20, 22, 21, 25, 24]];
+        for (db, values) in dbs.iter_mut().zip(series.iter()) {
+            for (i, v) in values.iter().enumerate() {
+                db.append_record(RecordInfo {
+                    time_offset: i as u32 * 60,
+                    value: *v,
+                })
+                .expect("could not append record.");
+            }
+        }
+        let at = origin.add_seconds(240);
+        let mut dbs: Vec<&mut PhysicalDB> = dbs.iter_mut().collect();
+        let run = |dbs: &mut [&mut PhysicalDB], q: &str| -> Vec<f64> {
+            PromQuery::parse(q)
+                .unwrap()
+                .evaluate(dbs, at)
+                .unwrap()
+                .iter()
+                .map(|s| s.value)
+                .collect()
+        };
+
+        assert_eq!(run(&mut dbs, "requests"), vec![5.0, 4.0]);
+        assert_eq!(
+            run(&mut dbs, "rate(requests[5m])"),
+            vec![11.0 / 240.0, 4.0 / 240.0]
+        );
+        assert_eq!(
+            run(&mut dbs, "rate(requests[5m]) > 0.02"),
+            vec![11.0 / 240.0]
+        );
+        assert_eq!(run(&mut dbs, "avg_over_time(temperature[2m])"), vec![24.5]);
+        assert_eq!(run(&mut dbs, "count_over_time(temperature[1h])"), vec![5.0]);
+        assert_eq!(
+            run(&mut dbs, "max_over_time(temperature[10m]) <= 24"),
+            Vec::<f64>::new()
+        );
+
+        for p in &paths {
+            let _ = fs::remove_file(p);
+        }
+        let _ = fs::remove_dir("promql");
+    }
+}
@@ -0,0 +1,673 @@
+//! Queries computed over a time range of a DB.
+//!
+//! Every query stream the records from the file, so they never need to hold the whole range in memory.
+
+use crate::{PhysicalDB, RecordInfo, RecordValue, Stats, TSLiteError, Timestamp};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+/// A span of time, `start` included and `end` excluded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Interval {
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+/// A point of a series derived from the records, such as a smoothed series.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Point {
+    pub time: Timestamp,
+    pub value: f64,
+}
+
+/// Where a series spent its time relatively to a threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdReport {
+    /// The total time spent on the requested side of the threshold.
+    pub duration: Duration,
+    /// Every interval during which the series was on the requested side of the threshold.
+    pub intervals: Vec<Interval>,
+}
+
+/// The direction in which a series crossed a threshold.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CrossingDirection {
+    /// The series went from below or at the threshold to above it.
+    Rising,
+    /// The series went from above the threshold to below or at it.
+    Falling,
+}
+
+/// The moment a series crossed a threshold.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Crossing {
+    /// The date of the first record on the other side of the threshold.
+    pub time: Timestamp,
+    pub direction: CrossingDirection,
+}
+
+/// The correlation between two series when the second one is shifted by `lag` samples.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LagCorrelation {
+    /// A positive lag means the first series leads the second one by `lag` samples.
+    pub lag: i64,
+    /// Pearson correlation coefficient, `None` if there was not enough overlapping samples
+    /// or if one of the series is constant over the overlap.
+    pub correlation: Option<f64>,
+}
+
+/// Compute the correlation between two aligned series for every lag within `[-max_lag, max_lag]`.
+/// Both series must be sampled at the same instants, `a[i]` and `b[i]` being taken at the same time.
+pub fn cross_correlate(a: &[f64], b: &[f64], max_lag: usize) -> Vec<LagCorrelation> {
+    let a: Vec<Option<f64>> = a.iter().map(|v| Some(*v)).collect();
+    let b: Vec<Option<f64>> = b.iter().map(|v| Some(*v)).collect();
+    correlate_sparse(&a, &b, max_lag)
+}
+
+/// Same as [`cross_correlate`] but samples can be missing, pairs with a missing sample are ignored.
+fn correlate_sparse(a: &[Option<f64>], b: &[Option<f64>], max_lag: usize) -> Vec<LagCorrelation> {
+    let max_lag = max_lag as i64;
+    (-max_lag..=max_lag)
+        .map(|lag| {
+            let pairs: Vec<(f64, f64)> = (0..a.len() as i64)
+                .filter(|i| i + lag >= 0 && i + lag < b.len() as i64)
+                .filter_map(|i| match (a[i as usize], b[(i + lag) as usize]) {
+                    (Some(x), Some(y)) => Some((x, y)),
+                    _ => None,
+                })
+                .collect();
+            LagCorrelation {
+                lag,
+                correlation: pearson(&pairs),
+            }
+        })
+        .collect()
+}
+
+fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in pairs {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x) * (x - mean_x);
+        var_y += (y - mean_y) * (y - mean_y);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+
+    Some(cov / (var_x * var_y).sqrt())
+}
+
+/// A record ranked by its value, used to keep the best records of a range in a heap.
+/// Values are compared as floats with a total order, ties are broken by `tie`.
+struct Ranked<V: RecordValue> {
+    key: f64,
+    tie: i64,
+    record: RecordInfo<V>,
+}
+
+impl<V: RecordValue> Ranked<V> {
+    fn new(record: RecordInfo<V>, tie: i64) -> Ranked<V> {
+        Ranked {
+            key: record.value.as_f64(),
+            tie,
+            record,
+        }
+    }
+}
+
+impl<V: RecordValue> PartialEq for Ranked<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<V: RecordValue> Eq for Ranked<V> {}
+
+impl<V: RecordValue> PartialOrd for Ranked<V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<V: RecordValue> Ord for Ranked<V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key
+            .total_cmp(&other.key)
+            .then(self.tie.cmp(&other.tie))
+    }
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Return the `k` records with the highest values within `[start, end[`, sorted from the highest to the lowest.
+    /// If several records share the same value, the oldest ones are kept.
+    pub fn top_k(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        k: usize,
+    ) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Min-heap holding the best `k` records seen so far, its top is the first one to evict.
+        // Among equal values the newest record ranks lower, so it is evicted first.
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        self.scan_range(&start.into(), &end.into(), |r| {
+            heap.push(Reverse(Ranked::new(r, -(r.time_offset as i64))));
+            if heap.len() > k {
+                heap.pop();
+            }
+        })?;
+
+        Ok(heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| ranked.record)
+            .collect())
+    }
+
+    /// Return the `k` records with the lowest values within `[start, end[`, sorted from the lowest to the highest.
+    /// If several records share the same value, the oldest ones are kept.
+    pub fn bottom_k(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        k: usize,
+    ) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Max-heap holding the best `k` records seen so far, its top is the first one to evict.
+        // Among equal values the newest record ranks higher, so it is evicted first.
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        self.scan_range(&start.into(), &end.into(), |r| {
+            heap.push(Ranked::new(r, r.time_offset as i64));
+            if heap.len() > k {
+                heap.pop();
+            }
+        })?;
+
+        Ok(heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|ranked| ranked.record)
+            .collect())
+    }
+
+    /// Compute how long the series stayed strictly above `threshold` within `[start, end[`.
+    /// The series is seen as a step function: a record's value holds until the next record,
+    /// and the last record of the range holds until `end`. Until the first record of the range, the value
+    /// is the one of the last record before `start`, if any.
+    pub fn time_above(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        threshold: V,
+    ) -> Result<ThresholdReport, TSLiteError> {
+        self.time_where(&start.into(), &end.into(), |v| v > threshold)
+    }
+
+    /// Compute how long the series stayed strictly below `threshold` within `[start, end[`.
+    /// See [`PhysicalDB::time_above`] for how the time between records is accounted.
+    pub fn time_below(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        threshold: V,
+    ) -> Result<ThresholdReport, TSLiteError> {
+        self.time_where(&start.into(), &end.into(), |v| v < threshold)
+    }
+
+    /// List every time the series crossed `threshold` within `[start, end[`.
+    /// A value equal to the threshold counts as being below it.
+    pub fn crossings(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        threshold: V,
+    ) -> Result<Vec<Crossing>, TSLiteError> {
+        let mut offsets = Vec::new();
+        let mut above: Option<bool> = None;
+        self.scan_range(&start.into(), &end.into(), |r| {
+            let now_above = r.value > threshold;
+            if let Some(was_above) = above {
+                if was_above != now_above {
+                    let direction = if now_above {
+                        CrossingDirection::Rising
+                    } else {
+                        CrossingDirection::Falling
+                    };
+                    offsets.push((r.time_offset, direction));
+                }
+            }
+            above = Some(now_above);
+        })?;
+
+        Ok(offsets
+            .into_iter()
+            .map(|(off, direction)| Crossing {
+                time: self.offset_to_date(off),
+                direction,
+            })
+            .collect())
+    }
+
+    /// Compute the exponentially weighted moving average of the records within `[start, end[`.
+    /// Each point is `alpha * value + (1 - alpha) * previous`, the first point being the first value.
+    /// `alpha` must be within `]0, 1]`, the lower it is, the smoother the series.
+    pub fn ewma(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        alpha: f64,
+    ) -> Result<Vec<Point>, TSLiteError> {
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            return Err(TSLiteError::InvalidParameter(format!(
+                "EWMA alpha must be within ]0, 1], got {}.",
+                alpha
+            )));
+        }
+
+        let mut smoothed: Vec<(u32, f64)> = Vec::new();
+        let mut previous: Option<f64> = None;
+        self.scan_range(&start.into(), &end.into(), |r| {
+            let value = match previous {
+                Some(p) => alpha * r.value.as_f64() + (1.0 - alpha) * p,
+                None => r.value.as_f64(),
+            };
+            previous = Some(value);
+            smoothed.push((r.time_offset, value));
+        })?;
+
+        Ok(smoothed
+            .into_iter()
+            .map(|(off, value)| Point {
+                time: self.offset_to_date(off),
+                value,
+            })
+            .collect())
+    }
+
+    /// Compute the correlation between this series and `other` for every lag within `[-max_lag, max_lag]`.
+    /// Both series are aligned by sampling them every `step` within `[start, end[`, each sample taking the value
+    /// of the latest record at or before it. A positive lag means this series leads `other` by `lag * step`.
+    pub fn cross_correlate_with(
+        &mut self,
+        other: &mut PhysicalDB<V>,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        step: Duration,
+        max_lag: usize,
+    ) -> Result<Vec<LagCorrelation>, TSLiteError> {
+        let step = step.as_nanos() as i128;
+        if step == 0 {
+            return Err(TSLiteError::InvalidParameter(
+                "Sampling step must not be zero.".to_string(),
+            ));
+        }
+
+        let (start, end) = (start.into(), end.into());
+        let a = self.sample_and_hold(&start, &end, step)?;
+        let b = other.sample_and_hold(&start, &end, step)?;
+        Ok(correlate_sparse(&a, &b, max_lag))
+    }
+
+    /// Sample the series every `step` nanoseconds within `[start, end[`, each sample taking the value of the latest
+    /// record at or before it. Samples taken before the first record of the range are `None`.
+    fn sample_and_hold(
+        &mut self,
+        start: &Timestamp,
+        end: &Timestamp,
+        step: i128,
+    ) -> Result<Vec<Option<f64>>, TSLiteError> {
+        let mut records: Vec<(u32, f64)> = Vec::new();
+        let unit = self.header().offset_unit.nanoseconds() as i128;
+        self.scan_range(start, end, |r| {
+            records.push((r.time_offset, r.value.as_f64()))
+        })?;
+
+        // Work with nanoseconds relative to the origin of the DB, negative before it.
+        let header = *self.header();
+        let end = header.elapsed(end);
+        let mut samples = Vec::new();
+        let mut next = 0;
+        let mut held: Option<f64> = None;
+        let mut t = header.elapsed(start);
+        while t < end {
+            while next < records.len() && records[next].0 as i128 * unit <= t {
+                held = Some(records[next].1);
+                next += 1;
+            }
+            samples.push(held);
+            t += step;
+        }
+
+        Ok(samples)
+    }
+
+    /// Call `f` with the start of every non-empty bucket of `bucket` seconds within `[start, end[`, as Unix
+    /// seconds, and the statistics of its records. Buckets are aligned on the Unix epoch and come in the order
+    /// the records are stored, so a bucket is reported more than once if the records are not chronologically ordered.
+    pub(crate) fn scan_buckets<F>(
+        &mut self,
+        start: &Timestamp,
+        end: &Timestamp,
+        bucket: i64,
+        mut f: F,
+    ) -> Result<(), TSLiteError>
+    where
+        F: FnMut(i64, &Stats),
+    {
+        let header = *self.header();
+        let mut current: Option<(i64, Stats)> = None;
+        self.scan_range(start, end, |r| {
+            let time = header.offset_to_date(r.time_offset).unix_seconds();
+            let key = time - time.rem_euclid(bucket);
+            match current.as_mut() {
+                Some((k, stats)) if *k == key => stats.push(r.value.as_f64()),
+                _ => {
+                    if let Some((k, stats)) = current.take() {
+                        f(k, &stats);
+                    }
+                    let mut stats = Stats::default();
+                    stats.push(r.value.as_f64());
+                    current = Some((key, stats));
+                }
+            }
+        })?;
+        if let Some((k, stats)) = current {
+            f(k, &stats);
+        }
+        Ok(())
+    }
+
+    fn time_where<P>(
+        &mut self,
+        start: &Timestamp,
+        end: &Timestamp,
+        predicate: P,
+    ) -> Result<ThresholdReport, TSLiteError>
+    where
+        P: Fn(V) -> bool,
+    {
+        self.refresh_if_changed()?;
+        let (start_offset, end_offset) = match self.offset_range(start, end) {
+            Some(range) => range,
+            None => {
+                return Ok(ThresholdReport {
+                    duration: Duration::from_secs(0),
+                    intervals: Vec::new(),
+                })
+            }
+        };
+
+        // The value held at `start` is the one of the last record before it.
+        let first = self.first_not_before(start_offset)?;
+        let mut open: Option<u32> = None;
+        if first > 0 && predicate(self.read_record(first - 1)?.value) {
+            open = Some(start_offset);
+        }
+        let mut spans: Vec<(u32, u32)> = Vec::new();
+        self.scan_range(start, end, |r| match (open, predicate(r.value)) {
+            (None, true) => open = Some(r.time_offset),
+            (Some(from), false) => {
+                if r.time_offset > from {
+                    spans.push((from, r.time_offset));
+                }
+                open = None;
+            }
+            _ => {}
+        })?;
+        if let Some(from) = open {
+            spans.push((from, end_offset));
+        }
+
+        let unit = self.header().offset_unit.nanoseconds() as u64;
+        let nanos: u64 = spans.iter().map(|(s, e)| (e - s) as u64 * unit).sum();
+        Ok(ThresholdReport {
+            duration: Duration::from_nanos(nanos),
+            intervals: spans
+                .into_iter()
+                .map(|(s, e)| Interval {
+                    start: self.offset_to_date(s),
+                    end: self.offset_to_date(e),
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn top_and_bottom_k() {
+        let path = "top_k.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        let values = [3, 9, 1, 9, 7, 0, 5, 8];
+        for (i, v) in values.iter().enumerate() {
+            db.append_record(RecordInfo {
+                time_offset: i as u32 * 10,
+                value: *v,
+            })
+            .expect("could not append record.");
+        }
+
+        // The range excludes the first and last record.
+        let start = origin.add_seconds(5);
+        let end = origin.add_seconds(70);
+
+        let top = db.top_k(start, end, 3).expect("could not query top k.");
+        let top: Vec<(u32, u8)> = top.iter().map(|r| (r.time_offset, r.value)).collect();
+        assert_eq!(top, vec![(10, 9), (30, 9), (40, 7)]);
+
+        let bottom = db
+            .bottom_k(start, end, 2)
+            .expect("could not query bottom k.");
+        let bottom: Vec<(u32, u8)> = bottom.iter().map(|r| (r.time_offset, r.value)).collect();
+        assert_eq!(bottom, vec![(50, 0), (20, 1)]);
+
+        let none = db
+            .top_k(
+                origin.add_seconds(-2 * 86_400),
+                origin.add_seconds(-86_400),
+                3,
+            )
+            .expect("could not query top k.");
+        assert!(none.is_empty());
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn threshold_analytics() {
+        let path = "threshold.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        // above 50 from 10 to 30, and from 50 to the end of the range.
+        for (off, value) in [(0, 20), (10, 60), (20, 80), (30, 50), (40, 10), (50, 90)].iter() {
+            db.append_record(RecordInfo {
+                time_offset: *off,
+                value: *value,
+            })
+            .expect("could not append record.");
+        }
+
+        let end = origin.add_seconds(60);
+        let above = db.time_above(origin, end, 50).expect("could not query.");
+        assert_eq!(above.duration, Duration::from_secs(30));
+        assert_eq!(
+            above.intervals,
+            vec![
+                Interval {
+                    start: origin.add_seconds(10),
+                    end: origin.add_seconds(30),
+                },
+                Interval {
+                    start: origin.add_seconds(50),
+                    end,
+                },
+            ]
+        );
+
+        let below = db.time_below(origin, end, 50).expect("could not query.");
+        assert_eq!(below.duration, Duration::from_secs(20));
+
+        // Already above the threshold at `start`, by the record at 20.
+        let start = origin.add_seconds(25);
+        let above = db.time_above(start, end, 50).expect("could not query.");
+        assert_eq!(above.duration, Duration::from_secs(15));
+        assert_eq!(
+            above.intervals[0],
+            Interval {
+                start,
+                end: origin.add_seconds(30),
+            }
+        );
+        let below = db.time_below(start, end, 50).expect("could not query.");
+        assert_eq!(below.duration, Duration::from_secs(10));
+        // A record at `start` replaces the value held before it.
+        let above = db
+            .time_above(origin.add_seconds(30), end, 50)
+            .expect("could not query.");
+        assert_eq!(above.duration, Duration::from_secs(10));
+
+        let crossings = db.crossings(origin, end, 50).expect("could not query.");
+        let directions: Vec<(Timestamp, CrossingDirection)> =
+            crossings.iter().map(|c| (c.time, c.direction)).collect();
+        assert_eq!(
+            directions,
+            vec![
+                (origin.add_seconds(10), CrossingDirection::Rising),
+                (origin.add_seconds(30), CrossingDirection::Falling),
+                (origin.add_seconds(50), CrossingDirection::Rising),
+            ]
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn ewma() {
+        let path = "ewma.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for (off, value) in [(0, 10), (1, 20), (2, 20), (3, 0)].iter() {
+            db.append_record(RecordInfo {
+                time_offset: *off,
+                value: *value,
+            })
+            .expect("could not append record.");
+        }
+
+        let end = origin.add_seconds(10);
+        let points = db.ewma(origin, end, 0.5).expect("could not compute ewma.");
+        let values: Vec<f64> = points.iter().map(|p| p.value).collect();
+        assert_eq!(values, vec![10.0, 15.0, 17.5, 8.75]);
+        assert_eq!(points[3].time, origin.add_seconds(3));
+
+        assert!(db.ewma(origin, end, 0.0).is_err());
+        assert!(db.ewma(origin, end, 1.5).is_err());
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn cross_correlation() {
+        let a = [1.0, 2.0, 3.0, 2.0, 1.0, 2.0, 3.0, 2.0, 1.0];
+        // `b` is `a` delayed by two samples.
+        let b = [0.0, 0.0, 1.0, 2.0, 3.0, 2.0, 1.0, 2.0, 3.0];
+        let corr = cross_correlate(&a, &b, 3);
+        assert_eq!(corr.len(), 7);
+        let best = corr
+            .iter()
+            .filter(|c| c.correlation.is_some())
+            .max_by(|x, y| x.correlation.partial_cmp(&y.correlation).unwrap())
+            .unwrap();
+        assert_eq!(best.lag, 2);
+        assert!((best.correlation.unwrap() - 1.0).abs() < 1e-9);
+
+        let pa = "xcorr_a.db";
+        let pb = "xcorr_b.db";
+        let _ = fs::remove_file(pa);
+        let _ = fs::remove_file(pb);
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut da: PhysicalDB =
+            PhysicalDB::create(Path::new(pa), Some(origin)).expect("could not create db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(pb), Some(origin)).expect("could not create db.");
+        for i in 0..a.len() {
+            let rec = |v: f64| RecordInfo {
+                time_offset: i as u32 * 60,
+                value: v as u8,
+            };
+            da.append_record(rec(a[i]))
+                .expect("could not append record.");
+            db.append_record(rec(b[i]))
+                .expect("could not append record.");
+        }
+
+        let end = origin.add_seconds(9 * 60);
+        let corr = da
+            .cross_correlate_with(&mut db, origin, end, Duration::from_secs(60), 3)
+            .expect("could not correlate.");
+        assert!((corr[5].correlation.unwrap() - 1.0).abs() < 1e-9);
+        assert_eq!(corr[5].lag, 2);
+        assert!(da
+            .cross_correlate_with(&mut db, origin, end, Duration::ZERO, 3)
+            .is_err());
+
+        // The same series sampled every 100 milliseconds.
+        let _ = fs::remove_file(pa);
+        let _ = fs::remove_file(pb);
+        let options = crate::DbOptions {
+            offset_unit: crate::OffsetUnit::Milliseconds,
+            ..Default::default()
+        };
+        let mut da: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(pa), Some(origin), &options).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(pb), Some(origin), &options).unwrap();
+        for i in 0..a.len() {
+            let rec = |v: f64| RecordInfo {
+                time_offset: i as u32 * 100,
+                value: v as u8,
+            };
+            da.append_record(rec(a[i])).unwrap();
+            db.append_record(rec(b[i])).unwrap();
+        }
+        let end = origin.add_millis(900);
+        let corr = da
+            .cross_correlate_with(&mut db, origin, end, Duration::from_millis(100), 3)
+            .expect("could not correlate.");
+        assert_eq!(corr[5].lag, 2);
+        assert!((corr[5].correlation.unwrap() - 1.0).abs() < 1e-9);
+
+        let _ = fs::remove_file(pa);
+        let _ = fs::remove_file(pb);
+    }
+}
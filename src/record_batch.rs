@@ -0,0 +1,83 @@
+//! Arrow RecordBatches of the records, to hand them over to analytics tooling.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError, Timestamp};
+use arrow::array::{ArrayRef, Float64Array, TimestampMillisecondArray};
+use arrow::record_batch::RecordBatch;
+use std::sync::Arc;
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// A RecordBatch of every record whose date is within `[start, end[`, with a `time` column of UTC
+    /// timestamps in milliseconds and a `value` column of the values as `f64`.
+    pub fn to_arrow(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<RecordBatch, TSLiteError> {
+        self.refresh_if_changed()?;
+        let header = self.header;
+        let mut times = Vec::new();
+        let mut values = Vec::new();
+        self.scan_range(&start.into(), &end.into(), |r| {
+            times.push(header.offset_to_date(r.time_offset).unix_millis());
+            values.push(r.value.as_f64());
+        })?;
+
+        let times: ArrayRef = Arc::new(TimestampMillisecondArray::from(times).with_timezone("UTC"));
+        let values: ArrayRef = Arc::new(Float64Array::from(values));
+        RecordBatch::try_from_iter(vec![("time", times), ("value", values)])
+            .map_err(|e| TSLiteError::InvalidParameter(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordInfo;
+    use arrow::datatypes::{DataType, TimeUnit};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn records_to_arrow() {
+        let path = "record_batch.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for i in 0..10u16 {
+            db.append_record(RecordInfo {
+                time_offset: i as u32 * 60,
+                value: i * 100,
+            })
+            .expect("could not append record.");
+        }
+
+        let batch = db
+            .to_arrow(origin.add_seconds(120), origin.add_seconds(300))
+            .unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(
+            batch.schema().field(0).data_type(),
+            &DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+        );
+        let times = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        assert_eq!(
+            times.value(0),
+            origin.add_seconds(120).unix_seconds() * 1000
+        );
+        let values = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(values.values().to_vec(), vec![200.0, 300.0, 400.0]);
+        assert_eq!(db.to_arrow(origin, origin).unwrap().num_rows(), 0);
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,154 @@
+//! Fixing a DB whose record count doesn't match the length of its file, as `check_db_file` reports with
+//! `MismatchRecordAmount`.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError, RECORDS_START};
+use std::io::{Read, Seek, SeekFrom};
+
+/// What [`PhysicalDB::repair`] changed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct RepairReport {
+    /// The number of records the header counted before the repair.
+    pub records_before: u64,
+    /// The number of records the header counts after the repair.
+    pub records_after: u64,
+    /// The number of octets cut from the end of the file: a partly written record, or records failing
+    /// their checksum past the ones the header counted.
+    pub truncated_octets: u64,
+}
+
+impl RepairReport {
+    /// Whether nothing had to be changed.
+    pub fn is_clean(&self) -> bool {
+        self.records_before == self.records_after && self.truncated_octets == 0
+    }
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Make the record count of the header match the records actually in the file.
+    ///
+    /// A partly written record at the end of the file is cut. If the header counts more records than the file
+    /// holds, the count is lowered to the records that are there. If the file holds whole records past the
+    /// count, which happens when the process crashed between writing them and updating the header, they are
+    /// counted again, unless the DB has record checksums and they fail them, in which case they are cut.
+    /// The records are not checked otherwise, `check_db_file` still reports the other issues.
+    /// Pending records of a buffered DB are not affected, they are written after the repaired ones.
+    pub fn repair(&mut self) -> Result<RepairReport, TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        self.refresh()?;
+        self.check_stale()?;
+
+        let len = self
+            .file
+            .as_ref()
+            .unwrap()
+            .metadata()
+            .map_err(TSLiteError::Io)?
+            .len();
+        // The octets past the records are then the digest footer, not records appended before a crash.
+        let len = match self.digest_footer()? {
+            Some(_) => self.records_end(),
+            None => len,
+        };
+        let size = self.header.record_size::<V>();
+        let before = self.header.records_number;
+        let mut after = len.saturating_sub(RECORDS_START) / size;
+        if let Some(capacity) = self.header.ring_capacity() {
+            after = after.min(capacity);
+        }
+        if after > before && self.header.record_checksums() {
+            let mut bytes = vec![0; size as usize];
+            let mut file = self.file.as_ref().unwrap();
+            for index in before..after {
+                file.seek(SeekFrom::Start(RECORDS_START + index * size))
+                    .and_then(|_| file.read_exact(&mut bytes))
+                    .map_err(TSLiteError::Io)?;
+                if self.header.decode_record::<V>(index, &bytes).is_err() {
+                    after = index;
+                    break;
+                }
+            }
+        }
+
+        let end = RECORDS_START + after * size;
+        let truncated = len.saturating_sub(end);
+        if truncated > 0 {
+            self.file
+                .as_ref()
+                .unwrap()
+                .set_len(end)
+                .map_err(TSLiteError::Io)?;
+        }
+        if after != before {
+            self.header.records_number = after;
+            self.write_header()?;
+        }
+
+        Ok(RepairReport {
+            records_before: before,
+            records_after: after,
+            truncated_octets: truncated,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbIssue, DbOptions, RecordInfo, Timestamp};
+    use std::fs::{self, OpenOptions};
+    use std::path::Path;
+
+    #[test]
+    fn repair_record_amount() {
+        let path = "repair.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            record_checksums: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        for i in 0..4u16 {
+            db.append_record(RecordInfo {
+                time_offset: i as u32,
+                value: i,
+            })
+            .expect("could not append record.");
+        }
+        assert!(db.repair().unwrap().is_clean());
+        let size = db.header().record_size::<u16>();
+
+        // The last record is torn.
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_len(RECORDS_START + 3 * size + 2).unwrap();
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::MismatchRecordAmount);
+        assert_eq!(
+            db.repair().unwrap(),
+            RepairReport {
+                records_before: 4,
+                records_after: 3,
+                truncated_octets: 2,
+            }
+        );
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+        assert_eq!(fs::metadata(path).unwrap().len(), RECORDS_START + 3 * size);
+
+        // Records written without their header update are counted again, up to the first damaged one.
+        db.header.records_number = 1;
+        db.write_header().unwrap();
+        let mut bytes = fs::read(path).unwrap();
+        bytes.extend(vec![0xAB; size as usize]);
+        fs::write(path, bytes).unwrap();
+        let report = db.repair().unwrap();
+        assert_eq!((report.records_before, report.records_after), (1, 3));
+        assert_eq!(report.truncated_octets, size);
+        assert_eq!(db.read_record(2).unwrap().value, 2);
+
+        let _ = fs::remove_file(path);
+    }
+}
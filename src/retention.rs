@@ -0,0 +1,289 @@
+//! Dropping old records, and handing them to the application before they disappear.
+
+use crate::{PhysicalDB, Record, RecordValue, TSLiteError, Timestamp};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::time::Duration;
+
+/// Number of records read or moved at once while pruning, which bounds the memory it uses.
+const PRUNE_CHUNK: usize = 4096;
+
+type EvictionCallback<V> = dyn FnMut(&[Record<V>]) + Send;
+
+/// The callback called with the records evicted from a DB.
+pub(crate) struct Evictor<V: RecordValue>(Box<EvictionCallback<V>>);
+
+impl<V: RecordValue> Evictor<V> {
+    /// Hand `records` to the callback, unless there are none.
+    pub(crate) fn evict(&mut self, records: &[Record<V>]) {
+        if !records.is_empty() {
+            (self.0)(records);
+        }
+    }
+}
+
+impl<V: RecordValue> fmt::Debug for Evictor<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Evictor")
+    }
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Set a callback called with the records about to be dropped from the DB, oldest first,
+    /// so they can be archived or aggregated elsewhere. It replaces any previous callback.
+    /// The records are dropped by [`PhysicalDB::prune_before`] and, in a ring DB, by the appends overwriting
+    /// the oldest records and by [`PhysicalDB::insert_record`] once it is full.
+    /// A large eviction is handed over in several consecutive slices.
+    pub fn on_evict<F>(&mut self, callback: F)
+    where
+        F: FnMut(&[Record<V>]) + Send + 'static,
+    {
+        self.evictor = Some(Evictor(Box::new(callback)));
+    }
+
+    /// Drop every record anterior to `time` and return how many were dropped.
+    /// The records are assumed to be chronologically ordered: the first record at or after `time` stops the eviction.
+    /// The remaining records are written to a new file, renamed over the DB once synced like with
+    /// [`PhysicalDB::compact`], so a crash leaves either every record or only the remaining ones.
+    /// As with it, the other handles on the DB get a `StaleHandle` error until they are reopened, and the
+    /// lock held on the file, if any, is released.
+    pub fn prune_before(&mut self, time: impl Into<Timestamp>) -> Result<u64, TSLiteError> {
+        self.refresh_if_changed()?;
+        let header = self.header;
+        let cutoff = header.offset_not_before(&time.into());
+        if cutoff <= 0 {
+            return Ok(0);
+        }
+        let cutoff = cutoff.min(u32::MAX as i64) as u32;
+
+        let transforms = self.header.transforms;
+        let total = self.header.records_number;
+        let size = header.record_size::<V>() as usize;
+        let mut buffer = vec![0; PRUNE_CHUNK * size];
+        let mut source_chain = None;
+        let mut evicted = 0;
+        'evict: while evicted < total {
+            let n = self.read_records_into(evicted, &mut buffer)?;
+            let mut records = Vec::with_capacity(n);
+            for bytes in buffer[..n * size].chunks(size) {
+                let index = evicted + records.len() as u64;
+                let mut record = header.decode_record::<V>(index, bytes)?;
+                if record.time_offset >= cutoff {
+                    break;
+                }
+                let quantized = transforms.undelta(index, record.value, &mut source_chain);
+                record.value = transforms.dequantize(quantized);
+                records.push(record.resolve(&header));
+            }
+            evicted += records.len() as u64;
+            if let Some(evictor) = self.evictor.as_mut() {
+                evictor.evict(&records);
+            }
+            if records.len() < n {
+                break 'evict;
+            }
+        }
+        if evicted == 0 {
+            return Ok(0);
+        }
+
+        self.rewrite_from(evicted)?;
+        self.unordered_from = self.unordered_from.map(|i| i.saturating_sub(evicted));
+
+        Ok(evicted)
+    }
+
+    /// Drop every record older than `age` according to the clock of the DB, and return how many were dropped.
+    /// Call it periodically, or schedule it with [`Task::Prune`](crate::Task::Prune), so the file of a
+    /// long-running collector doesn't grow without bound.
+    pub fn prune_older_than(&mut self, age: Duration) -> Result<u64, TSLiteError> {
+        let cutoff = self.options.now().unix_nanos() - age.as_nanos() as i128;
+        if cutoff <= self.header.origin_date.unix_nanos() {
+            return Ok(0);
+        }
+        self.prune_before(Timestamp::from_unix_nanos(cutoff))
+    }
+
+    /// Drop every record dated before `cutoff`, and return how many were dropped,
+    /// see [`PhysicalDB::prune_before`].
+    #[cfg(feature = "chrono")]
+    pub fn purge_older_than(&mut self, cutoff: DateTime<Utc>) -> Result<u64, TSLiteError> {
+        self.prune_before(cutoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbIssue, RecordInfo, RECORDS_START};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn prune_with_eviction_callback() {
+        let path = "prune.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for i in 0..10 {
+            db.append_record(RecordInfo {
+                time_offset: i * 10,
+                value: i as u8,
+            })
+            .expect("could not append record.");
+        }
+
+        let archive = Arc::new(Mutex::new(Vec::new()));
+        let sink = archive.clone();
+        db.on_evict(move |records| sink.lock().unwrap().extend_from_slice(records));
+
+        assert_eq!(db.prune_before(origin).unwrap(), 0);
+        assert_eq!(db.prune_before(origin.add_seconds(35)).unwrap(), 4);
+        let archive = archive.lock().unwrap();
+        assert_eq!(archive.len(), 4);
+        assert_eq!(archive[0].time, origin);
+        assert_eq!(archive[3].time, origin.add_seconds(30));
+        assert_eq!(archive[3].value, 3);
+
+        assert_eq!(db.read_header().unwrap().records_number, 6);
+        assert_eq!(db.read_record(0).unwrap().time_offset, 40);
+        assert_eq!(db.read_record(5).unwrap().value, 9);
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+        assert_eq!(
+            fs::metadata(path).unwrap().len(),
+            RECORDS_START + 6 * RecordInfo::<u8>::SIZE
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn prune_older_than_age() {
+        let path = "prune_age.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(crate::MockClock::new(origin));
+        let options = crate::DbOptions {
+            clock: Some(clock.clone()),
+            ..Default::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        for i in 0..48 {
+            db.append_record(RecordInfo {
+                time_offset: i * 3600,
+                value: i as u8,
+            })
+            .expect("could not append record.");
+        }
+
+        let day = Duration::from_secs(86_400);
+        assert_eq!(db.prune_older_than(day).unwrap(), 0);
+        clock.set(origin.add_seconds(48 * 3600));
+        assert_eq!(db.prune_older_than(day).unwrap(), 24);
+        assert_eq!(db.read_record(0).unwrap().value, 24);
+        assert_eq!(
+            db.prune_older_than(Duration::from_secs(u64::MAX)).unwrap(),
+            0
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn prune_replaces_the_file() {
+        let (path, ring) = ("prune_replace.db", "prune_ring.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(ring);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = crate::DbOptions {
+            transforms: crate::Transforms {
+                delta: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options).unwrap();
+        let records: Vec<RecordInfo<u16>> = (0..200)
+            .map(|i| RecordInfo {
+                time_offset: i,
+                value: (i * 7 % 100) as u16,
+            })
+            .collect();
+        db.append_records(&records).unwrap();
+        let other: PhysicalDB<u16> = PhysicalDB::open_path(Path::new(path)).unwrap();
+
+        // The remaining records are re-encoded against their new keyframes.
+        assert_eq!(db.prune_before(origin.add_seconds(100)).unwrap(), 100);
+        let values: Vec<u16> = db.iter().map(|r| r.unwrap().value).collect();
+        assert_eq!(
+            values,
+            records[100..].iter().map(|r| r.value).collect::<Vec<u16>>()
+        );
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+        assert_eq!(other.check_stale(), Err(TSLiteError::StaleHandle));
+        assert!(!Path::new("prune_replace.db.compact.tmp").exists());
+
+        // A ring that wrapped around is pruned from its oldest record.
+        let options = crate::DbOptions {
+            ring_capacity: Some(5),
+            ..Default::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(ring), Some(origin), &options).unwrap();
+        db.append_records(&records[..8]).unwrap();
+        assert_eq!(db.prune_before(origin.add_seconds(5)).unwrap(), 2);
+        let offsets: Vec<u32> = db.iter().map(|r| r.unwrap().time_offset).collect();
+        assert_eq!(offsets, vec![5, 6, 7]);
+        db.append_records(&records[8..12]).unwrap();
+        let offsets: Vec<u32> = db.iter().map(|r| r.unwrap().time_offset).collect();
+        assert_eq!(offsets, vec![7, 8, 9, 10, 11]);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(ring);
+    }
+
+    #[test]
+    fn prune_older_than_sub_second_age() {
+        let path = "prune_millis.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(crate::MockClock::new(origin.add_seconds(10)));
+        let options = crate::DbOptions {
+            offset_unit: crate::OffsetUnit::Milliseconds,
+            clock: Some(clock.clone()),
+            ..Default::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options).unwrap();
+        // A record every 100 ms up to the current time.
+        for i in 0..100 {
+            db.append_record(RecordInfo {
+                time_offset: i * 100,
+                value: i as u8,
+            })
+            .unwrap();
+        }
+
+        assert_eq!(db.prune_older_than(Duration::from_millis(250)).unwrap(), 98);
+        assert_eq!(db.read_record(0).unwrap().time_offset, 9_800);
+        #[cfg(feature = "chrono")]
+        {
+            use std::convert::TryFrom;
+            let cutoff = chrono::DateTime::try_from(origin.add_millis(9_850)).unwrap();
+            assert_eq!(db.purge_older_than(cutoff).unwrap(), 1);
+            assert_eq!(db.read_record(0).unwrap().time_offset, 9_900);
+        }
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,268 @@
+//! Ring DBs, enabled with the `ring_capacity` option of [`DbOptions`](crate::DbOptions).
+//!
+//! Once a ring DB holds its capacity of records, each append overwrites the oldest record in place, so the file
+//! never grows past the capacity, which suits embedded devices with a small flash.
+//! The capacity and the slot of the oldest record are recorded in the header by an extension entry.
+//! Record indexes stay logical: the record 0 is the oldest one, wherever it is stored. The files using it
+//! can't be read correctly by the versions before it, which return the records in the order they are stored.
+
+use crate::{DbHeader, PhysicalDB, RecordValue, TSLiteError};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// The extension tag recording the capacity of a ring DB and the slot of its oldest record.
+pub(crate) const TAG: u8 = 0xF3;
+
+impl DbHeader {
+    /// The maximum number of records of a ring DB, `None` if the DB is not a ring.
+    pub fn ring_capacity(&self) -> Option<u64> {
+        self.ring().map(|(capacity, _)| capacity)
+    }
+
+    /// The capacity and the slot of the oldest record of a ring DB.
+    fn ring(&self) -> Option<(u64, u64)> {
+        match self.extensions.get(TAG) {
+            Some(data) if data.len() == 16 => Some((
+                LittleEndian::read_u64(&data[..8]),
+                LittleEndian::read_u64(&data[8..]),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Record the capacity of a ring DB and the slot of its oldest record.
+    pub(crate) fn set_ring(&mut self, capacity: u64, start: u64) -> Result<(), TSLiteError> {
+        let mut data = [0; 16];
+        LittleEndian::write_u64(&mut data[..8], capacity);
+        LittleEndian::write_u64(&mut data[8..], start);
+        self.extensions.set(TAG, &data)
+    }
+
+    /// The slot of the file storing the record at index `index`, which is the index itself unless the
+    /// DB is a ring that wrapped around.
+    pub fn record_slot(&self, index: u64) -> u64 {
+        match self.ring() {
+            Some((capacity, start)) => (start + index) % capacity,
+            None => index,
+        }
+    }
+
+    /// The number of records that can be written from the slot of the record at index `index` without
+    /// wrapping around the end of a ring, `u64::MAX` if the DB is not a ring.
+    pub(crate) fn contiguous_slots(&self, index: u64) -> u64 {
+        match self.ring() {
+            Some((capacity, _)) => capacity - self.record_slot(index),
+            None => u64::MAX,
+        }
+    }
+
+    /// Account for `count` records appended from the slot after the newest record, without wrapping around:
+    /// the oldest records they overwrite are dropped.
+    pub(crate) fn ring_append(&mut self, count: u64) -> Result<(), TSLiteError> {
+        match self.ring() {
+            Some((capacity, start)) => {
+                let total = self.records_number + count;
+                let overwritten = total.saturating_sub(capacity);
+                self.records_number = total.min(capacity);
+                self.set_ring(capacity, (start + overwritten) % capacity)
+            }
+            None => {
+                self.records_number += count;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Hand the records that appending `count` records to a full ring DB overwrites to the eviction callback,
+    /// see [`PhysicalDB::on_evict`]. The append is expected not to wrap around the end of the ring.
+    pub(crate) fn evict_overwritten(&mut self, count: u64) -> Result<(), TSLiteError> {
+        let header = self.header;
+        let overwritten = match header.ring_capacity() {
+            Some(capacity) if self.evictor.is_some() => {
+                (header.records_number + count).saturating_sub(capacity)
+            }
+            _ => return Ok(()),
+        };
+        if overwritten == 0 {
+            return Ok(());
+        }
+
+        let size = header.record_size::<V>() as usize;
+        let mut buffer = vec![0; overwritten as usize * size];
+        let n = self.read_records_into(0, &mut buffer)?;
+        let mut chain = None;
+        let mut records = Vec::with_capacity(n);
+        for (index, bytes) in buffer[..n * size].chunks(size).enumerate() {
+            let mut record = header.decode_record::<V>(index as u64, bytes)?;
+            let quantized = header
+                .transforms
+                .undelta(index as u64, record.value, &mut chain);
+            record.value = header.transforms.dequantize(quantized);
+            records.push(record.resolve(&header));
+        }
+        if let Some(evictor) = self.evictor.as_mut() {
+            evictor.evict(&records);
+        }
+        Ok(())
+    }
+
+    /// Rewrite the records of a ring DB in the order of their indexes from the first slot, so the slots and
+    /// the indexes match. The file is replaced like with [`PhysicalDB::compact`], so a crash leaves either
+    /// the previous file or the unrolled one.
+    pub(crate) fn unroll_ring(&mut self) -> Result<(), TSLiteError> {
+        match self.header.ring() {
+            Some((_, start)) if start != 0 => self.rewrite_from(0).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DbOptions, PhysicalDB, RecordInfo, TSLiteError, Timestamp, Transforms};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    fn offsets(db: &mut PhysicalDB<u16>) -> Vec<u32> {
+        db.iter().map(|r| r.unwrap().time_offset).collect()
+    }
+
+    #[test]
+    fn ring_overwrites_oldest_records() {
+        let path = "ring.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            ring_capacity: Some(5),
+            record_checksums: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        let append = |db: &mut PhysicalDB<u16>, offsets: std::ops::Range<u32>| {
+            let records: Vec<RecordInfo<u16>> = offsets
+                .map(|i| RecordInfo {
+                    time_offset: i,
+                    value: i as u16 * 10,
+                })
+                .collect();
+            db.append_records(&records)
+                .expect("could not append records.");
+        };
+        append(&mut db, 0..3);
+        assert_eq!(offsets(&mut db), vec![0, 1, 2]);
+
+        // A batch wrapping around the end of the file.
+        append(&mut db, 3..7);
+        assert_eq!(db.header().records_number, 5);
+        assert_eq!(db.header().ring_capacity(), Some(5));
+        assert_eq!(offsets(&mut db), vec![2, 3, 4, 5, 6]);
+        assert_eq!(db.read_record(0).unwrap().value, 20);
+        assert_eq!(db.last_n(2).unwrap()[1].value, 60);
+        let size = db.header().record_size::<u16>();
+        assert_eq!(
+            fs::metadata(path).unwrap().len(),
+            crate::RECORDS_START + 5 * size
+        );
+
+        // The start is in the header, so it survives reopening the DB.
+        drop(db);
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::open_path(Path::new(path)).expect("could not open db.");
+        append(&mut db, 7..19);
+        assert_eq!(offsets(&mut db), vec![14, 15, 16, 17, 18]);
+        let range = db
+            .read_range(origin.add_seconds(15), origin.add_seconds(18))
+            .unwrap();
+        assert_eq!(range.len(), 3);
+        db.update_record(4, 7).unwrap();
+        assert_eq!(db.read_record(4).unwrap().value, 7);
+        assert!(db.check_all().unwrap().is_empty());
+
+        // Pruning puts the records back in order from the first slot.
+        assert_eq!(db.prune_before(origin.add_seconds(16)).unwrap(), 2);
+        assert_eq!(offsets(&mut db), vec![16, 17, 18]);
+        append(&mut db, 19..22);
+        assert_eq!(offsets(&mut db), vec![17, 18, 19, 20, 21]);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn ring_evicts_overwritten_records() {
+        let path = "ring_evict.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            ring_capacity: Some(4),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        db.on_evict(move |records| sink.lock().unwrap().extend_from_slice(records));
+
+        let records: Vec<RecordInfo<u16>> = (0..11)
+            .map(|i| RecordInfo {
+                time_offset: i,
+                value: i as u16 * 10,
+            })
+            .collect();
+        db.append_records(&records[..4]).unwrap();
+        assert!(evicted.lock().unwrap().is_empty());
+        db.append_record(records[4]).unwrap();
+        // A batch wrapping around the end of the file, overwriting more than a ring of records.
+        db.append_records(&records[5..]).unwrap();
+
+        let evicted = evicted.lock().unwrap();
+        assert_eq!(evicted.len(), 7);
+        for (i, record) in evicted.iter().enumerate() {
+            assert_eq!(record.time, origin.add_seconds(i as i64));
+            assert_eq!(record.value, i as u16 * 10);
+        }
+        assert_eq!(offsets(&mut db), vec![7, 8, 9, 10]);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn ring_invalid_options() {
+        let path = "ring_invalid.db";
+        let _ = fs::remove_file(path);
+
+        for options in [
+            DbOptions {
+                ring_capacity: Some(0),
+                ..DbOptions::default()
+            },
+            DbOptions {
+                ring_capacity: Some(10),
+                transforms: Transforms {
+                    delta: true,
+                    ..Transforms::default()
+                },
+                ..DbOptions::default()
+            },
+            DbOptions {
+                ring_capacity: Some(10),
+                wal: true,
+                ..DbOptions::default()
+            },
+        ]
+        .iter()
+        {
+            assert!(matches!(
+                PhysicalDB::<u16>::create_with_options(Path::new(path), None, options),
+                Err(TSLiteError::InvalidParameter(_))
+            ));
+        }
+        assert!(!Path::new(path).exists());
+    }
+}
@@ -0,0 +1,276 @@
+//! Rollups of a DB into a new DB holding one aggregated record per time bucket.
+
+use crate::{
+    Aggregation, DbOptions, OffsetUnit, PhysicalDB, RecordInfo, RecordValue, TSLiteError,
+    Timestamp, LATEST, NANOS_PER_SECOND,
+};
+use std::path::Path;
+use std::time::Duration;
+
+/// Number of aggregated records appended at once.
+const ROLLUP_BATCH: usize = 4096;
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Create a new DB at `path` holding `aggregation` over every `bucket` of this one, and return it.
+    ///
+    /// Buckets are aligned on the Unix epoch, the new DB starts at the bucket holding the origin of this one,
+    /// and each aggregated record is dated at the start of its bucket. Empty buckets are skipped.
+    /// The new DB keeps the transforms of this one and uses the coarsest offset unit dividing `bucket`.
+    /// Fail with `AlreadyExists` if there is already a file at `path`.
+    pub fn downsample_to(
+        &mut self,
+        path: &Path,
+        bucket: Duration,
+        aggregation: Aggregation,
+    ) -> Result<PhysicalDB<V>, TSLiteError> {
+        let size = bucket.as_secs() as i64;
+        if size == 0 {
+            return Err(TSLiteError::InvalidParameter(
+                "The bucket must last at least one second.".to_string(),
+            ));
+        }
+        self.refresh_if_changed()?;
+        let header = *self.header();
+        let offset_unit = [OffsetUnit::Hours, OffsetUnit::Minutes, OffsetUnit::Seconds]
+            .iter()
+            .copied()
+            .find(|u| size % u.seconds() == 0)
+            .unwrap();
+        let origin = header.origin_date.unix_seconds();
+        let origin = Timestamp::from_unix(origin - origin.rem_euclid(size));
+        let options = DbOptions {
+            offset_unit,
+            transforms: header.transforms,
+            ..DbOptions::default()
+        };
+        let mut rollup = PhysicalDB::create_with_options(path, Some(origin), &options)?;
+
+        self.rollup_buckets(&mut rollup, size, aggregation, &header.origin_date, &LATEST)?;
+
+        Ok(rollup)
+    }
+
+    /// Append to `rollup` the buckets of this DB which are complete and not rolled up yet, and return how
+    /// many records were appended. Run it periodically to keep a rollup up to date, such as 5 minutes
+    /// aggregates kept forever while the raw records are pruned after a week.
+    ///
+    /// The buckets are aligned on the Unix epoch, and the ones not rolled up yet start after the date of the
+    /// last record of `rollup`. A bucket is complete once it ended according to the clock of this DB: records
+    /// appended to it afterward are not rolled up.
+    /// Fail with `InvalidParameter` if `bucket` is not a multiple of the offset unit of `rollup`.
+    pub fn downsample_into(
+        &mut self,
+        rollup: &mut PhysicalDB<V>,
+        bucket: Duration,
+        aggregation: Aggregation,
+    ) -> Result<u64, TSLiteError> {
+        let size = bucket.as_secs() as i64;
+        if size == 0 || (size * NANOS_PER_SECOND) % rollup.header().offset_unit.nanoseconds() != 0 {
+            return Err(TSLiteError::InvalidParameter(
+                "The bucket must be a whole number of seconds and of offset units of the rollup."
+                    .to_string(),
+            ));
+        }
+        self.refresh_if_changed()?;
+        rollup.refresh_if_changed()?;
+        let header = *rollup.header();
+        let start = match rollup.last_n(1)?.first() {
+            Some(last) => header.offset_to_date(last.time_offset).add_seconds(size),
+            None => self.header().origin_date,
+        };
+        let now = self.options.now().unix_seconds();
+        let end = Timestamp::from_unix(now - now.rem_euclid(size));
+        if start >= end {
+            return Ok(0);
+        }
+
+        let before = header.records_number;
+        self.rollup_buckets(rollup, size, aggregation, &start, &end)?;
+        Ok(rollup.header().records_number - before)
+    }
+
+    /// Append to `rollup` `aggregation` over every bucket of `size` seconds of the records within `[start, end[`.
+    fn rollup_buckets(
+        &mut self,
+        rollup: &mut PhysicalDB<V>,
+        size: i64,
+        aggregation: Aggregation,
+        start: &Timestamp,
+        end: &Timestamp,
+    ) -> Result<(), TSLiteError> {
+        let rollup_header = *rollup.header();
+        let mut batch = Vec::with_capacity(ROLLUP_BATCH);
+        let mut failure = None;
+        self.scan_buckets(start, end, size, |key, stats| {
+            if failure.is_some() {
+                return;
+            }
+            let value = match stats.get(aggregation) {
+                Some(value) => V::from_f64(value),
+                None => return,
+            };
+            let result = rollup_header
+                .checked_offset(&Timestamp::from_unix(key))
+                .and_then(|time_offset| {
+                    batch.push(RecordInfo { time_offset, value });
+                    if batch.len() < ROLLUP_BATCH {
+                        return Ok(());
+                    }
+                    let appended = rollup.append_records(&batch);
+                    batch.clear();
+                    appended
+                });
+            failure = result.err();
+        })?;
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        rollup.append_records(&batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn downsample_into_new_db() {
+        let source = "rollup_source.db";
+        let target = "rollup_target.db";
+        let _ = fs::remove_file(source);
+        let _ = fs::remove_file(target);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 7, 30).unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(source), Some(origin)).expect("could not create db.");
+        // Every 30 seconds from 00:07:30 to 00:32, skipping the 10 minutes starting at 00:20.
+        for i in 0..50u32 {
+            if (25..45).contains(&i) {
+                continue;
+            }
+            db.append_record(RecordInfo {
+                time_offset: i * 30,
+                value: i as u8,
+            })
+            .expect("could not append record.");
+        }
+
+        assert!(db
+            .downsample_to(
+                Path::new(target),
+                Duration::from_millis(500),
+                Aggregation::Max
+            )
+            .is_err());
+        let mut rollup = db
+            .downsample_to(
+                Path::new(target),
+                Duration::from_secs(600),
+                Aggregation::Max,
+            )
+            .expect("could not downsample.");
+        let header = *rollup.header();
+        assert_eq!(
+            header.origin_date,
+            Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(header.offset_unit, OffsetUnit::Minutes);
+        assert_eq!(header.records_number, 3);
+        let records = rollup.records(header.origin_date, LATEST).unwrap();
+        let buckets: Vec<(i64, u8)> = records
+            .iter()
+            .map(|r| {
+                (
+                    r.time.unix_seconds() - header.origin_date.unix_seconds(),
+                    r.value,
+                )
+            })
+            .collect();
+        assert_eq!(buckets, vec![(0, 4), (600, 24), (1800, 49)]);
+
+        assert_eq!(
+            db.downsample_to(
+                Path::new(target),
+                Duration::from_secs(600),
+                Aggregation::Max
+            )
+            .err(),
+            Some(TSLiteError::AlreadyExists)
+        );
+
+        let _ = fs::remove_file(source);
+        let _ = fs::remove_file(target);
+    }
+
+    #[test]
+    fn downsample_into_existing_rollup() {
+        let source = "rollup_incremental_source.db";
+        let target = "rollup_incremental_target.db";
+        let _ = fs::remove_file(source);
+        let _ = fs::remove_file(target);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let clock = std::sync::Arc::new(crate::MockClock::new(origin));
+        let options = DbOptions {
+            clock: Some(clock.clone()),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(source), Some(origin), &options)
+                .expect("could not create db.");
+        let rollup_options = DbOptions {
+            offset_unit: OffsetUnit::Minutes,
+            ..DbOptions::default()
+        };
+        let mut rollup: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(target), Some(origin), &rollup_options)
+                .expect("could not create rollup.");
+        let every_minute = |db: &mut PhysicalDB, minutes: std::ops::Range<u32>| {
+            for i in minutes {
+                db.append_record(RecordInfo {
+                    time_offset: i * 60,
+                    value: i as u8,
+                })
+                .expect("could not append record.");
+            }
+        };
+        let five_minutes = Duration::from_secs(300);
+        assert!(db
+            .downsample_into(&mut rollup, Duration::from_secs(90), Aggregation::Sum)
+            .is_err());
+
+        // The bucket starting at 00:10 is not complete yet.
+        every_minute(&mut db, 0..12);
+        clock.set(origin.add_seconds(12 * 60));
+        assert_eq!(
+            db.downsample_into(&mut rollup, five_minutes, Aggregation::Sum)
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            db.downsample_into(&mut rollup, five_minutes, Aggregation::Sum)
+                .unwrap(),
+            0
+        );
+
+        // The raw records can be pruned once rolled up.
+        db.prune_before(origin.add_seconds(10 * 60)).unwrap();
+        every_minute(&mut db, 12..17);
+        clock.set(origin.add_seconds(30 * 60));
+        assert_eq!(
+            db.downsample_into(&mut rollup, five_minutes, Aggregation::Sum)
+                .unwrap(),
+            2
+        );
+        let records = rollup.records(origin, LATEST).unwrap();
+        let buckets: Vec<(i64, u8)> = records
+            .iter()
+            .map(|r| (r.time.unix_seconds() - origin.unix_seconds(), r.value))
+            .collect();
+        assert_eq!(buckets, vec![(0, 10), (300, 35), (600, 60), (900, 31)]);
+
+        let _ = fs::remove_file(source);
+        let _ = fs::remove_file(target);
+    }
+}
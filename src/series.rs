@@ -0,0 +1,292 @@
+//! A storage-agnostic interface over a time serie.
+
+use crate::{
+    PhysicalDB, Record, RecordInfo, RecordValue, TSLiteError, Timestamp, EARLIEST, LATEST,
+};
+use std::time::Duration;
+
+/// The aggregations that can be computed over a range of records.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Aggregation {
+    Min,
+    Max,
+    Mean,
+    Sum,
+    Count,
+}
+
+/// Summary statistics of the values of a range of records.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stats {
+    pub count: u64,
+    pub sum: f64,
+    /// `None` if the range is empty.
+    pub min: Option<f64>,
+    /// `None` if the range is empty.
+    pub max: Option<f64>,
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        Stats {
+            count: 0,
+            sum: 0.0,
+            min: None,
+            max: None,
+        }
+    }
+}
+
+impl Stats {
+    /// Account for one more value.
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |m| m.min(value)));
+        self.max = Some(self.max.map_or(value, |m| m.max(value)));
+    }
+
+    /// The mean of the values, `None` if the range is empty.
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.sum / self.count as f64)
+    }
+
+    /// Pick the result of `aggregation` out of the statistics.
+    /// Only `Count` and `Sum` have a value for an empty range.
+    pub fn get(&self, aggregation: Aggregation) -> Option<f64> {
+        match aggregation {
+            Aggregation::Min => self.min,
+            Aggregation::Max => self.max,
+            Aggregation::Mean => self.mean(),
+            Aggregation::Sum => Some(self.sum),
+            Aggregation::Count => Some(self.count as f64),
+        }
+    }
+}
+
+/// The operations every storage strategy of a time serie supports,
+/// so applications can be written against the trait and swap the storage underneath.
+/// Ranges are always `[start, end[`.
+pub trait TimeSeries {
+    /// The type of the values stored in the serie.
+    type Value: RecordValue;
+
+    /// Add a record at `time`, which must not be anterior to the origin of the serie.
+    fn append(&mut self, time: Timestamp, value: Self::Value) -> Result<(), TSLiteError>;
+
+    /// Return every record within `[start, end[`.
+    fn range(
+        &mut self,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Record<Self::Value>>, TSLiteError>;
+
+    /// Return every record dated `start` or later.
+    fn since(&mut self, start: Timestamp) -> Result<Vec<Record<Self::Value>>, TSLiteError> {
+        self.range(start, LATEST)
+    }
+
+    /// Return every record of the last `duration` up to now, now included.
+    fn last(&mut self, duration: Duration) -> Result<Vec<Record<Self::Value>>, TSLiteError> {
+        let now = self.now().unix_nanos();
+        let start = (now - duration.as_nanos() as i128).max(EARLIEST.unix_nanos());
+        self.range(
+            Timestamp::from_unix_nanos(start),
+            Timestamp::from_unix_nanos(now + 1),
+        )
+    }
+
+    /// The current time, the system one unless the serie has a clock of its own.
+    fn now(&self) -> Timestamp {
+        Timestamp::now()
+    }
+
+    /// Compute the statistics of the records within `[start, end[`.
+    fn stats(&mut self, start: Timestamp, end: Timestamp) -> Result<Stats, TSLiteError> {
+        let mut stats = Stats::default();
+        for r in self.range(start, end)? {
+            stats.push(r.value.as_f64());
+        }
+        Ok(stats)
+    }
+
+    /// Compute `aggregation` over the records within `[start, end[`, `None` if it is undefined for an empty range.
+    fn aggregate(
+        &mut self,
+        start: Timestamp,
+        end: Timestamp,
+        aggregation: Aggregation,
+    ) -> Result<Option<f64>, TSLiteError> {
+        Ok(self.stats(start, end)?.get(aggregation))
+    }
+}
+
+impl<V: RecordValue> TimeSeries for PhysicalDB<V> {
+    type Value = V;
+
+    fn append(&mut self, time: Timestamp, value: V) -> Result<(), TSLiteError> {
+        let time_offset = self.header().checked_offset(&time)?;
+        self.append_record(RecordInfo { time_offset, value })
+    }
+
+    fn range(&mut self, start: Timestamp, end: Timestamp) -> Result<Vec<Record<V>>, TSLiteError> {
+        self.records(start, end)
+    }
+
+    fn now(&self) -> Timestamp {
+        self.options.now()
+    }
+
+    fn stats(&mut self, start: Timestamp, end: Timestamp) -> Result<Stats, TSLiteError> {
+        // Stream the records instead of collecting the range.
+        let mut stats = Stats::default();
+        self.scan_range(&start, &end, |r| stats.push(r.value.as_f64()))?;
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Clock, DbOptions, MockClock};
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn fill<T: TimeSeries<Value = u8>>(series: &mut T, origin: Timestamp) {
+        for (i, v) in [4, 8, 15, 16, 23, 42].iter().enumerate() {
+            series
+                .append(origin.add_seconds(i as i64), *v)
+                .expect("could not append record.");
+        }
+    }
+
+    #[test]
+    fn physical_db_as_time_series() {
+        let path = "time_series.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(MockClock::new(origin.add_seconds(86_400)));
+        let options = DbOptions {
+            clock: Some(clock.clone()),
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        fill(&mut db, origin);
+        assert!(db.append(origin.add_seconds(-1), 0).is_err());
+        assert_eq!(
+            db.append(origin.add_seconds(u32::MAX as i64 + 1), 0),
+            Err(TSLiteError::OffsetOverflow)
+        );
+
+        let start = origin.add_seconds(1);
+        let end = origin.add_seconds(5);
+        let range = db.range(start, end).expect("could not read range.");
+        let values: Vec<u8> = range.iter().map(|r| r.value).collect();
+        assert_eq!(values, vec![8, 15, 16, 23]);
+        assert_eq!(range[0].time, start);
+        assert_eq!(range[3].time, origin.add_seconds(4));
+
+        let stats = TimeSeries::stats(&mut db, start, end).expect("could not compute stats.");
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.min, Some(8.0));
+        assert_eq!(stats.max, Some(23.0));
+        assert_eq!(stats.mean(), Some(15.5));
+        assert_eq!(
+            db.aggregate(start, end, Aggregation::Sum).unwrap(),
+            Some(62.0)
+        );
+        assert_eq!(
+            db.aggregate(
+                end.add_seconds(1),
+                end.add_seconds(86_400),
+                Aggregation::Min
+            )
+            .unwrap(),
+            None
+        );
+
+        let values =
+            |records: Vec<Record>| -> Vec<u8> { records.iter().map(|r| r.value).collect() };
+        assert_eq!(
+            values(db.since(origin.add_seconds(4)).unwrap()),
+            vec![23, 42]
+        );
+        let now = clock.now();
+        db.append(now.add_seconds(-3600), 1).unwrap();
+        db.append(now.add_seconds(-60), 2).unwrap();
+        db.append(now, 3).unwrap();
+        assert_eq!(
+            values(db.last(Duration::from_secs(600)).unwrap()),
+            vec![2, 3]
+        );
+        assert_eq!(
+            values(db.last(Duration::from_secs(7200)).unwrap()),
+            vec![1, 2, 3]
+        );
+        // Half a second, and not the current second.
+        clock.set(now.add_millis(500));
+        assert_eq!(
+            values(db.last(Duration::from_millis(500)).unwrap()),
+            vec![3]
+        );
+        assert_eq!(db.last(Duration::from_millis(499)).unwrap(), vec![]);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn aggregate_across_chunks() {
+        let path = "aggregate_chunks.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = crate::DbOptions {
+            record_checksums: true,
+            transforms: crate::Transforms {
+                delta: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        let records: Vec<RecordInfo<u16>> = (0..1000)
+            .map(|i| RecordInfo {
+                time_offset: i,
+                value: (i % 100) as u16,
+            })
+            .collect();
+        db.append_records(&records)
+            .expect("could not append records.");
+
+        // The range spans several chunks and ends in the middle of one.
+        let (start, end) = (origin.add_seconds(100), origin.add_seconds(950));
+        assert_eq!(
+            db.aggregate(start, end, Aggregation::Count).unwrap(),
+            Some(850.0)
+        );
+        assert_eq!(
+            db.aggregate(start, end, Aggregation::Sum).unwrap(),
+            Some((100..950).map(|i| (i % 100) as f64).sum())
+        );
+        assert_eq!(
+            db.aggregate(origin.add_seconds(990), LATEST, Aggregation::Mean)
+                .unwrap(),
+            Some(94.5)
+        );
+        assert_eq!(
+            db.aggregate(start, end, Aggregation::Max).unwrap(),
+            Some(99.0)
+        );
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,110 @@
+//! Persist every registered DB when the process shuts down.
+//!
+//! Relying on `Drop` during process teardown is fragile: statics are never dropped and a signal kills
+//! the process without unwinding. DBs registered with [`PhysicalDB::register_for_shutdown`] are instead
+//! closed explicitly by [`flush_and_close_all`], which can also be hooked to SIGTERM and SIGINT with the
+//! `signal` feature.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError};
+use std::sync::{Arc, Mutex, Weak};
+
+/// A DB shared between the application and the shutdown registry.
+pub type SharedDB<V = u8> = Arc<Mutex<PhysicalDB<V>>>;
+
+/// What the registry needs from a DB, independently of its value type.
+trait Closable: Send {
+    fn flush_and_close(&mut self) -> Result<(), TSLiteError>;
+}
+
+impl<V: RecordValue + Send> Closable for PhysicalDB<V> {
+    fn flush_and_close(&mut self) -> Result<(), TSLiteError> {
+        if self.file.is_some() {
+            self.write_header()?;
+        }
+        self.close()
+    }
+}
+
+static REGISTRY: Mutex<Vec<Weak<Mutex<dyn Closable>>>> = Mutex::new(Vec::new());
+
+impl<V: RecordValue + Send + 'static> PhysicalDB<V> {
+    /// Hand the DB over to the shutdown registry, so [`flush_and_close_all`] persists it.
+    /// The registry only keeps a weak reference: a DB dropped by the application is simply forgotten.
+    pub fn register_for_shutdown(self) -> SharedDB<V> {
+        let shared = Arc::new(Mutex::new(self));
+        let weak: Weak<Mutex<dyn Closable>> = Arc::downgrade(&shared) as Weak<Mutex<dyn Closable>>;
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        registry.retain(|db| db.strong_count() > 0);
+        registry.push(weak);
+        shared
+    }
+}
+
+/// Write the header and sync every registered DB still alive, then close their file.
+/// Every DB is processed even if some fail, the first error is returned.
+/// A DB closed this way is re-opened transparently if it is used again.
+pub fn flush_and_close_all() -> Result<(), TSLiteError> {
+    let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let mut result = Ok(());
+    for db in registry.iter().filter_map(|db| db.upgrade()) {
+        let mut db = db.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = db.flush_and_close() {
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+    result
+}
+
+/// Spawn a thread that calls [`flush_and_close_all`] when the process receives SIGTERM or SIGINT,
+/// then exits the process with the conventional `128 + signal` status.
+#[cfg(all(feature = "signal", unix))]
+pub fn install_signal_handler() -> Result<(), TSLiteError> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGTERM, SIGINT]).map_err(TSLiteError::Io)?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            let _ = flush_and_close_all();
+            std::process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordInfo;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn close_registered_dbs() {
+        let path = "shutdown.db";
+        let _ = fs::remove_file(path);
+
+        let db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let shared = db.register_for_shutdown();
+        shared
+            .lock()
+            .unwrap()
+            .append_record(RecordInfo {
+                time_offset: 5,
+                value: 10,
+            })
+            .expect("could not append record.");
+        assert!(shared.lock().unwrap().file.is_some());
+
+        flush_and_close_all().expect("could not close dbs.");
+        assert!(shared.lock().unwrap().file.is_none());
+        let mut reopened: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(reopened.read_header().unwrap().records_number, 1);
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,372 @@
+//! A simulated disk and clock, to test crash consistency and retention deterministically.
+//!
+//! [`SimDisk`] holds a DB file in memory and tells apart what was written from what was synced, so a crash
+//! can drop the writes that weren't synced yet. Its writes, syncs and truncations are numbered, and faults
+//! can be scheduled at any of them: run a scenario once to count its operations, then run it again with a
+//! crash at each of them in turn. Pair it with a [`MockClock`](crate::MockClock) to control the time.
+//!
+//! [`SimSeries`] stores a time serie on a [`SimDisk`] with the layout of a DB file, writing it in the same
+//! order as [`PhysicalDB`](crate::PhysicalDB): the records, then the header, then a sync.
+
+use crate::format::{
+    decode_header, encode_header, record_capacity, record_position, RECORDS_START,
+};
+use crate::{
+    checked_header, Clock, DbHeader, DbIssue, Extensions, HeaderCopy, OffsetUnit, Record,
+    RecordInfo, RecordValue, TSLiteError, TimeSeries, Timestamp, Transforms,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A fault injected at an operation of a [`SimDisk`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The operation fails without any effect.
+    Error,
+    /// Only the first octets of the write are stored, then it fails. On a sync or a truncation, like `Error`.
+    Torn(usize),
+    /// The process stops right before the operation: it fails, and every write not synced yet is lost.
+    Crash,
+}
+
+#[derive(Debug, Default)]
+struct DiskState {
+    /// The content as the process sees it.
+    content: Vec<u8>,
+    /// The content that survives a crash.
+    synced: Vec<u8>,
+    operations: u64,
+    faults: BTreeMap<u64, Fault>,
+}
+
+/// A file held in memory, whose writes are only durable once synced. Its clones share the same content.
+#[derive(Debug, Clone, Default)]
+pub struct SimDisk {
+    state: Rc<RefCell<DiskState>>,
+}
+
+impl SimDisk {
+    pub fn new() -> SimDisk {
+        SimDisk::default()
+    }
+
+    /// Number of writes, syncs and truncations done so far, failed ones included.
+    pub fn operations(&self) -> u64 {
+        self.state.borrow().operations
+    }
+
+    /// Inject `fault` at the operation numbered `operation`, counting from 0.
+    pub fn schedule(&self, operation: u64, fault: Fault) {
+        self.state.borrow_mut().faults.insert(operation, fault);
+    }
+
+    /// Drop every write not synced yet, as a power loss would.
+    pub fn crash(&self) {
+        let mut state = self.state.borrow_mut();
+        state.content = state.synced.clone();
+    }
+
+    /// The content of the file as the process sees it.
+    pub fn content(&self) -> Vec<u8> {
+        self.state.borrow().content.clone()
+    }
+
+    /// Count an operation and return the fault scheduled for it, after applying its crash.
+    fn next_operation(&self) -> Option<Fault> {
+        let mut state = self.state.borrow_mut();
+        let operation = state.operations;
+        let fault = state.faults.remove(&operation);
+        state.operations += 1;
+        if fault == Some(Fault::Crash) {
+            state.content = state.synced.clone();
+        }
+        fault
+    }
+
+    fn write_at(&self, bytes: &[u8], position: usize) -> Result<(), TSLiteError> {
+        let fault = self.next_operation();
+        let stored = match fault {
+            None => bytes,
+            Some(Fault::Torn(n)) => &bytes[..n.min(bytes.len())],
+            Some(_) => &[],
+        };
+        if !stored.is_empty() {
+            let mut state = self.state.borrow_mut();
+            if state.content.len() < position + stored.len() {
+                state.content.resize(position + stored.len(), 0);
+            }
+            state.content[position..position + stored.len()].copy_from_slice(stored);
+        }
+        fault_result(fault)
+    }
+
+    fn sync(&self) -> Result<(), TSLiteError> {
+        let fault = self.next_operation();
+        if fault.is_none() {
+            let mut state = self.state.borrow_mut();
+            state.synced = state.content.clone();
+        }
+        fault_result(fault)
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), TSLiteError> {
+        let fault = self.next_operation();
+        if fault.is_none() {
+            self.state.borrow_mut().content.truncate(len);
+        }
+        fault_result(fault)
+    }
+}
+
+fn fault_result(fault: Option<Fault>) -> Result<(), TSLiteError> {
+    match fault {
+        None => Ok(()),
+        Some(fault) => Err(TSLiteError::Io(io::Error::other(format!(
+            "Simulated fault: {:?}",
+            fault
+        )))),
+    }
+}
+
+/// A time serie stored on a [`SimDisk`] with the layout of a DB file. Transforms are not supported.
+#[derive(Debug)]
+pub struct SimSeries<V: RecordValue = u8> {
+    disk: SimDisk,
+    clock: Arc<dyn Clock>,
+    header: DbHeader,
+    value: PhantomData<V>,
+}
+
+impl<V: RecordValue> SimSeries<V> {
+    /// Create an empty serie on `disk`, overwriting its content, `origin_date` defaulting to the time of `clock`.
+    pub fn create(
+        disk: SimDisk,
+        clock: Arc<dyn Clock>,
+        origin_date: Option<Timestamp>,
+    ) -> Result<SimSeries<V>, TSLiteError> {
+        let origin_date = origin_date.unwrap_or_else(|| clock.now());
+        if !origin_date.is_valid() {
+            return Err(TSLiteError::InvalidTimestamp);
+        }
+        let mut header = DbHeader {
+            origin_date,
+            records_number: 0,
+            offset_unit: OffsetUnit::Seconds,
+            transforms: Transforms::default(),
+            extensions: Extensions::default(),
+        };
+        header.set_value_type::<V>()?;
+        disk.truncate(0)?;
+        disk.write_at(&encode_header(&header), 0)?;
+        disk.sync()?;
+
+        Ok(SimSeries {
+            disk,
+            clock,
+            header,
+            value: PhantomData,
+        })
+    }
+
+    /// Open the serie stored on `disk`, as after a restart.
+    pub fn open(disk: SimDisk, clock: Arc<dyn Clock>) -> Result<SimSeries<V>, TSLiteError> {
+        let header = decode_header(&disk.content())?;
+        header.check_width::<V>()?;
+        if !header.transforms.is_identity() {
+            return Err(TSLiteError::InvalidParameter(
+                "Simulated series don't support transforms.".to_string(),
+            ));
+        }
+        if header.record_checksums() {
+            return Err(TSLiteError::InvalidParameter(
+                "Simulated series don't support record checksums.".to_string(),
+            ));
+        }
+        Ok(SimSeries {
+            disk,
+            clock,
+            header,
+            value: PhantomData,
+        })
+    }
+
+    /// The header of the serie.
+    pub fn header(&self) -> &DbHeader {
+        &self.header
+    }
+
+    /// Add a record at the current time of the clock.
+    pub fn append_now(&mut self, value: V) -> Result<(), TSLiteError> {
+        let now = self.clock.now();
+        self.append(now, value)
+    }
+
+    /// Drop every record older than `age` at the current time of the clock, and return how many were dropped.
+    /// Like [`PhysicalDB::prune_before`](crate::PhysicalDB::prune_before), the remaining records are moved to
+    /// the start of the file, then the header is written and the file shrunk.
+    pub fn prune_older_than(&mut self, age: Duration) -> Result<u64, TSLiteError> {
+        let cutoff = self.clock.now().add_seconds(-(age.as_secs() as i64));
+        let records = self.read_records()?;
+        let evicted = records
+            .iter()
+            .take_while(|r| self.header.offset_to_date(r.time_offset) < cutoff)
+            .count();
+        if evicted == 0 {
+            return Ok(0);
+        }
+
+        let kept: Vec<u8> = records[evicted..]
+            .iter()
+            .flat_map(|r| r.as_bytes())
+            .collect();
+        self.disk
+            .write_at(&kept, record_position::<V>(0) as usize)?;
+        let mut header = self.header;
+        header.records_number -= evicted as u64;
+        self.write_header(header)?;
+        self.disk
+            .truncate(record_position::<V>(header.records_number) as usize)?;
+
+        Ok(evicted as u64)
+    }
+
+    /// Look for the issues [`PhysicalDB::check_db_file`](crate::PhysicalDB::check_db_file) reports.
+    pub fn check(&self) -> DbIssue {
+        let content = self.disk.content();
+        if (content.len() as u64) < RECORDS_START {
+            return DbIssue::HeaderCorrupted;
+        }
+        // A damaged primary copy is reported even if the shadow one is fine.
+        let header = match checked_header(&content) {
+            Ok((header, HeaderCopy::Primary)) => header,
+            Err(TSLiteError::InvalidTimestamp) => return DbIssue::OriginDateInvalid,
+            _ => return DbIssue::HeaderCorrupted,
+        };
+        if record_capacity::<V>(content.len() as u64) < header.records_number {
+            return DbIssue::MismatchRecordAmount;
+        }
+        let mut time_offset = 0;
+        for i in 0..header.records_number {
+            let position = record_position::<V>(i) as usize;
+            let record = match RecordInfo::<V>::try_from(&content[position..]) {
+                Ok(record) => record,
+                Err(_) => return DbIssue::RecordCorrupted(i),
+            };
+            if record.time_offset < time_offset {
+                return DbIssue::UnorderedRecord(i);
+            }
+            time_offset = record.time_offset;
+        }
+        DbIssue::None
+    }
+
+    fn read_records(&self) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+        let content = self.disk.content();
+        if record_capacity::<V>(content.len() as u64) < self.header.records_number {
+            return Err(TSLiteError::ShortRead("records".to_string()));
+        }
+        (0..self.header.records_number)
+            .map(|i| RecordInfo::try_from(&content[record_position::<V>(i) as usize..]))
+            .collect()
+    }
+
+    /// Write and sync both copies of `header`, then keep it.
+    fn write_header(&mut self, header: DbHeader) -> Result<(), TSLiteError> {
+        self.disk.write_at(&encode_header(&header), 0)?;
+        self.disk.sync()?;
+        self.header = header;
+        Ok(())
+    }
+}
+
+impl<V: RecordValue> TimeSeries for SimSeries<V> {
+    type Value = V;
+
+    fn append(&mut self, time: Timestamp, value: V) -> Result<(), TSLiteError> {
+        let time_offset = self.header.checked_offset(&time)?;
+        let position = record_position::<V>(self.header.records_number) as usize;
+        self.disk
+            .write_at(&RecordInfo { time_offset, value }.as_bytes(), position)?;
+        let mut header = self.header;
+        header.records_number += 1;
+        self.write_header(header)
+    }
+
+    /// The records are assumed to be chronologically ordered: the first record past `end` stops the read.
+    fn range(&mut self, start: Timestamp, end: Timestamp) -> Result<Vec<Record<V>>, TSLiteError> {
+        let start = self.header.offset_not_before(&start).max(0);
+        let end = self.header.offset_not_before(&end);
+        Ok(self
+            .read_records()?
+            .iter()
+            .take_while(|r| (r.time_offset as i64) < end)
+            .filter(|r| r.time_offset as i64 >= start)
+            .map(|r| r.resolve(&self.header))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockClock;
+
+    /// Append five records a minute apart, then drop those older than three minutes.
+    fn scenario(disk: &SimDisk, clock: &MockClock) -> Result<(), TSLiteError> {
+        let mut series: SimSeries = SimSeries::create(disk.clone(), Arc::new(clock.clone()), None)?;
+        for i in 0..5 {
+            series.append_now(i)?;
+            clock.advance(Duration::from_secs(60));
+        }
+        series.prune_older_than(Duration::from_secs(180))?;
+        Ok(())
+    }
+
+    #[test]
+    fn crash_at_every_operation() {
+        let start = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let disk = SimDisk::new();
+        let clock = MockClock::new(start);
+        scenario(&disk, &clock).unwrap();
+        let operations = disk.operations();
+        assert_eq!(operations, 3 + 5 * 3 + 4);
+
+        let mut series: SimSeries = SimSeries::open(disk.clone(), Arc::new(clock.clone())).unwrap();
+        assert_eq!(series.check(), DbIssue::None);
+        let values: Vec<u8> = series
+            .since(start)
+            .unwrap()
+            .iter()
+            .map(|r| r.value)
+            .collect();
+        assert_eq!(values, vec![2, 3, 4]);
+        assert_eq!(series.header().origin_date, start);
+
+        // Up to the retention, a crash loses at most the record being appended.
+        for crash in 3..3 + 5 * 3 {
+            let disk = SimDisk::new();
+            let clock = MockClock::new(start);
+            disk.schedule(crash, Fault::Crash);
+            assert!(scenario(&disk, &clock).is_err());
+            let mut series: SimSeries = SimSeries::open(disk, Arc::new(clock)).unwrap();
+            assert_eq!(series.check(), DbIssue::None);
+            let records = series.since(start).unwrap();
+            assert_eq!(records.len() as u64, (crash - 3) / 3);
+        }
+
+        // A torn header write is recovered from the shadow copy.
+        let disk = SimDisk::new();
+        let clock = MockClock::new(start);
+        disk.schedule(4, Fault::Torn(15));
+        assert!(scenario(&disk, &clock).is_err());
+        let series: SimSeries = SimSeries::open(disk, Arc::new(clock)).unwrap();
+        assert_eq!(series.check(), DbIssue::HeaderCorrupted);
+        assert_eq!(series.header().records_number, 0);
+    }
+}
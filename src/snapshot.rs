@@ -0,0 +1,184 @@
+//! Consistent copies of a DB, to back it up while it is written to, and restoring them.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Copy the DB to `dest`, and return the number of records copied.
+    ///
+    /// The header is captured first and only the records it counts are copied, so the records appended while
+    /// the copy is made, by this handle or another process, are left out instead of making the copy invalid.
+    /// The copy is written next to `dest` and renamed over it once synced, so a previous backup at `dest` is
+    /// only replaced by a complete one. Like with [`PhysicalDB::compact`], the octets past the records are
+    /// dropped and the records of a ring are stored from the first slot.
+    /// The records rewritten in place while the copy is made, by an update, a reorder or the appends to a full
+    /// ring, can be copied either way: take a shared lock while the writers take an exclusive one, see
+    /// [`PhysicalDB::lock_shared`].
+    /// Pending records of a buffered DB are written before the copy is made.
+    pub fn snapshot(&mut self, dest: &Path) -> Result<u64, TSLiteError> {
+        self.flush()?;
+        if self.file.is_none() {
+            self.open()?;
+        }
+        self.refresh_if_changed()?;
+        self.check_stale()?;
+
+        let partial = partial_path(dest);
+        let result = self.write_compacted(&partial);
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        result?;
+        fs::rename(&partial, dest).map_err(TSLiteError::Io)?;
+
+        Ok(self.header.records_number)
+    }
+
+    /// Install the backup at `backup` as the DB at `dest`, and return the number of records restored.
+    ///
+    /// The backup is checked first, like with [`PhysicalDB::check_all`]: its header, the order of its records
+    /// and their checksums if it has some. If it has an issue, nothing is written and the DB at `dest`, if
+    /// any, is left as it is. Otherwise the backup is copied next to `dest` and renamed over it once synced,
+    /// so `dest` holds either the previous DB or the whole backup. The handles open on the previous DB are
+    /// stale afterwards, see [`PhysicalDB::reopen`].
+    /// Fail with `InvalidData` if the backup has an issue, and with `ValueWidthMismatch` if it doesn't hold
+    /// `V` values.
+    pub fn restore(backup: &Path, dest: &Path) -> Result<u64, TSLiteError> {
+        let mut source: PhysicalDB<V> = PhysicalDB::open_path(backup)?;
+        if let Some(issue) = source.check_all()?.first() {
+            return Err(TSLiteError::InvalidData(format!(
+                "backup {}: {:?}",
+                backup.display(),
+                issue
+            )));
+        }
+
+        let partial = partial_path(dest);
+        let result = source.write_compacted(&partial);
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        result?;
+        fs::rename(&partial, dest).map_err(TSLiteError::Io)?;
+
+        Ok(source.header.records_number)
+    }
+}
+
+/// The path a snapshot or a restored backup is written to before being renamed to `dest`.
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_owned();
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbIssue, DbOptions, RecordInfo, RECORDS_START};
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[test]
+    fn snapshot_while_appending() {
+        let (path, backup) = ("snapshot.db", "snapshot_backup.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(backup);
+
+        let mut db: PhysicalDB<u32> =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let mut writer: PhysicalDB<u32> = PhysicalDB::open_path(Path::new(path)).unwrap();
+        let append = |db: &mut PhysicalDB<u32>, from: u32, to: u32| {
+            let records: Vec<RecordInfo<u32>> = (from..to)
+                .map(|i| RecordInfo {
+                    time_offset: i,
+                    value: i,
+                })
+                .collect();
+            db.append_records(&records).unwrap();
+        };
+        append(&mut writer, 0, 1000);
+        // An append in progress in another process, whose record is only partly written.
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(&[0xAB; 5])
+            .unwrap();
+
+        assert_eq!(db.snapshot(Path::new(backup)).unwrap(), 1000);
+        // Appended after the snapshot.
+        append(&mut writer, 1000, 1500);
+        let mut copy: PhysicalDB<u32> = PhysicalDB::open_path(Path::new(backup)).unwrap();
+        assert_eq!(copy.header().records_number, 1000);
+        assert_eq!(
+            fs::metadata(backup).unwrap().len(),
+            RECORDS_START + 1000 * RecordInfo::<u32>::SIZE
+        );
+        assert_eq!(copy.check_db_file().unwrap(), DbIssue::None);
+        assert_eq!(copy.last_n(1).unwrap()[0].value, 999);
+        drop(copy);
+
+        // A newer snapshot replaces the previous one.
+        assert_eq!(db.snapshot(Path::new(backup)).unwrap(), 1500);
+        let mut copy: PhysicalDB<u32> = PhysicalDB::open_path(Path::new(backup)).unwrap();
+        assert_eq!(copy.last_n(1).unwrap()[0].value, 1499);
+        assert!(!partial_path(Path::new(backup)).exists());
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(backup);
+    }
+
+    #[test]
+    fn restore_checked_backup() {
+        let (path, backup) = ("restore.db", "restore_backup.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(backup);
+
+        let options = DbOptions {
+            record_checksums: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u32> =
+            PhysicalDB::create_with_options(Path::new(path), None, &options).unwrap();
+        let records: Vec<RecordInfo<u32>> = (0..100)
+            .map(|i| RecordInfo {
+                time_offset: i,
+                value: i,
+            })
+            .collect();
+        db.append_records(&records[..60]).unwrap();
+        db.snapshot(Path::new(backup)).unwrap();
+        db.append_records(&records[60..]).unwrap();
+        drop(db);
+
+        assert_eq!(
+            PhysicalDB::<u32>::restore(Path::new(backup), Path::new(path)).unwrap(),
+            60
+        );
+        let mut restored: PhysicalDB<u32> = PhysicalDB::open_path(Path::new(path)).unwrap();
+        assert_eq!(restored.header().records_number, 60);
+        assert_eq!(restored.last_n(1).unwrap()[0].value, 59);
+        assert_eq!(restored.check_db_file().unwrap(), DbIssue::None);
+        restored.append_records(&records[60..]).unwrap();
+        drop(restored);
+
+        // A backup damaged in transfer doesn't replace the live DB.
+        let len = fs::metadata(backup).unwrap().len();
+        let mut file = OpenOptions::new().write(true).open(backup).unwrap();
+        file.seek(SeekFrom::Start(len - 1)).unwrap();
+        file.write_all(&[0xFF]).unwrap();
+        drop(file);
+        let result = PhysicalDB::<u32>::restore(Path::new(backup), Path::new(path));
+        assert!(matches!(result, Err(TSLiteError::InvalidData(_))));
+        assert!(PhysicalDB::<u64>::restore(Path::new(backup), Path::new(path)).is_err());
+        let mut live: PhysicalDB<u32> = PhysicalDB::open_path(Path::new(path)).unwrap();
+        assert_eq!(live.header().records_number, 100);
+        assert_eq!(live.check_db_file().unwrap(), DbIssue::None);
+        assert!(!partial_path(Path::new(path)).exists());
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(backup);
+    }
+}
@@ -0,0 +1,377 @@
+//! A small SQL-like language for ad-hoc queries, mapped onto the range and aggregation APIs.
+//!
+//! ```text
+//! SELECT value FROM temperature WHERE time >= '2024-01-01' AND time < '2024-01-02 12:00:00'
+//! SELECT avg(value) FROM temperature WHERE time > '2024-01-01' GROUP BY 1h
+//! ```
+//!
+//! The selection is either `value` (or `*`), which lists the records, or one of `min`, `max`, `avg`, `sum`
+//! and `count` applied to `value`. The conditions only bound `time`, with `<`, `<=`, `>` and `>=`.
+//! `GROUP BY` takes a duration in seconds, minutes, hours or days (`30s`, `5m`, `1h`, `1d`).
+//! Keywords are case insensitive, dates are UTC.
+
+use crate::{Aggregation, PhysicalDB, Point, RecordValue, Stats, TSLiteError, Timestamp, LATEST};
+use std::time::Duration;
+
+/// A parsed query. The table name is not resolved: the caller maps [`SqlQuery::source`] to a DB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlQuery {
+    /// The name after `FROM`.
+    pub source: String,
+    /// The aggregation selected, `None` to list the records.
+    pub aggregation: Option<Aggregation>,
+    /// The lower bound of the range, included.
+    pub start: Option<Timestamp>,
+    /// The upper bound of the range, excluded.
+    pub end: Option<Timestamp>,
+    /// The size of the `GROUP BY` buckets.
+    pub group_by: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 8] = [">=", "<=", ">", "<", "(", ")", "*", ","];
+
+fn syntax_error(message: &str) -> TSLiteError {
+    TSLiteError::InvalidParameter(format!("Invalid query: {}", message))
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, TSLiteError> {
+    let mut tokens = Vec::new();
+    let mut rest = query.trim_start();
+    while !rest.is_empty() {
+        if let Some(text) = rest.strip_prefix('\'') {
+            let end = text
+                .find('\'')
+                .ok_or_else(|| syntax_error("unterminated string."))?;
+            tokens.push(Token::Text(text[..end].to_string()));
+            rest = &text[end + 1..];
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(syntax_error(&format!(
+                    "unexpected character '{}'.",
+                    rest.chars().next().unwrap()
+                )));
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), TSLiteError> {
+        if !self.peek_keyword(keyword) {
+            return Err(syntax_error(&format!("expected {}.", keyword)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn symbol(&mut self, symbol: &'static str) -> Result<(), TSLiteError> {
+        match self.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            _ => Err(syntax_error(&format!("expected '{}'.", symbol))),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, TSLiteError> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(w),
+            _ => Err(syntax_error("expected a name.")),
+        }
+    }
+
+    fn selection(&mut self) -> Result<Option<Aggregation>, TSLiteError> {
+        if let Some(Token::Symbol("*")) = self.tokens.get(self.position) {
+            self.position += 1;
+            return Ok(None);
+        }
+        let name = self.word()?.to_ascii_lowercase();
+        let aggregation = match name.as_str() {
+            "value" => return Ok(None),
+            "min" => Aggregation::Min,
+            "max" => Aggregation::Max,
+            "avg" | "mean" => Aggregation::Mean,
+            "sum" => Aggregation::Sum,
+            "count" => Aggregation::Count,
+            _ => return Err(syntax_error(&format!("unknown selection '{}'.", name))),
+        };
+        self.symbol("(")?;
+        match self.next() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("value") => {}
+            Some(Token::Symbol("*")) if aggregation == Aggregation::Count => {}
+            _ => return Err(syntax_error("aggregations only apply to value.")),
+        }
+        self.symbol(")")?;
+        Ok(Some(aggregation))
+    }
+
+    /// Parse `time <op> 'date'` and narrow the range accordingly.
+    fn condition(&mut self, query: &mut SqlQuery) -> Result<(), TSLiteError> {
+        if !self.word()?.eq_ignore_ascii_case("time") {
+            return Err(syntax_error("conditions only apply to time."));
+        }
+        let operator = match self.next() {
+            Some(Token::Symbol(s)) if s != "(" && s != ")" && s != "*" && s != "," => s,
+            _ => return Err(syntax_error("expected a comparison.")),
+        };
+        let date = match self.next() {
+            Some(Token::Text(t)) => parse_timestamp(&t)
+                .ok_or_else(|| syntax_error(&format!("invalid date '{}'.", t)))?,
+            _ => return Err(syntax_error("expected a quoted date.")),
+        };
+        // Dates have a one second resolution, so strict bounds move by one second.
+        match operator {
+            ">=" => query.start = query.start.max(Some(date)),
+            ">" => query.start = query.start.max(Some(date.add_seconds(1))),
+            "<" => query.end = Some(query.end.map_or(date, |e| e.min(date))),
+            _ => {
+                let end = date.add_seconds(1);
+                query.end = Some(query.end.map_or(end, |e| e.min(end)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SqlQuery {
+    /// Parse a query, failing with `InvalidParameter` on a syntax error.
+    pub fn parse(query: &str) -> Result<SqlQuery, TSLiteError> {
+        let mut parser = Parser {
+            tokens: tokenize(query)?,
+            position: 0,
+        };
+        parser.keyword("select")?;
+        let aggregation = parser.selection()?;
+        parser.keyword("from")?;
+        let mut query = SqlQuery {
+            source: parser.word()?,
+            aggregation,
+            start: None,
+            end: None,
+            group_by: None,
+        };
+        if parser.peek_keyword("where") {
+            parser.position += 1;
+            parser.condition(&mut query)?;
+            while parser.peek_keyword("and") {
+                parser.position += 1;
+                parser.condition(&mut query)?;
+            }
+        }
+        if parser.peek_keyword("group") {
+            parser.position += 1;
+            parser.keyword("by")?;
+            let bucket = parser.word()?;
+            query.group_by = Some(
+                parse_duration(&bucket)
+                    .ok_or_else(|| syntax_error(&format!("invalid duration '{}'.", bucket)))?,
+            );
+            if query.aggregation.is_none() {
+                return Err(syntax_error("GROUP BY requires an aggregation."));
+            }
+        }
+        if parser.position < parser.tokens.len() {
+            return Err(syntax_error("unexpected trailing input."));
+        }
+        Ok(query)
+    }
+}
+
+/// Parse `YYYY-MM-DD`, optionally followed by ` HH:MM[:SS]` or `THH:MM[:SS]` and a `Z`.
+pub(crate) fn parse_timestamp(text: &str) -> Option<Timestamp> {
+    let text = text.trim().trim_end_matches('Z');
+    let (date, time) = match text.find([' ', 'T']) {
+        Some(i) => (&text[..i], &text[i + 1..]),
+        None => (text, "00:00:00"),
+    };
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time.split(':').collect();
+    if date.len() != 3 || time.len() < 2 || time.len() > 3 {
+        return None;
+    }
+    Timestamp::new(
+        date[0].parse().ok()?,
+        date[1].parse().ok()?,
+        date[2].parse().ok()?,
+        time[0].parse().ok()?,
+        time[1].parse().ok()?,
+        time.get(2).map_or(Some(0), |s| s.parse().ok())?,
+    )
+    .ok()
+}
+
+/// Parse a positive number of seconds, minutes, hours or days such as `90s`, `5m`, `1h` or `7d`.
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit())?;
+    let amount: u64 = text[..split].parse().ok()?;
+    let unit = match &text[split..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return None,
+    };
+    Some(Duration::from_secs(amount.checked_mul(unit)?)).filter(|d| d.as_secs() > 0)
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Run a parsed query against this DB, whatever its [`SqlQuery::source`].
+    ///
+    /// Listing the records gives one point per record. An aggregation gives a single point dated at the
+    /// start of the range, or at the origin of the DB if the range is unbounded, and nothing if it is
+    /// undefined on an empty range. With `GROUP BY`, there is one point per non-empty bucket, dated at
+    /// its start. Buckets are aligned on the Unix epoch and the records are assumed to be chronologically ordered.
+    pub fn execute(&mut self, query: &SqlQuery) -> Result<Vec<Point>, TSLiteError> {
+        let header = *self.header();
+        let start = query.start.unwrap_or(header.origin_date);
+        let end = query.end.unwrap_or(LATEST);
+
+        let mut points = Vec::new();
+        let aggregation = match query.aggregation {
+            Some(aggregation) => aggregation,
+            None => {
+                self.scan_range(&start, &end, |r| {
+                    points.push(Point {
+                        time: header.offset_to_date(r.time_offset),
+                        value: r.value.as_f64(),
+                    })
+                })?;
+                return Ok(points);
+            }
+        };
+
+        match query.group_by {
+            Some(bucket) => {
+                self.scan_buckets(&start, &end, bucket.as_secs() as i64, |key, stats| {
+                    if let Some(value) = stats.get(aggregation) {
+                        points.push(Point {
+                            time: Timestamp::from_unix(key),
+                            value,
+                        });
+                    }
+                })?
+            }
+            None => {
+                let mut stats = Stats::default();
+                self.scan_range(&start, &end, |r| stats.push(r.value.as_f64()))?;
+                if let Some(value) = stats.get(aggregation) {
+                    points.push(Point { time: start, value });
+                }
+            }
+        }
+
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordInfo;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn parse_and_execute() {
+        let query = SqlQuery::parse(
+            "select AVG(value) from db WHERE time > '2020-01-01' and time <= '2020-01-01T02:00:00Z' group by 1h",
+        )
+        .unwrap();
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            query,
+            SqlQuery {
+                source: "db".to_string(),
+                aggregation: Some(Aggregation::Mean),
+                start: Some(origin.add_seconds(1)),
+                end: Some(origin.add_seconds(7201)),
+                group_by: Some(Duration::from_secs(3600)),
+            }
+        );
+        for invalid in &[
+            "SELECT value",
+            "SELECT median(value) FROM db",
+            "SELECT value FROM db WHERE time > '2020-02-30'",
+            "SELECT value FROM db WHERE value > '2020-01-01'",
+            "SELECT value FROM db GROUP BY 1h",
+            "SELECT count(*) FROM db GROUP BY 1w",
+            "SELECT value FROM db LIMIT 1",
+        ] {
+            assert!(SqlQuery::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        let path = "sql.db";
+        let _ = fs::remove_file(path);
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for (offset, value) in &[(0, 10), (1800, 20), (3600, 30), (5400, 50), (9000, 70)] {
+            db.append_record(RecordInfo {
+                time_offset: *offset,
+                value: *value,
+            })
+            .expect("could not append record.");
+        }
+
+        let run = |db: &mut PhysicalDB, q: &str| -> Vec<(i64, f64)> {
+            db.execute(&SqlQuery::parse(q).unwrap())
+                .unwrap()
+                .iter()
+                .map(|p| (p.time.unix_seconds() - origin.unix_seconds(), p.value))
+                .collect()
+        };
+        assert_eq!(
+            run(&mut db, "SELECT avg(value) FROM db GROUP BY 1h"),
+            vec![(0, 15.0), (3600, 40.0), (7200, 70.0)]
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                "SELECT * FROM db WHERE time >= '2020-01-01 01:00' AND time < '2020-01-01 02:00'"
+            ),
+            vec![(3600, 30.0), (5400, 50.0)]
+        );
+        assert_eq!(
+            run(&mut db, "SELECT count(*) FROM db WHERE time > '2020-01-01'"),
+            vec![(1, 4.0)]
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                "SELECT max(value) FROM db WHERE time > '2020-01-02'"
+            ),
+            vec![]
+        );
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,244 @@
+//! A [`TimeSeries`] stored in a blob of a SQLite database, for applications that already carry one.
+//!
+//! Each series is a row of the `tslite_series` table, whose blob holds the same bytes as a DB file,
+//! followed by spare room. The blob is read and written in place with SQLite's incremental I/O, and grows
+//! by doubling its size, so appending a record doesn't rewrite the whole series.
+//! Every append is a SQLite transaction.
+
+use crate::{
+    checked_header, DbHeader, Extensions, OffsetUnit, Record, RecordInfo, RecordValue, TSLiteError,
+    TimeSeries, Timestamp, Transforms, RECORDS_START,
+};
+use rusqlite::{params, Connection, OptionalExtension, MAIN_DB};
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+
+const TABLE: &str = "tslite_series";
+const COLUMN: &str = "data";
+/// Number of records read at once when reading a range.
+const READ_CHUNK: usize = 4096;
+
+fn sqlite_error(e: rusqlite::Error) -> TSLiteError {
+    TSLiteError::Backend(e.to_string())
+}
+
+/// A time serie stored in a blob of the `tslite_series` table of a SQLite database.
+#[derive(Debug)]
+pub struct SqliteSeries<'c, V: RecordValue = u8> {
+    connection: &'c Connection,
+    row: i64,
+    header: DbHeader,
+    value: PhantomData<V>,
+}
+
+impl<'c, V: RecordValue> SqliteSeries<'c, V> {
+    /// Open the series called `name` in the database of `connection`, creating it if it doesn't exist.
+    /// `origin_date` is only used to create the series, `None` meaning the current date and time.
+    pub fn open(
+        connection: &'c Connection,
+        name: &str,
+        origin_date: Option<Timestamp>,
+    ) -> Result<SqliteSeries<'c, V>, TSLiteError> {
+        connection
+            .execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (name TEXT PRIMARY KEY, {} BLOB NOT NULL)",
+                    TABLE, COLUMN
+                ),
+                [],
+            )
+            .map_err(sqlite_error)?;
+        let row: Option<i64> = connection
+            .query_row(
+                &format!("SELECT rowid FROM {} WHERE name = ?1", TABLE),
+                [name],
+                |r| r.get(0),
+            )
+            .optional()
+            .map_err(sqlite_error)?;
+
+        let (row, header) = match row {
+            Some(row) => {
+                let mut buffer = vec![0; RECORDS_START as usize];
+                connection
+                    .blob_open(MAIN_DB, TABLE, COLUMN, row, true)
+                    .and_then(|blob| blob.read_at_exact(&mut buffer, 0))
+                    .map_err(sqlite_error)?;
+                let (header, _) = checked_header(&buffer)?;
+                header.check_width::<V>()?;
+                if !header.transforms.is_identity() {
+                    return Err(TSLiteError::InvalidParameter(
+                        "Series stored in SQLite don't support transforms.".to_string(),
+                    ));
+                }
+                if header.record_checksums() {
+                    return Err(TSLiteError::InvalidParameter(
+                        "Series stored in SQLite don't support record checksums.".to_string(),
+                    ));
+                }
+                (row, header)
+            }
+            None => {
+                let origin_date = origin_date.unwrap_or_else(Timestamp::now);
+                if !origin_date.is_valid() {
+                    return Err(TSLiteError::InvalidTimestamp);
+                }
+                let mut header = DbHeader {
+                    origin_date,
+                    records_number: 0,
+                    offset_unit: OffsetUnit::Seconds,
+                    transforms: Transforms::default(),
+                    extensions: Extensions::default(),
+                };
+                header.set_value_type::<V>()?;
+                let mut data = header.as_checked_bytes();
+                data.extend(header.as_checked_bytes());
+                connection
+                    .execute(
+                        &format!("INSERT INTO {} (name, {}) VALUES (?1, ?2)", TABLE, COLUMN),
+                        params![name, data],
+                    )
+                    .map_err(sqlite_error)?;
+                (connection.last_insert_rowid(), header)
+            }
+        };
+
+        Ok(SqliteSeries {
+            connection,
+            row,
+            header,
+            value: PhantomData,
+        })
+    }
+
+    /// The header of the series.
+    pub fn header(&self) -> &DbHeader {
+        &self.header
+    }
+}
+
+impl<V: RecordValue> TimeSeries for SqliteSeries<'_, V> {
+    type Value = V;
+
+    fn append(&mut self, time: Timestamp, value: V) -> Result<(), TSLiteError> {
+        let time_offset = self.header.checked_offset(&time)?;
+        let size = RecordInfo::<V>::SIZE as usize;
+        let position = RECORDS_START as usize + self.header.records_number as usize * size;
+        let mut header = self.header;
+        header.records_number += 1;
+        let mut header_bytes = header.as_checked_bytes();
+        header_bytes.extend(header.as_checked_bytes());
+
+        let transaction = self
+            .connection
+            .unchecked_transaction()
+            .map_err(sqlite_error)?;
+        let length = transaction
+            .blob_open(MAIN_DB, TABLE, COLUMN, self.row, true)
+            .map_err(sqlite_error)?
+            .len();
+        if length < position + size {
+            // Concatenating gives text, cast it back so the content of the blob is kept as it is.
+            transaction
+                .execute(
+                    &format!(
+                        "UPDATE {} SET {} = CAST({} || zeroblob(?1) AS BLOB) WHERE rowid = ?2",
+                        TABLE, COLUMN, COLUMN
+                    ),
+                    params![length.max(size) as i64, self.row],
+                )
+                .map_err(sqlite_error)?;
+        }
+        let record = RecordInfo { time_offset, value }.as_bytes();
+        {
+            let mut blob = transaction
+                .blob_open(MAIN_DB, TABLE, COLUMN, self.row, false)
+                .map_err(sqlite_error)?;
+            blob.write_at(&record, position).map_err(sqlite_error)?;
+            blob.write_at(&header_bytes, 0).map_err(sqlite_error)?;
+        }
+        transaction.commit().map_err(sqlite_error)?;
+        self.header = header;
+
+        Ok(())
+    }
+
+    /// The records are assumed to be chronologically ordered: the first record past `end` stops the read.
+    fn range(&mut self, start: Timestamp, end: Timestamp) -> Result<Vec<Record<V>>, TSLiteError> {
+        let start = self.header.offset_not_before(&start).max(0);
+        let end = self.header.offset_not_before(&end);
+        let mut records = Vec::new();
+        if end <= start {
+            return Ok(records);
+        }
+
+        let blob = self
+            .connection
+            .blob_open(MAIN_DB, TABLE, COLUMN, self.row, true)
+            .map_err(sqlite_error)?;
+        let size = RecordInfo::<V>::SIZE as usize;
+        let mut buffer = vec![0; READ_CHUNK * size];
+        let mut first = 0;
+        while first < self.header.records_number as usize {
+            let n = READ_CHUNK.min(self.header.records_number as usize - first);
+            blob.read_at_exact(
+                &mut buffer[..n * size],
+                RECORDS_START as usize + first * size,
+            )
+            .map_err(sqlite_error)?;
+            for bytes in buffer[..n * size].chunks(size) {
+                let record = RecordInfo::<V>::try_from(bytes)?;
+                if record.time_offset as i64 >= end {
+                    return Ok(records);
+                }
+                if record.time_offset as i64 >= start {
+                    records.push(record.resolve(&self.header));
+                }
+            }
+            first += n;
+        }
+
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_in_sqlite() {
+        let connection = Connection::open_in_memory().unwrap();
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut series: SqliteSeries = SqliteSeries::open(&connection, "temperature", Some(origin))
+            .expect("could not open series.");
+        for i in 0..100 {
+            series
+                .append(origin.add_seconds(i * 10), i as u8)
+                .expect("could not append record.");
+        }
+        assert_eq!(
+            series.append(origin.add_seconds(-1), 0),
+            Err(TSLiteError::BeforeOrigin)
+        );
+        let mut other: SqliteSeries<f32> =
+            SqliteSeries::open(&connection, "humidity", None).expect("could not open series.");
+        other
+            .append(Timestamp::now(), 0.5)
+            .expect("could not append record.");
+
+        let mut series: SqliteSeries =
+            SqliteSeries::open(&connection, "temperature", None).expect("could not open series.");
+        assert_eq!(series.header().origin_date, origin);
+        assert_eq!(series.header().records_number, 100);
+        let range = series
+            .range(origin.add_seconds(15), origin.add_seconds(45))
+            .unwrap();
+        let values: Vec<u8> = range.iter().map(|r| r.value).collect();
+        assert_eq!(values, vec![2, 3, 4]);
+        assert_eq!(range[0].time, origin.add_seconds(20));
+        let stats = series.stats(origin, origin.add_seconds(1000)).unwrap();
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.max, Some(99.0));
+    }
+}
@@ -0,0 +1,130 @@
+//! An overview of a whole DB in one call, such as what a monitoring UI shows about it.
+
+use crate::{PhysicalDB, RecordValue, Stats, TSLiteError, Timestamp};
+use std::time::Duration;
+
+/// An overview of a DB, see [`PhysicalDB::stats`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DbStats {
+    /// Number of records in the DB.
+    pub records: u64,
+    /// Size of the DB file in octets.
+    pub file_size: u64,
+    /// The origin of the time offsets of the records.
+    pub origin: Timestamp,
+    /// The date of the earliest record, `None` if the DB is empty.
+    pub first: Option<Timestamp>,
+    /// The date of the latest record, `None` if the DB is empty.
+    pub last: Option<Timestamp>,
+    /// The lowest value, `None` if the DB is empty.
+    pub min: Option<f64>,
+    /// The highest value, `None` if the DB is empty.
+    pub max: Option<f64>,
+    /// The mean of the values, `None` if the DB is empty.
+    pub mean: Option<f64>,
+    /// The time between the first and the last record divided by the number of intervals between the
+    /// records, `None` if the DB has less than two records.
+    pub mean_interval: Option<Duration>,
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Compute an overview of the DB: its size, its first and last dates and the statistics of its values.
+    /// Every record is read once, by chunks. Pending records of a buffered DB are not accounted for.
+    /// To compute the statistics of a range of records only, see [`TimeSeries::stats`](crate::TimeSeries::stats).
+    pub fn stats(&mut self) -> Result<DbStats, TSLiteError> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        self.refresh_if_changed()?;
+        let header = self.header;
+        let file_size = self
+            .file
+            .as_ref()
+            .unwrap()
+            .metadata()
+            .map_err(TSLiteError::Io)?
+            .len();
+
+        let mut values = Stats::default();
+        let mut offsets: Option<(u32, u32)> = None;
+        for r in self.iter() {
+            let r = r?;
+            values.push(r.value.as_f64());
+            offsets = Some(
+                offsets.map_or((r.time_offset, r.time_offset), |(first, last)| {
+                    (first.min(r.time_offset), last.max(r.time_offset))
+                }),
+            );
+        }
+        let mean_interval = match offsets {
+            Some((first, last)) if values.count > 1 => {
+                let span = (last - first) as u128 * header.offset_unit.nanoseconds() as u128;
+                let nanos = span / (values.count - 1) as u128;
+                Some(Duration::from_nanos(nanos as u64))
+            }
+            _ => None,
+        };
+
+        Ok(DbStats {
+            records: header.records_number,
+            file_size,
+            origin: header.origin_date,
+            first: offsets.map(|(first, _)| header.offset_to_date(first)),
+            last: offsets.map(|(_, last)| header.offset_to_date(last)),
+            min: values.min,
+            max: values.max,
+            mean: values.mean(),
+            mean_interval,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, OffsetUnit, RecordInfo, RECORDS_START};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn stats_of_a_db() {
+        let path = "stats.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            offset_unit: OffsetUnit::Milliseconds,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<i16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options).unwrap();
+        let empty = db.stats().unwrap();
+        assert_eq!(empty.records, 0);
+        assert_eq!(empty.origin, origin);
+        assert_eq!(
+            (empty.first, empty.mean, empty.mean_interval),
+            (None, None, None)
+        );
+
+        // A record every 1.5 seconds, from 2 seconds after the origin.
+        let records: Vec<RecordInfo<i16>> = (0..5)
+            .map(|i| RecordInfo {
+                time_offset: 2_000 + i * 1_500,
+                value: i as i16 * 10 - 15,
+            })
+            .collect();
+        db.append_records(&records).unwrap();
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.records, 5);
+        assert_eq!(stats.file_size, RECORDS_START + 5 * RecordInfo::<i16>::SIZE);
+        assert_eq!(stats.first, Some(origin.add_seconds(2)));
+        assert_eq!(stats.last, Some(origin.add_seconds(8)));
+        assert_eq!(
+            (stats.min, stats.max, stats.mean),
+            (Some(-15.0), Some(25.0), Some(5.0))
+        );
+        assert_eq!(stats.mean_interval, Some(Duration::from_millis(1_500)));
+
+        let _ = fs::remove_file(path);
+    }
+}
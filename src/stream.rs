@@ -0,0 +1,413 @@
+//! The native record stream, to move records between DBs through pipes and sockets.
+//!
+//! A stream is a header followed by records until the end of the stream:
+//! - the magic `TSLS`,
+//! - the origin of the time offsets of the records, serialized like a [`Timestamp`],
+//! - the [`OffsetUnit`] of the offsets,
+//! - the width of the values, in octets,
+//! - the records, serialized like [`RecordInfo`], with their values as they are read from the DB:
+//!   the transforms of the DB the stream comes from are already inverted.
+//!
+//! As the number of records isn't in the header, a stream can be written without knowing it in advance.
+//!
+//! Records can also be exported as CSV or NDJSON, for other tools.
+
+use crate::{
+    DbHeader, OffsetUnit, PhysicalDB, RecordInfo, RecordValue, TSLiteError, Timestamp, LATEST,
+};
+use std::convert::TryFrom;
+use std::io::{self, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 4] = b"TSLS";
+/// Size of the stream header: the magic, the origin, the offset unit and the value width.
+const STREAM_HEADER_SIZE: usize = 4 + 7 + 1 + 1;
+/// Number of records appended at once when importing.
+const IMPORT_BATCH: usize = 4096;
+
+/// The formats records can be exported to.
+/// Dates are in UTC, or in the local time of the DB if it records a timezone (with the `chrono-tz` feature).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The native record stream, which [`PhysicalDB::import_from`] reads.
+    Native,
+    /// A `time,value` header line, then one line per record, dates being in RFC 3339.
+    Csv,
+    /// One `{"time":…,"value":…}` object per line, dates being in RFC 3339 and non-finite values `null`.
+    Ndjson,
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ`, with 3, 6 or 9 digits of fraction of second between the seconds and the `Z` if it has one.
+fn rfc3339(t: &Timestamp) -> String {
+    let fraction = match t.nanosecond {
+        0 => String::new(),
+        n if n % 1_000_000 == 0 => format!(".{:03}", n / 1_000_000),
+        n if n % 1_000 == 0 => format!(".{:06}", n / 1_000),
+        n => format!(".{:09}", n),
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        t.year, t.month, t.day, t.hour, t.minute, t.second, fraction
+    )
+}
+
+/// The date of a record as exported, in the local time of the DB if it records a timezone.
+fn export_date(header: &DbHeader, time_offset: u32) -> String {
+    #[cfg(feature = "chrono-tz")]
+    if header.timezone().is_some() {
+        return header
+            .local_date(time_offset)
+            .format("%Y-%m-%dT%H:%M:%S%.f%:z")
+            .to_string();
+    }
+    rfc3339(&header.offset_to_date(time_offset))
+}
+
+/// Fill `buf` from `reader` unless the stream ends first, and return how many octets were read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Read the header of a stream of `V` values, and return its origin and offset unit.
+fn read_stream_header<V: RecordValue, R: Read>(
+    reader: &mut R,
+) -> Result<(Timestamp, OffsetUnit), TSLiteError> {
+    let mut header = [0; STREAM_HEADER_SIZE];
+    let n = read_full(reader, &mut header).map_err(TSLiteError::Io)?;
+    if n < STREAM_HEADER_SIZE || &header[..4] != MAGIC {
+        return Err(TSLiteError::InvalidData("not a record stream".to_string()));
+    }
+    let origin = Timestamp::decode(&header[4..11])?;
+    let offset_unit = OffsetUnit::from_id(header[11]).ok_or_else(|| {
+        TSLiteError::InvalidData("unknown offset unit in the record stream".to_string())
+    })?;
+    if header[12] as usize != V::WIDTH {
+        return Err(TSLiteError::InvalidParameter(format!(
+            "The stream holds values of {} octets, expected {}.",
+            header[12],
+            V::WIDTH
+        )));
+    }
+    Ok((origin, offset_unit))
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Append every record of the native stream read from `reader`, and return how many were appended.
+    ///
+    /// The records are re-dated against the origin and offset unit of this DB, and their values go through
+    /// its transforms. They are appended by batches: if a record is rejected or the stream is cut in the
+    /// middle of a record, the batches before it stay appended.
+    pub fn import_from<R: Read>(&mut self, mut reader: R) -> Result<u64, TSLiteError> {
+        let (origin, offset_unit) = read_stream_header::<V, R>(&mut reader)?;
+        let stream = DbHeader {
+            origin_date: origin,
+            records_number: 0,
+            offset_unit,
+            transforms: Default::default(),
+            extensions: Default::default(),
+        };
+
+        let size = RecordInfo::<V>::SIZE as usize;
+        let mut buffer = vec![0; IMPORT_BATCH * size];
+        let mut batch = Vec::with_capacity(IMPORT_BATCH);
+        let mut imported = 0;
+        loop {
+            let n = read_full(&mut reader, &mut buffer).map_err(TSLiteError::Io)?;
+            batch.clear();
+            for bytes in buffer[..n - n % size].chunks(size) {
+                let record = RecordInfo::<V>::try_from(bytes)?;
+                let time = stream.offset_to_date(record.time_offset);
+                batch.push(RecordInfo {
+                    time_offset: self.header.checked_offset(&time)?,
+                    value: record.value,
+                });
+            }
+            self.append_records(&batch)?;
+            imported += batch.len() as u64;
+            if n % size != 0 {
+                return Err(TSLiteError::ShortRead("stream".to_string()));
+            }
+            if n < buffer.len() {
+                return Ok(imported);
+            }
+        }
+    }
+
+    /// Write every record whose date is within `[start, end[` to `writer` in `format`, and return how many
+    /// were written. The records are written as they are read, so the memory used doesn't depend on the range.
+    pub fn export_to<W: Write>(
+        &mut self,
+        writer: W,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+        format: ExportFormat,
+    ) -> Result<u64, TSLiteError> {
+        self.refresh_if_changed()?;
+        let header = self.header;
+        let mut writer = BufWriter::new(writer);
+        let written = match format {
+            ExportFormat::Native => {
+                let mut stream_header = MAGIC.to_vec();
+                stream_header.extend(header.origin_date.as_bytes());
+                stream_header.extend([header.offset_unit.id(), V::WIDTH as u8]);
+                writer.write_all(&stream_header)
+            }
+            ExportFormat::Csv => writer.write_all(b"time,value\n"),
+            ExportFormat::Ndjson => Ok(()),
+        };
+        written.map_err(TSLiteError::Io)?;
+
+        let mut count = 0;
+        let mut failure = None;
+        self.scan_range(&start.into(), &end.into(), |r| {
+            if failure.is_some() {
+                return;
+            }
+            let written = match format {
+                ExportFormat::Native => writer.write_all(&r.as_bytes()),
+                ExportFormat::Csv => writeln!(
+                    writer,
+                    "{},{}",
+                    export_date(&header, r.time_offset),
+                    r.value.as_f64()
+                ),
+                ExportFormat::Ndjson => {
+                    let value = r.value.as_f64();
+                    let value = match value.is_finite() {
+                        true => value.to_string(),
+                        false => "null".to_string(),
+                    };
+                    writeln!(
+                        writer,
+                        "{{\"time\":\"{}\",\"value\":{}}}",
+                        export_date(&header, r.time_offset),
+                        value
+                    )
+                }
+            };
+            match written {
+                Ok(()) => count += 1,
+                Err(e) => failure = Some(e),
+            }
+        })?;
+        if let Some(e) = failure {
+            return Err(TSLiteError::Io(e));
+        }
+        writer.flush().map_err(TSLiteError::Io)?;
+
+        Ok(count)
+    }
+
+    /// Return every record dated after `since`, such as the date of the last record a sync job shipped.
+    /// The first of them is found with a binary search, so the cost depends on the number of new records
+    /// rather than on the size of the DB. Records in the same offset unit as `since` are left out with it.
+    pub fn export_since(
+        &mut self,
+        since: impl Into<Timestamp>,
+    ) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+        let start = self.after(&since.into())?;
+        self.read_range(start, LATEST)
+    }
+
+    /// Write every record dated after `since` to `writer` in `format`, and return how many were written,
+    /// see [`PhysicalDB::export_since`] and [`PhysicalDB::export_to`].
+    pub fn export_since_to<W: Write>(
+        &mut self,
+        writer: W,
+        since: impl Into<Timestamp>,
+        format: ExportFormat,
+    ) -> Result<u64, TSLiteError> {
+        let start = self.after(&since.into())?;
+        self.export_to(writer, start, LATEST, format)
+    }
+
+    /// The date of the first offset after the one of `since`.
+    fn after(&mut self, since: &Timestamp) -> Result<Timestamp, TSLiteError> {
+        self.refresh_if_changed()?;
+        let offset = self.header.date_to_offset(since);
+        Ok(match offset {
+            o if o < 0 => self.header.origin_date,
+            o if o >= u32::MAX as i64 => LATEST,
+            o => self.header.offset_to_date(o as u32 + 1),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, Transforms};
+    use std::fs;
+    use std::path::Path;
+
+    /// A stream of `records`, dated in minutes from `origin`.
+    fn stream<V: RecordValue>(origin: Timestamp, records: &[(u32, V)]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(origin.as_bytes());
+        bytes.push(OffsetUnit::Minutes.id());
+        bytes.push(V::WIDTH as u8);
+        for &(time_offset, value) in records {
+            bytes.extend(RecordInfo { time_offset, value }.as_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn import_native_stream() {
+        let path = "stream_import.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            transforms: Transforms {
+                delta: true,
+                ..Transforms::default()
+            },
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        let records: Vec<(u32, u16)> = (0..5000).map(|i| (i, (i * 7) as u16)).collect();
+        let bytes = stream(origin.add_seconds(3600), &records);
+        assert_eq!(db.import_from(&bytes[..]), Ok(5000));
+        let imported = db.records(origin, LATEST).unwrap();
+        assert_eq!(imported.len(), 5000);
+        assert_eq!(imported[0].time, origin.add_seconds(3600));
+        assert_eq!(imported[4999].time, origin.add_seconds(3600 + 4999 * 60));
+        assert_eq!(imported[4999].value, 4999 * 7);
+
+        // A cut stream keeps what was read before the cut.
+        let bytes = stream(origin, &[(5100, 1u16), (5101, 2)]);
+        assert!(db.import_from(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(db.header().records_number, 5001);
+        assert!(db.import_from(&b"TSLR"[..]).is_err());
+        assert!(db.import_from(&stream(origin, &[(0, 1u8)])[..]).is_err());
+        assert_eq!(
+            db.import_from(&stream(origin.add_seconds(-60), &[(0, 1u16)])[..]),
+            Err(TSLiteError::BeforeOrigin)
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn export_to_formats() {
+        let path = "stream_export.db";
+        let copy = "stream_export_copy.db";
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(copy);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB<f32> =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        for (i, v) in [1.5, f32::NAN, 3.0, 4.25].iter().enumerate() {
+            db.append_record(RecordInfo {
+                time_offset: i as u32 * 30,
+                value: *v,
+            })
+            .expect("could not append record.");
+        }
+
+        let mut csv = Vec::new();
+        let exported = db.export_to(&mut csv, origin, origin.add_seconds(60), ExportFormat::Csv);
+        assert_eq!(exported, Ok(2));
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time,value\n2020-01-01T00:00:00Z,1.5\n2020-01-01T00:00:30Z,NaN\n"
+        );
+        assert_eq!(
+            rfc3339(&origin.add_millis(1_250)),
+            "2020-01-01T00:00:01.250Z"
+        );
+        let mut ndjson = Vec::new();
+        db.export_to(
+            &mut ndjson,
+            origin.add_seconds(30),
+            LATEST,
+            ExportFormat::Ndjson,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(ndjson).unwrap(),
+            "{\"time\":\"2020-01-01T00:00:30Z\",\"value\":null}\n\
+             {\"time\":\"2020-01-01T00:01:00Z\",\"value\":3}\n\
+             {\"time\":\"2020-01-01T00:01:30Z\",\"value\":4.25}\n"
+        );
+
+        let mut native = Vec::new();
+        db.export_to(
+            &mut native,
+            origin.add_seconds(60),
+            LATEST,
+            ExportFormat::Native,
+        )
+        .unwrap();
+        let mut target: PhysicalDB<f32> =
+            PhysicalDB::create(Path::new(copy), Some(origin.add_seconds(60)))
+                .expect("could not create db.");
+        assert_eq!(target.import_from(&native[..]), Ok(2));
+        assert_eq!(target.read_record(1).unwrap().time_offset, 30);
+        assert_eq!(target.read_record(1).unwrap().value, 4.25);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(copy);
+    }
+
+    #[test]
+    fn export_new_records() {
+        let path = "stream_export_since.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+        let records: Vec<RecordInfo<u16>> = (0..10_000)
+            .map(|i| RecordInfo {
+                time_offset: i * 10,
+                value: i as u16,
+            })
+            .collect();
+        db.append_records(&records).unwrap();
+
+        // The last record shipped by a previous run.
+        let shipped = origin.add_seconds(99_980);
+        let new = db.export_since(shipped).unwrap();
+        assert_eq!(
+            new.iter().map(|r| r.value).collect::<Vec<u16>>(),
+            vec![9_999]
+        );
+        assert_eq!(
+            db.export_since(origin.add_millis(99_985_500))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            db.export_since(origin.add_seconds(-60)).unwrap().len(),
+            10_000
+        );
+        assert!(db
+            .export_since(origin.add_seconds(99_990))
+            .unwrap()
+            .is_empty());
+
+        let mut csv = Vec::new();
+        let exported = db.export_since_to(&mut csv, origin.add_seconds(99_970), ExportFormat::Csv);
+        assert_eq!(exported, Ok(2));
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time,value\n2020-01-02T03:46:20Z,9998\n2020-01-02T03:46:30Z,9999\n"
+        );
+
+        let _ = fs::remove_file(path);
+    }
+}
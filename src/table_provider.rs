@@ -0,0 +1,268 @@
+//! A DataFusion table over DB files, to query them in SQL.
+//!
+//! The table has a `time` column, a UTC timestamp in milliseconds, and a `value` column, the values as `f64`.
+//! The comparisons of `time` with a constant are pushed down to the scan: only the records within the range
+//! they allow are read from each file, DataFusion still checks them afterwards.
+
+use crate::{PhysicalDB, RecordValue, Timestamp, LATEST};
+use async_trait::async_trait;
+use datafusion::arrow::array::{ArrayRef, Float64Array, TimestampMillisecondArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::{DataFusionError, ScalarValue};
+use datafusion::datasource::memory::MemorySourceConfig;
+use datafusion::logical_expr::{
+    BinaryExpr, Expr, Operator, TableProviderFilterPushDown, TableType,
+};
+use datafusion::physical_plan::ExecutionPlan;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Name of the column holding the dates of the records.
+const TIME: &str = "time";
+/// Name of the column holding the values of the records.
+const VALUE: &str = "value";
+
+/// A DataFusion table holding the records of one or more DB files of `V` values, read when the table is scanned.
+pub struct TsliteTable<V: RecordValue = u8> {
+    paths: Vec<PathBuf>,
+    schema: SchemaRef,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<V: RecordValue> fmt::Debug for TsliteTable<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TsliteTable")
+            .field("paths", &self.paths)
+            .finish()
+    }
+}
+
+impl<V: RecordValue> TsliteTable<V> {
+    /// A table over the DB files at `paths`. The files are only opened when the table is scanned.
+    pub fn new<P: Into<PathBuf>>(paths: impl IntoIterator<Item = P>) -> TsliteTable<V> {
+        let schema = Schema::new(vec![
+            Field::new(
+                TIME,
+                DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+                false,
+            ),
+            Field::new(VALUE, DataType::Float64, false),
+        ]);
+        TsliteTable {
+            paths: paths.into_iter().map(Into::into).collect(),
+            schema: Arc::new(schema),
+            value: PhantomData,
+        }
+    }
+
+    /// The paths of the DB files of the table.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Read the records of every file within `[start, end[`, as Unix seconds, up to `limit` records.
+    fn read(
+        &self,
+        start: i64,
+        end: i64,
+        limit: Option<usize>,
+    ) -> Result<RecordBatch, DataFusionError> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut times = Vec::new();
+        let mut values = Vec::new();
+        for path in &self.paths {
+            if times.len() >= limit {
+                break;
+            }
+            let failed =
+                |e| DataFusionError::Execution(format!("Could not read {}: {}", path.display(), e));
+            let mut db: PhysicalDB<V> = PhysicalDB::new(path, None).map_err(failed)?;
+            let header = *db.header();
+            let start = Timestamp::from_unix(start.max(header.origin_date.unix_seconds()));
+            let end = Timestamp::from_unix(end.min(LATEST.unix_seconds()));
+            db.scan_range(&start, &end, |r| {
+                if times.len() < limit {
+                    times.push(header.offset_to_date(r.time_offset).unix_millis());
+                    values.push(r.value.as_f64());
+                }
+            })
+            .map_err(failed)?;
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(TimestampMillisecondArray::from(times).with_timezone("UTC")),
+            Arc::new(Float64Array::from(values)),
+        ];
+        Ok(RecordBatch::try_new(Arc::clone(&self.schema), columns)?)
+    }
+}
+
+/// The range `[start, end[` of Unix seconds allowed by a comparison of the `time` column with a constant,
+/// `None` if `filter` is not such a comparison. The range may be wider than what the filter allows.
+fn time_range(filter: &Expr) -> Option<(i64, i64)> {
+    let (left, op, right) = match filter {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => (left, *op, right),
+        _ => return None,
+    };
+    let (seconds, op) = match (left.as_ref(), right.as_ref()) {
+        (Expr::Column(c), Expr::Literal(value, _)) if c.name == TIME => (seconds(value)?, op),
+        (Expr::Literal(value, _), Expr::Column(c)) if c.name == TIME => {
+            (seconds(value)?, op.swap()?)
+        }
+        _ => return None,
+    };
+    match op {
+        Operator::Eq => Some((seconds, seconds.saturating_add(1))),
+        Operator::Gt | Operator::GtEq => Some((seconds, i64::MAX)),
+        Operator::Lt | Operator::LtEq => Some((i64::MIN, seconds.saturating_add(1))),
+        _ => None,
+    }
+}
+
+/// The Unix seconds of a timestamp constant, rounded down.
+fn seconds(value: &ScalarValue) -> Option<i64> {
+    match value {
+        ScalarValue::TimestampSecond(Some(s), _) => Some(*s),
+        ScalarValue::TimestampMillisecond(Some(ms), _) => Some(ms.div_euclid(1_000)),
+        ScalarValue::TimestampMicrosecond(Some(us), _) => Some(us.div_euclid(1_000_000)),
+        ScalarValue::TimestampNanosecond(Some(ns), _) => Some(ns.div_euclid(1_000_000_000)),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl<V: RecordValue + 'static> TableProvider for TsliteTable<V> {
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> Result<Vec<TableProviderFilterPushDown>, DataFusionError> {
+        Ok(filters
+            .iter()
+            .map(|f| match time_range(f) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>, DataFusionError> {
+        let (start, end) = filters
+            .iter()
+            .filter_map(time_range)
+            .fold((i64::MIN, i64::MAX), |(start, end), (s, e)| {
+                (start.max(s), end.min(e))
+            });
+        // DataFusion only passes a limit when no filter has to be checked after the scan, so the first
+        // records read are the ones it returns.
+        let batch = self.read(start, end, limit)?;
+        let plan = MemorySourceConfig::try_new_exec(
+            &[vec![batch]],
+            Arc::clone(&self.schema),
+            projection.cloned(),
+        )?;
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordInfo;
+    use datafusion::arrow::array::{Array, Float64Array, Int64Array};
+    use datafusion::prelude::SessionContext;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn sql_over_files() {
+        let paths = ["table_provider_a.db", "table_provider_b.db"];
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        for (n, path) in paths.iter().enumerate() {
+            let _ = fs::remove_file(path);
+            let mut db: PhysicalDB =
+                PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+            for i in 0..10u32 {
+                db.append_record(RecordInfo {
+                    time_offset: i * 60,
+                    value: (i as usize * (n + 1)) as u8,
+                })
+                .expect("could not append record.");
+            }
+        }
+
+        let table: TsliteTable = TsliteTable::new(paths.iter().copied());
+        let filter = Expr::BinaryExpr(BinaryExpr::new(
+            Box::new(Expr::Literal(
+                ScalarValue::TimestampMillisecond(Some(1_500), None),
+                None,
+            )),
+            Operator::Lt,
+            Box::new(Expr::Column(TIME.into())),
+        ));
+        assert_eq!(time_range(&filter), Some((1, i64::MAX)));
+        let latest = Expr::BinaryExpr(BinaryExpr::new(
+            Box::new(Expr::Column(TIME.into())),
+            Operator::LtEq,
+            Box::new(Expr::Literal(
+                ScalarValue::TimestampSecond(Some(i64::MAX), None),
+                None,
+            )),
+        ));
+        assert_eq!(time_range(&latest), Some((i64::MIN, i64::MAX)));
+        assert_eq!(
+            table.read(i64::MIN, i64::MAX, Some(12)).unwrap().num_rows(),
+            12
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let batches = runtime.block_on(async {
+            let ctx = SessionContext::new();
+            ctx.register_table("sensors", Arc::new(table)).unwrap();
+            ctx.sql(
+                "SELECT count(*) AS n, sum(value) AS total FROM sensors \
+                 WHERE time >= '2020-01-01T00:05:00Z' AND time < '2020-01-01T00:08:00Z'",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap()
+        });
+        let count = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        let total = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(count.value(0), 6);
+        // 5 + 6 + 7 in the first file, twice that in the second.
+        assert_eq!(total.value(0), 54.0);
+
+        for path in paths.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Internal metrics emitted through the `metrics` facade when the `metrics` feature is enabled,
+//! so they reach whatever exporter the application installed. Without the feature, this is all no-ops.
+//!
+//! - `tslite_records_appended` (counter): records appended to any DB.
+//! - `tslite_fsync_duration_seconds` (histogram): time spent syncing DB files to the disk.
+//! - `tslite_corruptions_detected` (counter): damaged headers and issues found by `check_db_file`.
+
+use crate::fail_points;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Count `n` appended records.
+pub(crate) fn records_appended(n: u64) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tslite_records_appended").increment(n);
+    #[cfg(not(feature = "metrics"))]
+    let _ = n;
+}
+
+/// Count a corruption found in a DB file.
+pub(crate) fn corruption_detected() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tslite_corruptions_detected").increment(1);
+}
+
+/// `File::sync_data`, timed.
+pub(crate) fn sync_data(file: &File) -> io::Result<()> {
+    fail_points::hit(fail_points::FSYNC)?;
+    timed_sync(|| file.sync_data())
+}
+
+/// `File::sync_all`, timed.
+pub(crate) fn sync_all(file: &File) -> io::Result<()> {
+    fail_points::hit(fail_points::FSYNC)?;
+    timed_sync(|| file.sync_all())
+}
+
+/// Sync the directory holding the file at `path`, so the creation or the renaming of the file survives a crash.
+/// Directories can't be opened on Windows, where this does nothing.
+pub(crate) fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(feature = "metrics")]
+fn timed_sync<F: FnOnce() -> io::Result<()>>(sync: F) -> io::Result<()> {
+    let start = std::time::Instant::now();
+    let result = sync();
+    metrics::histogram!("tslite_fsync_duration_seconds").record(start.elapsed().as_secs_f64());
+    result
+}
+
+#[cfg(not(feature = "metrics"))]
+fn timed_sync<F: FnOnce() -> io::Result<()>>(sync: F) -> io::Result<()> {
+    sync()
+}
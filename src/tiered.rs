@@ -0,0 +1,400 @@
+//! A [`TimeSeries`] whose recent records are in a local DB and whose older records are immutable segments
+//! in an object storage, such as S3.
+//!
+//! Appends go to a local DB used as a write buffer. Once it holds enough records, they are sealed into a
+//! segment, uploaded, and dropped from the buffer. A segment is named after the dates of its first and last
+//! records, so a range only downloads the segments it overlaps.
+//!
+//! With the `zstd` feature, the segments can be compressed with zstd, see [`TieredSeries::set_compression`].
+//! Their keys end with `.seg.zst` rather than `.seg`, and they are decompressed when a range reads them.
+//!
+//! The storage is reached through the [`ObjectStore`] trait, to implement over the client of your provider.
+//! [`DirectoryStore`] implements it over a local directory.
+
+use crate::{
+    block_records, encode_block, PhysicalDB, Record, RecordInfo, RecordValue, TSLiteError,
+    TimeSeries, Timestamp, LATEST,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Size of the segment header: the origin of the time offsets of its records, in Unix seconds.
+const SEGMENT_HEADER_SIZE: usize = 8;
+/// Appended to the keys of the segments compressed with zstd.
+const COMPRESSED_SUFFIX: &str = ".zst";
+
+/// The operations needed from an object storage.
+pub trait ObjectStore {
+    /// Store `data` under `key`, replacing any previous object.
+    fn put(&mut self, key: &str, data: &[u8]) -> Result<(), TSLiteError>;
+
+    /// Fetch the object stored under `key`.
+    fn get(&mut self, key: &str) -> Result<Vec<u8>, TSLiteError>;
+
+    /// List the keys starting with `prefix`, in any order.
+    fn list(&mut self, prefix: &str) -> Result<Vec<String>, TSLiteError>;
+}
+
+/// An [`ObjectStore`] keeping each object in a file of a directory, keys being relative paths.
+#[derive(Debug, Clone)]
+pub struct DirectoryStore {
+    root: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(root: &Path) -> DirectoryStore {
+        DirectoryStore {
+            root: root.to_path_buf(),
+        }
+    }
+}
+
+impl ObjectStore for DirectoryStore {
+    fn put(&mut self, key: &str, data: &[u8]) -> Result<(), TSLiteError> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(TSLiteError::Io)?;
+        }
+        fs::write(path, data).map_err(TSLiteError::Io)
+    }
+
+    fn get(&mut self, key: &str) -> Result<Vec<u8>, TSLiteError> {
+        fs::read(self.root.join(key)).map_err(TSLiteError::Io)
+    }
+
+    fn list(&mut self, prefix: &str) -> Result<Vec<String>, TSLiteError> {
+        // Keys are listed from the directory of the prefix.
+        let (directory, _) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+        let entries = match fs::read_dir(self.root.join(directory)) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(Vec::new()),
+        };
+        Ok(entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .map(|name| match directory {
+                "" => name,
+                _ => format!("{}/{}", directory, name),
+            })
+            .filter(|key| key.starts_with(prefix))
+            .collect())
+    }
+}
+
+/// A time serie whose recent records are buffered in a local DB, and whose older records are sealed into
+/// segments kept in an object storage.
+pub struct TieredSeries<S: ObjectStore, V: RecordValue = u8> {
+    store: S,
+    prefix: String,
+    buffer: PhysicalDB<V>,
+    segment_records: u64,
+    /// The zstd level of the segments sealed, `None` to store them uncompressed.
+    #[cfg(feature = "zstd")]
+    compression: Option<i32>,
+}
+
+impl<S: ObjectStore, V: RecordValue> TieredSeries<S, V> {
+    /// Open the series whose segments are stored under `prefix/` in `store`, buffering the appends in the DB
+    /// at `buffer`, which is created if needed with `origin_date` as origin. The buffer is sealed into a
+    /// segment every `segment_records` records.
+    pub fn open(
+        store: S,
+        prefix: &str,
+        buffer: &Path,
+        origin_date: Option<Timestamp>,
+        segment_records: u64,
+    ) -> Result<TieredSeries<S, V>, TSLiteError> {
+        if segment_records == 0 {
+            return Err(TSLiteError::InvalidParameter(
+                "A segment must hold at least one record.".to_string(),
+            ));
+        }
+        Ok(TieredSeries {
+            store,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            buffer: PhysicalDB::new(buffer, origin_date)?,
+            segment_records,
+            #[cfg(feature = "zstd")]
+            compression: None,
+        })
+    }
+
+    /// Compress the segments sealed from now on with zstd at `level`, or store them uncompressed if `None`,
+    /// the default. The segments already sealed are left as they are, and are read either way.
+    #[cfg(feature = "zstd")]
+    pub fn set_compression(&mut self, level: Option<i32>) {
+        self.compression = level;
+    }
+
+    /// The object storage holding the segments.
+    pub fn store(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Upload the buffered records as a segment and empty the buffer, returning the key of the segment,
+    /// `None` if the buffer was empty.
+    /// If the process stops between the upload and the emptying of the buffer, its records are uploaded again
+    /// by the next seal, and are then returned twice by the ranges.
+    pub fn seal(&mut self) -> Result<Option<String>, TSLiteError> {
+        let header = *self.buffer.header();
+        let records = self.buffer.records(header.origin_date, LATEST)?;
+        let (first, last) = match (
+            records.iter().map(|r| r.time).min(),
+            records.iter().map(|r| r.time).max(),
+        ) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Ok(None),
+        };
+
+        let origin = first.unix_seconds();
+        let block: Vec<RecordInfo<V>> = records
+            .iter()
+            .map(|r| RecordInfo {
+                time_offset: (r.time.unix_seconds() - origin) as u32,
+                value: r.value,
+            })
+            .collect();
+        let mut segment = origin.to_le_bytes().to_vec();
+        segment.extend(encode_block(&block));
+        #[cfg(feature = "zstd")]
+        let (segment, suffix) = match self.compression {
+            Some(level) => (
+                zstd::encode_all(&segment[..], level).map_err(TSLiteError::Io)?,
+                COMPRESSED_SUFFIX,
+            ),
+            None => (segment, ""),
+        };
+        #[cfg(not(feature = "zstd"))]
+        let suffix = "";
+        let key = format!(
+            "{}/{}-{}.seg{}",
+            self.prefix,
+            compact_date(&first),
+            compact_date(&last),
+            suffix
+        );
+        self.store.put(&key, &segment)?;
+        self.buffer.prune_before(LATEST)?;
+
+        Ok(Some(key))
+    }
+
+    /// The keys of the segments overlapping `[start, end[`, in chronological order.
+    fn segments(&mut self, start: &Timestamp, end: &Timestamp) -> Result<Vec<String>, TSLiteError> {
+        let mut keys: Vec<(Timestamp, String)> = self
+            .store
+            .list(&format!("{}/", self.prefix))?
+            .into_iter()
+            .filter_map(|key| {
+                let name = key.rsplit('/').next()?;
+                let name = name
+                    .strip_suffix(COMPRESSED_SUFFIX)
+                    .unwrap_or(name)
+                    .strip_suffix(".seg")?;
+                let (first, last) = name.split_once('-')?;
+                let (first, last) = (parse_compact_date(first)?, parse_compact_date(last)?);
+                Some((first, last, key))
+            })
+            .filter(|(first, last, _)| first < end && last >= start)
+            .map(|(first, _, key)| (first, key))
+            .collect();
+        keys.sort();
+        Ok(keys.into_iter().map(|(_, key)| key).collect())
+    }
+}
+
+impl<S: ObjectStore, V: RecordValue> TimeSeries for TieredSeries<S, V> {
+    type Value = V;
+
+    fn append(&mut self, time: Timestamp, value: V) -> Result<(), TSLiteError> {
+        self.buffer.append(time, value)?;
+        if self.buffer.header().records_number >= self.segment_records {
+            self.seal()?;
+        }
+        Ok(())
+    }
+
+    /// The records of the segments overlapping the range, oldest segment first, then the buffered records.
+    fn range(&mut self, start: Timestamp, end: Timestamp) -> Result<Vec<Record<V>>, TSLiteError> {
+        let mut records = Vec::new();
+        for key in self.segments(&start, &end)? {
+            let segment = read_segment(&key, self.store.get(&key)?)?;
+            if segment.len() < SEGMENT_HEADER_SIZE {
+                return Err(TSLiteError::ShortRead(format!("segment {}", key)));
+            }
+            let mut origin = [0; SEGMENT_HEADER_SIZE];
+            origin.copy_from_slice(&segment[..SEGMENT_HEADER_SIZE]);
+            let origin = i64::from_le_bytes(origin);
+            for r in block_records::<V>(&segment[SEGMENT_HEADER_SIZE..])? {
+                let r = r?;
+                let time = Timestamp::from_unix(origin + r.time_offset as i64);
+                if time >= start && time < end {
+                    records.push(Record {
+                        time,
+                        value: r.value,
+                    });
+                }
+            }
+        }
+        records.extend(self.buffer.records(start, end)?);
+        Ok(records)
+    }
+}
+
+/// Decompress `segment` if its key tells it was compressed.
+fn read_segment(key: &str, segment: Vec<u8>) -> Result<Vec<u8>, TSLiteError> {
+    if !key.ends_with(COMPRESSED_SUFFIX) {
+        return Ok(segment);
+    }
+    #[cfg(feature = "zstd")]
+    return zstd::decode_all(&segment[..]).map_err(TSLiteError::Io);
+    #[cfg(not(feature = "zstd"))]
+    Err(TSLiteError::InvalidData(format!(
+        "segment {} is compressed with zstd, which needs the zstd feature",
+        key
+    )))
+}
+
+/// `YYYYMMDDTHHMMSS`, which sorts like the dates.
+fn compact_date(t: &Timestamp) -> String {
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}",
+        t.year, t.month, t.day, t.hour, t.minute, t.second
+    )
+}
+
+fn parse_compact_date(text: &str) -> Option<Timestamp> {
+    if text.len() != 15 || text.as_bytes()[8] != b'T' {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<u8>().ok();
+    Timestamp::new(
+        text.get(0..4)?.parse().ok()?,
+        field(4..6)?,
+        field(6..8)?,
+        field(9..11)?,
+        field(11..13)?,
+        field(13..15)?,
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiered_series() {
+        let root = Path::new("tiered_store");
+        let buffer = Path::new("tiered_buffer.db");
+        let _ = fs::remove_dir_all(root);
+        let _ = fs::remove_file(buffer);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut series: TieredSeries<DirectoryStore> = TieredSeries::open(
+            DirectoryStore::new(root),
+            "sensors/a",
+            buffer,
+            Some(origin),
+            10,
+        )
+        .expect("could not open series.");
+        for i in 0..25 {
+            series
+                .append(origin.add_seconds(i * 60), i as u8)
+                .expect("could not append record.");
+        }
+        let mut keys = series.store().list("sensors/a/").unwrap();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "sensors/a/20200101T000000-20200101T000900.seg",
+                "sensors/a/20200101T001000-20200101T001900.seg"
+            ]
+        );
+        assert_eq!(series.buffer.header().records_number, 5);
+
+        let values = |series: &mut TieredSeries<DirectoryStore>, start: i64, end: i64| -> Vec<u8> {
+            series
+                .range(origin.add_seconds(start), origin.add_seconds(end))
+                .unwrap()
+                .iter()
+                .map(|r| r.value)
+                .collect()
+        };
+        assert_eq!(values(&mut series, 0, 1500), (0..25).collect::<Vec<u8>>());
+        assert_eq!(values(&mut series, 570, 630), vec![10]);
+        assert_eq!(values(&mut series, 1130, 1330), vec![19, 20, 21, 22]);
+        // Only the overlapping segment is read.
+        fs::remove_file(root.join("sensors/a/20200101T000000-20200101T000900.seg")).unwrap();
+        assert_eq!(values(&mut series, 600, 700), vec![10, 11]);
+
+        assert_eq!(
+            series.seal().unwrap().unwrap(),
+            "sensors/a/20200101T002000-20200101T002400.seg"
+        );
+        assert_eq!(series.seal().unwrap(), None);
+        assert_eq!(values(&mut series, 1380, 1500), vec![23, 24]);
+
+        let _ = fs::remove_dir_all(root);
+        let _ = fs::remove_file(buffer);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_segments() {
+        let root = Path::new("tiered_zstd_store");
+        let buffer = Path::new("tiered_zstd_buffer.db");
+        let _ = fs::remove_dir_all(root);
+        let _ = fs::remove_file(buffer);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let mut series: TieredSeries<DirectoryStore, u32> = TieredSeries::open(
+            DirectoryStore::new(root),
+            "cold",
+            buffer,
+            Some(origin),
+            1000,
+        )
+        .expect("could not open series.");
+        let append = |series: &mut TieredSeries<DirectoryStore, u32>,
+                      range: std::ops::Range<i64>| {
+            for i in range {
+                series
+                    .append(origin.add_seconds(i), (i % 7) as u32)
+                    .expect("could not append record.");
+            }
+        };
+        append(&mut series, 0..1000);
+        series.set_compression(Some(3));
+        append(&mut series, 1000..2000);
+        append(&mut series, 2000..2010);
+
+        let mut keys = series.store().list("cold/").unwrap();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "cold/20200101T000000-20200101T001639.seg",
+                "cold/20200101T001640-20200101T003319.seg.zst"
+            ]
+        );
+        let size = |key: &str| fs::metadata(root.join(key)).unwrap().len();
+        assert!(size(&keys[1]) < size(&keys[0]));
+
+        // The range overlaps both segments and the uncompressed tail in the buffer.
+        let records = series
+            .range(origin.add_seconds(990), origin.add_seconds(2005))
+            .unwrap();
+        assert_eq!(records.len(), 1015);
+        assert!(records
+            .iter()
+            .enumerate()
+            .all(|(i, r)| r.time == origin.add_seconds(990 + i as i64)
+                && r.value == ((990 + i) % 7) as u32));
+
+        let _ = fs::remove_dir_all(root);
+        let _ = fs::remove_file(buffer);
+    }
+}
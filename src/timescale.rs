@@ -0,0 +1,189 @@
+//! The timescale of the time offsets: UTC, where every day has 86400 seconds, or a TAI-like continuous
+//! timescale counting the leap seconds.
+//!
+//! With UTC, the offset between two dates ignores the leap seconds inserted between them, like Unix time does,
+//! and a record taken during a leap second can't be told apart from the one taken the second before.
+//! With TAI, an offset is the number of seconds that actually elapsed since the origin, and the leap seconds
+//! are only accounted for when converting an offset to a date or back, from the table of the leap seconds
+//! announced up to the build of the crate. Dates before 1972 are assumed to be 10 seconds behind TAI, the
+//! rubber seconds of the earlier UTC are not modeled.
+
+use crate::{Extensions, Timestamp};
+
+/// The tag of the extension entry recording a timescale other than UTC.
+pub(crate) const TAG: u8 = 0xF0;
+
+/// TAI − UTC before the first leap second, from 1972-01-01.
+const INITIAL_OFFSET: i64 = 10;
+
+/// The Unix time from which each leap second is counted, that is the midnight right after it, and TAI − UTC
+/// from then on.
+const LEAP_SECONDS: [(i64, i64); 27] = [
+    (78_796_800, 11),    // 1972-07-01
+    (94_694_400, 12),    // 1973-01-01
+    (126_230_400, 13),   // 1974-01-01
+    (157_766_400, 14),   // 1975-01-01
+    (189_302_400, 15),   // 1976-01-01
+    (220_924_800, 16),   // 1977-01-01
+    (252_460_800, 17),   // 1978-01-01
+    (283_996_800, 18),   // 1979-01-01
+    (315_532_800, 19),   // 1980-01-01
+    (362_793_600, 20),   // 1981-07-01
+    (394_329_600, 21),   // 1982-07-01
+    (425_865_600, 22),   // 1983-07-01
+    (489_024_000, 23),   // 1985-07-01
+    (567_993_600, 24),   // 1988-01-01
+    (631_152_000, 25),   // 1990-01-01
+    (662_688_000, 26),   // 1991-01-01
+    (709_948_800, 27),   // 1992-07-01
+    (741_484_800, 28),   // 1993-07-01
+    (773_020_800, 29),   // 1994-07-01
+    (820_454_400, 30),   // 1996-01-01
+    (867_715_200, 31),   // 1997-07-01
+    (915_148_800, 32),   // 1999-01-01
+    (1_136_073_600, 33), // 2006-01-01
+    (1_230_768_000, 34), // 2009-01-01
+    (1_341_100_800, 35), // 2012-07-01
+    (1_435_708_800, 36), // 2015-07-01
+    (1_483_228_800, 37), // 2017-01-01
+];
+
+/// The difference in seconds between TAI and UTC at `date`.
+pub fn tai_utc_offset(date: &Timestamp) -> i64 {
+    let unix = date.unix_seconds();
+    LEAP_SECONDS
+        .iter()
+        .take_while(|(start, _)| unix >= *start)
+        .last()
+        .map_or(INITIAL_OFFSET, |(_, offset)| *offset)
+}
+
+/// The timescale of the time offsets of a DB, recorded in its header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Timescale {
+    /// Every day has 86400 seconds, the leap seconds are ignored.
+    #[default]
+    Utc,
+    /// Offsets count the seconds that actually elapsed, leap seconds included.
+    Tai,
+}
+
+impl Timescale {
+    /// The timescale recorded in `extensions`, `None` if it is unknown to this version.
+    pub(crate) fn from_extensions(extensions: &Extensions) -> Option<Timescale> {
+        match extensions.get(TAG) {
+            None => Some(Timescale::Utc),
+            Some([1]) => Some(Timescale::Tai),
+            Some(_) => None,
+        }
+    }
+
+    /// Record the timescale in `extensions`, UTC being recorded by the absence of an entry.
+    pub(crate) fn record(self, extensions: &mut Extensions) {
+        match self {
+            Timescale::Utc => {
+                extensions.remove(TAG);
+            }
+            Timescale::Tai => extensions
+                .set(TAG, &[1])
+                .expect("the extension area has room for the timescale"),
+        }
+    }
+
+    /// The number of seconds of `date` on this timescale, from an arbitrary epoch.
+    pub(crate) fn seconds(self, date: &Timestamp) -> i64 {
+        match self {
+            Timescale::Utc => date.unix_seconds(),
+            Timescale::Tai => date.unix_seconds() + tai_utc_offset(date),
+        }
+    }
+
+    /// The date at `seconds` on this timescale, and whether it falls within a leap second.
+    /// A leap second is returned as the last second of its day, which it follows.
+    pub(crate) fn date(self, seconds: i64) -> (Timestamp, bool) {
+        if self == Timescale::Utc {
+            return (Timestamp::from_unix(seconds), false);
+        }
+        let mut offset = INITIAL_OFFSET;
+        for (start, next) in LEAP_SECONDS.iter() {
+            if seconds >= start + next {
+                offset = *next;
+            } else if seconds >= start + offset {
+                return (Timestamp::from_unix(start - 1), true);
+            } else {
+                break;
+            }
+        }
+        (Timestamp::from_unix(seconds - offset), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbHeader, DbOptions, PhysicalDB, RecordInfo, TimeSeries};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn offsets_across_leap_second() {
+        let path = "timescale_tai.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2016, 12, 31, 23, 59, 0).unwrap();
+        let after = Timestamp::new(2017, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(tai_utc_offset(&origin), 36);
+        assert_eq!(tai_utc_offset(&after), 37);
+        assert_eq!(
+            tai_utc_offset(&Timestamp::new(1970, 1, 1, 0, 0, 0).unwrap()),
+            10
+        );
+
+        let options = DbOptions {
+            timescale: Timescale::Tai,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        assert_eq!(db.header().date_to_offset(&after), 61);
+        for time_offset in 59..62 {
+            db.append_record(RecordInfo {
+                time_offset,
+                value: time_offset as u8,
+            })
+            .expect("could not append record.");
+        }
+        db.close().unwrap();
+
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        let header = *db.header();
+        assert_eq!(header.timescale(), Timescale::Tai);
+        let last_second = Timestamp::new(2016, 12, 31, 23, 59, 59).unwrap();
+        assert_eq!(header.offset_to_date(59), last_second);
+        assert_eq!(header.offset_to_date(60), last_second);
+        assert_eq!(header.offset_to_date(61), after);
+        assert!(!header.is_leap_second(59));
+        assert!(header.is_leap_second(60));
+        assert!(!header.is_leap_second(61));
+        let times: Vec<Timestamp> = db
+            .range(origin, after.add_seconds(1))
+            .unwrap()
+            .iter()
+            .map(|r| r.time)
+            .collect();
+        assert_eq!(times, vec![last_second, last_second, after]);
+
+        // On UTC, the same dates are a second closer.
+        let utc = DbHeader {
+            extensions: Extensions::default(),
+            ..header
+        };
+        assert_eq!(utc.timescale(), Timescale::Utc);
+        assert_eq!(utc.date_to_offset(&after), 60);
+        assert_eq!(utc.offset_to_date(60), after);
+
+        let _ = fs::remove_file(path);
+    }
+}
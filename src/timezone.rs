@@ -0,0 +1,145 @@
+//! Local time for the DBs deployed in a given timezone.
+//!
+//! The origin and the offsets of a DB stay in UTC, the IANA id of the timezone is only recorded in the extensions
+//! of the header, so every reader of the DB agrees on the local time used for the calendar buckets and the exports.
+
+use crate::{
+    DbHeader, DbOptions, InvalidTimestamp, PhysicalDB, RecordValue, Stats, TSLiteError, Timestamp,
+};
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::path::Path;
+
+/// The tag of the extension entry holding the IANA id of the timezone.
+pub(crate) const TAG: u8 = 0xF1;
+
+impl Timestamp {
+    /// The timestamp of the local date and time `local` in `tz`.
+    /// When the clocks are turned back, the earliest of the two matching instants is used.
+    /// Fail if `local` is skipped when the clocks are turned forward.
+    pub fn from_local(local: &NaiveDateTime, tz: Tz) -> Result<Timestamp, InvalidTimestamp> {
+        match tz.from_local_datetime(local) {
+            LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => {
+                Ok(Timestamp::from(date.with_timezone(&Utc)))
+            }
+            LocalResult::None => Err(InvalidTimestamp),
+        }
+    }
+}
+
+impl DbHeader {
+    /// The timezone recorded in the header, `None` if there is none or its id is unknown to this version.
+    pub fn timezone(&self) -> Option<Tz> {
+        let id = std::str::from_utf8(self.extensions.get(TAG)?).ok()?;
+        id.parse().ok()
+    }
+
+    /// Resolve a time offset into a date in the timezone of the DB, UTC if it has none.
+    pub fn local_date(&self, time_offset: u32) -> DateTime<Tz> {
+        self.offset_to_datetime(time_offset)
+            .with_timezone(&self.timezone().unwrap_or(Tz::UTC))
+    }
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Create a new DB whose origin is the local date and time `local_origin` in `tz`, and record `tz` in its header.
+    /// Fail with `InvalidTimestamp` if `local_origin` doesn't exist in `tz`.
+    pub fn create_local(
+        path: &Path,
+        local_origin: &NaiveDateTime,
+        tz: Tz,
+        options: &DbOptions,
+    ) -> Result<PhysicalDB<V>, TSLiteError> {
+        let origin = Timestamp::from_local(local_origin, tz)?;
+        let options = DbOptions {
+            timezone: Some(tz),
+            ..options.clone()
+        };
+        PhysicalDB::create_with_options(path, Some(origin), &options)
+    }
+
+    /// The statistics of the records within `[start, end[` for each local calendar day, in the timezone of the DB.
+    /// Days without records are skipped. Days come in the order the records are stored, so a day is reported
+    /// more than once if the records are not chronologically ordered.
+    pub fn local_days(
+        &mut self,
+        start: impl Into<Timestamp>,
+        end: impl Into<Timestamp>,
+    ) -> Result<Vec<(NaiveDate, Stats)>, TSLiteError> {
+        self.refresh_if_changed()?;
+        let header = *self.header();
+        let mut days: Vec<(NaiveDate, Stats)> = Vec::new();
+        self.scan_range(&start.into(), &end.into(), |r| {
+            let day = header.local_date(r.time_offset).date_naive();
+            match days.last_mut() {
+                Some((d, stats)) if *d == day => stats.push(r.value.as_f64()),
+                _ => {
+                    let mut stats = Stats::default();
+                    stats.push(r.value.as_f64());
+                    days.push((day, stats));
+                }
+            }
+        })?;
+        Ok(days)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExportFormat, RecordInfo, LATEST};
+    use std::fs;
+
+    #[test]
+    fn local_origin_and_days() {
+        let path = "timezone_local.db";
+        let _ = fs::remove_file(path);
+
+        let tz: Tz = "Europe/Paris".parse().unwrap();
+        let local = NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(22, 0, 0)
+            .unwrap();
+        let mut db: PhysicalDB =
+            PhysicalDB::create_local(Path::new(path), &local, tz, &DbOptions::default())
+                .expect("could not create db.");
+        let origin = Timestamp::new(2020, 1, 1, 21, 0, 0).unwrap();
+        assert_eq!(db.header().origin_date, origin);
+        for (hour, value) in [(0, 1), (1, 3), (2, 10), (5, 20)] {
+            db.append_record(RecordInfo {
+                time_offset: hour * 3600,
+                value,
+            })
+            .expect("could not append record.");
+        }
+        db.close().unwrap();
+
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().timezone(), Some(tz));
+        assert_eq!(db.header().local_date(0).naive_local(), local);
+        let days = db.local_days(origin, LATEST).unwrap();
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].0, NaiveDate::from_ymd_opt(2020, 1, 1).unwrap());
+        assert_eq!(days[0].1.sum, 4.0);
+        assert_eq!(days[1].0, NaiveDate::from_ymd_opt(2020, 1, 2).unwrap());
+        assert_eq!(days[1].1.count, 2);
+
+        let mut csv = Vec::new();
+        db.export_to(&mut csv, origin, origin.add_seconds(1), ExportFormat::Csv)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time,value\n2020-01-01T22:00:00+01:00,1\n"
+        );
+
+        // 02:30 doesn't exist in Paris when the clocks are turned forward.
+        let skipped = NaiveDate::from_ymd_opt(2020, 3, 29)
+            .unwrap()
+            .and_hms_opt(2, 30, 0)
+            .unwrap();
+        assert_eq!(Timestamp::from_local(&skipped, tz), Err(InvalidTimestamp));
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,337 @@
+//! Transforms applied to the values when they are appended, and inverted when they are read.
+//!
+//! They make smooth signals more compressible: clamping and quantizing reduce the amplitude of the values,
+//! and storing the difference with the previous value turns a slowly changing signal into small numbers.
+//! The transforms of a DB are chosen at its creation and recorded in its header.
+
+use crate::{PhysicalDB, RecordValue, TSLiteError};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+
+/// Size of the serialized transforms in the header: the flags, the clamp bounds and the scale.
+pub(crate) const TRANSFORMS_SIZE: u64 = 1 + 3 * 8;
+/// With `delta`, one record out of `KEYFRAME_INTERVAL` is stored whole,
+/// so decoding a record never requires to read more than that many records.
+const KEYFRAME_INTERVAL: u64 = 64;
+
+const DELTA_FLAG: u8 = 1;
+const CLAMP_FLAG: u8 = 1 << 1;
+const SCALE_FLAG: u8 = 1 << 2;
+
+/// The transforms applied to the values of a DB.
+///
+/// A value is clamped, then quantized, then stored as a difference with the previous one.
+/// Reading it gives back the clamped and quantized value.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transforms {
+    /// Store each value as the difference with the previous one, wrapping around on overflow.
+    pub delta: bool,
+    /// Clamp the values within `[min, max]`.
+    pub clamp: Option<(f64, f64)>,
+    /// Store `round(value / scale)` and read back `stored * scale`.
+    pub scale: Option<f64>,
+}
+
+impl Transforms {
+    /// Whether the values are stored as they are given.
+    pub fn is_identity(&self) -> bool {
+        !self.delta && self.clamp.is_none() && self.scale.is_none()
+    }
+
+    /// Check the parameters of the transforms.
+    pub(crate) fn validate(&self) -> Result<(), TSLiteError> {
+        if let Some((min, max)) = self.clamp {
+            if min.is_nan() || max.is_nan() || min > max {
+                return Err(TSLiteError::InvalidParameter(
+                    "The clamp lower bound must not be above the upper bound.".to_string(),
+                ));
+            }
+        }
+        if let Some(scale) = self.scale {
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(TSLiteError::InvalidParameter(
+                    "The quantization scale must be strictly positive.".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn as_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.delta {
+            flags |= DELTA_FLAG;
+        }
+        if self.clamp.is_some() {
+            flags |= CLAMP_FLAG;
+        }
+        if self.scale.is_some() {
+            flags |= SCALE_FLAG;
+        }
+        let (min, max) = self.clamp.unwrap_or((0.0, 0.0));
+
+        let mut store: Vec<u8> = Vec::with_capacity(TRANSFORMS_SIZE as usize);
+        store.push(flags);
+        store.write_f64::<LittleEndian>(min).unwrap();
+        store.write_f64::<LittleEndian>(max).unwrap();
+        store
+            .write_f64::<LittleEndian>(self.scale.unwrap_or(0.0))
+            .unwrap();
+        store
+    }
+
+    pub(crate) fn from_bytes(d: &[u8]) -> Transforms {
+        let mut reader = Cursor::new(d);
+        let flags = reader.read_u8().unwrap();
+        let min = reader.read_f64::<LittleEndian>().unwrap();
+        let max = reader.read_f64::<LittleEndian>().unwrap();
+        let scale = reader.read_f64::<LittleEndian>().unwrap();
+        Transforms {
+            delta: flags & DELTA_FLAG != 0,
+            clamp: Some((min, max)).filter(|_| flags & CLAMP_FLAG != 0),
+            scale: Some(scale).filter(|_| flags & SCALE_FLAG != 0),
+        }
+    }
+
+    /// Clamp and quantize a value.
+    pub(crate) fn quantize<V: RecordValue>(&self, value: V) -> V {
+        if self.clamp.is_none() && self.scale.is_none() {
+            return value;
+        }
+        let mut v = value.as_f64();
+        if let Some((min, max)) = self.clamp {
+            v = v.max(min).min(max);
+        }
+        if let Some(scale) = self.scale {
+            v = (v / scale).round();
+        }
+        V::from_f64(v)
+    }
+
+    /// The value a quantized value stands for.
+    pub(crate) fn dequantize<V: RecordValue>(&self, quantized: V) -> V {
+        match self.scale {
+            Some(scale) => V::from_f64(quantized.as_f64() * scale),
+            None => quantized,
+        }
+    }
+
+    /// The stored form of the quantized value of the record at `index`.
+    /// `previous` holds the quantized value of the record before it, and is updated to this one.
+    pub(crate) fn delta<V: RecordValue>(
+        &self,
+        index: u64,
+        quantized: V,
+        previous: &mut Option<V>,
+    ) -> V {
+        let stored = match *previous {
+            Some(p) if self.delta && !is_keyframe(index) => wrapping_op(quantized, p, true),
+            _ => quantized,
+        };
+        *previous = Some(quantized);
+        stored
+    }
+
+    /// The quantized value of the record at `index` from its stored form, the inverse of [`Transforms::delta`].
+    pub(crate) fn undelta<V: RecordValue>(
+        &self,
+        index: u64,
+        stored: V,
+        previous: &mut Option<V>,
+    ) -> V {
+        let quantized = match *previous {
+            Some(p) if self.delta && !is_keyframe(index) => wrapping_op(stored, p, false),
+            _ => stored,
+        };
+        *previous = Some(quantized);
+        quantized
+    }
+
+    /// The stored form of the value of the record at `index`.
+    pub(crate) fn encode<V: RecordValue>(
+        &self,
+        index: u64,
+        value: V,
+        previous: &mut Option<V>,
+    ) -> V {
+        self.delta(index, self.quantize(value), previous)
+    }
+
+    /// The value of the record at `index` from its stored form.
+    pub(crate) fn decode<V: RecordValue>(
+        &self,
+        index: u64,
+        stored: V,
+        previous: &mut Option<V>,
+    ) -> V {
+        self.dequantize(self.undelta(index, stored, previous))
+    }
+}
+
+fn is_keyframe(index: u64) -> bool {
+    index.is_multiple_of(KEYFRAME_INTERVAL)
+}
+
+/// Subtract or add two values as little-endian integers of `V::WIDTH` octets, wrapping around on overflow.
+fn wrapping_op<V: RecordValue>(a: V, b: V, subtract: bool) -> V {
+    let mut a_bytes = vec![0; V::WIDTH];
+    let mut b_bytes = vec![0; V::WIDTH];
+    a.encode(&mut a_bytes);
+    b.encode(&mut b_bytes);
+
+    let mut carry = false;
+    for (x, y) in a_bytes.iter_mut().zip(b_bytes) {
+        let (r, c1) = if subtract {
+            x.overflowing_sub(y)
+        } else {
+            x.overflowing_add(y)
+        };
+        let (r, c2) = if subtract {
+            r.overflowing_sub(carry as u8)
+        } else {
+            r.overflowing_add(carry as u8)
+        };
+        *x = r;
+        carry = c1 || c2;
+    }
+    V::decode(&a_bytes)
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// The quantized value of the record before `index` when it is needed to decode or encode it, `None` otherwise.
+    pub(crate) fn chain_before(&mut self, index: u64) -> Result<Option<V>, TSLiteError> {
+        let transforms = self.header.transforms;
+        if !transforms.delta || is_keyframe(index) {
+            return Ok(None);
+        }
+
+        let keyframe = index - index % KEYFRAME_INTERVAL;
+        let size = self.header.record_size::<V>() as usize;
+        let mut buffer = vec![0; (index - keyframe) as usize * size];
+        let n = self.read_records_into(keyframe, &mut buffer)?;
+        if n as u64 != index - keyframe {
+            return Err(TSLiteError::IndexOutOfBound);
+        }
+        let mut previous = None;
+        for (i, bytes) in buffer.chunks(size).enumerate() {
+            let stored = self
+                .header
+                .decode_record::<V>(keyframe + i as u64, bytes)?
+                .value;
+            transforms.undelta(keyframe + i as u64, stored, &mut previous);
+        }
+        Ok(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbIssue, DbOptions, RecordInfo, Timestamp};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn wrapping_arithmetic() {
+        assert_eq!(wrapping_op(3u8, 5u8, true), 254);
+        assert_eq!(wrapping_op(254u8, 5u8, false), 3);
+        let transforms = Transforms {
+            clamp: Some((10.0, 200.0)),
+            scale: Some(4.0),
+            ..Transforms::default()
+        };
+        assert_eq!(transforms.quantize(3u8), 3);
+        assert_eq!(transforms.dequantize(transforms.quantize(3u8)), 12);
+        assert_eq!(transforms.dequantize(transforms.quantize(250u8)), 200);
+        assert_eq!(Transforms::from_bytes(&transforms.as_bytes()), transforms);
+    }
+
+    #[test]
+    fn transformed_db() {
+        let path = "transforms.db";
+        let _ = fs::remove_file(path);
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            transforms: Transforms {
+                delta: true,
+                clamp: Some((0.0, 240.0)),
+                scale: Some(2.0),
+            },
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB =
+            PhysicalDB::create_with_options(Path::new(path), Some(origin), &options)
+                .expect("could not create db.");
+        let values: Vec<u8> = (0..150u32).map(|i| (128 + (i * 7) % 120) as u8).collect();
+        for (i, v) in values.iter().enumerate() {
+            db.append_record(RecordInfo {
+                time_offset: 2 * i as u32,
+                value: *v,
+            })
+            .expect("could not append record.");
+        }
+        db.append_record(RecordInfo {
+            time_offset: 300,
+            value: 255,
+        })
+        .expect("could not append record.");
+
+        // The values are clamped, then quantized by steps of 2.
+        let expected = |v: u8| ((v.min(240) as f64 / 2.0).round() * 2.0) as u8;
+        assert_eq!(db.read_record(100).unwrap().value, expected(values[100]));
+        assert_eq!(db.read_record(150).unwrap().value, 240);
+        let read: Vec<u8> = db
+            .records(origin, origin.add_seconds(300))
+            .unwrap()
+            .iter()
+            .map(|r| r.value)
+            .collect();
+        assert_eq!(
+            read,
+            values.iter().map(|v| expected(*v)).collect::<Vec<u8>>()
+        );
+        // A range starting past a keyframe decodes its records from that keyframe.
+        let read: Vec<u8> = db
+            .read_range(origin.add_seconds(250), origin.add_seconds(262))
+            .unwrap()
+            .iter()
+            .map(|r| r.value)
+            .collect();
+        assert_eq!(
+            read,
+            values[125..131]
+                .iter()
+                .map(|v| expected(*v))
+                .collect::<Vec<u8>>()
+        );
+
+        // The transforms are read back from the header.
+        db.close().expect("could not close db.");
+        let mut db: PhysicalDB =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().transforms, options.transforms);
+
+        // Updating a value keeps the following ones.
+        db.update_record(70, 20).expect("could not update record.");
+        assert_eq!(db.read_record(70).unwrap().value, 20);
+        assert_eq!(db.read_record(71).unwrap().value, expected(values[71]));
+
+        // Moving records around keeps the values.
+        db.append_record(RecordInfo {
+            time_offset: 11,
+            value: 100,
+        })
+        .expect("could not append record.");
+        db.reorder_record().expect("could not reorder db.");
+        assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+        assert_eq!(db.read_record(6).unwrap().value, 100);
+        assert_eq!(db.read_record(7).unwrap().value, expected(values[6]));
+        assert_eq!(db.prune_before(origin.add_seconds(20)).unwrap(), 11);
+        assert_eq!(db.read_record(0).unwrap().value, expected(values[10]));
+        assert_eq!(db.read_record(100).unwrap().value, expected(values[110]));
+
+        let _ = fs::remove_file(path);
+    }
+}
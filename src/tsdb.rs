@@ -0,0 +1,580 @@
+//! Import of the samples of a Prometheus TSDB block.
+//!
+//! A block is a directory holding an `index` file, which lists the series with their labels and the references
+//! of their chunks, and a `chunks` directory of segment files holding the samples, compressed with the
+//! Gorilla XOR encoding. Only the version 2 of the index and XOR chunks are supported. Checksums are not verified.
+
+use crate::codec::{read_varint, unzigzag};
+use crate::{PhysicalDB, RecordInfo, RecordValue, TSLiteError, Timestamp};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const INDEX_MAGIC: u32 = 0xBAAA_D700;
+const CHUNKS_MAGIC: u32 = 0x85BD_40DD;
+/// The table of content at the end of the index: six section offsets and a checksum.
+const TOC_SIZE: usize = 6 * 8 + 4;
+/// Series entries are aligned on 16 octets, which lets their offset divided by 16 serve as their id.
+const SERIES_ALIGNMENT: usize = 16;
+const XOR_ENCODING: u8 = 1;
+/// Number of samples appended at once.
+const IMPORT_BATCH: usize = 4096;
+
+/// A series written by [`import_tsdb_block`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedSeries {
+    /// The labels of the series, `__name__` included, sorted by name.
+    pub labels: Vec<(String, String)>,
+    /// The DB the samples were written to.
+    pub path: PathBuf,
+    /// Number of samples written.
+    pub samples: u64,
+}
+
+struct Series {
+    labels: Vec<(String, String)>,
+    chunks: Vec<u64>,
+}
+
+fn corrupted(what: &str) -> TSLiteError {
+    TSLiteError::InvalidData(format!("Prometheus block: {}", what))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, TSLiteError> {
+    fs::read(path).map_err(|e| {
+        TSLiteError::Io(io::Error::new(
+            e.kind(),
+            format!("{}: {}", path.display(), e),
+        ))
+    })
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(data.get(at..at + 8)?);
+    Some(u64::from_be_bytes(bytes))
+}
+
+/// Import every series of the block at `block` whose name is `metric` into a new DB in `destination`,
+/// and return what was written.
+///
+/// Each DB is named after the labels of its series, such as `http_requests,job=api.db`, and starts at its
+/// first sample. Timestamps are truncated to the second and values are converted with `V::from_f64`.
+/// Fail with `AlreadyExists` if one of the DBs already exists.
+pub fn import_tsdb_block<V: RecordValue>(
+    block: &Path,
+    metric: &str,
+    destination: &Path,
+) -> Result<Vec<ImportedSeries>, TSLiteError> {
+    let index = read_file(&block.join("index"))?;
+    let mut segments: Vec<PathBuf> = fs::read_dir(block.join("chunks"))
+        .map_err(TSLiteError::Io)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    segments.sort();
+    let mut loaded: Vec<Option<Vec<u8>>> = vec![None; segments.len()];
+
+    let mut imported = Vec::new();
+    for series in read_series(&index)? {
+        if !series
+            .labels
+            .iter()
+            .any(|(name, value)| name == "__name__" && value == metric)
+        {
+            continue;
+        }
+
+        let mut samples = Vec::new();
+        for reference in &series.chunks {
+            let segment = (reference >> 32) as usize;
+            let path = segments
+                .get(segment)
+                .ok_or_else(|| corrupted("missing chunk segment"))?;
+            if loaded[segment].is_none() {
+                let data = read_file(path)?;
+                if be_u32(&data, 0) != Some(CHUNKS_MAGIC) {
+                    return Err(corrupted("invalid chunk segment"));
+                }
+                loaded[segment] = Some(data);
+            }
+            let data = loaded[segment].as_ref().unwrap();
+            samples.extend(read_chunk(data, (reference & 0xFFFF_FFFF) as usize)?);
+        }
+        if samples.is_empty() {
+            continue;
+        }
+
+        let path = destination.join(file_name(&series.labels));
+        write_samples::<V>(&path, &samples)?;
+        imported.push(ImportedSeries {
+            labels: series.labels,
+            path,
+            samples: samples.len() as u64,
+        });
+    }
+
+    Ok(imported)
+}
+
+fn write_samples<V: RecordValue>(path: &Path, samples: &[(i64, f64)]) -> Result<(), TSLiteError> {
+    let origin = Timestamp::from_unix(samples[0].0.div_euclid(1000));
+    let mut db: PhysicalDB<V> = PhysicalDB::create(path, Some(origin))?;
+    let header = *db.header();
+    for chunk in samples.chunks(IMPORT_BATCH) {
+        let batch = chunk
+            .iter()
+            .map(|(t, v)| {
+                Ok(RecordInfo {
+                    time_offset: header
+                        .checked_offset(&Timestamp::from_unix(t.div_euclid(1000)))?,
+                    value: V::from_f64(*v),
+                })
+            })
+            .collect::<Result<Vec<_>, TSLiteError>>()?;
+        db.append_records(&batch)?;
+    }
+    db.close()
+}
+
+/// `metric,label=value,...` with the characters that are unsafe in a file name replaced by `_`.
+fn file_name(labels: &[(String, String)]) -> String {
+    let mut parts: Vec<String> = labels
+        .iter()
+        .filter(|(name, _)| name == "__name__")
+        .map(|(_, value)| value.clone())
+        .collect();
+    parts.extend(
+        labels
+            .iter()
+            .filter(|(name, _)| name != "__name__")
+            .map(|(name, value)| format!("{}={}", name, value)),
+    );
+    let name: String = parts
+        .join(",")
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '.' | ',' | '=' => c,
+            _ => '_',
+        })
+        .collect();
+    format!("{}.db", name)
+}
+
+/// Read the symbols and every series of an index.
+fn read_series(index: &[u8]) -> Result<Vec<Series>, TSLiteError> {
+    if be_u32(index, 0) != Some(INDEX_MAGIC) {
+        return Err(corrupted("invalid index"));
+    }
+    if index.get(4) != Some(&2) {
+        return Err(corrupted("unsupported index version"));
+    }
+    let toc_start = index
+        .len()
+        .checked_sub(TOC_SIZE)
+        .ok_or_else(|| corrupted("truncated index"))?;
+    let toc: Vec<usize> = (0..6)
+        .map(|i| be_u64(index, toc_start + i * 8).unwrap() as usize)
+        .collect();
+
+    // Symbols: the length of the table, the number of symbols, then length-prefixed strings.
+    let count = be_u32(index, toc[0] + 4).ok_or_else(|| corrupted("truncated symbols"))?;
+    let mut data = index.get(toc[0] + 8..).unwrap_or(&[]);
+    let mut symbols = Vec::with_capacity(count.min(1 << 20) as usize);
+    for _ in 0..count {
+        let length = read_varint(&mut data).ok_or_else(|| corrupted("truncated symbols"))? as usize;
+        let symbol = data
+            .get(..length)
+            .ok_or_else(|| corrupted("truncated symbols"))?;
+        symbols.push(String::from_utf8_lossy(symbol).into_owned());
+        data = &data[length..];
+    }
+    let symbol = |reference: u64| {
+        symbols
+            .get(reference as usize)
+            .cloned()
+            .ok_or_else(|| corrupted("unknown symbol"))
+    };
+
+    // The series section goes up to the next section.
+    let end = toc[2..]
+        .iter()
+        .copied()
+        .filter(|o| *o > toc[1])
+        .min()
+        .unwrap_or(toc_start)
+        .min(toc_start);
+    let mut series = Vec::new();
+    let mut position = toc[1];
+    loop {
+        position = position.div_ceil(SERIES_ALIGNMENT) * SERIES_ALIGNMENT;
+        if position >= end {
+            break;
+        }
+        let mut data = &index[position..end];
+        let length = read_varint(&mut data).ok_or_else(|| corrupted("truncated series"))? as usize;
+        if length == 0 {
+            break;
+        }
+        let header_length = end - position - data.len();
+        let mut entry = data
+            .get(..length)
+            .ok_or_else(|| corrupted("truncated series"))?;
+        position += header_length + length + 4;
+
+        let truncated = || corrupted("truncated series");
+        let mut labels = Vec::new();
+        for _ in 0..read_varint(&mut entry).ok_or_else(truncated)? {
+            let name = symbol(read_varint(&mut entry).ok_or_else(truncated)?)?;
+            let value = symbol(read_varint(&mut entry).ok_or_else(truncated)?)?;
+            labels.push((name, value));
+        }
+        // Chunks: the first time range and reference are whole, the next ones are deltas.
+        let mut chunks = Vec::new();
+        let mut max_time = 0;
+        let mut reference = 0i64;
+        for i in 0..read_varint(&mut entry).ok_or_else(truncated)? {
+            let min_time = if i == 0 {
+                unzigzag(read_varint(&mut entry).ok_or_else(truncated)?)
+            } else {
+                max_time + read_varint(&mut entry).ok_or_else(truncated)? as i64
+            };
+            max_time = min_time + read_varint(&mut entry).ok_or_else(truncated)? as i64;
+            let delta = read_varint(&mut entry).ok_or_else(truncated)?;
+            reference = if i == 0 {
+                delta as i64
+            } else {
+                reference + unzigzag(delta)
+            };
+            chunks.push(reference as u64);
+        }
+        series.push(Series { labels, chunks });
+    }
+
+    Ok(series)
+}
+
+/// Decode the chunk at `offset` of a segment: its length, its encoding, its data and a checksum.
+fn read_chunk(segment: &[u8], offset: usize) -> Result<Vec<(i64, f64)>, TSLiteError> {
+    let mut data = segment
+        .get(offset..)
+        .ok_or_else(|| corrupted("invalid chunk reference"))?;
+    let length = read_varint(&mut data).ok_or_else(|| corrupted("truncated chunk"))? as usize;
+    match data.first() {
+        Some(&XOR_ENCODING) => {}
+        Some(_) => return Err(corrupted("unsupported chunk encoding")),
+        None => return Err(corrupted("truncated chunk")),
+    }
+    let chunk = data
+        .get(1..1 + length)
+        .ok_or_else(|| corrupted("truncated chunk"))?;
+    decode_xor(chunk).ok_or_else(|| corrupted("truncated chunk"))
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Option<bool> {
+        let byte = self.data.get(self.position / 8)?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Some(bit)
+    }
+
+    fn bits(&mut self, n: u32) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.bit()? as u64;
+        }
+        Some(value)
+    }
+
+    fn uvarint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for i in 0..10 {
+            let byte = self.bits(8)?;
+            value |= (byte & 0x7F) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// Decode a Gorilla XOR chunk: the number of samples (u16), then a bit stream of the timestamps as
+/// delta-of-deltas and of the values XORed with the previous one.
+fn decode_xor(chunk: &[u8]) -> Option<Vec<(i64, f64)>> {
+    let count = u16::from_be_bytes([*chunk.first()?, *chunk.get(1)?]) as usize;
+    let mut reader = BitReader {
+        data: &chunk[2..],
+        position: 0,
+    };
+    let mut samples = Vec::with_capacity(count);
+    let mut time = 0i64;
+    let mut delta = 0i64;
+    let mut value = 0u64;
+    let (mut leading, mut trailing) = (0u32, 0u32);
+    for i in 0..count {
+        match i {
+            0 => {
+                time = unzigzag(reader.uvarint()?);
+                value = reader.bits(64)?;
+            }
+            _ => {
+                if i == 1 {
+                    delta = reader.uvarint()? as i64;
+                } else {
+                    let mut prefix = 0;
+                    while prefix < 4 && reader.bit()? {
+                        prefix += 1;
+                    }
+                    let size = [0, 14, 17, 20, 64][prefix];
+                    let mut dod = reader.bits(size)? as i64;
+                    if size > 0 && size < 64 && dod > 1 << (size - 1) {
+                        dod -= 1 << size;
+                    }
+                    delta += dod;
+                }
+                time += delta;
+                if reader.bit()? {
+                    if reader.bit()? {
+                        leading = reader.bits(5)? as u32;
+                        let significant = match reader.bits(6)? as u32 {
+                            0 => 64,
+                            n => n,
+                        };
+                        trailing = 64u32.checked_sub(leading + significant)?;
+                    }
+                    let significant = 64 - leading - trailing;
+                    value ^= reader.bits(significant)? << trailing;
+                }
+            }
+        }
+        samples.push((time, f64::from_bits(value)));
+    }
+    Some(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{write_varint, zigzag};
+
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        length: usize,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u64, n: u32) {
+            for i in (0..n).rev() {
+                if self.length.is_multiple_of(8) {
+                    self.data.push(0);
+                }
+                if value >> i & 1 == 1 {
+                    *self.data.last_mut().unwrap() |= 0x80 >> (self.length % 8);
+                }
+                self.length += 1;
+            }
+        }
+
+        fn uvarint(&mut self, value: u64) {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+            for b in bytes {
+                self.bits(b as u64, 8);
+            }
+        }
+    }
+
+    fn encode_xor(samples: &[(i64, f64)]) -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        let (mut time, mut delta, mut value) = (0i64, 0i64, 0u64);
+        for (i, (t, v)) in samples.iter().enumerate() {
+            let v = v.to_bits();
+            match i {
+                0 => {
+                    writer.uvarint(zigzag(*t));
+                    writer.bits(v, 64);
+                }
+                _ => {
+                    if i == 1 {
+                        writer.uvarint((t - time) as u64);
+                    } else {
+                        let dod = (t - time) - delta;
+                        match [14u32, 17, 20]
+                            .iter()
+                            .position(|n| -(1 << (n - 1)) < dod && dod <= 1 << (n - 1))
+                        {
+                            _ if dod == 0 => writer.bits(0, 1),
+                            Some(p) => {
+                                writer.bits([0b10, 0b110, 0b1110][p], p as u32 + 2);
+                                writer.bits(dod as u64, [14, 17, 20][p]);
+                            }
+                            None => {
+                                writer.bits(0b1111, 4);
+                                writer.bits(dod as u64, 64);
+                            }
+                        }
+                    }
+                    delta = t - time;
+                    let xor = v ^ value;
+                    if xor == 0 {
+                        writer.bits(0, 1);
+                    } else {
+                        let leading = xor.leading_zeros().min(31);
+                        let trailing = xor.trailing_zeros();
+                        let significant = 64 - leading - trailing;
+                        writer.bits(0b11, 2);
+                        writer.bits(leading as u64, 5);
+                        writer.bits(significant as u64 % 64, 6);
+                        writer.bits(xor >> trailing, significant);
+                    }
+                }
+            }
+            time = *t;
+            value = v;
+        }
+        let mut chunk = (samples.len() as u16).to_be_bytes().to_vec();
+        chunk.extend(writer.data);
+        chunk
+    }
+
+    /// Labels and samples of a series.
+    type BlockSeries<'a> = (Vec<(&'a str, &'a str)>, Vec<(i64, f64)>);
+
+    /// Write a block holding `series`, one chunk per series, as Prometheus would.
+    fn write_block(path: &Path, series: &[BlockSeries]) {
+        let mut symbols: Vec<&str> = series
+            .iter()
+            .flat_map(|(labels, _)| labels.iter().flat_map(|(n, v)| vec![*n, *v]))
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        let mut segment = CHUNKS_MAGIC.to_be_bytes().to_vec();
+        segment.extend(&[1, 0, 0, 0]);
+        let mut references = Vec::new();
+        for (_, samples) in series {
+            references.push(segment.len() as u64);
+            let chunk = encode_xor(samples);
+            write_varint(&mut segment, chunk.len() as u64);
+            segment.push(XOR_ENCODING);
+            segment.extend(chunk);
+            segment.extend(&[0; 4]);
+        }
+
+        let mut index = INDEX_MAGIC.to_be_bytes().to_vec();
+        index.push(2);
+        let symbols_start = index.len();
+        let mut table = (symbols.len() as u32).to_be_bytes().to_vec();
+        for s in &symbols {
+            write_varint(&mut table, s.len() as u64);
+            table.extend(s.as_bytes());
+        }
+        index.extend(&(table.len() as u32).to_be_bytes());
+        index.extend(table);
+        index.extend(&[0; 4]);
+        let series_start = index.len();
+        for ((labels, samples), reference) in series.iter().zip(references) {
+            index.resize(index.len().div_ceil(16) * 16, 0);
+            let mut entry = Vec::new();
+            write_varint(&mut entry, labels.len() as u64);
+            for (n, v) in labels {
+                write_varint(
+                    &mut entry,
+                    symbols.iter().position(|s| s == n).unwrap() as u64,
+                );
+                write_varint(
+                    &mut entry,
+                    symbols.iter().position(|s| s == v).unwrap() as u64,
+                );
+            }
+            write_varint(&mut entry, 1);
+            write_varint(&mut entry, zigzag(samples[0].0));
+            write_varint(
+                &mut entry,
+                (samples[samples.len() - 1].0 - samples[0].0) as u64,
+            );
+            write_varint(&mut entry, reference);
+            write_varint(&mut index, entry.len() as u64);
+            index.extend(entry);
+            index.extend(&[0; 4]);
+        }
+        let postings_start = index.len() as u64;
+        for offset in &[symbols_start as u64, series_start as u64, 0, 0] {
+            index.extend(&offset.to_be_bytes());
+        }
+        index.extend(&postings_start.to_be_bytes());
+        index.extend(&postings_start.to_be_bytes());
+        index.extend(&[0; 4]);
+
+        fs::create_dir_all(path.join("chunks")).unwrap();
+        fs::write(path.join("index"), index).unwrap();
+        fs::write(path.join("chunks").join("000001"), segment).unwrap();
+    }
+
+    #[test]
+    fn import_block() {
+        let block = Path::new("tsdb_block");
+        let _ = fs::remove_dir_all(block);
+
+        let start = 1_577_836_800_000; // 2020-01-01
+                                       // Irregular intervals, so the timestamps use every delta-of-delta size.
+        let intervals = [15_000, 15_003, 14_998, 15_250, 6_000, 85_000, 1_600_000];
+        let temperatures = |base: f64| -> Vec<(i64, f64)> {
+            let mut time = start;
+            (0..300)
+                .map(|i| {
+                    time += intervals[i % 7];
+                    (time, base + (i % 10) as f64 * 0.5)
+                })
+                .collect()
+        };
+        let a = temperatures(20.0);
+        let b = temperatures(-4.0);
+        write_block(
+            block,
+            &[
+                (vec![("__name__", "temp"), ("room", "a/1")], a.clone()),
+                (vec![("__name__", "temp"), ("room", "b")], b.clone()),
+                (vec![("__name__", "up")], vec![(start, 1.0)]),
+            ],
+        );
+        assert_eq!(decode_xor(&encode_xor(&a)).unwrap(), a);
+
+        let imported = import_tsdb_block::<f64>(block, "temp", block).expect("could not import.");
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].path, block.join("temp,room=a_1.db"));
+        assert_eq!(imported[1].labels[1], ("room".to_string(), "b".to_string()));
+        assert_eq!(imported[1].samples, 300);
+
+        let mut db: PhysicalDB<f64> =
+            PhysicalDB::new(&imported[1].path, None).expect("could not open db.");
+        assert_eq!(db.header().records_number, 300);
+        for i in &[0, 1, 5, 6, 299] {
+            let record = db.read_record(*i as u64).unwrap();
+            let (time, value) = b[*i];
+            assert_eq!(
+                db.header().origin_date.unix_seconds() + record.time_offset as i64,
+                time.div_euclid(1000)
+            );
+            assert_eq!(record.value, value);
+        }
+        assert_eq!(
+            import_tsdb_block::<f64>(block, "temp", block).err(),
+            Some(TSLiteError::AlreadyExists)
+        );
+
+        let _ = fs::remove_dir_all(block);
+    }
+}
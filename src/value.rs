@@ -0,0 +1,200 @@
+//! The values that can be stored in a record.
+
+use crate::{DbHeader, Extensions, TSLiteError};
+use std::fmt::Debug;
+
+/// The extension tag holding the width of the values, absent if they are one octet wide.
+pub(crate) const TAG: u8 = 0xF6;
+
+/// A fixed-size value that can be stored in a record.
+///
+/// Implement it to store your own types: every value of a type must be encoded with exactly `WIDTH` octets.
+/// Numbers should be encoded with little-endian ordering, like everything else in the DB.
+pub trait RecordValue: Copy + PartialOrd + Debug {
+    /// Number of octets taken by the value in the file.
+    const WIDTH: usize;
+
+    /// Write the value in `out`, which is exactly `WIDTH` octets long.
+    fn encode(&self, out: &mut [u8]);
+
+    /// Read a value from `bytes`, which is exactly `WIDTH` octets long.
+    fn decode(bytes: &[u8]) -> Self;
+
+    /// The value as a float, used by the queries computing statistics and derived series.
+    fn as_f64(&self) -> f64;
+
+    /// The value closest to `value`, used by the write-time transforms.
+    fn from_f64(value: f64) -> Self;
+}
+
+macro_rules! number_value {
+    ($from_f64:expr => $($t:ty),*) => {$(
+        impl RecordValue for $t {
+            const WIDTH: usize = std::mem::size_of::<$t>();
+
+            fn encode(&self, out: &mut [u8]) {
+                out.copy_from_slice(&self.to_le_bytes());
+            }
+
+            fn decode(bytes: &[u8]) -> $t {
+                let mut array = [0; std::mem::size_of::<$t>()];
+                array.copy_from_slice(bytes);
+                <$t>::from_le_bytes(array)
+            }
+
+            fn as_f64(&self) -> f64 {
+                *self as f64
+            }
+
+            fn from_f64(value: f64) -> $t {
+                $from_f64(value) as $t
+            }
+        }
+    )*};
+}
+
+// Integers round to the nearest value and saturate at their bounds.
+number_value!(f64::round => u8, u16, u32, u64, i8, i16, i32, i64);
+number_value!(std::convert::identity => f32, f64);
+
+impl DbHeader {
+    /// The number of octets of the values of the records, see [`RecordValue::WIDTH`].
+    pub fn value_width(&self) -> u8 {
+        width_from_extensions(&self.extensions).unwrap_or(1)
+    }
+
+    /// Record in the extensions that the values of the DB are `V` values.
+    pub(crate) fn set_value_type<V: RecordValue>(&mut self) -> Result<(), TSLiteError> {
+        if V::WIDTH == 1 {
+            self.extensions.remove(TAG);
+            return Ok(());
+        }
+        self.extensions.set(TAG, &[V::WIDTH as u8])
+    }
+}
+
+/// The width of the values recorded in `extensions`, one octet if there is no entry for it.
+/// `None` if the entry is damaged.
+pub(crate) fn width_from_extensions(extensions: &Extensions) -> Option<u8> {
+    match extensions.get(TAG) {
+        None => Some(1),
+        Some(&[width]) if width > 1 => Some(width),
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PhysicalDB, RecordInfo};
+    use std::fs;
+    use std::path::Path;
+
+    /// A temperature in hundredths of a degree, as a downstream crate could define it.
+    #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+    struct Celsius(i16);
+
+    impl RecordValue for Celsius {
+        const WIDTH: usize = 2;
+
+        fn encode(&self, out: &mut [u8]) {
+            out.copy_from_slice(&self.0.to_le_bytes());
+        }
+
+        fn decode(bytes: &[u8]) -> Celsius {
+            Celsius(i16::from_le_bytes([bytes[0], bytes[1]]))
+        }
+
+        fn as_f64(&self) -> f64 {
+            self.0 as f64 / 100.0
+        }
+
+        fn from_f64(value: f64) -> Celsius {
+            Celsius((value * 100.0).round() as i16)
+        }
+    }
+
+    #[test]
+    fn custom_value_type() {
+        let (path, narrow) = ("custom_value.db", "narrow_value.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(narrow);
+
+        let mut db: PhysicalDB<Celsius> =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let records = [
+            RecordInfo {
+                time_offset: 1,
+                value: Celsius(-1250),
+            },
+            RecordInfo {
+                time_offset: 2,
+                value: Celsius(2175),
+            },
+        ];
+        for r in records.iter() {
+            db.append_record(*r).expect("could not append record.");
+        }
+        db.update_record(0, Celsius(-1300))
+            .expect("could not update record.");
+
+        assert_eq!(db.read_record(0).unwrap().value, Celsius(-1300));
+        assert_eq!(db.read_record(1).unwrap(), records[1]);
+        assert_eq!(
+            fs::metadata(path).unwrap().len(),
+            crate::RECORDS_START + 2 * RecordInfo::<Celsius>::SIZE
+        );
+
+        // The width is recorded in the header, so the DB can't be opened with a type of another width.
+        drop(db);
+        assert_eq!(
+            PhysicalDB::<u32>::new(Path::new(path), None).err(),
+            Some(crate::TSLiteError::ValueWidthMismatch(2))
+        );
+        let mut db: PhysicalDB<i16> =
+            PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+        assert_eq!(db.header().value_width(), 2);
+        // It is an extension entry, absent for one-octet values.
+        assert_eq!(db.header().extensions.get(TAG), Some(&[2][..]));
+        let bytes =
+            PhysicalDB::<u8>::create(Path::new(narrow), None).expect("could not create db.");
+        assert_eq!(bytes.header().extensions.get(TAG), None);
+        assert_eq!(bytes.header().value_width(), 1);
+        assert_eq!(db.read_record(1).unwrap().value, 2175);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(narrow);
+    }
+
+    #[test]
+    fn float_values() {
+        let path = "float_value.db";
+        let _ = fs::remove_file(path);
+
+        let mut db: PhysicalDB<f64> =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        let values = [21.375, -0.5, f64::INFINITY, f64::NAN, 1e-300];
+        for (i, v) in values.iter().enumerate() {
+            db.append_record(RecordInfo {
+                time_offset: 10 - i as u32,
+                value: *v,
+            })
+            .expect("could not append record.");
+        }
+        db.update_record(1, 230.5)
+            .expect("could not update record.");
+        db.reorder_record().expect("could not reorder records.");
+
+        let read: Vec<f64> = (0..5).map(|i| db.read_record(i).unwrap().value).collect();
+        assert_eq!(read[0], 1e-300);
+        assert!(read[1].is_nan());
+        assert_eq!(read[2..], [f64::INFINITY, 230.5, 21.375]);
+        assert_eq!(f32::from_f64(0.1).as_f64(), 0.1f32 as f64);
+        assert_eq!(
+            fs::metadata(path).unwrap().len(),
+            crate::RECORDS_START + 5 * RecordInfo::<f64>::SIZE
+        );
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,167 @@
+//! A journal of the appends in progress, enabled with the `wal` option of [`DbOptions`](crate::DbOptions).
+//!
+//! Before an append touches the DB file, the records it writes and the index of the first of them are written
+//! and synced to a journal next to the DB, named after it with `.wal` appended. The journal is removed once the records
+//! and the header are synced. If the process crashes in between, the DB file may hold the header without the
+//! records or the records without the header: the journal is then replayed when the DB is opened, so both are
+//! written again. A journal whose write was itself interrupted fails its checksum and is discarded, the DB
+//! file not having been touched yet.
+//!
+//! ```text
+//! +------------------------[JOURNAL]------------------------+
+//! | first index | records length |    records    |  CRC32   |
+//! |    64bit    |     32bit      |               |  32bit   |
+//! +---------------------------------------------------------+
+//! ```
+
+use crate::format::RECORDS_START;
+use crate::{telemetry, write_at, PhysicalDB, RecordValue, TSLiteError};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, Cursor, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// The path of the journal of the DB at `path`. The suffix is appended to the whole file name, so it is never
+/// the path of the DB itself, whatever its extension.
+pub(crate) fn journal_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".wal");
+    PathBuf::from(name)
+}
+
+/// Write and sync the journal of an append of the serialized `records` at index `first`, and sync its directory
+/// so the journal itself survives a crash.
+/// Fail with `InvalidParameter` if the records don't fit in a journal, whose length is on 32 bits.
+pub(crate) fn begin(path: &Path, first: u64, records: &[u8]) -> Result<(), TSLiteError> {
+    let length = u32::try_from(records.len()).map_err(|_| {
+        TSLiteError::InvalidParameter(format!(
+            "{} octets of records don't fit in the journal.",
+            records.len()
+        ))
+    })?;
+    let mut bytes = Vec::with_capacity(16 + records.len());
+    bytes.write_u64::<LittleEndian>(first)?;
+    bytes.write_u32::<LittleEndian>(length)?;
+    bytes.extend_from_slice(records);
+    let crc = crc32fast::hash(&bytes);
+    bytes.write_u32::<LittleEndian>(crc)?;
+
+    let journal = journal_path(path);
+    let file = File::create(&journal)?;
+    write_at(&file, &bytes, 0)?;
+    telemetry::sync_data(&file)?;
+    telemetry::sync_parent(&journal).map_err(TSLiteError::Io)
+}
+
+/// Remove the journal once its append is synced.
+pub(crate) fn commit(path: &Path) -> io::Result<()> {
+    match fs::remove_file(journal_path(path)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The index of the first record and the serialized records of a journal, `None` if it is torn or
+/// doesn't hold whole records of `size` octets.
+fn parse(bytes: &[u8], size: usize) -> Option<(u64, &[u8])> {
+    if bytes.len() < 16 {
+        return None;
+    }
+    let (content, crc) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(content) != Cursor::new(crc).read_u32::<LittleEndian>().ok()? {
+        return None;
+    }
+    let mut reader = Cursor::new(content);
+    let first = reader.read_u64::<LittleEndian>().ok()?;
+    let length = reader.read_u32::<LittleEndian>().ok()? as usize;
+    let records = &content[12..];
+    if records.len() != length || !length.is_multiple_of(size) {
+        return None;
+    }
+    Some((first, records))
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Replay the journal left by an interrupted append, if any, and return whether there was one to replay.
+    pub(crate) fn replay_journal(&mut self) -> Result<bool, TSLiteError> {
+        let path = journal_path(&self.path);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(TSLiteError::Io(e)),
+        };
+
+        let size = self.header.record_size::<V>();
+        let replayed = match parse(&bytes, size as usize) {
+            Some((first, records)) if first <= self.header.records_number => {
+                if self.file.is_none() {
+                    self.open()?;
+                }
+                let file = self.file.as_ref().unwrap();
+                write_at(file, records, RECORDS_START + first * size).map_err(TSLiteError::Io)?;
+                self.header.records_number = first + records.len() as u64 / size;
+                self.write_header()?;
+                true
+            }
+            _ => false,
+        };
+        commit(&self.path).map_err(TSLiteError::Io)?;
+        Ok(replayed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbOptions, RecordInfo, Timestamp};
+
+    #[test]
+    fn replay_interrupted_append() {
+        let path = Path::new("wal_replay.db");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(journal_path(path));
+        assert_eq!(journal_path(path), Path::new("wal_replay.db.wal"));
+        assert_eq!(journal_path(Path::new("x.wal")), Path::new("x.wal.wal"));
+
+        let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let options = DbOptions {
+            wal: true,
+            ..DbOptions::default()
+        };
+        let mut db: PhysicalDB<u16> = PhysicalDB::create_with_options(path, Some(origin), &options)
+            .expect("could not create db.");
+        db.append_record(RecordInfo {
+            time_offset: 0,
+            value: 1,
+        })
+        .expect("could not append record.");
+        assert!(!journal_path(path).exists());
+
+        // The process crashed after the journal of the second append was synced.
+        let record = RecordInfo {
+            time_offset: 10,
+            value: 2u16,
+        };
+        begin(path, 1, &record.as_bytes()).unwrap();
+        drop(db);
+        let mut db: PhysicalDB<u16> =
+            PhysicalDB::new_with_options(path, None, &options).expect("could not open db.");
+        assert!(!journal_path(path).exists());
+        assert_eq!(db.header().records_number, 2);
+        assert_eq!(db.read_record(1).unwrap(), record);
+
+        // A torn journal is discarded.
+        begin(path, 2, &record.as_bytes()).unwrap();
+        let mut bytes = fs::read(journal_path(path)).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        fs::write(journal_path(path), bytes).unwrap();
+        drop(db);
+        let db: PhysicalDB<u16> =
+            PhysicalDB::new_with_options(path, None, &options).expect("could not open db.");
+        assert!(!journal_path(path).exists());
+        assert_eq!(db.header().records_number, 2);
+
+        let _ = fs::remove_file(path);
+    }
+}
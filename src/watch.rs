@@ -0,0 +1,126 @@
+//! Watching a DB for the records appended by other handles or processes, by polling its file.
+
+use crate::{DbHeader, PhysicalDB, RecordInfo, RecordValue, TSLiteError};
+use std::collections::VecDeque;
+use std::thread;
+use std::time::Duration;
+
+/// The records appended to a DB after the ones its handle knew of when [`PhysicalDB::watch`] was called.
+///
+/// As an iterator, it blocks until a record is appended, looking for new ones every poll interval, and
+/// never ends unless reading the DB fails. [`Watch::poll`] returns the new records without waiting.
+/// If the DB loses records, such as when it is pruned, it is watched from its new end.
+#[derive(Debug)]
+pub struct Watch<'a, V: RecordValue = u8> {
+    db: &'a mut PhysicalDB<V>,
+    /// The number of records of the DB when it was last polled.
+    seen: u64,
+    poll_interval: Duration,
+    /// The records polled but not returned by the iterator yet.
+    pending: VecDeque<RecordInfo<V>>,
+    failed: bool,
+}
+
+impl<V: RecordValue> PhysicalDB<V> {
+    /// Watch the DB for the records appended after the ones this handle already knows of, looking for them
+    /// every `poll_interval`. The header isn't read again, so no record appended after a read through this
+    /// handle is missed. Only the file length and modification date are checked while nothing is appended.
+    pub fn watch(&mut self, poll_interval: Duration) -> Watch<'_, V> {
+        Watch {
+            seen: self.header.records_number,
+            db: self,
+            poll_interval,
+            pending: VecDeque::new(),
+            failed: false,
+        }
+    }
+}
+
+impl<V: RecordValue> Watch<'_, V> {
+    /// The header of the watched DB, as of the last poll.
+    pub fn header(&self) -> &DbHeader {
+        self.db.header()
+    }
+
+    /// The records appended since the last poll, with their values decoded, in the order they are stored.
+    pub fn poll(&mut self) -> Result<Vec<RecordInfo<V>>, TSLiteError> {
+        let mut records: Vec<RecordInfo<V>> = self.pending.drain(..).collect();
+        self.db.refresh_if_changed()?;
+        let total = self.db.header.records_number;
+        if total > self.seen {
+            records.extend(self.db.last_n((total - self.seen) as usize)?);
+        }
+        self.seen = total;
+        Ok(records)
+    }
+}
+
+impl<V: RecordValue> Iterator for Watch<'_, V> {
+    type Item = Result<RecordInfo<V>, TSLiteError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+            match self.poll() {
+                Ok(records) if records.is_empty() => thread::sleep(self.poll_interval),
+                Ok(records) => self.pending.extend(records),
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PhysicalDB, RecordInfo};
+    use std::fs;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn watch_appended_records() {
+        let path = "watch.db";
+        let _ = fs::remove_file(path);
+
+        let mut db: PhysicalDB =
+            PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+        for i in 0..3 {
+            db.append_record(RecordInfo {
+                time_offset: i,
+                value: i as u8,
+            })
+            .unwrap();
+        }
+
+        let mut watched: PhysicalDB = PhysicalDB::open_path(Path::new(path)).unwrap();
+        let mut watch = watched.watch(Duration::from_millis(5));
+        assert!(watch.poll().unwrap().is_empty());
+
+        let writer = thread::spawn(move || {
+            for i in 3..6 {
+                thread::sleep(Duration::from_millis(20));
+                db.append_record(RecordInfo {
+                    time_offset: i,
+                    value: i as u8,
+                })
+                .unwrap();
+            }
+        });
+        let values: Vec<u8> = watch.by_ref().take(3).map(|r| r.unwrap().value).collect();
+        assert_eq!(values, vec![3, 4, 5]);
+        writer.join().unwrap();
+        assert!(watch.poll().unwrap().is_empty());
+        assert_eq!(watch.header().records_number, 6);
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,116 @@
+//! The `tslite` command-line tool, run on a DB file.
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tslite::{PhysicalDB, RecordInfo, Timestamp};
+
+fn tslite(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tslite"))
+        .args(args)
+        .output()
+        .expect("could not run tslite.")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn inspect_and_append() {
+    let path = "cli.db";
+    let _ = fs::remove_file(path);
+
+    let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+    let mut db: PhysicalDB<u16> =
+        PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+    db.set_label("unit", "celsius").unwrap();
+    db.append_record(RecordInfo {
+        time_offset: 60,
+        value: 21,
+    })
+    .unwrap();
+    drop(db);
+
+    let info = tslite(&["info", path]);
+    assert!(info.status.success());
+    let info = stdout(&info);
+    assert!(info.contains("origin: 2020-01-01T00:00:00Z"));
+    assert!(info.contains("records: 1"));
+    assert!(info.contains("value width: 2"));
+    assert!(info.contains("label: unit=celsius"));
+
+    let at = (origin.unix_seconds() + 120).to_string();
+    assert!(tslite(&["append", path, "22", "--at", &at])
+        .status
+        .success());
+    assert_eq!(
+        stdout(&tslite(&["dump", path])),
+        "time,value\n2020-01-01T00:01:00Z,21\n2020-01-01T00:02:00Z,22\n"
+    );
+    assert_eq!(
+        stdout(&tslite(&[
+            "--type", "i16", "dump", path, "--format", "ndjson"
+        ]))
+        .lines()
+        .count(),
+        2
+    );
+
+    assert!(tslite(&["check", path]).status.success());
+    assert!(tslite(&["repair", path]).status.success());
+    assert!(!tslite(&["--type", "u8", "info", path]).status.success());
+    assert!(!tslite(&["frobnicate", path]).status.success());
+    assert!(!tslite(&["info", "cli_missing.db"]).status.success());
+
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn tail_follows_appends() {
+    let path = "cli_tail.db";
+    let _ = fs::remove_file(path);
+
+    let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+    let mut db: PhysicalDB =
+        PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+    for i in 0..5 {
+        db.append_record(RecordInfo {
+            time_offset: i * 60,
+            value: i as u8,
+        })
+        .unwrap();
+    }
+    assert_eq!(
+        stdout(&tslite(&["tail", path, "--lines", "2"])),
+        "2020-01-01T00:03:00Z,3\n2020-01-01T00:04:00Z,4\n"
+    );
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tslite"))
+        .args(["tail", path, "-f", "--lines", "1", "--interval", "10"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("could not run tslite.");
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    assert_eq!(lines.next().unwrap().unwrap(), "2020-01-01T00:04:00Z,4");
+    // Appended by another handle while the tool follows the DB.
+    db.append_records(&[
+        RecordInfo {
+            time_offset: 300,
+            value: 5,
+        },
+        RecordInfo {
+            time_offset: 360,
+            value: 6,
+        },
+    ])
+    .unwrap();
+    assert_eq!(lines.next().unwrap().unwrap(), "2020-01-01T00:05:00Z,5");
+    assert_eq!(lines.next().unwrap().unwrap(), "2020-01-01T00:06:00Z,6");
+    child.kill().unwrap();
+    let _ = child.wait();
+
+    let _ = fs::remove_file(path);
+}
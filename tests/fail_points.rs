@@ -0,0 +1,82 @@
+//! The fail points are configured for the whole process, so they are exercised in their own test binary.
+#![cfg(feature = "failpoints")]
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use tslite::{fail_points, DbIssue, PhysicalDB, RecordInfo, TSLiteError, Timestamp};
+
+fn record(time_offset: u32, value: u8) -> RecordInfo {
+    RecordInfo { time_offset, value }
+}
+
+#[test]
+fn recover_from_failed_writes() {
+    let scenario = fail::FailScenario::setup();
+    let path = "fail_points.db";
+    let _ = fs::remove_file(path);
+
+    let origin = Timestamp::new(2020, 1, 1, 0, 0, 0).unwrap();
+    let mut db: PhysicalDB =
+        PhysicalDB::create(Path::new(path), Some(origin)).expect("could not create db.");
+    db.append_record(record(0, 1)).unwrap();
+
+    // Each step of an append fails in turn, leaving the DB as it was.
+    for (point, step) in [
+        (fail_points::WRITE_RECORD, 1),
+        (fail_points::WRITE_HEADER, 2),
+        (fail_points::FSYNC, 3),
+    ]
+    .iter()
+    {
+        fail::cfg(*point, "return(disk full)").unwrap();
+        let e = db.append_record(record(*step, 2)).unwrap_err();
+        assert_eq!(e, TSLiteError::Io(io::ErrorKind::Other.into()));
+        assert_eq!(e.to_string(), "I/O error: disk full");
+        fail::remove(*point);
+        assert_eq!(db.header().records_number, 1);
+    }
+
+    // A crash between the write of the header and the sync leaves a consistent file.
+    fail::cfg(fail_points::FSYNC, "panic").unwrap();
+    let crashed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _ = db.append_record(record(10, 3));
+    }));
+    assert!(crashed.is_err());
+    fail::remove(fail_points::FSYNC);
+
+    let mut db: PhysicalDB = PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+    assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+    assert_eq!(db.header().records_number, 2);
+    assert_eq!(db.read_record(1).unwrap(), record(10, 3));
+
+    let _ = fs::remove_file(path);
+    scenario.teardown();
+}
+
+#[test]
+fn shadow_header_written_on_sync() {
+    let scenario = fail::FailScenario::setup();
+    let path = "fail_points_shadow.db";
+    let _ = fs::remove_file(path);
+
+    let mut db: PhysicalDB =
+        PhysicalDB::create(Path::new(path), None).expect("could not create db.");
+    db.append_record(record(0, 1)).unwrap();
+    db.append_record(record(1, 2)).unwrap();
+
+    // The appends only wrote the primary copy, the write of the shadow copy fails when syncing.
+    fail::cfg(fail_points::WRITE_HEADER, "return(disk full)").unwrap();
+    assert!(db.sync().is_err());
+    fail::remove(fail_points::WRITE_HEADER);
+    assert_eq!(db.header().records_number, 2);
+    drop(db);
+
+    let mut db: PhysicalDB = PhysicalDB::new(Path::new(path), None).expect("could not open db.");
+    assert_eq!(db.header().records_number, 2);
+    assert_eq!(db.read_record(1).unwrap(), record(1, 2));
+    assert_eq!(db.check_db_file().unwrap(), DbIssue::None);
+
+    let _ = fs::remove_file(path);
+    scenario.teardown();
+}